@@ -0,0 +1,17 @@
+//! the grpc feature's code for proto/pscan.proto, made with the protoc
+//! from protoc-bin-vendored so building needs none installed. the client
+//! is for the tests, which bring their own channel.
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/pscan.proto");
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("no protoc for this platform");
+        std::env::set_var("PROTOC", protoc);
+        tonic_prost_build::configure()
+            .build_transport(false)
+            .compile_protos(&["proto/pscan.proto"], &["proto"])
+            .expect("can't compile proto/pscan.proto");
+    }
+}
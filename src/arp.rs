@@ -0,0 +1,186 @@
+//! arp discovery of hosts on directly connected ethernet subnets
+
+use crate::iface::{self, MacAddr};
+use std::io;
+use std::net::IpAddr;
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+/// a host that answered an arp request
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ArpReply {
+    pub ip: IpAddr,
+    pub mac: MacAddr,
+    pub latency: Duration,
+}
+
+/// ask for the mac of every host in `hosts` that sits on one of our
+/// ethernet subnets; the rest are left alone. fails without the privileges
+/// for a packet socket.
+pub(crate) fn arp_sweep(
+    hosts: &[IpAddr],
+    timeout: Duration,
+    cancelled: &AtomicBool,
+) -> io::Result<Vec<ArpReply>> {
+    let interfaces = iface::interfaces()?;
+    let mut replies = Vec::new();
+    for interface in &interfaces {
+        let local: Vec<IpAddr> = hosts
+            .iter()
+            .copied()
+            .filter(|&ip| iface::local_interface(&interfaces, ip) == Some(interface))
+            .collect();
+        if !local.is_empty() {
+            replies.extend(sys::sweep(interface, &local, timeout, cancelled)?);
+        }
+    }
+    Ok(replies)
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use super::ArpReply;
+    use crate::iface::Interface;
+    use crate::packet::{self, ETH_P_ARP};
+    use crate::transport::Readable;
+    use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+    use std::collections::HashMap;
+    use std::io::{self, ErrorKind};
+    use std::mem::{self, MaybeUninit};
+    use std::net::IpAddr;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::{Duration, Instant};
+
+    /// a non-blocking packet socket that sees arp frames on one interface
+    struct ArpSocket {
+        socket: Socket,
+        readable: Readable,
+    }
+
+    impl ArpSocket {
+        fn open(interface: &Interface) -> io::Result<ArpSocket> {
+            let protocol = ETH_P_ARP.to_be();
+            let socket = Socket::new(
+                Domain::PACKET,
+                Type::RAW,
+                Some(Protocol::from(i32::from(protocol))),
+            )?;
+            socket.set_nonblocking(true)?;
+            // SAFETY: the storage comes zeroed and is big enough for a
+            // sockaddr_ll, whose size is what we say was written
+            let ((), addr) = unsafe {
+                SockAddr::try_init(|storage, len| {
+                    let addr = &mut *storage.cast::<libc::sockaddr_ll>();
+                    addr.sll_family = libc::AF_PACKET as u16;
+                    addr.sll_protocol = protocol;
+                    addr.sll_ifindex = interface.index as libc::c_int;
+                    *len = mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t;
+                    Ok(())
+                })
+            }?;
+            socket.bind(&addr)?;
+            Ok(ArpSocket {
+                readable: Readable::new(&socket)?,
+                socket,
+            })
+        }
+
+        fn send(&self, frame: &[u8]) -> io::Result<()> {
+            self.socket.send(frame).map(drop)
+        }
+
+        fn recv<'a>(&self, buf: &'a mut [u8]) -> io::Result<&'a [u8]> {
+            // SAFETY: recv only writes initialised bytes into the buffer,
+            // and only the n it reports are read back
+            let uninit = unsafe { &mut *(buf as *mut [u8] as *mut [MaybeUninit<u8>]) };
+            let n = self.socket.recv(uninit)?;
+            Ok(&buf[..n])
+        }
+
+        /// wait until something new is readable, or `wait` passes
+        fn wait(&self, wait: Duration) -> io::Result<()> {
+            self.readable.wait(wait)
+        }
+    }
+
+    pub(super) fn sweep(
+        interface: &Interface,
+        hosts: &[IpAddr],
+        timeout: Duration,
+        cancelled: &AtomicBool,
+    ) -> io::Result<Vec<ArpReply>> {
+        let socket = ArpSocket::open(interface)?;
+        let mut sent: HashMap<IpAddr, Instant> = HashMap::with_capacity(hosts.len());
+        let mut replies = Vec::new();
+        let mut buf = vec![0u8; 1514];
+        let mut drain = |sent: &mut HashMap<IpAddr, Instant>, replies: &mut Vec<ArpReply>| {
+            while let Ok(frame) = socket.recv(&mut buf) {
+                let Some((ip, mac)) = packet::parse_arp_reply(frame) else {
+                    continue;
+                };
+                let ip = IpAddr::V4(ip);
+                if let Some(at) = sent.remove(&ip) {
+                    let latency = at.elapsed();
+                    replies.push(ArpReply { ip, mac, latency });
+                }
+            }
+        };
+
+        for &ip in hosts {
+            if cancelled.load(Ordering::Relaxed) {
+                return Ok(replies);
+            }
+            let IpAddr::V4(target) = ip else { continue };
+            let frame = packet::arp_request(interface.mac, interface.addr, target);
+            loop {
+                match socket.send(&frame) {
+                    Ok(()) => {
+                        sent.insert(ip, Instant::now());
+                        break;
+                    }
+                    // send buffer full: give it a moment
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                        let _ = socket.wait(Duration::from_millis(10));
+                        drain(&mut sent, &mut replies);
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            drain(&mut sent, &mut replies);
+        }
+
+        // wait out the last requests' timeout
+        let deadline = Instant::now() + timeout;
+        while !sent.is_empty() && !cancelled.load(Ordering::Relaxed) {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            match socket.wait(left.min(Duration::from_millis(100))) {
+                Ok(()) => drain(&mut sent, &mut replies),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(replies)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use super::ArpReply;
+    use crate::iface::Interface;
+    use std::io;
+    use std::net::IpAddr;
+    use std::sync::atomic::AtomicBool;
+    use std::time::Duration;
+
+    pub(super) fn sweep(
+        _interface: &Interface,
+        _hosts: &[IpAddr],
+        _timeout: Duration,
+        _cancelled: &AtomicBool,
+    ) -> io::Result<Vec<ArpReply>> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
@@ -0,0 +1,70 @@
+use std::io::{ErrorKind, Read};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+/// how much to read from an open port, and for how long
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BannerOptions {
+    /// stop after this many bytes
+    pub max_bytes: usize,
+    /// total time to wait for the service to speak first
+    pub timeout: Duration,
+}
+
+impl Default for BannerOptions {
+    fn default() -> Self {
+        BannerOptions {
+            max_bytes: 256,
+            timeout: Duration::from_millis(500),
+        }
+    }
+}
+
+/// read whatever a freshly connected service sends on its own.
+/// returns the sanitized text, or None if it stayed quiet.
+pub fn read_banner(stream: &mut TcpStream, opts: &BannerOptions) -> Option<String> {
+    let deadline = Instant::now() + opts.timeout;
+    let mut buf = vec![0u8; opts.max_bytes];
+    let mut len = 0;
+
+    while len < buf.len() {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() || stream.set_read_timeout(Some(left)).is_err() {
+            break;
+        }
+        match stream.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        }
+    }
+
+    banner_text(&buf[..len])
+}
+
+/// printable text for raw banner bytes: trailing whitespace is dropped,
+/// control characters and non-ascii bytes are escaped (`\r`, `\n`, `\x00`),
+/// so a banner is always a single safe line. None for an empty banner.
+pub fn banner_text(raw: &[u8]) -> Option<String> {
+    let end = raw
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(0, |i| i + 1);
+    if end == 0 {
+        return None;
+    }
+
+    let mut text = String::with_capacity(end);
+    for &b in &raw[..end] {
+        match b {
+            b'\\' => text.push_str("\\\\"),
+            b'\r' => text.push_str("\\r"),
+            b'\n' => text.push_str("\\n"),
+            b'\t' => text.push_str("\\t"),
+            0x20..=0x7e => text.push(b as char),
+            _ => text.push_str(&format!("\\x{b:02x}")),
+        }
+    }
+    Some(text)
+}
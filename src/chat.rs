@@ -0,0 +1,111 @@
+//! chat channels to post scan summaries to, for --chat: slack, discord and
+//! microsoft teams incoming webhooks, which all take a json body with the
+//! message text in it
+
+use crate::Webhook;
+use serde_json::json;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+/// discord refuses messages longer than this
+const DISCORD_MAX: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatKind {
+    Slack,
+    Discord,
+    Teams,
+}
+
+impl ChatKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChatKind::Slack => "slack",
+            ChatKind::Discord => "discord",
+            ChatKind::Teams => "teams",
+        }
+    }
+}
+
+impl fmt::Display for ChatKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// a channel's incoming webhook
+#[derive(Debug, Clone)]
+pub struct ChatTarget {
+    pub kind: ChatKind,
+    webhook: Webhook,
+}
+
+impl FromStr for ChatTarget {
+    type Err = String;
+
+    /// `slack:URL`, `discord:URL` or `teams:URL`; a bare URL on one of
+    /// their usual hosts says which it is by itself. all three hand out
+    /// https urls, posted to over rustls as webhooks are.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, url) = match s.split_once(':') {
+            Some(("slack", url)) => (Some(ChatKind::Slack), url),
+            Some(("discord", url)) => (Some(ChatKind::Discord), url),
+            Some(("teams", url)) => (Some(ChatKind::Teams), url),
+            _ => (None, s),
+        };
+        let host = url
+            .split_once("://")
+            .map(|(_, rest)| rest.split(['/', ':']).next().unwrap_or(""))
+            .unwrap_or("");
+        let kind = match kind {
+            Some(kind) => kind,
+            None if host == "hooks.slack.com" => ChatKind::Slack,
+            None if host == "discord.com" || host == "discordapp.com" => ChatKind::Discord,
+            None if host.ends_with(".webhook.office.com") || host.ends_with(".logic.azure.com") => {
+                ChatKind::Teams
+            }
+            None => {
+                return Err(format!(
+                    "can't tell which chat '{s}' is for; write slack:, discord: or teams: before it"
+                ))
+            }
+        };
+        Ok(ChatTarget {
+            kind,
+            webhook: url.parse()?,
+        })
+    }
+}
+
+impl fmt::Display for ChatTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} channel", self.kind)
+    }
+}
+
+impl ChatTarget {
+    /// the json body that posts `text` as a message
+    pub fn payload(&self, text: &str) -> String {
+        match self.kind {
+            ChatKind::Slack => json!({ "text": text }).to_string(),
+            ChatKind::Discord => {
+                let text = if text.chars().count() > DISCORD_MAX {
+                    let cut: String = text.chars().take(DISCORD_MAX - 1).collect();
+                    format!("{cut}…")
+                } else {
+                    text.to_string()
+                };
+                json!({ "content": text }).to_string()
+            }
+            // teams reads the text as markdown, where a lone newline
+            // doesn't break the line
+            ChatKind::Teams => json!({ "text": text.replace('\n', "  \n") }).to_string(),
+        }
+    }
+
+    /// post `text`, with the webhook's retries
+    pub fn post(&self, text: &str) -> io::Result<()> {
+        self.webhook.deliver(&self.payload(text))
+    }
+}
@@ -0,0 +1,329 @@
+//! defaults for the command line from toml config files and `PSCAN_*`
+//! environment variables, layered so a later file (then the environment,
+//! then the command line) overrides an earlier one
+
+use crate::profile::ProfileTable;
+use crate::report::{self, ReportOptions};
+use crate::{check_allowed, Profile, ScanError, ScanHost, Target, Webhook, BUILTIN_PROFILES};
+use serde::de::{self, DeserializeOwned, Deserializer};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::io;
+use std::ops::Range;
+use std::path::PathBuf;
+use toml::Spanned;
+use tracing::warn;
+
+/// the settings a config file may hold; anything left out keeps the
+/// built-in default:
+///
+/// ```toml
+/// timeout_ms = 200
+/// threads = 16
+/// format = "json"
+/// profile = "quick"     # when --profile isn't given
+/// webhooks = ["https://hooks.example.com/pscan"]
+///
+/// [targets]
+/// allow = ["10.0.0.0/8", "scanme.example.com"]  # refuse anything else
+///
+/// [profiles.mail]       # for --profile mail, see `Profile`
+/// ports = [25, 465, 587, 993]
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub timeout_ms: Option<u64>,
+    pub threads: Option<usize>,
+    pub format: Option<String>,
+    /// the profile scans use when --profile doesn't pick one
+    pub profile: Option<String>,
+    /// posted to when the scan finishes, as with --webhook
+    pub webhooks: Vec<Webhook>,
+    /// the only targets that may be scanned, when set: ips, names or
+    /// cidr networks
+    pub allow: Option<Vec<Target>>,
+    /// the config's own profiles, by name; they win over built-in ones
+    /// of the same name
+    pub profiles: Vec<(String, Profile)>,
+}
+
+/// a config file as written, checked by `Config::parse`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    timeout_ms: Option<Spanned<i64>>,
+    threads: Option<Spanned<i64>>,
+    format: Option<Spanned<String>>,
+    profile: Option<String>,
+    webhooks: Option<Spanned<Strings>>,
+    targets: Option<TargetsTable>,
+    #[serde(default)]
+    profiles: BTreeMap<String, ProfileTable>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TargetsTable {
+    allow: Option<Spanned<Strings>>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let file: ConfigFile = parse_toml(text)?;
+        let at = |span: Range<usize>| move |e: String| error_at(text, span.clone(), &e);
+        let mut config = Config::default();
+        if let Some(ms) = file.timeout_ms {
+            config.timeout_ms = Some(positive("timeout_ms", *ms.get_ref()).map_err(at(ms.span()))?);
+        }
+        if let Some(n) = file.threads {
+            config.threads = Some(threads(*n.get_ref()).map_err(at(n.span()))?);
+        }
+        if let Some(format) = file.format {
+            config.format = Some(check_format(format.get_ref()).map_err(at(format.span()))?);
+        }
+        config.profile = file.profile;
+        if let Some(urls) = file.webhooks {
+            config.webhooks = webhooks(&urls.get_ref().0).map_err(at(urls.span()))?;
+        }
+        if let Some(allow) = file.targets.and_then(|t| t.allow) {
+            config.allow = Some(allowed(&allow.get_ref().0).map_err(at(allow.span()))?);
+        }
+        for (name, profile) in file.profiles {
+            let profile = Profile::from_table(&name, profile, text)?;
+            config.profiles.push((name, profile));
+        }
+        Ok(config)
+    }
+
+    /// the `PSCAN_*` environment variables, a layer between the config
+    /// files and the command line: PSCAN_TIMEOUT_MS, PSCAN_THREADS,
+    /// PSCAN_FORMAT, PSCAN_PROFILE, and PSCAN_WEBHOOKS and
+    /// PSCAN_ALLOW_TARGETS as comma separated lists. PSCAN_CONFIG names
+    /// the config file, and is left to whoever reads it. other PSCAN_
+    /// names are warned about and ignored.
+    pub fn from_env() -> Result<Config, String> {
+        Config::from_vars(std::env::vars_os())
+    }
+
+    /// `from_env`, from these variables; only PSCAN_ values have to be
+    /// utf-8
+    pub fn from_vars<I, K, V>(vars: I) -> Result<Config, String>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<OsString>,
+        V: Into<OsString>,
+    {
+        let mut config = Config::default();
+        for (var, text) in vars {
+            let var = var.into();
+            if !var.as_encoded_bytes().starts_with(b"PSCAN_") {
+                continue;
+            }
+            let var = var.to_string_lossy().into_owned();
+            let name = &var["PSCAN_".len()..];
+            if !KNOWN_VARS.contains(&name) {
+                warn!(
+                    "ignoring {var}, not a setting (expected PSCAN_{})",
+                    KNOWN_VARS.join(", PSCAN_")
+                );
+                continue;
+            }
+            let text = text
+                .into()
+                .into_string()
+                .map_err(|_| format!("{var}: not valid utf-8"))?;
+            let number = |key: &str| {
+                text.trim()
+                    .parse::<i64>()
+                    .map_err(|_| format!("{key} must be a number, not '{text}'"))
+            };
+            let list = || -> Vec<String> {
+                let items = text.split(',').map(str::trim).filter(|s| !s.is_empty());
+                items.map(str::to_string).collect()
+            };
+            let set = (|| {
+                match name {
+                    "TIMEOUT_MS" => {
+                        config.timeout_ms = Some(positive("timeout_ms", number("timeout_ms")?)?)
+                    }
+                    "THREADS" => config.threads = Some(threads(number("threads")?)?),
+                    "FORMAT" => config.format = Some(check_format(&text)?),
+                    "PROFILE" => config.profile = Some(text.clone()),
+                    "WEBHOOKS" => config.webhooks = webhooks(&list())?,
+                    "ALLOW_TARGETS" => config.allow = Some(allowed(&list())?),
+                    _ => {}
+                }
+                Ok::<_, String>(())
+            })();
+            set.map_err(|e| format!("{var}: {e}"))?;
+        }
+        Ok(config)
+    }
+
+    /// this config with whatever `over` sets taking its place
+    pub fn merge(self, over: Config) -> Config {
+        Config {
+            timeout_ms: over.timeout_ms.or(self.timeout_ms),
+            threads: over.threads.or(self.threads),
+            format: over.format.or(self.format),
+            profile: over.profile.or(self.profile),
+            webhooks: if over.webhooks.is_empty() {
+                self.webhooks
+            } else {
+                over.webhooks
+            },
+            allow: over.allow.or(self.allow),
+            profiles: {
+                let mut profiles = self.profiles;
+                profiles.retain(|(name, _)| over.profiles.iter().all(|(n, _)| n != name));
+                profiles.extend(over.profiles);
+                profiles
+            },
+        }
+    }
+
+    /// the profile called `name`: the config's own, else a built-in one
+    pub fn profile(&self, name: &str) -> Result<Profile, String> {
+        self.profiles
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, profile)| profile.clone())
+            .or_else(|| Profile::builtin(name))
+            .ok_or_else(|| {
+                let known: Vec<&str> = BUILTIN_PROFILES
+                    .into_iter()
+                    .chain(
+                        self.profiles
+                            .iter()
+                            .map(|(n, _)| n.as_str())
+                            .filter(|n| !BUILTIN_PROFILES.contains(n)),
+                    )
+                    .collect();
+                format!("unknown profile '{name}' (expected {})", known.join(", "))
+            })
+    }
+
+    /// the files read when no other is named, in order: the system-wide
+    /// `/etc/pscan/config.toml`, then the user's own,
+    /// `$XDG_CONFIG_HOME/pscan/config.toml` or `~/.config/pscan/config.toml`
+    pub fn default_paths() -> Vec<PathBuf> {
+        let mut paths = vec![PathBuf::from("/etc/pscan/config.toml")];
+        let user = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+        if let Some(dir) = user {
+            paths.push(dir.join("pscan").join("config.toml"));
+        }
+        paths
+    }
+
+    /// whether `host` may be scanned: always, unless `[targets] allow` is
+    /// set and names neither the host, one of its names nor a network
+    /// holding it
+    pub fn authorizes(&self, host: &ScanHost) -> bool {
+        self.check_allowed(std::slice::from_ref(host)).is_ok()
+    }
+
+    /// refuse the first of `hosts` the config doesn't authorize
+    pub fn check_allowed(&self, hosts: &[ScanHost]) -> Result<(), ScanError> {
+        check_allowed(hosts, self.allow.as_deref())
+    }
+}
+
+/// the PSCAN_ names `from_vars` reads, after the prefix
+const KNOWN_VARS: [&str; 7] = [
+    "TIMEOUT_MS",
+    "THREADS",
+    "FORMAT",
+    "PROFILE",
+    "WEBHOOKS",
+    "ALLOW_TARGETS",
+    "CONFIG",
+];
+
+/// `T` from a toml document, or what's wrong with it and on which line
+pub(crate) fn parse_toml<T: DeserializeOwned>(text: &str) -> Result<T, String> {
+    toml::from_str(text).map_err(|e| match e.span() {
+        Some(span) => error_at(text, span, e.message()),
+        None => e.message().to_string(),
+    })
+}
+
+/// `what`, as said about the part of `text` at `span`
+pub(crate) fn error_at(text: &str, span: Range<usize>, what: &str) -> String {
+    let line = text.as_bytes()[..span.start.min(text.len())]
+        .iter()
+        .filter(|&&c| c == b'\n')
+        .count();
+    format!("line {}: {what}", line + 1)
+}
+
+/// `["a", "b"]`, or a single `"a"`
+pub(crate) struct Strings(pub Vec<String>);
+
+impl<'de> Deserialize<'de> for Strings {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        struct Visitor;
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = Strings;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a string or a list of strings")
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Strings, E> {
+                Ok(Strings(vec![s.to_string()]))
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Strings, A::Error> {
+                let mut items = Vec::new();
+                while let Some(item) = seq.next_element()? {
+                    items.push(item);
+                }
+                Ok(Strings(items))
+            }
+        }
+        d.deserialize_any(Visitor)
+    }
+}
+
+fn positive(key: &str, n: i64) -> Result<u64, String> {
+    u64::try_from(n)
+        .ok()
+        .filter(|&n| n >= 1)
+        .ok_or_else(|| format!("{key} must be at least 1, not {n}"))
+}
+
+fn threads(n: i64) -> Result<usize, String> {
+    let n = positive("threads", n)?;
+    usize::try_from(n).map_err(|_| format!("threads {n} is too big"))
+}
+
+/// `format`, when it names a report format
+fn check_format(format: &str) -> Result<String, String> {
+    report::create(format, Box::new(io::sink()), &ReportOptions::default())
+        .map_err(|e| format!("format: {e}"))?;
+    Ok(format.to_string())
+}
+
+fn webhooks(urls: &[String]) -> Result<Vec<Webhook>, String> {
+    urls.iter()
+        .enumerate()
+        .map(|(i, url)| url.parse().map_err(|e| format!("webhooks[{i}]: {e}")))
+        .collect()
+}
+
+fn allowed(targets: &[String]) -> Result<Vec<Target>, String> {
+    targets
+        .iter()
+        .enumerate()
+        .map(|(i, target)| {
+            target
+                .parse()
+                .map_err(|e| format!("targets.allow[{i}]: {e}"))
+        })
+        .collect()
+}
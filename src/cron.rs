@@ -0,0 +1,189 @@
+//! cron expressions, for the schedule subcommand
+
+use crate::report::util::civil_from_days;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// when a schedule fires: the five fields of a crontab line (minute, hour,
+/// day of month, month, day of week) in local time, or one of @hourly,
+/// @daily, @weekly, @monthly and @yearly
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cron {
+    text: String,
+    /// one bit per allowed value
+    minutes: u64,
+    hours: u32,
+    days: u32,
+    months: u16,
+    weekdays: u8,
+    /// `*` for the day of month or week: when only one of them is
+    /// restricted, only that one counts; when both are, either will do
+    any_day: bool,
+    any_weekday: bool,
+}
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+impl FromStr for Cron {
+    type Err = String;
+
+    /// `0 2 * * *`, `*/15 9-17 * * mon-fri`, `@daily`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.trim();
+        let expanded = match text.to_ascii_lowercase().as_str() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            _ => text,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!(
+                "invalid schedule '{text}' (expected five fields: minute hour day month weekday)"
+            ));
+        };
+        let field = |value, name, min, max, names: &[&str]| {
+            parse_field(value, min, max, names)
+                .map_err(|e| format!("invalid {name} '{value}' in '{text}': {e}"))
+        };
+        // sunday is 0 or 7
+        let mut weekdays = field(weekday, "weekday", 0, 7, &WEEKDAYS)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(Cron {
+            text: text.to_string(),
+            minutes: field(minute, "minute", 0, 59, &[])?,
+            hours: field(hour, "hour", 0, 23, &[])? as u32,
+            days: field(day, "day", 1, 31, &[])? as u32,
+            months: field(month, "month", 1, 12, &MONTHS)? as u16,
+            weekdays: (weekdays & 0x7f) as u8,
+            any_day: day.starts_with('*'),
+            any_weekday: weekday.starts_with('*'),
+        })
+    }
+}
+
+impl fmt::Display for Cron {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// one field: `*`, `5`, `1-5`, `*/15`, `1-30/2`, names where the field has
+/// them, or a comma separated list of those. one bit per value.
+fn parse_field(s: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    let value = |v: &str| -> Result<u32, String> {
+        let lower = v.to_ascii_lowercase();
+        let n = match names.iter().position(|name| *name == lower) {
+            // names count from the field's first value
+            Some(at) => at as u32 + min,
+            None => v.parse().map_err(|_| format!("'{v}' isn't a number"))?,
+        };
+        if n < min || n > max {
+            return Err(format!("{n} is out of range ({min}-{max})"));
+        }
+        Ok(n)
+    };
+    let mut bits = 0u64;
+    for part in s.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("bad step '{step}'")),
+            },
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (value(a)?, value(b)?),
+                // `5/15` runs from 5 to the end
+                None if step > 1 => (value(range)?, max),
+                None => {
+                    let n = value(range)?;
+                    (n, n)
+                }
+            },
+        };
+        if start > end {
+            return Err(format!("backwards range '{range}'"));
+        }
+        for n in (start..=end).step_by(step as usize) {
+            bits |= 1 << n;
+        }
+    }
+    Ok(bits)
+}
+
+impl Cron {
+    /// whether the schedule fires on this (local) date
+    fn day_matches(&self, month: u32, day: u32, weekday: u32) -> bool {
+        if self.months & (1 << month) == 0 {
+            return false;
+        }
+        let by_day = self.days & (1 << day) != 0;
+        let by_weekday = self.weekdays & (1 << weekday) != 0;
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (false, true) => by_day,
+            (true, false) => by_weekday,
+            (false, false) => by_day || by_weekday,
+        }
+    }
+
+    /// the first time after `t` the schedule fires, at the start of a
+    /// minute; None when it never does (e.g. `0 0 30 2 *`)
+    pub fn next_after(&self, t: SystemTime) -> Option<SystemTime> {
+        let now = match t.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() as i64,
+            Err(_) => 0,
+        };
+        let mut at = now - now.rem_euclid(60) + 60;
+        // whole days are skipped at a time, so this covers years
+        for _ in 0..100_000 {
+            let local = at + utc_offset(at);
+            let days = local.div_euclid(86_400);
+            let rem = local.rem_euclid(86_400);
+            let (_, month, day) = civil_from_days(days);
+            // 1970-01-01 was a thursday
+            let weekday = (days + 4).rem_euclid(7) as u32;
+            let (hour, minute) = (rem / 3600, rem % 3600 / 60);
+            if !self.day_matches(month, day, weekday) {
+                at += 86_400 - rem;
+            } else if self.hours & (1 << hour) == 0 {
+                at += 3600 - rem % 3600;
+            } else if self.minutes & (1 << minute) == 0 {
+                at += 60;
+            } else {
+                return Some(UNIX_EPOCH + Duration::from_secs(at as u64));
+            }
+        }
+        None
+    }
+}
+
+/// seconds local time is ahead of utc at `t`
+#[cfg(unix)]
+fn utc_offset(t: i64) -> i64 {
+    let time = t as libc::time_t;
+    // SAFETY: tm is plain data, filled in by localtime_r
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    // SAFETY: both pointers are valid for the call
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return 0;
+    }
+    tm.tm_gmtoff as i64
+}
+
+/// no time zone database to ask: schedules run in utc
+#[cfg(not(unix))]
+fn utc_offset(_t: i64) -> i64 {
+    0
+}
@@ -0,0 +1,119 @@
+//! what changed between two saved scans
+
+use crate::{PortState, SavedHost, SavedScan, ScanResult};
+use std::fmt;
+use std::net::IpAddr;
+
+/// how a port differs between the old scan and the new one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// open now, but wasn't before
+    Opened,
+    /// open before, but isn't now
+    Closed,
+    /// open in both, running something else
+    ServiceChanged,
+}
+
+impl Change {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Change::Opened => "opened",
+            Change::Closed => "closed",
+            Change::ServiceChanged => "changed",
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// one port that differs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortChange {
+    pub ip: IpAddr,
+    pub port: u16,
+    pub change: Change,
+    /// the port's state in each scan; None when that scan's report doesn't
+    /// list it (it only keeps open ports) or didn't cover the host at all
+    pub old_state: Option<PortState>,
+    pub new_state: Option<PortState>,
+    /// service name and detected version, e.g. "ssh OpenSSH 9.6p1"
+    pub old_service: Option<String>,
+    pub new_service: Option<String>,
+}
+
+/// every port that opened, closed or changed service from `old` to `new`,
+/// by ip and then port. a port only one of the scans covered hasn't
+/// changed, and one the new scan never got an answer from isn't closed.
+pub fn diff_scans(old: &SavedScan, new: &SavedScan) -> Vec<PortChange> {
+    let mut ips: Vec<IpAddr> = old
+        .hosts
+        .iter()
+        .chain(&new.hosts)
+        .map(|h| h.host.ip)
+        .collect();
+    ips.sort();
+    ips.dedup();
+
+    let mut changes = Vec::new();
+    for ip in ips {
+        let (before, after) = (old.host(ip), new.host(ip));
+        let mut ports: Vec<u16> = [before, after]
+            .into_iter()
+            .flatten()
+            .flat_map(|h| h.results.iter().map(|r| r.port))
+            .collect();
+        ports.sort_unstable();
+        ports.dedup();
+        for port in ports {
+            let was = before.and_then(|h| find(h, port));
+            let now = after.and_then(|h| find(h, port));
+            let was_open = was.is_some_and(ScanResult::is_open);
+            let is_open = now.is_some_and(ScanResult::is_open);
+            let change = match (was_open, is_open) {
+                (false, true) if !old.covers(port) => continue,
+                (false, true) => Change::Opened,
+                (true, true) if describe(was) != describe(now) => Change::ServiceChanged,
+                (true, false) if unanswered(after, now) || !new.covers(port) => continue,
+                (true, false) => Change::Closed,
+                _ => continue,
+            };
+            changes.push(PortChange {
+                ip,
+                port,
+                change,
+                old_state: was.map(|r| r.state),
+                new_state: now.map(|r| r.state),
+                old_service: describe(was),
+                new_service: describe(now),
+            });
+        }
+    }
+    changes
+}
+
+fn find(host: &SavedHost, port: u16) -> Option<&ScanResult> {
+    host.results.iter().find(|r| r.port == port)
+}
+
+/// whether the new scan just didn't get to a port, rather than finding it
+/// shut
+fn unanswered(host: Option<&SavedHost>, result: Option<&ScanResult>) -> bool {
+    match result {
+        Some(r) => r.state == PortState::Unknown,
+        None => host.is_some_and(|h| h.incomplete),
+    }
+}
+
+fn describe(result: Option<&ScanResult>) -> Option<String> {
+    let r = result?;
+    let name = r.service_name()?;
+    match r.service.as_ref().map(|m| m.summary()) {
+        Some(version) if !version.is_empty() => Some(format!("{name} {version}")),
+        _ => Some(name.to_string()),
+    }
+}
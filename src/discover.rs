@@ -0,0 +1,175 @@
+//! finding out which hosts are up before scanning their ports
+
+use crate::arp::arp_sweep;
+use crate::raw::echo_sweep;
+use crate::{Engine, MacAddr, PortState, ScanError, Scanner, Transport};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// ports tried by tcp pings: most hosts that are up answer on one of them,
+/// either accepting or resetting the connection
+pub const DEFAULT_PING_PORTS: &[u16] = &[80, 443, 22, 3389];
+
+/// settings for `discover`
+#[derive(Debug, Clone)]
+pub struct DiscoverOptions {
+    /// tcp ports to ping; an accept or a reset means the host is up
+    pub ports: Vec<u16>,
+    /// how long to wait for an answer
+    pub timeout: Duration,
+    /// send icmp echo requests first, when raw sockets are available
+    pub icmp: bool,
+    /// before anything else, arp for hosts on directly connected ethernet
+    /// subnets (linux, root or CAP_NET_RAW)
+    pub arp: bool,
+    /// hosts pinged over tcp at once
+    pub concurrency: usize,
+    /// the local address tcp pings leave from (default: the routing's choice)
+    pub source: Option<IpAddr>,
+    /// the network interface tcp pings leave through
+    pub interface: Option<String>,
+    pub cancelled: Arc<AtomicBool>,
+}
+
+impl Default for DiscoverOptions {
+    fn default() -> Self {
+        DiscoverOptions {
+            ports: DEFAULT_PING_PORTS.to_vec(),
+            timeout: Duration::from_millis(500),
+            icmp: true,
+            arp: false,
+            concurrency: 64,
+            source: None,
+            interface: None,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+/// what showed a host to be up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Liveness {
+    /// it answered an arp request
+    ArpReply,
+    /// it answered an icmp echo request
+    EchoReply,
+    /// it accepted a connection on this port
+    Accepted(u16),
+    /// it reset a connection to this port
+    Refused(u16),
+}
+
+impl fmt::Display for Liveness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Liveness::ArpReply => f.pad("arp-response"),
+            Liveness::EchoReply => f.pad("echo-reply"),
+            Liveness::Accepted(port) => f.pad(&format!("syn-ack/{port}")),
+            Liveness::Refused(port) => f.pad(&format!("conn-refused/{port}")),
+        }
+    }
+}
+
+/// a host that answered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiveHost {
+    pub ip: IpAddr,
+    pub reason: Liveness,
+    pub latency: Duration,
+    /// its hardware address, when it answered an arp request
+    pub mac: Option<MacAddr>,
+}
+
+impl LiveHost {
+    fn new(ip: IpAddr, reason: Liveness, latency: Duration) -> Self {
+        LiveHost {
+            ip,
+            reason,
+            latency,
+            mac: None,
+        }
+    }
+}
+
+/// the hosts that are up, in the order given. each step only asks the
+/// hosts that haven't answered yet: arp for local hosts when `arp` is set,
+/// then an icmp echo request when `icmp` is set, then tcp connects to
+/// `ports`. the arp and icmp steps are skipped without raw sockets.
+/// fails when the threads for the tcp step can't be started.
+pub fn discover(hosts: &[IpAddr], opts: &DiscoverOptions) -> Result<Vec<LiveHost>, ScanError> {
+    let mut found: HashMap<IpAddr, LiveHost> = HashMap::new();
+    let quiet = |found: &HashMap<IpAddr, LiveHost>| -> Vec<IpAddr> {
+        hosts
+            .iter()
+            .copied()
+            .filter(|ip| !found.contains_key(ip))
+            .collect()
+    };
+
+    if opts.arp {
+        if let Ok(replies) = arp_sweep(hosts, opts.timeout, &opts.cancelled) {
+            for r in replies {
+                let mut host = LiveHost::new(r.ip, Liveness::ArpReply, r.latency);
+                host.mac = Some(r.mac);
+                found.insert(r.ip, host);
+            }
+        }
+    }
+
+    if opts.icmp {
+        if let Ok(replies) = echo_sweep(&quiet(&found), opts.timeout, &opts.cancelled) {
+            for (ip, latency) in replies {
+                found.insert(ip, LiveHost::new(ip, Liveness::EchoReply, latency));
+            }
+        }
+    }
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(opts.concurrency.max(1))
+        .build()
+        .map_err(|e| ScanError::PoolBuild(e.to_string()))?;
+    let pinged: Vec<LiveHost> = pool.install(|| {
+        quiet(&found)
+            .par_iter()
+            .filter_map(|&ip| tcp_ping(ip, opts))
+            .collect()
+    });
+    found.extend(pinged.into_iter().map(|h| (h.ip, h)));
+
+    Ok(hosts.iter().filter_map(|ip| found.remove(ip)).collect())
+}
+
+/// connect to every ping port at once; the quickest answer wins
+fn tcp_ping(ip: IpAddr, opts: &DiscoverOptions) -> Option<LiveHost> {
+    let mut transport = Transport::direct();
+    if let Some(source) = opts.source {
+        transport = transport.source_ip(source);
+    }
+    if let Some(name) = &opts.interface {
+        transport = transport.interface(name.clone());
+    }
+    let scanner = Scanner::builder()
+        .transport(transport)
+        .engine(Engine::Async)
+        .concurrency(opts.ports.len().max(1))
+        .timeout(opts.timeout)
+        .cancel_flag(opts.cancelled.clone())
+        .build();
+    scanner
+        .scan(ip, opts.ports.iter().copied())
+        .into_iter()
+        .filter_map(|r| {
+            let reason = match r.state {
+                PortState::Open => Liveness::Accepted(r.port),
+                PortState::Closed => Liveness::Refused(r.port),
+                _ => return None,
+            };
+            Some(LiveHost::new(ip, reason, r.latency.unwrap_or_default()))
+        })
+        .min_by_key(|h| h.latency)
+}
@@ -0,0 +1,642 @@
+//! resolving target names, through the system resolver or straight
+//! against a given dns server, in the clear or over tls or https
+
+use crate::rng::random_u32;
+use crate::tls;
+use crate::ScanError;
+use rayon::prelude::*;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+
+/// how long to wait for each answer, and how often to ask over udp
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+const QUERY_TRIES: usize = 2;
+
+/// names looked up at once by `resolve_all`
+const BULK_CONCURRENCY: usize = 32;
+
+/// which addresses a name may resolve to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum IpFamily {
+    /// both, ipv4 addresses first
+    #[default]
+    Any,
+    V4,
+    V6,
+}
+
+impl IpFamily {
+    pub fn contains(self, ip: IpAddr) -> bool {
+        match self {
+            IpFamily::Any => true,
+            IpFamily::V4 => ip.is_ipv4(),
+            IpFamily::V6 => ip.is_ipv6(),
+        }
+    }
+}
+
+impl fmt::Display for IpFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            IpFamily::Any => "ip",
+            IpFamily::V4 => "ipv4",
+            IpFamily::V6 => "ipv6",
+        })
+    }
+}
+
+/// turns target names into ips and ips back into names. answers (and
+/// failures) are remembered for as long as the resolver and its clones live.
+///
+/// a name's addresses always come back in the same order: ipv4 before
+/// ipv6, each ascending. so "the first address" is the lowest ipv4 one
+/// when there is any, unless the resolver is limited to ipv6.
+#[derive(Debug, Clone, Default)]
+pub struct Resolver {
+    upstream: Upstream,
+    family: IpFamily,
+    cache: Arc<Mutex<HashMap<(IpFamily, String), Cached>>>,
+}
+
+/// a remembered answer, or the error it failed with
+type Cached = Result<Vec<IpAddr>, ScanError>;
+
+/// where the questions go
+#[derive(Debug, Clone, Default)]
+enum Upstream {
+    /// getaddrinfo
+    #[default]
+    System,
+    /// plain dns: udp, with tcp for answers too big for a datagram
+    Plain(SocketAddr),
+    /// dns over tls (rfc 7858), checking the certificate against `name`
+    Tls { addr: SocketAddr, name: String },
+    /// dns over https (rfc 8484), posting to `path` on `name`
+    Https {
+        addr: SocketAddr,
+        name: String,
+        path: String,
+    },
+}
+
+impl Resolver {
+    /// the system resolver (getaddrinfo), with its hosts file and search domains
+    pub fn system() -> Self {
+        Resolver::default()
+    }
+
+    /// ask `server` directly. names are looked up as given: no hosts file
+    /// and no search domains.
+    pub fn with_server(server: SocketAddr) -> Self {
+        Resolver {
+            upstream: Upstream::Plain(server),
+            ..Resolver::default()
+        }
+    }
+
+    /// ask the dns-over-tls server `server` (`host` or `host:port`, port
+    /// 853 by default). the server's own name goes through the system
+    /// resolver once; give an ip to avoid even that.
+    pub fn over_tls(server: &str) -> Result<Self, ScanError> {
+        let (name, port) = split_host_port(server, 853)?;
+        let addr = server_addr(&name, port)?;
+        Ok(Resolver {
+            upstream: Upstream::Tls { addr, name },
+            ..Resolver::default()
+        })
+    }
+
+    /// ask the dns-over-https endpoint at `url`
+    /// (`https://host[:port][/path]`, path /dns-query by default). the
+    /// host is looked up like `over_tls` does.
+    pub fn over_https(url: &str) -> Result<Self, ScanError> {
+        let rest = url
+            .strip_prefix("https://")
+            .ok_or_else(|| ScanError::InvalidTarget {
+                target: url.to_string(),
+                message: format!("doh url '{url}' must start with https://"),
+            })?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/dns-query"),
+        };
+        let (name, port) = split_host_port(authority, 443)?;
+        let addr = server_addr(&name, port)?;
+        Ok(Resolver {
+            upstream: Upstream::Https {
+                addr,
+                name,
+                path: path.to_string(),
+            },
+            ..Resolver::default()
+        })
+    }
+
+    /// only hand out addresses of `family`; ips and networks of the other
+    /// family are refused
+    pub fn family(mut self, family: IpFamily) -> Self {
+        self.family = family;
+        self
+    }
+
+    /// the addresses this resolver hands out
+    pub fn ip_family(&self) -> IpFamily {
+        self.family
+    }
+
+    /// whether `ip` is of the family this resolver is limited to
+    pub fn accepts(&self, ip: IpAddr) -> bool {
+        self.family.contains(ip)
+    }
+
+    /// the server asked, when it isn't the system resolver
+    pub fn server(&self) -> Option<SocketAddr> {
+        match &self.upstream {
+            Upstream::System => None,
+            Upstream::Plain(addr) | Upstream::Tls { addr, .. } | Upstream::Https { addr, .. } => {
+                Some(*addr)
+            }
+        }
+    }
+
+    /// every ip `target` (an ip or a domain name) resolves to
+    pub fn resolve(&self, target: &str) -> Result<Vec<IpAddr>, ScanError> {
+        if let Ok(ip) = target.parse::<IpAddr>() {
+            if !self.accepts(ip) {
+                return Err(ScanError::ResolveFailed {
+                    target: target.to_string(),
+                    message: format!("target '{target}' is not an {} address", self.family),
+                });
+            }
+            return Ok(vec![ip]);
+        }
+        let key = (self.family, target.to_ascii_lowercase());
+        if let Some(cached) = self
+            .cache
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&key)
+        {
+            return cached.clone();
+        }
+        let resolved = self.lookup(target);
+        self.cache
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key, resolved.clone());
+        resolved
+    }
+
+    /// `resolve` for many targets at once, a few dozen lookups in flight.
+    /// each distinct name is asked once; the answers, in the order given,
+    /// are cached for later `resolve` calls.
+    pub fn resolve_all(&self, targets: &[&str]) -> Vec<Result<Vec<IpAddr>, ScanError>> {
+        let mut seen = HashSet::new();
+        let distinct: Vec<&str> = targets
+            .iter()
+            .copied()
+            .filter(|t| seen.insert(t.to_ascii_lowercase()))
+            .collect();
+        if distinct.len() > 1 {
+            // without the threads, the names are looked up one by one below
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(BULK_CONCURRENCY.min(distinct.len()))
+                .build();
+            if let Ok(pool) = pool {
+                pool.install(|| {
+                    distinct.par_iter().for_each(|t| {
+                        let _ = self.resolve(t);
+                    })
+                });
+            }
+        }
+        targets.iter().map(|t| self.resolve(t)).collect()
+    }
+
+    /// ask the upstream, bypassing the cache
+    fn lookup(&self, target: &str) -> Result<Vec<IpAddr>, ScanError> {
+        let failed = |message: String| ScanError::ResolveFailed {
+            target: target.to_string(),
+            message,
+        };
+        let ips: BTreeSet<IpAddr> = match &self.upstream {
+            Upstream::System => (target, 0)
+                .to_socket_addrs()
+                .map_err(|e| failed(format!("failed to resolve target '{target}': {e}")))?
+                .map(|addr| addr.ip())
+                .filter(|&ip| self.accepts(ip))
+                .collect(),
+            upstream => {
+                let mut ips = BTreeSet::new();
+                let qtypes = match self.family {
+                    IpFamily::Any => &[TYPE_A, TYPE_AAAA][..],
+                    IpFamily::V4 => &[TYPE_A],
+                    IpFamily::V6 => &[TYPE_AAAA],
+                };
+                for &qtype in qtypes {
+                    let answers = query(upstream, target, qtype)
+                        .map_err(|e| failed(format!("failed to resolve target '{target}': {e}")))?;
+                    ips.extend(answers.into_iter().filter_map(|r| match r {
+                        Record::Ip(ip) => Some(ip),
+                        Record::Name(_) => None,
+                    }));
+                }
+                ips
+            }
+        };
+
+        if ips.is_empty() {
+            return Err(failed(format!(
+                "no {} addresses found for target '{target}'",
+                self.family
+            )));
+        }
+        Ok(ips.into_iter().collect())
+    }
+
+    /// the name `ip`'s ptr record points at, if it has one
+    pub fn reverse(&self, ip: IpAddr) -> Option<String> {
+        if let Upstream::System = self.upstream {
+            return crate::reverse_lookup(ip);
+        }
+        query(&self.upstream, &reverse_name(ip), TYPE_PTR)
+            .ok()?
+            .into_iter()
+            .find_map(|r| match r {
+                Record::Name(name) => Some(name),
+                Record::Ip(_) => None,
+            })
+    }
+}
+
+/// parse a dns server given as an ip, optionally with a port
+/// (`10.0.0.53`, `10.0.0.53:5353`, `[fd00::53]:53`)
+pub fn parse_dns_server(s: &str) -> Result<SocketAddr, String> {
+    if let Ok(ip) = s.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, 53));
+    }
+    s.parse::<SocketAddr>()
+        .map_err(|_| format!("invalid dns server '{s}' (expected an ip, optionally with :port)"))
+}
+
+/// `host`, `host:port`, `v6` or `[v6]:port`
+fn split_host_port(s: &str, default_port: u16) -> Result<(String, u16), ScanError> {
+    if s.parse::<Ipv6Addr>().is_ok() {
+        return Ok((s.to_string(), default_port));
+    }
+    let invalid = |message: String| ScanError::InvalidTarget {
+        target: s.to_string(),
+        message,
+    };
+    let (host, port) = match s.rsplit_once(':') {
+        Some((host, port)) => {
+            let port = port
+                .parse()
+                .map_err(|_| invalid(format!("invalid port in dns server '{s}'")))?;
+            (host, port)
+        }
+        None => (s, default_port),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return Err(invalid(format!("dns server '{s}' has no host")));
+    }
+    Ok((host.to_string(), port))
+}
+
+/// where to connect for an encrypted dns server
+fn server_addr(name: &str, port: u16) -> Result<SocketAddr, ScanError> {
+    let failed = |message: String| ScanError::ResolveFailed {
+        target: name.to_string(),
+        message,
+    };
+    (name, port)
+        .to_socket_addrs()
+        .map_err(|e| failed(format!("failed to resolve dns server '{name}': {e}")))?
+        .next()
+        .ok_or_else(|| failed(format!("no ip addresses found for dns server '{name}'")))
+}
+
+/// the in-addr.arpa / ip6.arpa name of an ip
+fn reverse_name(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, d] = v4.octets();
+            format!("{d}.{c}.{b}.{a}.in-addr.arpa")
+        }
+        IpAddr::V6(v6) => {
+            let mut name = String::with_capacity(72);
+            for byte in v6.octets().iter().rev() {
+                name += &format!("{:x}.{:x}.", byte & 0xf, byte >> 4);
+            }
+            name + "ip6.arpa"
+        }
+    }
+}
+
+/// the answers we care about
+#[derive(Debug)]
+enum Record {
+    Ip(IpAddr),
+    Name(String),
+}
+
+/// ask `upstream` for the `qtype` records of `name`
+fn query(upstream: &Upstream, name: &str, qtype: u16) -> io::Result<Vec<Record>> {
+    // doh wants id 0 so answers can be cached; tls and https need no
+    // spoofing protection from it
+    let id = match upstream {
+        Upstream::Https { .. } => 0,
+        _ => random_u32() as u16,
+    };
+    let request = build_query(id, name, qtype)?;
+    let response = match upstream {
+        Upstream::System => return Err(io::ErrorKind::Unsupported.into()),
+        Upstream::Plain(addr) => exchange_udp(*addr, &request)?,
+        Upstream::Tls { addr, name } => {
+            exchange_stream(&mut tls::connect(*addr, name, QUERY_TIMEOUT)?, &request)?
+        }
+        Upstream::Https { addr, name, path } => exchange_https(*addr, name, path, &request)?,
+    };
+    if !answers(&request, &response) {
+        return Err(invalid("answer doesn't match the query"));
+    }
+    parse_response(&response, qtype)
+}
+
+/// whether `response` answers `request`: the same id, and the question
+/// echoed back (names compare without case)
+fn answers(request: &[u8], response: &[u8]) -> bool {
+    let question = &request[12..];
+    response.len() >= request.len()
+        && response[..2] == request[..2]
+        && response[4..6] == [0, 1]
+        && response[12..request.len()].eq_ignore_ascii_case(question)
+}
+
+/// send `request` over udp, switching to tcp when the answer didn't fit
+fn exchange_udp(server: SocketAddr, request: &[u8]) -> io::Result<Vec<u8>> {
+    let bind: SocketAddr = match server {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(bind)?;
+    socket.connect(server)?;
+    socket.set_read_timeout(Some(QUERY_TIMEOUT))?;
+
+    let mut buf = [0u8; 1232];
+    for _ in 0..QUERY_TRIES {
+        socket.send(request)?;
+        loop {
+            let n = match socket.recv(&mut buf) {
+                Ok(n) => n,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    break
+                }
+                Err(e) => return Err(e),
+            };
+            // a late answer to an earlier try, or someone else's
+            if !answers(request, &buf[..n]) {
+                continue;
+            }
+            if buf[2] & 0x02 != 0 {
+                let mut stream = TcpStream::connect_timeout(&server, QUERY_TIMEOUT)?;
+                stream.set_read_timeout(Some(QUERY_TIMEOUT))?;
+                return exchange_stream(&mut stream, request);
+            }
+            return Ok(buf[..n].to_vec());
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        format!("no answer from dns server {server}"),
+    ))
+}
+
+/// send `request` over a stream (tcp or tls), each message prefixed with
+/// its length
+fn exchange_stream(stream: &mut impl ReadWrite, request: &[u8]) -> io::Result<Vec<u8>> {
+    let mut framed = (request.len() as u16).to_be_bytes().to_vec();
+    framed.extend_from_slice(request);
+    stream.write_all(&framed)?;
+
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len)?;
+    let mut response = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut response)?;
+    Ok(response)
+}
+
+trait ReadWrite: Read + Write {}
+impl<T: Read + Write> ReadWrite for T {}
+
+/// post `request` to a doh endpoint and return the answer from the body
+fn exchange_https(addr: SocketAddr, name: &str, path: &str, request: &[u8]) -> io::Result<Vec<u8>> {
+    let mut stream = tls::connect(addr, name, QUERY_TIMEOUT)?;
+    let host = match (name.parse::<Ipv6Addr>(), addr.port()) {
+        (Ok(_), 443) => format!("[{name}]"),
+        (Ok(_), port) => format!("[{name}]:{port}"),
+        (Err(_), 443) => name.to_string(),
+        (Err(_), port) => format!("{name}:{port}"),
+    };
+    let head = format!(
+        "POST {path} HTTP/1.1\r\nHost: {host}\r\nContent-Type: application/dns-message\r\n\
+         Accept: application/dns-message\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        request.len()
+    );
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(request)?;
+    let mut raw = Vec::new();
+    stream.read_to_end(&mut raw)?;
+    http_body(&raw)
+}
+
+/// the body of a 200 response, de-chunked
+fn http_body(raw: &[u8]) -> io::Result<Vec<u8>> {
+    let end = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| invalid("truncated http response"))?;
+    let head = String::from_utf8_lossy(&raw[..end]);
+    let body = &raw[end + 4..];
+    let mut lines = head.lines();
+    let status = lines.next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(io::Error::other(format!("doh server answered '{status}'")));
+    }
+
+    let header = |wanted: &str| {
+        head.lines().skip(1).find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case(wanted)
+                .then(|| value.trim().to_string())
+        })
+    };
+    if header("transfer-encoding").is_some_and(|v| v.eq_ignore_ascii_case("chunked")) {
+        return dechunk(body);
+    }
+    match header("content-length").and_then(|v| v.parse::<usize>().ok()) {
+        Some(len) => body
+            .get(..len)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| invalid("truncated http body")),
+        None => Ok(body.to_vec()),
+    }
+}
+
+/// join the chunks of a chunked http body
+fn dechunk(mut body: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    loop {
+        let line_end = body
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or_else(|| invalid("truncated http chunk"))?;
+        let size = String::from_utf8_lossy(&body[..line_end]);
+        let size = size.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| invalid("bad http chunk size"))?;
+        if size == 0 {
+            return Ok(out);
+        }
+        let start = line_end + 2;
+        let chunk = body
+            .get(start..start + size)
+            .ok_or_else(|| invalid("truncated http chunk"))?;
+        out.extend_from_slice(chunk);
+        body = body.get(start + size + 2..).unwrap_or_default();
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// a recursive query for one name
+fn build_query(id: u16, name: &str, qtype: u16) -> io::Result<Vec<u8>> {
+    let mut q = Vec::with_capacity(18 + name.len());
+    q.extend_from_slice(&id.to_be_bytes());
+    q.extend_from_slice(&[0x01, 0x00]); // recursion desired
+    q.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]); // one question
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("'{name}' is not a valid domain name"),
+            ));
+        }
+        q.push(label.len() as u8);
+        q.extend_from_slice(label.as_bytes());
+    }
+    q.push(0);
+    q.extend_from_slice(&qtype.to_be_bytes());
+    q.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(q)
+}
+
+/// the records of type `qtype` in the answer section
+fn parse_response(msg: &[u8], qtype: u16) -> io::Result<Vec<Record>> {
+    match msg[3] & 0x0f {
+        0 => {}
+        3 => return Err(io::Error::new(io::ErrorKind::NotFound, "no such domain")),
+        rcode => {
+            return Err(io::Error::other(format!(
+                "dns server answered with error code {rcode}"
+            )))
+        }
+    }
+    let count = |at: usize| u16::from_be_bytes([msg[at], msg[at + 1]]) as usize;
+    let (questions, answers) = (count(4), count(6));
+
+    let mut at = 12;
+    for _ in 0..questions {
+        at = skip_name(msg, at)? + 4;
+    }
+    let mut records = Vec::new();
+    for _ in 0..answers {
+        at = skip_name(msg, at)?;
+        let fixed = msg
+            .get(at..at + 10)
+            .ok_or_else(|| invalid("truncated answer"))?;
+        let rtype = u16::from_be_bytes([fixed[0], fixed[1]]);
+        let len = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+        let start = at + 10;
+        let data = msg
+            .get(start..start + len)
+            .ok_or_else(|| invalid("truncated answer"))?;
+        at = start + len;
+
+        if rtype != qtype {
+            // cnames on the way to the answer
+            continue;
+        }
+        match rtype {
+            TYPE_A if len == 4 => {
+                let octets: [u8; 4] = data.try_into().expect("length checked");
+                records.push(Record::Ip(IpAddr::from(octets)));
+            }
+            TYPE_AAAA if len == 16 => {
+                let octets: [u8; 16] = data.try_into().expect("length checked");
+                records.push(Record::Ip(IpAddr::from(octets)));
+            }
+            TYPE_PTR => records.push(Record::Name(read_name(msg, start)?)),
+            _ => {}
+        }
+    }
+    Ok(records)
+}
+
+/// the offset just past the (possibly compressed) name at `at`
+fn skip_name(msg: &[u8], mut at: usize) -> io::Result<usize> {
+    loop {
+        let len = *msg.get(at).ok_or_else(|| invalid("truncated name"))?;
+        match len {
+            0 => return Ok(at + 1),
+            l if l & 0xc0 == 0xc0 => return Ok(at + 2),
+            l => at += 1 + l as usize,
+        }
+    }
+}
+
+/// the name at `at`, following compression pointers
+fn read_name(msg: &[u8], mut at: usize) -> io::Result<String> {
+    let mut labels: Vec<String> = Vec::new();
+    // every pointer has to go backwards, so this can't loop forever
+    let mut limit = at;
+    loop {
+        let len = *msg.get(at).ok_or_else(|| invalid("truncated name"))? as usize;
+        if len == 0 {
+            break;
+        }
+        if len & 0xc0 == 0xc0 {
+            let low = *msg.get(at + 1).ok_or_else(|| invalid("truncated name"))? as usize;
+            let target = (len & 0x3f) << 8 | low;
+            if target >= limit {
+                return Err(invalid("bad compression pointer"));
+            }
+            limit = target;
+            at = target;
+            continue;
+        }
+        let label = msg
+            .get(at + 1..at + 1 + len)
+            .ok_or_else(|| invalid("truncated name"))?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        at += 1 + len;
+    }
+    Ok(labels.join("."))
+}
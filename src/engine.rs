@@ -0,0 +1,845 @@
+use crate::http::http_probe_via;
+use crate::tls::tls_probe_via;
+use crate::udp::{self, probe_udp};
+use crate::{
+    banner_text, connect_port, read_banner, trace_attempt, PortState, Protocol, ScanResult, Scanner,
+};
+use rayon::prelude::*;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tracing::warn;
+
+/// default number of in-flight connects for the async engine
+pub const DEFAULT_CONCURRENCY: usize = 512;
+
+/// how many probes may hold a socket at once before the process runs out
+/// of file descriptors (the RLIMIT_NOFILE soft limit, less some headroom),
+/// if there is such a limit. past it connects fail with EMFILE. windows
+/// has no per-process descriptor cap for sockets, so None there.
+#[cfg(unix)]
+pub fn socket_limit() -> Option<usize> {
+    // kept back from the probes' sockets, for stdio, report files, dns
+    // lookups and the like
+    const HEADROOM: libc::rlim_t = 64;
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: limit is a valid rlimit to fill in
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0
+        || limit.rlim_cur == libc::RLIM_INFINITY
+    {
+        return None;
+    }
+    let usable = limit.rlim_cur.saturating_sub(HEADROOM).max(1);
+    Some(usize::try_from(usable).unwrap_or(usize::MAX))
+}
+
+#[cfg(not(unix))]
+pub fn socket_limit() -> Option<usize> {
+    None
+}
+
+/// which scanning engine drives the probes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Engine {
+    /// blocking connects, one probe per worker thread
+    #[default]
+    Threads,
+    /// non-blocking connects multiplexed over a few threads, on tokio with
+    /// the `tokio` feature
+    Async,
+}
+
+impl FromStr for Engine {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "threads" | "thread" => Ok(Engine::Threads),
+            "async" => Ok(Engine::Async),
+            _ => Err(format!("unknown engine '{s}' (expected threads or async)")),
+        }
+    }
+}
+
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Engine::Threads => "threads",
+            Engine::Async => "async",
+        })
+    }
+}
+
+/// callback invoked for every finished port, from whichever thread finished it
+pub(crate) type OnResult<'a> = &'a (dyn Fn(&ScanResult) + Sync);
+
+/// bump progress, notify the callback and keep the result
+pub(crate) fn record(
+    scanner: &Scanner,
+    on_result: OnResult,
+    results: &mut Vec<ScanResult>,
+    mut r: ScanResult,
+) {
+    finished(scanner, on_result, &mut r);
+    results.push(r);
+}
+
+fn finished(scanner: &Scanner, on_result: OnResult, r: &mut ScanResult) {
+    // ports the scan never got to are made without a protocol in mind
+    r.protocol = scanner.protocol;
+    if r.is_open() {
+        scanner.found_open();
+    }
+    if let Some(p) = &scanner.progress {
+        p.fetch_add(1, Ordering::Relaxed);
+    }
+    if let Some(p) = &scanner.tracker {
+        p.port_done(scanner.tracked.as_deref(), r.is_open());
+    }
+    on_result(r);
+}
+
+/// one blocking probe, plus a banner read when the port is open
+fn probe(scanner: &Scanner, ip: IpAddr, port: u16) -> ScanResult {
+    if scanner.protocol == Protocol::Udp {
+        let (mut result, reply) = probe_udp(
+            scanner.addr(ip, port),
+            &scanner.transport,
+            &scanner.pacer,
+            scanner.probe_timeout(),
+            &scanner.retry,
+            &scanner.cancelled,
+        );
+        match result.latency {
+            Some(rtt) => scanner.answered(rtt),
+            None => scanner.unanswered(result.state),
+        }
+        // whatever the service answered is its banner
+        if let (Some(opts), Some(reply)) = (&scanner.banner, reply) {
+            result.banner = banner_text(&reply[..reply.len().min(opts.max_bytes)]);
+        }
+        return result;
+    }
+    let (mut result, stream) = connect_port(
+        &scanner.transport,
+        &scanner.pacer,
+        scanner.addr(ip, port),
+        scanner.probe_timeout(),
+        &scanner.retry,
+        &scanner.cancelled,
+    );
+    match result.latency {
+        Some(rtt) => scanner.answered(rtt),
+        None => scanner.unanswered(result.state),
+    }
+    if let (Some(opts), Some(mut stream)) = (&scanner.banner, stream) {
+        result.banner = read_banner(&mut stream, opts);
+    }
+    enrich(scanner, ip, &mut result);
+    result
+}
+
+/// follow-up probes for an open port, each on a fresh connection
+pub(crate) fn enrich(scanner: &Scanner, ip: IpAddr, result: &mut ScanResult) {
+    if !result.is_open()
+        || scanner.protocol != Protocol::Tcp
+        || scanner.cancelled.load(Ordering::Relaxed)
+    {
+        return;
+    }
+    let addr = scanner.addr(ip, result.port);
+    let name = scanner.server_name.as_deref();
+    // raw scans never hold a connection to read the banner from
+    if let (Some(opts), true) = (&scanner.banner, scanner.scan_type.is_raw()) {
+        if let Ok(mut stream) = scanner.transport.connect(addr, scanner.timeout) {
+            result.banner = read_banner(&mut stream, opts);
+        }
+    }
+    if let Some(timeout) = scanner.tls {
+        result.tls = tls_probe_via(&scanner.transport, addr, name, timeout).ok();
+    }
+    // plain http only; a port that just completed a tls handshake won't answer it
+    if let (Some(timeout), None) = (scanner.http, &result.tls) {
+        result.http = http_probe_via(&scanner.transport, addr, name, timeout).ok();
+    }
+    if let Some((probes, timeout)) = &scanner.version {
+        result.service = probes.identify_via(&scanner.transport, addr, *timeout);
+    }
+}
+
+/// blocking connects on the scanner's rayon pool, or inline on the
+/// calling thread when `concurrency` is 1 (or the pool can't be started)
+pub(crate) fn scan_threads<I>(
+    scanner: &Scanner,
+    ip: IpAddr,
+    ports: I,
+    on_result: OnResult,
+) -> Vec<ScanResult>
+where
+    I: IntoIterator<Item = u16>,
+    I::IntoIter: Send,
+{
+    let threads = scanner.concurrency;
+    let ports = ports.into_iter();
+    let pool = match threads {
+        1 => None,
+        _ => scanner
+            .thread_pool()
+            .inspect_err(|e| warn!("{e}, probing one port at a time"))
+            .ok(),
+    };
+    let mut results = if let Some(pool) = pool {
+        pool.install(|| {
+            ports
+                .par_bridge()
+                .map(|port| {
+                    // the pool has a thread per probe the congestion window
+                    // could ever allow; it (and the cap shared across
+                    // hosts) decides how many run right now
+                    let mut result = match scanner.slot() {
+                        Some(_slots) if !scanner.stopped() => probe(scanner, ip, port),
+                        _ => ScanResult::new(port, scanner.skipped_state()),
+                    };
+
+                    finished(scanner, on_result, &mut result);
+                    result
+                })
+                .collect::<Vec<_>>()
+        })
+    } else {
+        ports
+            .map(|port| {
+                let mut result = match scanner.slot() {
+                    Some(_slots) if !scanner.stopped() => probe(scanner, ip, port),
+                    _ => ScanResult::new(port, scanner.skipped_state()),
+                };
+                finished(scanner, on_result, &mut result);
+                result
+            })
+            .collect::<Vec<_>>()
+    };
+
+    results.sort_by_key(|r| r.port);
+    results
+}
+
+/// in-flight probes one event loop thread drives; a scan with a bigger
+/// concurrency spreads them over more loops, up to one per cpu
+const PROBES_PER_LOOP: usize = 512;
+
+/// non-blocking connects with up to `concurrency` in flight: tokio tasks
+/// on a few worker threads when built with the `tokio` feature (and not
+/// called from inside another runtime), otherwise driven by mio
+/// (epoll, kqueue or iocp underneath) from a handful of event loop
+/// threads, so thousands of probes can be outstanding without a thread
+/// each. semantics (retries, states, latency, cancellation) match
+/// `scan_threads`. banners are read inside the loops; follow-up probes
+/// (tls, http) block the loop that found the open port while the others
+/// carry on.
+pub(crate) fn scan_async<I>(
+    scanner: &Scanner,
+    ip: IpAddr,
+    ports: I,
+    on_result: OnResult,
+) -> Vec<ScanResult>
+where
+    I: IntoIterator<Item = u16>,
+    I::IntoIter: Send,
+{
+    use std::sync::Mutex;
+
+    let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let loops = scanner
+        .concurrency
+        .div_ceil(PROBES_PER_LOOP)
+        .clamp(1, cpus.max(1));
+    #[cfg(feature = "tokio")]
+    match crate::tokio_engine::runtime(loops) {
+        Ok(Some(runtime)) => {
+            return crate::tokio_engine::scan(&runtime, scanner, ip, ports.into_iter(), on_result)
+        }
+        Ok(None) => {}
+        Err(e) => warn!("can't start the tokio runtime ({e}), using mio's event loops"),
+    }
+
+    let ports = Mutex::new(ports.into_iter());
+
+    let mut results = if loops == 1 {
+        event_loop(scanner, ip, &ports, (0, 1), on_result)
+    } else {
+        std::thread::scope(|s| {
+            let handles: Vec<_> = (0..loops)
+                .map(|k| {
+                    let ports = &ports;
+                    s.spawn(move || event_loop(scanner, ip, ports, (k, loops), on_result))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().expect("event loop panicked"))
+                .collect()
+        })
+    };
+
+    // stopped before every port was taken by a loop
+    let rest = ports.into_inner().unwrap_or_else(|e| e.into_inner());
+    for port in rest {
+        record(
+            scanner,
+            on_result,
+            &mut results,
+            ScanResult::new(port, scanner.skipped_state()),
+        );
+    }
+
+    results.sort_by_key(|r| r.port);
+    results
+}
+
+/// one event loop of `scan_async`: loop `share.0` of `share.1`, keeping
+/// its part of the concurrency in flight with ports taken from `ports`
+fn event_loop<P>(
+    scanner: &Scanner,
+    ip: IpAddr,
+    ports: &std::sync::Mutex<P>,
+    share: (usize, usize),
+    on_result: OnResult,
+) -> Vec<ScanResult>
+where
+    P: Iterator<Item = u16>,
+{
+    use crate::retry::RetryQueue;
+    use crate::timing::Slot;
+    use mio::{Events, Interest, Poll, Token};
+    use std::collections::HashMap;
+    use std::io::{self, ErrorKind};
+    use std::time::Instant;
+
+    // wait in short slices so cancellation is noticed quickly
+    const POLL_SLICE: Duration = Duration::from_millis(100);
+    // how soon to look again when other hosts hold the whole shared cap
+    const CAP_WAIT: Duration = Duration::from_millis(5);
+    // ready sockets taken from the kernel per wait; the rest stay ready
+    // for the next one
+    const BATCH: usize = 1024;
+
+    struct InFlight<'s> {
+        socket: sys::Probe,
+        port: u16,
+        attempt: u8,
+        started: Instant,
+        /// when this connect (or banner read) gives up
+        deadline: Instant,
+        /// set once connected and waiting for a banner
+        reading: Option<Reading>,
+        /// its place under the cap shared across hosts
+        _slot: Slot<'s>,
+    }
+
+    struct Reading {
+        latency: Duration,
+        banner: Vec<u8>,
+    }
+
+    let (k, loops) = share;
+    // this loop's part of the window, which may shrink or grow as it goes
+    let limit = || ((scanner.window() + loops - 1 - k) / loops).max(1);
+    let next_port = || ports.lock().unwrap_or_else(|e| e.into_inner()).next();
+
+    let retry = &scanner.retry;
+    let banner = scanner.banner;
+    let udp = scanner.protocol == Protocol::Udp;
+    let mut retry_queue = RetryQueue::default();
+    let mut in_flight: HashMap<Token, InFlight> = HashMap::with_capacity(limit());
+    let mut next_token = 0;
+    let mut events = Events::with_capacity(BATCH);
+    let mut results = Vec::new();
+    let mut poll = match Poll::new() {
+        Ok(poll) => poll,
+        Err(_) => {
+            // without a poll nothing can be waited on; every port this
+            // loop would have taken is an error
+            while let Some(port) = next_port() {
+                record(
+                    scanner,
+                    on_result,
+                    &mut results,
+                    ScanResult::new(port, PortState::Error),
+                );
+            }
+            return results;
+        }
+    };
+
+    // a finished attempt either becomes a result or goes back for a retry
+    let finish = |results: &mut Vec<ScanResult>,
+                  retry_queue: &mut RetryQueue,
+                  port: u16,
+                  attempt: u8,
+                  state: PortState,
+                  elapsed: Duration| {
+        let latency = match state {
+            PortState::Open | PortState::Closed => Some(elapsed),
+            _ => None,
+        };
+        match latency {
+            Some(rtt) => scanner.answered(rtt),
+            None => scanner.unanswered(state),
+        }
+        if attempt < retry.retries && retry.should_retry(state) {
+            retry_queue.schedule(retry, port, attempt + 1);
+            return;
+        }
+        let mut result = ScanResult {
+            latency,
+            ..ScanResult::new(port, state)
+        };
+        enrich(scanner, ip, &mut result);
+        record(scanner, on_result, results, result);
+    };
+
+    // an open port is done once its banner read is over. dropping the
+    // socket closes it, which also takes it out of the poll
+    let finish_read = |results: &mut Vec<ScanResult>, f: InFlight| {
+        let reading = f.reading.expect("only called for banner reads");
+        let mut result = ScanResult {
+            latency: Some(reading.latency),
+            banner: banner_text(&reading.banner),
+            ..ScanResult::new(f.port, PortState::Open)
+        };
+        enrich(scanner, ip, &mut result);
+        record(scanner, on_result, results, result);
+    };
+
+    // an open port either finishes now or stays in flight for its banner
+    let start_read = |f: &mut InFlight, now: Instant| -> bool {
+        let Some(opts) = &banner else {
+            return false;
+        };
+        let latency = now.duration_since(f.started);
+        scanner.answered(latency);
+        f.deadline = now + opts.timeout;
+        f.reading = Some(Reading {
+            latency,
+            banner: Vec::with_capacity(opts.max_bytes),
+        });
+        true
+    };
+
+    // stopped gently, the probes in flight still get their answer
+    while !scanner.stopped() || (scanner.draining() && !in_flight.is_empty()) {
+        // top up the in-flight set, as fast as the scan delay allows
+        let mut paced = None;
+        while in_flight.len() < limit() && !scanner.stopped() {
+            let next = retry_queue
+                .pop()
+                .or_else(|| next_port().map(|port| (port, 0)));
+            let Some((port, attempt)) = next else {
+                break;
+            };
+            let Some(slot) = scanner.try_shared_slot() else {
+                retry_queue.push_front(port, attempt);
+                paced = Some(CAP_WAIT);
+                break;
+            };
+            if let Err(wait) = scanner.pacer.try_take() {
+                retry_queue.push_front(port, attempt);
+                paced = Some(wait);
+                break;
+            }
+
+            let started = Instant::now();
+            let timeout = retry.timeout(scanner.probe_timeout(), attempt);
+            let addr = scanner.addr(ip, port);
+            let start = scanner.transport.bound(addr, |local| {
+                if udp {
+                    sys::start_udp(addr, local, udp::udp_payload(port)).map(sys::Connect::Pending)
+                } else {
+                    sys::start_connect(addr, local)
+                }
+            });
+            let (socket, connected) = match start {
+                Ok(sys::Connect::Pending(socket)) => (socket, false),
+                Ok(sys::Connect::Done(socket)) => (socket, true),
+                Err(e) if sys::is_fd_exhaustion(&e) && !in_flight.is_empty() => {
+                    // out of descriptors: wait for in-flight probes to drain
+                    retry_queue.push_front(port, attempt);
+                    break;
+                }
+                Err(e) => {
+                    finish(
+                        &mut results,
+                        &mut retry_queue,
+                        port,
+                        attempt,
+                        PortState::from_error_kind(e.kind()),
+                        started.elapsed(),
+                    );
+                    continue;
+                }
+            };
+            let mut f = InFlight {
+                socket,
+                port,
+                attempt,
+                started,
+                deadline: started + timeout,
+                reading: None,
+                _slot: slot,
+            };
+            if connected && !start_read(&mut f, Instant::now()) {
+                drop(f);
+                finish(
+                    &mut results,
+                    &mut retry_queue,
+                    port,
+                    attempt,
+                    PortState::Open,
+                    started.elapsed(),
+                );
+                continue;
+            }
+
+            // a pending connect is over once the socket turns writable;
+            // udp replies and banners make it readable
+            let token = Token(next_token);
+            next_token += 1;
+            let interest = if udp || connected {
+                Interest::READABLE
+            } else {
+                Interest::WRITABLE
+            };
+            match poll.registry().register(&mut f.socket, token, interest) {
+                Ok(()) => {
+                    in_flight.insert(token, f);
+                }
+                // open all the same, just without a banner
+                Err(_) if f.reading.is_some() => finish_read(&mut results, f),
+                Err(e) => {
+                    drop(f);
+                    finish(
+                        &mut results,
+                        &mut retry_queue,
+                        port,
+                        attempt,
+                        PortState::from_error_kind(e.kind()),
+                        started.elapsed(),
+                    );
+                }
+            }
+        }
+
+        // the next probe the scan delay or a retry backoff holds back
+        let held = paced.or(retry_queue.next_due());
+        if in_flight.is_empty() {
+            if let Some(wait) = held {
+                std::thread::sleep(wait.min(POLL_SLICE));
+            } else if retry_queue.is_empty() {
+                break;
+            }
+            continue;
+        }
+
+        let now = Instant::now();
+        let wait = in_flight
+            .values()
+            .map(|f| f.deadline.saturating_duration_since(now))
+            .min()
+            .unwrap_or(POLL_SLICE)
+            .min(held.unwrap_or(POLL_SLICE));
+
+        if let Err(e) = poll.poll(&mut events, Some(wait)) {
+            if e.kind() == ErrorKind::Interrupted {
+                continue;
+            }
+            // the wait itself failed; nothing in flight can be trusted
+            for (_, f) in in_flight.drain() {
+                if f.reading.is_some() {
+                    finish_read(&mut results, f);
+                    continue;
+                }
+                record(
+                    scanner,
+                    on_result,
+                    &mut results,
+                    ScanResult::new(f.port, PortState::Error),
+                );
+            }
+            continue;
+        }
+
+        // the sockets with something to say, then the ones out of time
+        let now = Instant::now();
+        let expired: Vec<Token> = in_flight
+            .iter()
+            .filter(|(_, f)| now >= f.deadline)
+            .map(|(&token, _)| token)
+            .collect();
+        let due = events
+            .iter()
+            .map(|event| (event.token(), true))
+            .chain(expired.into_iter().map(|token| (token, false)));
+        for (token, is_ready) in due {
+            // already finished when it was ready and out of time at once
+            let Some(f) = in_flight.get_mut(&token) else {
+                continue;
+            };
+            if let Some(reading) = &mut f.reading {
+                let max = banner.map_or(0, |b| b.max_bytes);
+                let done =
+                    (is_ready && f.socket.read_some(&mut reading.banner, max)) || now >= f.deadline;
+                if done {
+                    let f = in_flight.remove(&token).expect("looked up above");
+                    finish_read(&mut results, f);
+                }
+                continue;
+            }
+
+            // the connect result, or for udp the reply
+            let outcome: Option<io::Result<Vec<u8>>> = if is_ready {
+                f.socket.outcome(udp::MAX_REPLY)
+            } else if now >= f.deadline {
+                Some(Err(io::Error::from(ErrorKind::TimedOut)))
+            } else {
+                None
+            };
+
+            let Some(outcome) = outcome else {
+                continue;
+            };
+            let state = match &outcome {
+                Ok(_) => PortState::Open,
+                Err(e) if udp => udp::state_for_error(e.kind()),
+                Err(e) => PortState::from_error_kind(e.kind()),
+            };
+            let error = outcome.as_ref().err().map(io::Error::kind);
+            trace_attempt(scanner.addr(ip, f.port), f.attempt, error, state);
+
+            if !udp && outcome.is_ok() && start_read(f, now) {
+                let watched = poll
+                    .registry()
+                    .reregister(&mut f.socket, token, Interest::READABLE);
+                if watched.is_err() {
+                    let f = in_flight.remove(&token).expect("looked up above");
+                    finish_read(&mut results, f);
+                }
+                continue;
+            }
+
+            let mut f = in_flight.remove(&token).expect("looked up above");
+            if let (true, Some(opts), Ok(reply)) = (udp, &banner, &outcome) {
+                // whatever the service answered is its banner
+                f.reading = Some(Reading {
+                    latency: now.duration_since(f.started),
+                    banner: reply[..reply.len().min(opts.max_bytes)].to_vec(),
+                });
+                finish_read(&mut results, f);
+                continue;
+            }
+            drop(f.socket);
+
+            finish(
+                &mut results,
+                &mut retry_queue,
+                f.port,
+                f.attempt,
+                state,
+                now.duration_since(f.started),
+            );
+        }
+    }
+
+    // stopped: open ports keep what they read so far,
+    // whatever never got an answer is reported as filtered (or unknown)
+    let (open, waiting): (Vec<_>, Vec<_>) =
+        in_flight.drain().partition(|(_, f)| f.reading.is_some());
+    for (_, f) in open {
+        finish_read(&mut results, f);
+    }
+    let unanswered = waiting
+        .into_iter()
+        .map(|(_, f)| f.port)
+        .chain(retry_queue.into_ports());
+    for port in unanswered {
+        record(
+            scanner,
+            on_result,
+            &mut results,
+            ScanResult::new(port, scanner.skipped_state()),
+        );
+    }
+
+    results
+}
+
+/// the async engine's probe sockets: made (and bound) with socket2, then
+/// handed to mio to wait on
+pub(crate) mod sys {
+    use crate::transport::{socket_for, Local};
+    use mio::event::Source;
+    use mio::net::{TcpStream, UdpSocket};
+    use mio::{Interest, Registry, Token};
+    use socket2::Type;
+    use std::io::{self, ErrorKind, Read};
+    use std::net::SocketAddr;
+
+    /// one probe's socket
+    pub enum Probe {
+        Tcp(TcpStream),
+        Udp(UdpSocket),
+    }
+
+    pub enum Connect {
+        /// the handshake is in progress, wait for writability
+        Pending(Probe),
+        /// connected immediately (common on loopback)
+        Done(Probe),
+    }
+
+    /// a non-blocking connect from `local` (when given) to `addr`
+    pub fn start_connect(addr: SocketAddr, local: Option<Local>) -> io::Result<Connect> {
+        let socket = socket_for(addr, local, Type::STREAM)?;
+        socket.set_nonblocking(true)?;
+        let done = match socket.connect(&addr.into()) {
+            Ok(()) => true,
+            Err(e) if in_progress(&e) => false,
+            Err(e) => return Err(e),
+        };
+        let probe = Probe::Tcp(TcpStream::from_std(socket.into()));
+        Ok(if done {
+            Connect::Done(probe)
+        } else {
+            Connect::Pending(probe)
+        })
+    }
+
+    /// a non-blocking connect that has started but not finished: EINPROGRESS
+    /// on unix, WSAEWOULDBLOCK on windows
+    fn in_progress(e: &io::Error) -> bool {
+        #[cfg(unix)]
+        if e.raw_os_error() == Some(libc::EINPROGRESS) {
+            return true;
+        }
+        e.kind() == ErrorKind::WouldBlock
+    }
+
+    /// a connected udp socket with `payload` already sent; wait for it to
+    /// turn readable for the reply
+    pub fn start_udp(addr: SocketAddr, local: Option<Local>, payload: &[u8]) -> io::Result<Probe> {
+        let socket = socket_for(addr, local, Type::DGRAM)?;
+        socket.set_nonblocking(true)?;
+        socket.connect(&addr.into())?;
+        socket.send(payload)?;
+        Ok(Probe::Udp(UdpSocket::from_std(socket.into())))
+    }
+
+    impl Probe {
+        /// what a ready socket says: for tcp how the connect went, for udp
+        /// one datagram of at most `max` bytes or the error queued on the
+        /// socket (connection refused after an icmp port unreachable).
+        /// None when there's nothing yet after all.
+        pub fn outcome(&mut self, max: usize) -> Option<io::Result<Vec<u8>>> {
+            match self {
+                Probe::Tcp(stream) => {
+                    match stream.take_error() {
+                        Ok(Some(e)) | Err(e) => return Some(Err(e)),
+                        Ok(None) => {}
+                    }
+                    // no error and no peer: still connecting
+                    match stream.peer_addr() {
+                        Ok(_) => Some(Ok(Vec::new())),
+                        Err(e) if e.kind() == ErrorKind::NotConnected => None,
+                        Err(e) => Some(Err(e)),
+                    }
+                }
+                Probe::Udp(socket) => {
+                    let mut buf = vec![0u8; max];
+                    loop {
+                        match socket.recv(&mut buf) {
+                            Ok(n) => {
+                                buf.truncate(n);
+                                return Some(Ok(buf));
+                            }
+                            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                            Err(e) if e.kind() == ErrorKind::WouldBlock => return None,
+                            Err(e) => return Some(Err(e)),
+                        }
+                    }
+                }
+            }
+        }
+
+        /// drain what is readable into `buf`, up to `max` bytes in total.
+        /// true once nothing more will come (eof, error or buffer full).
+        /// mio only wakes us for new data, so this reads until it would
+        /// block.
+        pub fn read_some(&mut self, buf: &mut Vec<u8>, max: usize) -> bool {
+            let Probe::Tcp(stream) = self else {
+                return true;
+            };
+            while buf.len() < max {
+                let len = buf.len();
+                buf.resize(max, 0);
+                let read = stream.read(&mut buf[len..]);
+                buf.truncate(len + read.as_ref().map_or(0, |&n| n));
+                match read {
+                    Ok(0) => return true,
+                    Ok(_) => continue,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => return e.kind() != ErrorKind::WouldBlock,
+                }
+            }
+            true
+        }
+    }
+
+    impl Source for Probe {
+        fn register(
+            &mut self,
+            registry: &Registry,
+            token: Token,
+            interests: Interest,
+        ) -> io::Result<()> {
+            match self {
+                Probe::Tcp(stream) => stream.register(registry, token, interests),
+                Probe::Udp(socket) => socket.register(registry, token, interests),
+            }
+        }
+
+        fn reregister(
+            &mut self,
+            registry: &Registry,
+            token: Token,
+            interests: Interest,
+        ) -> io::Result<()> {
+            match self {
+                Probe::Tcp(stream) => stream.reregister(registry, token, interests),
+                Probe::Udp(socket) => socket.reregister(registry, token, interests),
+            }
+        }
+
+        fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+            match self {
+                Probe::Tcp(stream) => stream.deregister(registry),
+                Probe::Udp(socket) => socket.deregister(registry),
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    pub fn is_fd_exhaustion(e: &io::Error) -> bool {
+        matches!(e.raw_os_error(), Some(libc::EMFILE) | Some(libc::ENFILE))
+    }
+
+    #[cfg(not(unix))]
+    pub fn is_fd_exhaustion(_: &io::Error) -> bool {
+        false
+    }
+}
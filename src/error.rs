@@ -0,0 +1,48 @@
+//! what can go wrong in the library, as values callers can match on
+
+use std::io;
+use thiserror::Error;
+
+/// why a library call failed. the message is the one shown to users; the
+/// variant and its fields are for code that handles the cause.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ScanError {
+    /// a target (or an encrypted dns server) didn't resolve, or not to an
+    /// address of the family asked for
+    #[error("{message}")]
+    ResolveFailed { target: String, message: String },
+    /// a target that can't be scanned as written: a bad zone, or a
+    /// network too big to expand
+    #[error("{message}")]
+    InvalidTarget { target: String, message: String },
+    /// a port, range or port list that doesn't parse
+    #[error("{0}")]
+    InvalidPortSpec(String),
+    /// a host the config's `[targets] allow` list leaves out
+    #[error(
+        "{target} isn't among the authorized targets ([targets] allow in the config), \
+         refusing to scan it"
+    )]
+    NotAuthorized { target: String },
+    /// the worker threads couldn't be started
+    #[error("failed to build the thread pool: {0}")]
+    PoolBuild(String),
+    /// the os refused something the scan needed
+    #[error("{message}")]
+    Io {
+        kind: io::ErrorKind,
+        message: String,
+    },
+    /// the scan was cancelled before it started
+    #[error("scan cancelled")]
+    Cancelled,
+}
+
+impl From<io::Error> for ScanError {
+    fn from(e: io::Error) -> Self {
+        ScanError::Io {
+            kind: e.kind(),
+            message: e.to_string(),
+        }
+    }
+}
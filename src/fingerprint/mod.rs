@@ -0,0 +1,220 @@
+//! service and version detection: send probes, match the replies against
+//! rules in the nmap-service-probes format, and report what is actually
+//! listening instead of guessing from the port number
+
+mod rules;
+
+pub use rules::{pattern, MatchRule, Probe};
+
+use crate::Transport;
+use std::io::{ErrorKind, Read, Write};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// the rules shipped with pscan
+const BUILTIN: &str = include_str!("service-probes.txt");
+/// stop reading a reply after this much
+const MAX_REPLY: usize = 4096;
+/// longest field value kept after substitution, in characters
+const MAX_FIELD: usize = 80;
+
+/// what a port turned out to be running
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ServiceMatch {
+    pub service: String,
+    pub product: Option<String>,
+    pub version: Option<String>,
+    /// anything else the rule pulled out (protocol version, os, ...)
+    pub info: Option<String>,
+    pub os: Option<String>,
+    pub hostname: Option<String>,
+}
+
+impl ServiceMatch {
+    /// product, version and info in one string, nmap style:
+    /// `OpenSSH 9.6p1 (protocol 2.0)`
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(p) = &self.product {
+            parts.push(p.clone());
+        }
+        if let Some(v) = &self.version {
+            parts.push(v.clone());
+        }
+        if let Some(i) = &self.info {
+            parts.push(format!("({i})"));
+        }
+        parts.join(" ")
+    }
+}
+
+/// a set of probes and their match rules
+#[derive(Debug, Clone)]
+pub struct ServiceProbes {
+    probes: Vec<Probe>,
+}
+
+impl ServiceProbes {
+    /// the built-in rules
+    pub fn builtin() -> ServiceProbes {
+        ServiceProbes::parse(BUILTIN).expect("built-in service probes are valid")
+    }
+
+    /// parse rules in the service-probes format
+    pub fn parse(text: &str) -> Result<ServiceProbes, String> {
+        Ok(ServiceProbes {
+            probes: rules::parse(text)?,
+        })
+    }
+
+    /// add another set of rules. rules for a probe we already have are
+    /// tried before the existing ones, so local files can override.
+    pub fn extend(&mut self, other: ServiceProbes) {
+        for mut probe in other.probes {
+            match self.probes.iter_mut().find(|p| p.name == probe.name) {
+                Some(existing) => {
+                    probe.rules.append(&mut existing.rules);
+                    existing.rules = probe.rules;
+                    if probe.ports.is_some() {
+                        existing.ports = probe.ports;
+                    }
+                }
+                None => self.probes.push(probe),
+            }
+        }
+    }
+
+    pub fn probes(&self) -> &[Probe] {
+        &self.probes
+    }
+
+    /// match a reply to the named probe against that probe's rules
+    pub fn match_response(&self, probe: &str, reply: &[u8]) -> Option<ServiceMatch> {
+        let probe = self.probes.iter().find(|p| p.name == probe)?;
+        match_rules(probe, reply)
+    }
+
+    /// find out what is listening on `addr`. the NULL probe (just listen)
+    /// goes first, then probes meant for this port, then everything else;
+    /// each on a fresh connection, each waiting at most `timeout`.
+    pub fn identify(&self, addr: SocketAddr, timeout: Duration) -> Option<ServiceMatch> {
+        self.identify_via(&Transport::direct(), addr, timeout)
+    }
+
+    /// `identify` over connections from `transport`
+    pub(crate) fn identify_via(
+        &self,
+        transport: &Transport,
+        addr: SocketAddr,
+        timeout: Duration,
+    ) -> Option<ServiceMatch> {
+        let port = addr.port();
+        let for_port = |p: &Probe| p.ports.as_ref().is_some_and(|s| s.contains(port));
+
+        let mut order: Vec<&Probe> = Vec::with_capacity(self.probes.len());
+        order.extend(self.probes.iter().filter(|p| p.payload.is_empty()));
+        order.extend(
+            self.probes
+                .iter()
+                .filter(|p| !p.payload.is_empty() && for_port(p)),
+        );
+        order.extend(
+            self.probes
+                .iter()
+                .filter(|p| !p.payload.is_empty() && !for_port(p)),
+        );
+
+        order
+            .into_iter()
+            .filter(|p| !p.rules.is_empty())
+            .find_map(|probe| run_probe(transport, probe, addr, timeout))
+    }
+}
+
+/// send one probe and read until a rule matches, the peer closes, or time
+/// runs out
+fn run_probe(
+    transport: &Transport,
+    probe: &Probe,
+    addr: SocketAddr,
+    timeout: Duration,
+) -> Option<ServiceMatch> {
+    let deadline = Instant::now() + timeout;
+    let mut stream = transport.connect(addr, timeout).ok()?;
+    if !probe.payload.is_empty() {
+        stream.set_write_timeout(Some(timeout)).ok()?;
+        stream.write_all(&probe.payload).ok()?;
+    }
+
+    let mut reply = Vec::new();
+    let mut buf = [0u8; 1024];
+    while reply.len() < MAX_REPLY {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        stream.set_read_timeout(Some(left)).ok()?;
+        match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                reply.extend_from_slice(&buf[..n]);
+                // most services answer in one go; don't wait out the
+                // timeout when the first chunk already says enough
+                if let Some(m) = match_rules(probe, &reply) {
+                    return Some(m);
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(_) => break,
+        }
+    }
+    if reply.is_empty() {
+        return None;
+    }
+    match_rules(probe, &reply)
+}
+
+fn match_rules(probe: &Probe, reply: &[u8]) -> Option<ServiceMatch> {
+    probe.rules.iter().find_map(|rule| {
+        let captures = rule.pattern.captures(reply)?;
+        let groups: Vec<_> = captures.iter().map(|m| m.map(|m| m.as_bytes())).collect();
+        let fill = |t: &Option<String>| t.as_deref().and_then(|t| substitute(t, &groups));
+        Some(ServiceMatch {
+            service: rule.service.clone(),
+            product: fill(&rule.product),
+            version: fill(&rule.version),
+            info: fill(&rule.info),
+            os: fill(&rule.os),
+            hostname: fill(&rule.hostname),
+        })
+    })
+}
+
+/// replace `$1`..`$9` with printable text from the capture groups; empty
+/// results (and the separators left dangling by empty groups) go away
+fn substitute(template: &str, groups: &[Option<&[u8]>]) -> Option<String> {
+    let mut out = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        let group = match (c, chars.peek().and_then(|d| d.to_digit(10))) {
+            ('$', Some(n)) if n > 0 => n as usize,
+            _ => {
+                out.push(c);
+                continue;
+            }
+        };
+        chars.next();
+        if let Some(Some(text)) = groups.get(group) {
+            out.extend(
+                text.iter()
+                    .map(|&b| b as char)
+                    .filter(|c| c.is_ascii_graphic() || *c == ' '),
+            );
+        }
+    }
+
+    let out = out.trim().trim_end_matches(';').trim_end();
+    let out: String = out.chars().take(MAX_FIELD).collect();
+    (!out.is_empty()).then_some(out)
+}
@@ -0,0 +1,195 @@
+use crate::PortSpec;
+use regex::bytes::{Regex, RegexBuilder};
+
+/// something to send to a port, and the rules for reading the reply
+#[derive(Debug, Clone)]
+pub struct Probe {
+    pub name: String,
+    /// bytes sent after connecting; empty means just listen
+    pub payload: Vec<u8>,
+    /// ports where this probe is tried before the others
+    pub ports: Option<PortSpec>,
+    pub rules: Vec<MatchRule>,
+}
+
+/// one `match` line: a service name, a pattern, and templates for the
+/// product, version and extra info (which may refer to `$1`..`$9`)
+#[derive(Debug, Clone)]
+pub struct MatchRule {
+    pub service: String,
+    pub pattern: Regex,
+    pub product: Option<String>,
+    pub version: Option<String>,
+    pub info: Option<String>,
+    pub os: Option<String>,
+    pub hostname: Option<String>,
+}
+
+/// compile a rule's pattern. it matches bytes, not text, as replies are
+/// often binary; `ignore_case` and `dot_all` are pcre's i and s flags.
+pub fn pattern(source: &str, ignore_case: bool, dot_all: bool) -> Result<Regex, String> {
+    RegexBuilder::new(source)
+        .unicode(false)
+        .octal(true)
+        .case_insensitive(ignore_case)
+        .dot_matches_new_line(dot_all)
+        .build()
+        .map_err(|e| format!("bad pattern '{source}': {e}"))
+}
+
+/// parse rules in the service-probes format (see service-probes.txt)
+pub fn parse(text: &str) -> Result<Vec<Probe>, String> {
+    let mut probes: Vec<Probe> = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let err = |e: String| format!("line {}: {e}", i + 1);
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim();
+
+        match keyword {
+            "Probe" => probes.push(parse_probe(rest).map_err(err)?),
+            "ports" | "match" => {
+                let Some(probe) = probes.last_mut() else {
+                    return Err(err(format!("'{keyword}' before any Probe")));
+                };
+                if keyword == "ports" {
+                    let ports = rest.parse::<PortSpec>().map_err(|e| err(e.to_string()))?;
+                    probe.ports = Some(ports);
+                } else {
+                    probe.rules.push(parse_match(rest).map_err(err)?);
+                }
+            }
+            // accepted for nmap compatibility, not used
+            "softmatch" | "rarity" | "totalwaitms" | "tcpwrappedms" | "sslports" | "fallback"
+            | "Exclude" => {}
+            _ => return Err(err(format!("unknown directive '{keyword}'"))),
+        }
+    }
+
+    Ok(probes)
+}
+
+/// `TCP <name> q|<payload>|`
+fn parse_probe(rest: &str) -> Result<Probe, String> {
+    let mut parts = rest.splitn(3, ' ');
+    let proto = parts.next().unwrap_or("");
+    if proto != "TCP" {
+        return Err(format!("unsupported probe protocol '{proto}'"));
+    }
+    let name = parts
+        .next()
+        .filter(|n| !n.is_empty())
+        .ok_or("probe without a name")?;
+    let payload = parts.next().unwrap_or("").trim();
+    let payload = payload
+        .strip_prefix('q')
+        .ok_or("probe payload must look like q|...|")?;
+    let (raw, _) = delimited(payload)?;
+
+    Ok(Probe {
+        name: name.to_string(),
+        payload: unescape(raw)?,
+        ports: None,
+        rules: Vec::new(),
+    })
+}
+
+/// `<service> m|<regex>|[flags] [p/..../] [v/..../] ...`
+fn parse_match(rest: &str) -> Result<MatchRule, String> {
+    let (service, rest) = rest.split_once(' ').ok_or("match without a pattern")?;
+    let rest = rest.trim_start();
+    let rest = rest
+        .strip_prefix('m')
+        .ok_or("pattern must look like m|...|")?;
+    let (regex, mut rest) = delimited(rest)?;
+
+    let flags_end = rest.find(' ').unwrap_or(rest.len());
+    let flags = &rest[..flags_end];
+    if let Some(bad) = flags.chars().find(|c| !matches!(c, 'i' | 's')) {
+        return Err(format!("unknown pattern flag '{bad}'"));
+    }
+    rest = &rest[flags_end..];
+    let pattern = pattern(regex, flags.contains('i'), flags.contains('s'))?;
+
+    let mut rule = MatchRule {
+        service: service.to_string(),
+        pattern,
+        product: None,
+        version: None,
+        info: None,
+        os: None,
+        hostname: None,
+    };
+
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+        if let Some(cpe) = rest.strip_prefix("cpe:") {
+            // cpe:/a:vendor:product/ , possibly followed by an 'a' flag
+            let (_, after) = delimited(cpe)?;
+            rest = after.strip_prefix('a').unwrap_or(after);
+            continue;
+        }
+        let field = rest.chars().next().unwrap_or(' ');
+        let (value, after) = delimited(&rest[field.len_utf8()..])?;
+        let slot = match field {
+            'p' => &mut rule.product,
+            'v' => &mut rule.version,
+            'i' => &mut rule.info,
+            'o' => &mut rule.os,
+            'h' => &mut rule.hostname,
+            // device type: accepted, not reported
+            'd' => &mut None,
+            _ => return Err(format!("unknown match field '{field}'")),
+        };
+        *slot = Some(value.to_string());
+        rest = after;
+    }
+
+    Ok(rule)
+}
+
+/// split `|text|rest` on its first character; returns (text, rest)
+fn delimited(s: &str) -> Result<(&str, &str), String> {
+    let delim = s.chars().next().ok_or("missing delimiter")?;
+    let body = &s[delim.len_utf8()..];
+    let end = body
+        .find(delim)
+        .ok_or_else(|| format!("missing closing '{delim}'"))?;
+    Ok((&body[..end], &body[end + delim.len_utf8()..]))
+}
+
+/// payload escapes: \r \n \t \0 \\ \xHH
+fn unescape(s: &str) -> Result<Vec<u8>, String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' {
+            out.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let e = *bytes.get(i + 1).ok_or("trailing backslash in payload")?;
+        i += 2;
+        out.push(match e {
+            b'r' => b'\r',
+            b'n' => b'\n',
+            b't' => b'\t',
+            b'0' => 0,
+            b'x' => {
+                let hex = s.get(i..i + 2).ok_or("bad \\x escape in payload")?;
+                i += 2;
+                u8::from_str_radix(hex, 16).map_err(|_| "bad \\x escape in payload")?
+            }
+            b => b,
+        });
+    }
+    Ok(out)
+}
@@ -0,0 +1,57 @@
+//! a grpc api for running scans (serve --grpc): StartScan streams each port
+//! as it finishes and Cancel stops a scan, as proto/pscan.proto describes.
+//! it is served by tonic and only built with the `grpc` feature; without
+//! it binding fails with `ErrorKind::Unsupported`.
+
+#[cfg(feature = "grpc")]
+mod service;
+
+/// the messages and service trait made from proto/pscan.proto
+#[cfg(feature = "grpc")]
+mod proto {
+    tonic::include_proto!("pscan.v1");
+}
+
+#[cfg(feature = "grpc")]
+pub use service::GrpcServer;
+
+#[cfg(feature = "grpc")]
+use std::sync::{Mutex, MutexGuard};
+
+/// lock a mutex, ignoring poisoning (a panicked call is already over)
+#[cfg(feature = "grpc")]
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(not(feature = "grpc"))]
+mod unsupported {
+    use crate::ServeOptions;
+    use std::convert::Infallible;
+    use std::io;
+    use std::net::SocketAddr;
+    use std::sync::atomic::AtomicBool;
+
+    /// never constructed: this build has no grpc
+    pub struct GrpcServer(Infallible);
+
+    impl GrpcServer {
+        pub fn bind(_addr: SocketAddr, _opts: ServeOptions) -> io::Result<GrpcServer> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "this build has no grpc (rebuild with --features grpc)",
+            ))
+        }
+
+        pub fn local_addr(&self) -> io::Result<SocketAddr> {
+            match self.0 {}
+        }
+
+        pub fn run(self, _stop: &AtomicBool) -> io::Result<()> {
+            match self.0 {}
+        }
+    }
+}
+
+#[cfg(not(feature = "grpc"))]
+pub use unsupported::GrpcServer;
@@ -0,0 +1,307 @@
+//! the pscan.v1.Scanner service
+
+use super::lock;
+use super::proto::scanner_server::{Scanner as ScannerApi, ScannerServer};
+use super::proto::{self, CancelReply, CancelRequest, ScanRequest};
+use crate::serve::token_matches;
+use crate::{
+    check_allowed, expand_targets_with, Engine, PortSpec, ScanHost, ScanResult, Scanner,
+    ServeOptions, Target, DEFAULT_CONCURRENCY,
+};
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tokio::time;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::metadata::MetadataValue;
+use tonic::{Request, Response, Status};
+
+/// results queued for a slow client before the scan waits for it
+const QUEUED: usize = 256;
+
+/// the grpc server, bound and ready to `run`
+pub struct GrpcServer {
+    listener: TcpListener,
+    service: Arc<Service>,
+}
+
+struct Service {
+    opts: ServeOptions,
+    /// the cancel flag of every scan running or waiting, by id
+    scans: Mutex<HashMap<String, Arc<AtomicBool>>>,
+    next_id: AtomicUsize,
+    /// a permit for each scan that may run at once; the rest wait
+    slots: Arc<Semaphore>,
+}
+
+impl GrpcServer {
+    pub fn bind(addr: SocketAddr, opts: ServeOptions) -> io::Result<GrpcServer> {
+        let slots = Arc::new(Semaphore::new(opts.max_jobs.max(1)));
+        Ok(GrpcServer {
+            listener: TcpListener::bind(addr)?,
+            service: Arc::new(Service {
+                opts,
+                scans: Mutex::default(),
+                next_id: AtomicUsize::new(1),
+                slots,
+            }),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// answer calls until `stop` is set. running scans are cancelled on the
+    /// way out, which ends their calls.
+    pub fn run(self, stop: &AtomicBool) -> io::Result<()> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+        let service = self.service;
+        runtime.block_on(async {
+            self.listener.set_nonblocking(true)?;
+            let incoming =
+                TcpListenerStream::new(tokio::net::TcpListener::from_std(self.listener)?);
+            let token = service.opts.token.clone();
+            let api = ScannerServer::with_interceptor(Api(service.clone()), move |request| {
+                authorize(token.as_deref(), request)
+            });
+            let stopped = async {
+                while !stop.load(Ordering::Relaxed) {
+                    time::sleep(Duration::from_millis(50)).await;
+                }
+                for cancel in lock(&service.scans).values() {
+                    cancel.store(true, Ordering::SeqCst);
+                }
+            };
+            tonic::transport::Server::builder()
+                .add_service(api)
+                .serve_with_incoming_shutdown(incoming, stopped)
+                .await
+                .map_err(io::Error::other)
+        })
+    }
+}
+
+/// with --token, every call needs `authorization: Bearer TOKEN`
+fn authorize(token: Option<&str>, request: Request<()>) -> Result<Request<()>, Status> {
+    let Some(token) = token else {
+        return Ok(request);
+    };
+    let given = request
+        .metadata()
+        .get("authorization")
+        .and_then(|a| a.to_str().ok())
+        .and_then(|a| a.strip_prefix("Bearer "));
+    if !token_matches(token, given) {
+        return Err(Status::unauthenticated("missing or wrong bearer token"));
+    }
+    Ok(request)
+}
+
+/// what StartScan asked for
+struct Wanted {
+    id: Option<String>,
+    targets: Vec<Target>,
+    ports: PortSpec,
+    timeout: Duration,
+    concurrency: usize,
+    show_closed: bool,
+}
+
+fn parse_scan_request(request: ScanRequest) -> Result<Wanted, String> {
+    // proto3 sends 0 for a number left out, which keeps the default
+    let number = |name: &str, n: u32, max: u32| {
+        if n <= max {
+            Ok(n)
+        } else {
+            Err(format!("{name} must be from 1 to {max}"))
+        }
+    };
+    let timeout_ms = number("timeout_ms", request.timeout_ms, 60_000)?;
+    let concurrency = number("concurrency", request.concurrency, 10_000)?;
+    let targets = request
+        .targets
+        .iter()
+        .map(|t| t.parse())
+        .collect::<Result<Vec<Target>, _>>()?;
+    if targets.is_empty() {
+        return Err("no targets".to_string());
+    }
+    Ok(Wanted {
+        id: Some(request.scan_id).filter(|id| !id.is_empty()),
+        targets,
+        ports: match request.ports.as_str() {
+            "" => PortSpec::top(1000),
+            ports => ports.parse::<PortSpec>().map_err(|e| e.to_string())?,
+        },
+        timeout: Duration::from_millis(match timeout_ms {
+            0 => 1000,
+            ms => u64::from(ms),
+        }),
+        concurrency: match concurrency {
+            0 => DEFAULT_CONCURRENCY,
+            n => n as usize,
+        },
+        show_closed: request.show_closed,
+    })
+}
+
+/// a scan's place in the service: its id until it ends
+struct Registered {
+    service: Arc<Service>,
+    id: String,
+}
+
+impl Drop for Registered {
+    fn drop(&mut self) {
+        lock(&self.service.scans).remove(&self.id);
+    }
+}
+
+/// the service tonic calls into
+struct Api(Arc<Service>);
+
+#[tonic::async_trait]
+impl ScannerApi for Api {
+    type StartScanStream = ReceiverStream<Result<proto::ScanResult, Status>>;
+
+    /// the response headers carry the scan's id as `scan-id`; they go out
+    /// once the scan has a slot and its targets are resolved
+    async fn start_scan(
+        &self,
+        request: Request<ScanRequest>,
+    ) -> Result<Response<Self::StartScanStream>, Status> {
+        let service = &self.0;
+        let wanted = parse_scan_request(request.into_inner()).map_err(Status::invalid_argument)?;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let scan = {
+            let mut scans = lock(&service.scans);
+            let id = match wanted.id.clone() {
+                Some(id) if scans.contains_key(&id) => {
+                    return Err(Status::already_exists(format!(
+                        "scan {id} is already running"
+                    )));
+                }
+                Some(id) => id,
+                None => loop {
+                    let id = service.next_id.fetch_add(1, Ordering::Relaxed).to_string();
+                    if !scans.contains_key(&id) {
+                        break id;
+                    }
+                },
+            };
+            scans.insert(id.clone(), cancelled.clone());
+            Registered {
+                service: service.clone(),
+                id,
+            }
+        };
+
+        let slot = wait_for_slot(service, &cancelled).await?;
+        let hosts = {
+            let resolver = service.opts.resolver.clone();
+            let targets = wanted.targets.clone();
+            tokio::task::spawn_blocking(move || expand_targets_with(&targets, false, &resolver))
+                .await
+                .map_err(|e| Status::internal(e.to_string()))?
+                .map_err(|e| Status::invalid_argument(format!("{e:#}")))?
+        };
+        check_allowed(&hosts, service.opts.allow.as_deref())
+            .map_err(|e| Status::permission_denied(e.to_string()))?;
+
+        let id = MetadataValue::try_from(scan.id.as_str())
+            .map_err(|_| Status::invalid_argument("scan_id isn't a valid header value"))?;
+        let (results, stream) = mpsc::channel(QUEUED);
+        tokio::task::spawn_blocking(move || {
+            let _scan = scan;
+            let _slot = slot;
+            run_scan(&wanted, hosts, &cancelled, &results);
+        });
+        let mut response = Response::new(ReceiverStream::new(stream));
+        response.metadata_mut().insert("scan-id", id);
+        Ok(response)
+    }
+
+    async fn cancel(
+        &self,
+        request: Request<CancelRequest>,
+    ) -> Result<Response<CancelReply>, Status> {
+        let id = request.into_inner().scan_id;
+        let Some(cancelled) = lock(&self.0.scans).get(&id).cloned() else {
+            return Err(Status::not_found(format!("no scan {id:?} is running")));
+        };
+        cancelled.store(true, Ordering::SeqCst);
+        Ok(Response::new(CancelReply { cancelled: true }))
+    }
+}
+
+/// a permit to scan, once a running scan gives one up, unless cancelled
+/// first
+async fn wait_for_slot(
+    service: &Service,
+    cancelled: &AtomicBool,
+) -> Result<OwnedSemaphorePermit, Status> {
+    loop {
+        if cancelled.load(Ordering::SeqCst) {
+            return Err(Status::cancelled("scan cancelled"));
+        }
+        let slot = service.slots.clone().acquire_owned();
+        if let Ok(slot) = time::timeout(Duration::from_millis(100), slot).await {
+            return slot.map_err(|e| Status::internal(e.to_string()));
+        }
+    }
+}
+
+/// scan `hosts` as `wanted` says, sending each port on to `results`. the
+/// call ends CANCELLED when the scan is; a client that's gone cancels it.
+fn run_scan(
+    wanted: &Wanted,
+    hosts: Vec<ScanHost>,
+    cancelled: &Arc<AtomicBool>,
+    results: &mpsc::Sender<Result<proto::ScanResult, Status>>,
+) {
+    let scanner = Scanner::builder()
+        .timeout(wanted.timeout)
+        .engine(Engine::Async)
+        .concurrency(wanted.concurrency)
+        .cancel_flag(cancelled.clone())
+        .build();
+    for host in hosts {
+        if cancelled.load(Ordering::Relaxed) {
+            break;
+        }
+        scanner
+            .for_host(&host)
+            .scan_host(host.ip, wanted.ports.iter(), |result| {
+                if !wanted.show_closed && !result.is_open() {
+                    return;
+                }
+                if results
+                    .blocking_send(Ok(result_message(&host, result)))
+                    .is_err()
+                {
+                    cancelled.store(true, Ordering::SeqCst);
+                }
+            });
+    }
+    if cancelled.load(Ordering::SeqCst) {
+        let _ = results.blocking_send(Err(Status::cancelled("scan cancelled")));
+    }
+}
+
+fn result_message(host: &ScanHost, result: &ScanResult) -> proto::ScanResult {
+    proto::ScanResult {
+        ip: host.ip.to_string(),
+        port: u32::from(result.port),
+        state: result.state.as_str().to_string(),
+        service: result.service_name().unwrap_or_default().to_string(),
+        latency_ms: result.latency_ms().unwrap_or_default(),
+        names: host.names.clone(),
+    }
+}
@@ -0,0 +1,213 @@
+//! reading back the scan history that --store keeps
+
+use crate::report::Db;
+use crate::{PortSpec, PortState};
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// a database of stored scans, opened for reading
+pub struct History {
+    db: Db,
+}
+
+/// one stored scan of one host
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostRecord {
+    pub scan_id: i64,
+    /// when the scan started, rfc 3339 in utc
+    pub started: String,
+    pub ip: IpAddr,
+    pub names: Vec<String>,
+    /// the ports the scan covered; None when the record can't say
+    pub ports: Option<PortSpec>,
+    /// the scan stopped before it finished this host
+    pub incomplete: bool,
+    /// the ports stored for the host: the open ones, or all of them when
+    /// the scan had --show-closed
+    pub results: Vec<PortRecord>,
+}
+
+/// one stored port
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortRecord {
+    pub port: u16,
+    pub state: PortState,
+    pub service: Option<String>,
+    /// the detected product and version, e.g. "OpenSSH 9.6p1"
+    pub version: Option<String>,
+}
+
+/// how one port on one host looked, scan after scan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortHistory {
+    pub ip: IpAddr,
+    pub port: u16,
+    /// every scan that covered the port, oldest first
+    pub seen: Vec<Sighting>,
+}
+
+/// the port in one scan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sighting {
+    pub scan_id: i64,
+    pub started: String,
+    /// None when the scan covered the port but didn't store it, so it
+    /// wasn't open
+    pub result: Option<PortRecord>,
+}
+
+impl Sighting {
+    pub fn is_open(&self) -> bool {
+        self.result
+            .as_ref()
+            .is_some_and(|r| r.state == PortState::Open)
+    }
+}
+
+impl PortHistory {
+    /// the first scan that found the port open
+    pub fn first_open(&self) -> Option<&Sighting> {
+        self.seen.iter().find(|s| s.is_open())
+    }
+
+    /// the last scan that found the port open
+    pub fn last_open(&self) -> Option<&Sighting> {
+        self.seen.iter().rev().find(|s| s.is_open())
+    }
+
+    /// how many scans found the port open
+    pub fn open_count(&self) -> usize {
+        self.seen.iter().filter(|s| s.is_open()).count()
+    }
+}
+
+impl History {
+    /// open an existing database written by --store
+    pub fn open(path: &Path) -> io::Result<History> {
+        if !path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no such database (scans are kept with --store)",
+            ));
+        }
+        Ok(History {
+            db: crate::report::open_database(path)?,
+        })
+    }
+
+    /// every stored scan of a host that started at or after `since`,
+    /// oldest first
+    #[cfg(feature = "sqlite")]
+    pub fn hosts(&self, since: Option<SystemTime>) -> io::Result<Vec<HostRecord>> {
+        self.read_hosts(since.map(crate::report::util::rfc3339))
+            .map_err(io::Error::other)
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    pub fn hosts(&self, _since: Option<SystemTime>) -> io::Result<Vec<HostRecord>> {
+        match self.db {}
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn read_hosts(&self, since: Option<String>) -> rusqlite::Result<Vec<HostRecord>> {
+        let mut results: std::collections::HashMap<i64, Vec<PortRecord>> = Default::default();
+        let mut ports = self.db.prepare(
+            "SELECT p.host_id, p.port, p.state, p.service, p.version FROM ports p \
+             JOIN hosts h ON h.id = p.host_id JOIN scans s ON s.id = h.scan_id \
+             WHERE ?1 IS NULL OR s.started >= ?1 ORDER BY p.host_id, p.port",
+        )?;
+        let mut rows = ports.query([&since])?;
+        while let Some(row) = rows.next()? {
+            let (Some(host_id), Some(port), Some(state)) = (
+                row.get::<_, Option<i64>>(0)?,
+                row.get::<_, Option<u16>>(1).ok().flatten(),
+                row.get::<_, Option<String>>(2)?
+                    .and_then(|s| s.parse().ok()),
+            ) else {
+                continue;
+            };
+            results.entry(host_id).or_default().push(PortRecord {
+                port,
+                state,
+                service: row.get(3)?,
+                version: row.get(4)?,
+            });
+        }
+
+        let mut hosts = Vec::new();
+        let mut scans = self.db.prepare(
+            "SELECT h.id, s.id, s.started, s.ports, h.ip, h.names, h.incomplete FROM hosts h \
+             JOIN scans s ON s.id = h.scan_id WHERE ?1 IS NULL OR s.started >= ?1 \
+             ORDER BY s.started, s.id, h.id",
+        )?;
+        let mut rows = scans.query([&since])?;
+        while let Some(row) = rows.next()? {
+            let (Some(host_id), Some(scan_id), Some(ip)) = (
+                row.get::<_, Option<i64>>(0)?,
+                row.get::<_, Option<i64>>(1)?,
+                row.get::<_, Option<String>>(4)?
+                    .and_then(|ip| ip.parse().ok()),
+            ) else {
+                continue;
+            };
+            let names: Option<String> = row.get(5)?;
+            let ports: Option<String> = row.get(3)?;
+            hosts.push(HostRecord {
+                scan_id,
+                started: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                ip,
+                names: names
+                    .unwrap_or_default()
+                    .split(',')
+                    .filter(|n| !n.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+                ports: ports.as_deref().and_then(crate::saved::recorded_ports),
+                incomplete: row.get::<_, Option<i64>>(6)?.unwrap_or(0) != 0,
+                results: results.remove(&host_id).unwrap_or_default(),
+            });
+        }
+        Ok(hosts)
+    }
+}
+
+/// the history of every port `records` found open at least once, by ip and
+/// then port. a scan that didn't cover a port, or stopped before finishing
+/// the host without storing it, is left out of that port's history.
+pub fn port_histories(records: &[HostRecord]) -> Vec<PortHistory> {
+    let mut ips: Vec<IpAddr> = records.iter().map(|r| r.ip).collect();
+    ips.sort();
+    ips.dedup();
+
+    let mut histories = Vec::new();
+    for ip in ips {
+        let scans: Vec<&HostRecord> = records.iter().filter(|r| r.ip == ip).collect();
+        let mut ports: Vec<u16> = scans
+            .iter()
+            .flat_map(|r| &r.results)
+            .filter(|p| p.state == PortState::Open)
+            .map(|p| p.port)
+            .collect();
+        ports.sort_unstable();
+        ports.dedup();
+        for port in ports {
+            let seen = scans
+                .iter()
+                .filter_map(|r| {
+                    let result = r.results.iter().find(|p| p.port == port).cloned();
+                    let covered =
+                        !r.incomplete && r.ports.as_ref().is_none_or(|p| p.contains(port));
+                    (result.is_some() || covered).then(|| Sighting {
+                        scan_id: r.scan_id,
+                        started: r.started.clone(),
+                        result,
+                    })
+                })
+                .collect();
+            histories.push(PortHistory { ip, port, seen });
+        }
+    }
+    histories
+}
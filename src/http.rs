@@ -0,0 +1,151 @@
+use crate::Transport;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// stop reading a response after this much; the title is near the top
+const MAX_RESPONSE: usize = 64 * 1024;
+/// longest title kept, in characters
+const MAX_TITLE: usize = 200;
+
+/// what `GET /` returned
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HttpInfo {
+    pub status: u16,
+    /// the `Server` header
+    pub server: Option<String>,
+    /// the page `<title>`, whitespace collapsed
+    pub title: Option<String>,
+}
+
+impl HttpInfo {
+    /// one line for tables: status, server and title
+    pub fn summary(&self) -> String {
+        let mut s = self.status.to_string();
+        if let Some(server) = &self.server {
+            s.push_str(&format!("; server {server}"));
+        }
+        if let Some(title) = &self.title {
+            s.push_str(&format!("; title {title}"));
+        }
+        s
+    }
+}
+
+/// send `GET /` over plain http and read the status, server header and
+/// title. `host` goes in the Host header (the ip is used without one).
+pub fn http_probe(addr: SocketAddr, host: Option<&str>, timeout: Duration) -> io::Result<HttpInfo> {
+    http_probe_via(&Transport::direct(), addr, host, timeout)
+}
+
+/// `http_probe` over a connection from `transport`
+pub(crate) fn http_probe_via(
+    transport: &Transport,
+    addr: SocketAddr,
+    host: Option<&str>,
+    timeout: Duration,
+) -> io::Result<HttpInfo> {
+    let deadline = Instant::now() + timeout;
+    let mut stream = transport.connect(addr, timeout)?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let host = match host {
+        Some(name) => name.to_string(),
+        None => match addr {
+            SocketAddr::V4(a) => a.ip().to_string(),
+            SocketAddr::V6(a) => format!("[{}]", a.ip()),
+        },
+    };
+    let host = if addr.port() == 80 {
+        host
+    } else {
+        format!("{host}:{}", addr.port())
+    };
+    // one write, so the request doesn't go out in fragments
+    let request = format!(
+        "GET / HTTP/1.1\r\nHost: {host}\r\nUser-Agent: pscan/{}\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+        env!("CARGO_PKG_VERSION")
+    );
+    stream.write_all(request.as_bytes())?;
+
+    let mut response = Vec::new();
+    let mut buf = [0u8; 4096];
+    while response.len() < MAX_RESPONSE {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        stream.set_read_timeout(Some(left))?;
+        match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => response.extend_from_slice(&buf[..n]),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            // a slow or keep-alive server: work with what arrived
+            Err(e) if !response.is_empty() && is_timeout(&e) => break,
+            Err(e) => return Err(e),
+        }
+    }
+
+    parse_response(&response)
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "not an http response"))
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+/// status, server header and title from a raw (possibly truncated) response
+pub fn parse_response(raw: &[u8]) -> Option<HttpInfo> {
+    let text = String::from_utf8_lossy(raw);
+    let (head, body) = match text.find("\r\n\r\n") {
+        Some(i) => (&text[..i], &text[i + 4..]),
+        None => (&text[..], ""),
+    };
+
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next()?;
+    let mut parts = status_line.splitn(3, ' ');
+    if !parts.next()?.starts_with("HTTP/") {
+        return None;
+    }
+    let status = parts.next()?.parse().ok()?;
+
+    let server = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("server"))
+        .map(|(_, value)| value.trim().to_string())
+        .filter(|v| !v.is_empty());
+
+    Some(HttpInfo {
+        status,
+        server,
+        title: title(body),
+    })
+}
+
+fn title(body: &str) -> Option<String> {
+    let lower = body.to_ascii_lowercase();
+    let open = lower.find("<title")?;
+    let start = open + lower[open..].find('>')? + 1;
+    let end = start
+        + lower[start..]
+            .find("</title")
+            .unwrap_or(lower.len() - start);
+
+    let raw = body[start..end]
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    let title: String = raw
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_TITLE)
+        .collect();
+    (!title.is_empty()).then_some(title)
+}
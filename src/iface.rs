@@ -0,0 +1,190 @@
+//! the host's network interfaces, for the scans that work below ip
+
+use crate::IpNet;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+
+/// an ethernet hardware address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MacAddr(pub [u8; 6]);
+
+impl MacAddr {
+    pub const BROADCAST: MacAddr = MacAddr([0xff; 6]);
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        f.pad(&format!("{a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}:{g:02x}"))
+    }
+}
+
+/// an ethernet interface that is up and has an ipv4 address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Interface {
+    pub name: String,
+    pub index: u32,
+    pub mac: MacAddr,
+    pub addr: Ipv4Addr,
+    pub prefix: u8,
+}
+
+impl Interface {
+    /// whether `ip` sits on this interface's subnet (no router in between)
+    pub fn is_local(&self, ip: IpAddr) -> bool {
+        IpNet::new(IpAddr::V4(self.addr), self.prefix).is_ok_and(|net| net.contains(ip))
+    }
+}
+
+/// the interfaces `ip` can be reached on directly, if any
+pub(crate) fn local_interface(interfaces: &[Interface], ip: IpAddr) -> Option<&Interface> {
+    interfaces.iter().find(|i| i.is_local(ip))
+}
+
+/// the index of the interface called `name`, if there is one
+#[cfg(unix)]
+pub(crate) fn interface_index(name: &str) -> Option<u32> {
+    let name = std::ffi::CString::new(name).ok()?;
+    // SAFETY: name is a valid nul-terminated string
+    let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+    (index != 0).then_some(index)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn interface_index(_name: &str) -> Option<u32> {
+    None
+}
+
+/// the ipv4 and ipv6 addresses of the interface called `name`
+#[cfg(unix)]
+pub(crate) fn interface_addrs(name: &str) -> io::Result<Vec<IpAddr>> {
+    use std::ffi::CStr;
+    use std::net::Ipv6Addr;
+
+    let mut head: *mut libc::ifaddrs = std::ptr::null_mut();
+    // SAFETY: getifaddrs fills in a list we free below
+    if unsafe { libc::getifaddrs(&mut head) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut addrs = Vec::new();
+    let mut cursor = head;
+    while !cursor.is_null() {
+        // SAFETY: cursor walks the list getifaddrs returned, which stays
+        // valid until freeifaddrs
+        let entry = unsafe { &*cursor };
+        cursor = entry.ifa_next;
+        // SAFETY: ifa_name is a nul-terminated string
+        let entry_name = unsafe { CStr::from_ptr(entry.ifa_name) };
+        if entry.ifa_addr.is_null() || entry_name.to_bytes() != name.as_bytes() {
+            continue;
+        }
+        // SAFETY: ifa_addr is non-null and starts with the family; the
+        // family says which sockaddr it is
+        unsafe {
+            match (*entry.ifa_addr).sa_family as libc::c_int {
+                libc::AF_INET => {
+                    let sin = &*(entry.ifa_addr as *const libc::sockaddr_in);
+                    let ip = Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr));
+                    addrs.push(IpAddr::V4(ip));
+                }
+                libc::AF_INET6 => {
+                    let sin6 = &*(entry.ifa_addr as *const libc::sockaddr_in6);
+                    addrs.push(IpAddr::V6(Ipv6Addr::from(sin6.sin6_addr.s6_addr)));
+                }
+                _ => {}
+            }
+        }
+    }
+    // SAFETY: head came from getifaddrs and is freed once
+    unsafe { libc::freeifaddrs(head) };
+    Ok(addrs)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn interface_addrs(_name: &str) -> io::Result<Vec<IpAddr>> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// every ethernet interface that is up, once per ipv4 address.
+/// loopback and interfaces without a mac (tunnels, ppp) are left out.
+#[cfg(target_os = "linux")]
+pub(crate) fn interfaces() -> io::Result<Vec<Interface>> {
+    use std::collections::HashMap;
+    use std::ffi::CStr;
+
+    let mut head: *mut libc::ifaddrs = std::ptr::null_mut();
+    // SAFETY: getifaddrs fills in a list we free below
+    if unsafe { libc::getifaddrs(&mut head) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut links: HashMap<String, (u32, MacAddr)> = HashMap::new();
+    let mut addrs: Vec<(String, Ipv4Addr, u8)> = Vec::new();
+    let mut cursor = head;
+    while !cursor.is_null() {
+        // SAFETY: cursor walks the list getifaddrs returned, which stays
+        // valid until freeifaddrs
+        let entry = unsafe { &*cursor };
+        cursor = entry.ifa_next;
+        let wanted = entry.ifa_flags & libc::IFF_UP as u32 != 0
+            && entry.ifa_flags & libc::IFF_LOOPBACK as u32 == 0;
+        if !wanted || entry.ifa_addr.is_null() {
+            continue;
+        }
+        // SAFETY: ifa_name is a nul-terminated string
+        let name = unsafe { CStr::from_ptr(entry.ifa_name) }
+            .to_string_lossy()
+            .into_owned();
+        // SAFETY: ifa_addr is non-null and starts with the family
+        let family = unsafe { (*entry.ifa_addr).sa_family } as libc::c_int;
+        match family {
+            libc::AF_PACKET => {
+                // SAFETY: AF_PACKET entries carry a sockaddr_ll
+                let ll = unsafe { &*(entry.ifa_addr as *const libc::sockaddr_ll) };
+                if ll.sll_halen == 6 {
+                    let mut mac = [0u8; 6];
+                    mac.copy_from_slice(&ll.sll_addr[..6]);
+                    links.insert(name, (ll.sll_ifindex as u32, MacAddr(mac)));
+                }
+            }
+            libc::AF_INET if !entry.ifa_netmask.is_null() => {
+                // SAFETY: AF_INET entries carry sockaddr_ins for the address
+                // and netmask
+                let (addr, mask) = unsafe {
+                    (
+                        &*(entry.ifa_addr as *const libc::sockaddr_in),
+                        &*(entry.ifa_netmask as *const libc::sockaddr_in),
+                    )
+                };
+                let addr = Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr));
+                let prefix = u32::from_be(mask.sin_addr.s_addr).count_ones() as u8;
+                addrs.push((name, addr, prefix));
+            }
+            _ => {}
+        }
+    }
+    // SAFETY: head came from getifaddrs and is freed once
+    unsafe { libc::freeifaddrs(head) };
+
+    Ok(addrs
+        .into_iter()
+        .filter_map(|(name, addr, prefix)| {
+            // aliases ("eth0:1") share their interface's link
+            let link = name.split(':').next().unwrap_or(&name);
+            let &(index, mac) = links.get(link)?;
+            Some(Interface {
+                name,
+                index,
+                mac,
+                addr,
+                prefix,
+            })
+        })
+        .collect())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn interfaces() -> io::Result<Vec<Interface>> {
+    Err(io::ErrorKind::Unsupported.into())
+}
@@ -1,162 +1,486 @@
-use anyhow::{bail, Context, Result};
-use rayon::prelude::*;
-use std::collections::BTreeSet;
-use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use crate::timing::Pacer;
+use std::fmt;
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr, TcpStream};
 use std::str::FromStr;
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tracing::trace;
 
-/// represents a port range like 1-1000
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct PortRange {
-    pub start: u16,
-    pub end: u16,
+mod arp;
+mod banner;
+mod chat;
+mod config;
+mod cron;
+mod diff;
+mod discover;
+mod dns;
+mod engine;
+mod error;
+pub mod fingerprint;
+mod grpc;
+mod history;
+mod http;
+mod iface;
+pub mod log;
+mod merge;
+mod metrics;
+mod notify;
+mod packet;
+mod policy;
+mod ports;
+mod profile;
+mod progress;
+mod raw;
+pub mod report;
+mod retry;
+mod rng;
+mod saved;
+mod scanner;
+mod serve;
+pub mod services;
+mod smtp;
+mod syslog;
+mod targets;
+mod timing;
+mod tls;
+#[cfg(feature = "tokio")]
+mod tokio_engine;
+mod transport;
+pub mod tui;
+mod udp;
+mod webhook;
+mod websocket;
+
+pub use banner::{banner_text, read_banner, BannerOptions};
+pub use chat::{ChatKind, ChatTarget};
+pub use config::Config;
+pub use cron::Cron;
+pub use diff::{diff_scans, Change, PortChange};
+pub use discover::{discover, DiscoverOptions, LiveHost, Liveness, DEFAULT_PING_PORTS};
+pub use dns::{parse_dns_server, IpFamily, Resolver};
+pub use engine::{socket_limit, Engine, DEFAULT_CONCURRENCY};
+pub use error::ScanError;
+pub use fingerprint::{ServiceMatch, ServiceProbes};
+pub use grpc::GrpcServer;
+pub use history::{port_histories, History, HostRecord, PortHistory, PortRecord, Sighting};
+pub use http::{http_probe, parse_response, HttpInfo};
+pub use iface::MacAddr;
+pub use merge::{merge_scans, merged_report, MergedHost};
+pub use metrics::{serve_metrics, Metrics, METRICS_CONTENT_TYPE};
+pub use notify::desktop_notify;
+pub use policy::{HostPolicy, Policy, Violation};
+pub use ports::{PortRange, PortSpec};
+pub use profile::{Profile, BUILTIN_PROFILES};
+pub use progress::{HostProgress, Progress, ProgressSnapshot};
+pub use raw::raw_sockets_available;
+pub use retry::{Backoff, RetryPolicy, DEFAULT_MAX_BACKOFF};
+pub use rng::Rng;
+pub use saved::{SavedHost, SavedScan};
+pub use scanner::{ScanOutcome, Scanner, ScannerBuilder, Stopped, DEFAULT_DEAD_AFTER};
+pub use serve::{ServeOptions, Server, ServerWaker};
+pub use services::Services;
+pub use smtp::{SmtpConfig, SmtpTls};
+pub use syslog::{Facility, SyslogTarget};
+pub use targets::{
+    check_allowed, exclude_hosts, expand_targets, expand_targets_with, parse_exclude_list,
+    parse_target_list, resolve_ptr_names, resolve_target, reverse_lookup, IpNet, ScanHost, Target,
+    MAX_NETWORK_HOSTS,
+};
+pub use timing::{Timing, DEFAULT_MAX_RTT_TIMEOUT, DEFAULT_MIN_RTT_TIMEOUT};
+pub use tls::{parse_certificate, tls_probe, CertInfo, TlsInfo};
+pub use transport::{Proxy, ProxyKind, Transport, DEFAULT_HOP_TIMEOUT, TOR_SOCKS};
+pub use udp::udp_payload;
+pub use webhook::{Webhook, SIGNATURE_HEADER};
+
+/// state of one scanned port, derived from how the connect attempt ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum PortState {
+    /// the connection was accepted
+    Open,
+    /// the host answered with a reset (connection refused)
+    Closed,
+    /// no answer before the timeout, or an icmp unreachable came back
+    Filtered,
+    /// any other socket error
+    Error,
+    /// udp, fin, null and xmas: nothing came back, so the port is open or
+    /// the probe (or its answer) was dropped
+    OpenFiltered,
+    /// ack scan: the port answered with a reset, so nothing in the way
+    /// drops it; whether it is open is unknown
+    Unfiltered,
+    /// never probed: the host ran out of time, or enough of its ports
+    /// were found open, before the port's turn
+    Unknown,
 }
 
-impl PortRange {
-    /// expand the range into a vector of ports to scan
-    pub fn to_vec(self) -> Vec<u16> {
-        (self.start..=self.end).collect()
+impl PortState {
+    /// classify a failed connect by its io error kind
+    pub fn from_error_kind(kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::ConnectionRefused => PortState::Closed,
+            ErrorKind::TimedOut
+            | ErrorKind::WouldBlock
+            | ErrorKind::HostUnreachable
+            | ErrorKind::NetworkUnreachable => PortState::Filtered,
+            _ => PortState::Error,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PortState::Open => "open",
+            PortState::Closed => "closed",
+            PortState::Filtered => "filtered",
+            PortState::Error => "error",
+            PortState::OpenFiltered => "open|filtered",
+            PortState::Unfiltered => "unfiltered",
+            PortState::Unknown => "unknown",
+        }
     }
 }
 
-/// parse `PortRange` from a string like "1-1000"
-impl FromStr for PortRange {
-    type Err = String;
+/// transport protocol a scan probes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Protocol {
+    #[default]
+    Tcp,
+    Udp,
+}
 
-    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        let parts: Vec<&str> = s.split('-').collect();
-        if parts.len() != 2 {
-            return Err("ports must be in format start-end (example: 1-1000)".to_string());
+impl Protocol {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
         }
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
 
-        let start: u16 = parts[0]
-            .trim()
-            .parse()
-            .map_err(|_| "start port must be a number".to_string())?;
+/// how tcp ports are probed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ScanType {
+    /// a full handshake through the os (no privileges needed)
+    #[default]
+    Connect,
+    /// raw syn, never completing the handshake (root or CAP_NET_RAW)
+    Syn,
+    /// raw fin: closed ports reset, open and filtered ones stay silent
+    Fin,
+    /// raw segment with no flags set, answered like fin
+    Null,
+    /// raw fin|psh|urg, answered like fin
+    Xmas,
+    /// raw ack: maps firewall rules, reporting unfiltered or filtered
+    Ack,
+}
 
-        let end: u16 = parts[1]
-            .trim()
-            .parse()
-            .map_err(|_| "end port must be a number".to_string())?;
+impl ScanType {
+    /// true for the types that craft packets on a raw socket
+    pub fn is_raw(self) -> bool {
+        self != ScanType::Connect
+    }
 
-        if start == 0 || end == 0 {
-            return Err("port range must be between 1 and 65535".to_string());
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ScanType::Connect => "connect",
+            ScanType::Syn => "syn",
+            ScanType::Fin => "fin",
+            ScanType::Null => "null",
+            ScanType::Xmas => "xmas",
+            ScanType::Ack => "ack",
         }
-        if start > end {
-            return Err("start port must be <= end port".to_string());
+    }
+}
+
+impl FromStr for ScanType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "connect" => Ok(ScanType::Connect),
+            "syn" => Ok(ScanType::Syn),
+            "fin" => Ok(ScanType::Fin),
+            "null" => Ok(ScanType::Null),
+            "xmas" => Ok(ScanType::Xmas),
+            "ack" => Ok(ScanType::Ack),
+            _ => Err(format!(
+                "unknown scan type '{s}' (expected connect, syn, fin, null, xmas or ack)"
+            )),
         }
+    }
+}
+
+impl fmt::Display for ScanType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+impl FromStr for PortState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            PortState::Open,
+            PortState::Closed,
+            PortState::Filtered,
+            PortState::Error,
+            PortState::OpenFiltered,
+            PortState::Unfiltered,
+            PortState::Unknown,
+        ]
+        .into_iter()
+        .find(|state| state.as_str().eq_ignore_ascii_case(s.trim()))
+        .ok_or_else(|| format!("unknown port state '{s}'"))
+    }
+}
 
-        Ok(PortRange { start, end })
+impl fmt::Display for PortState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
     }
 }
 
 /// status for one scanned port
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ScanResult {
     pub port: u16,
-    pub open: bool,
+    /// what the port was probed with
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub protocol: Protocol,
+    pub state: PortState,
+    /// time spent in the final connect attempt, only set when the host answered
+    pub latency: Option<Duration>,
+    /// what the service sent right after the connect, when banners are enabled
+    pub banner: Option<String>,
+    /// handshake details, when tls probing is enabled and the port speaks tls
+    pub tls: Option<TlsInfo>,
+    /// `GET /` results, when http probing is enabled and the port speaks http
+    pub http: Option<HttpInfo>,
+    /// what version detection found listening, when it is enabled
+    pub service: Option<ServiceMatch>,
 }
 
-/// resolve a target (ip or domain) into one or more ip addresses
-pub fn resolve_target(target: &str) -> Result<Vec<IpAddr>> {
-    let addrs = (target, 0)
-        .to_socket_addrs()
-        .with_context(|| format!("failed to resolve target '{target}'"))?;
+impl ScanResult {
+    /// a tcp result with only its port and state; the probes fill in the
+    /// rest
+    pub fn new(port: u16, state: PortState) -> Self {
+        ScanResult {
+            port,
+            protocol: Protocol::Tcp,
+            state,
+            latency: None,
+            banner: None,
+            tls: None,
+            http: None,
+            service: None,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state == PortState::Open
+    }
 
-    let mut ips = BTreeSet::new();
-    for addr in addrs {
-        ips.insert(addr.ip());
+    /// latency in milliseconds, for display and export
+    pub fn latency_ms(&self) -> Option<f64> {
+        self.latency.map(|d| d.as_secs_f64() * 1000.0)
     }
 
-    if ips.is_empty() {
-        bail!("no ip addresses found for target '{target}'");
+    /// the detected service name, or the usual one for the port and its
+    /// protocol
+    pub fn service_name(&self) -> Option<&str> {
+        self.service_name_in(services::builtin_table())
     }
 
-    Ok(ips.into_iter().collect())
+    /// `service_name`, with the usual names taken from `services`
+    pub fn service_name_in<'a>(&'a self, services: &'a Services) -> Option<&'a str> {
+        match &self.service {
+            Some(m) => Some(m.service.as_str()),
+            None => services.name_for(self.port, self.protocol),
+        }
+    }
 }
 
 /// try to connect to (ip, port) with a timeout.
-/// any error is treated as "not open".
+/// the result of the last attempt is returned; a cancelled probe
+/// never got an answer and is reported as filtered.
 pub fn probe_port(
     ip: IpAddr,
     port: u16,
     timeout: Duration,
     retries: u8,
     cancelled: &AtomicBool,
-) -> bool {
-    if cancelled.load(Ordering::Relaxed) {
-        return false;
-    }
+) -> ScanResult {
+    probe_port_with(ip, port, timeout, &RetryPolicy::new(retries), cancelled)
+}
+
+/// `probe_port` with retries spaced out and timed by `retry`
+pub fn probe_port_with(
+    ip: IpAddr,
+    port: u16,
+    timeout: Duration,
+    retry: &RetryPolicy,
+    cancelled: &AtomicBool,
+) -> ScanResult {
+    connect_port(
+        &Transport::direct(),
+        &Pacer::default(),
+        SocketAddr::new(ip, port),
+        timeout,
+        retry,
+        cancelled,
+    )
+    .0
+}
 
-    let addr = SocketAddr::new(ip, port);
-    let attempts = retries as usize + 1;
+/// `probe_port`, also handing back the connected stream for an open port
+pub(crate) fn connect_port(
+    transport: &Transport,
+    pacer: &Pacer,
+    addr: SocketAddr,
+    timeout: Duration,
+    retry: &RetryPolicy,
+    cancelled: &AtomicBool,
+) -> (ScanResult, Option<TcpStream>) {
+    let mut result = ScanResult::new(addr.port(), PortState::Filtered);
 
-    for _ in 0..attempts {
-        if cancelled.load(Ordering::Relaxed) {
-            return false;
+    for attempt in 0..=retry.retries {
+        if attempt > 0 && !retry.wait(attempt, cancelled) {
+            return (result, None);
         }
+        if !pacer.wait(cancelled) {
+            return (result, None);
+        }
+
+        let started = Instant::now();
+        let outcome = transport.connect(addr, retry.timeout(timeout, attempt));
+        let elapsed = started.elapsed();
 
-        if TcpStream::connect_timeout(&addr, timeout).is_ok() {
-            return true;
+        let stream = match outcome {
+            Ok(stream) => {
+                result.state = PortState::Open;
+                trace_attempt(addr, attempt, None, result.state);
+                Some(stream)
+            }
+            Err(e) => {
+                result.state = PortState::from_error_kind(e.kind());
+                trace_attempt(addr, attempt, Some(e.kind()), result.state);
+                None
+            }
+        };
+        result.latency = match result.state {
+            PortState::Open | PortState::Closed => Some(elapsed),
+            _ => None,
+        };
+
+        if stream.is_some() || !retry.should_retry(result.state) {
+            return (result, stream);
         }
     }
 
-    false
+    (result, None)
+}
+
+/// for -vv: how one attempt at a probe went, and the state it gives
+pub(crate) fn trace_attempt(
+    addr: SocketAddr,
+    attempt: u8,
+    error: Option<ErrorKind>,
+    state: PortState,
+) {
+    match error {
+        Some(kind) => trace!("{addr} attempt {}: {kind}, {state}", attempt + 1),
+        None => trace!("{addr} attempt {}: answered, {state}", attempt + 1),
+    }
 }
 
-/// scan a list of ports on one ip.
-pub fn scan_ip(
+/// scan a set of ports on one ip.
+/// ports are pulled lazily, so a full 1-65535 scan never needs
+/// the whole port list in memory up front. fails when the worker
+/// threads can't be started or `cancelled` is already set.
+/// this builds a `Scanner`, and its worker threads, for the one call;
+/// `scan_ip_with` reuses a scanner's across ips.
+#[allow(clippy::too_many_arguments)]
+pub fn scan_ip<I>(
     ip: IpAddr,
-    ports: &[u16],
+    ports: I,
     timeout: Duration,
     retries: u8,
     parallel: bool,
     threads: usize,
     progress_counter: Option<Arc<AtomicUsize>>,
     cancelled: Arc<AtomicBool>,
-) -> Vec<ScanResult> {
-    let mut results = if parallel {
-        let pool = rayon::ThreadPoolBuilder::new()
-            .num_threads(threads)
-            .build()
-            .expect("failed to build rayon thread pool");
-
-        pool.install(|| {
-            let progress_counter = progress_counter.clone();
-            ports
-                .par_iter()
-                .map(|&port| {
-                    let open = if cancelled.load(Ordering::Relaxed) {
-                        false
-                    } else {
-                        probe_port(ip, port, timeout, retries, &cancelled)
-                    };
-
-                    if let Some(p) = &progress_counter {
-                        p.fetch_add(1, Ordering::Relaxed);
-                    }
-
-                    ScanResult { port, open }
-                })
-                .collect::<Vec<_>>()
-        })
-    } else {
-        ports
-            .iter()
-            .map(|&port| {
-                let open = probe_port(ip, port, timeout, retries, &cancelled);
-
-                if let Some(p) = &progress_counter {
-                    p.fetch_add(1, Ordering::Relaxed);
-                }
-
-                ScanResult { port, open }
-            })
-            .collect::<Vec<_>>()
-    };
-
-    results.sort_by_key(|r| r.port);
-    results
+) -> Result<Vec<ScanResult>, ScanError>
+where
+    I: IntoIterator<Item = u16>,
+    I::IntoIter: Send,
+{
+    let mut builder = Scanner::builder()
+        .timeout(timeout)
+        .retries(retries)
+        .engine(Engine::Threads)
+        .concurrency(if parallel { threads } else { 1 })
+        .cancel_flag(cancelled);
+    if let Some(counter) = progress_counter {
+        builder = builder.progress(counter);
+    }
+    scan_ip_with(&builder.build(), ip, ports)
+}
+
+/// scan a set of ports on one ip with `scanner`, whose worker threads are
+/// started on the first call and reused by every later one; fails like
+/// `scan_ip`
+pub fn scan_ip_with<I>(
+    scanner: &Scanner,
+    ip: IpAddr,
+    ports: I,
+) -> Result<Vec<ScanResult>, ScanError>
+where
+    I: IntoIterator<Item = u16>,
+    I::IntoIter: Send,
+{
+    scanner.try_scan(ip, ports)
+}
+
+/// scan a set of ports on one ip with the async engine and up to
+/// `concurrency` connects in flight; fails like `scan_ip`.
+/// like `scan_ip`, this builds a scanner for the one call; give
+/// `scan_ip_with` one built with `Engine::Async` to reuse it.
+pub fn scan_ip_async<I>(
+    ip: IpAddr,
+    ports: I,
+    timeout: Duration,
+    retries: u8,
+    concurrency: usize,
+    progress_counter: Option<Arc<AtomicUsize>>,
+    cancelled: Arc<AtomicBool>,
+) -> Result<Vec<ScanResult>, ScanError>
+where
+    I: IntoIterator<Item = u16>,
+    I::IntoIter: Send,
+{
+    let mut builder = Scanner::builder()
+        .timeout(timeout)
+        .retries(retries)
+        .engine(Engine::Async)
+        .concurrency(concurrency)
+        .cancel_flag(cancelled);
+    if let Some(counter) = progress_counter {
+        builder = builder.progress(counter);
+    }
+    scan_ip_with(&builder.build(), ip, ports)
 }
@@ -0,0 +1,203 @@
+use super::{level_name, span_parts, span_text};
+use crate::report::util::rfc3339;
+use serde_json::json;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, MakeWriter};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// how a log file's lines are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// `2024-05-01T10:00:00.123Z trace [host ip=10.0.0.5] message`
+    #[default]
+    Text,
+    /// one json object per line, with time, level, target, spans and
+    /// message
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!(
+                "unknown log format '{other}' (expected text or json)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LogFormat::Text => "text",
+            LogFormat::Json => "json",
+        })
+    }
+}
+
+/// writes every event, down to each probe attempt, to a file, whatever the
+/// console shows. a file that would grow past `max_size` becomes `FILE.1`,
+/// the `FILE.1` before it `FILE.2`, and so on up to `keep` old files; with
+/// `keep` 0 it just starts over. a line that can't be written is dropped
+/// rather than stopping the scan.
+pub struct LogFile {
+    path: PathBuf,
+    format: LogFormat,
+    max_size: u64,
+    keep: usize,
+    open: Mutex<Open>,
+}
+
+struct Open {
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    /// appends to `path` if it is there, so a restarted watch carries on
+    /// the same log
+    pub fn create(path: &Path, format: LogFormat, max_size: u64, keep: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(LogFile {
+            path: path.to_path_buf(),
+            format,
+            max_size,
+            keep,
+            open: Mutex::new(Open { file, size }),
+        })
+    }
+
+    /// `FILE.n`
+    fn numbered(&self, n: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{n}"));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&self, open: &mut Open) -> io::Result<()> {
+        if self.keep > 0 {
+            // the oldest is overwritten by the one after it
+            for n in (1..self.keep).rev() {
+                let from = self.numbered(n);
+                if from.exists() {
+                    fs::rename(from, self.numbered(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.numbered(1))?;
+        }
+        open.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        open.size = 0;
+        Ok(())
+    }
+
+    /// a layer writing every event, down to each probe attempt, to this
+    /// file
+    pub fn layer<S>(self) -> impl Layer<S>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        tracing_subscriber::fmt::layer()
+            .event_format(FileLine(self.format))
+            .with_writer(self)
+    }
+}
+
+/// lays out the file's lines in its format
+struct FileLine(LogFormat);
+
+impl<S, N> FormatEvent<S, N> for FileLine
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let now = SystemTime::now();
+        let millis = now
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_millis();
+        let time = rfc3339(now).replace('Z', &format!(".{millis:03}Z"));
+        let level = level_name(*event.metadata().level());
+        let spans: Vec<_> = ctx
+            .event_scope()
+            .into_iter()
+            .flat_map(|s| s.from_root())
+            .map(|span| span_parts::<S, N>(&span))
+            .collect();
+        let mut message = String::new();
+        ctx.format_fields(Writer::new(&mut message), event)?;
+        match self.0 {
+            LogFormat::Text => {
+                write!(writer, "{time} {level}")?;
+                for span in spans {
+                    write!(writer, " [{}]", span_text(span))?;
+                }
+                writeln!(writer, " {message}")
+            }
+            LogFormat::Json => {
+                let spans: Vec<_> = spans
+                    .iter()
+                    .map(|(name, fields)| json!({ "name": name, "fields": fields }))
+                    .collect();
+                let line = json!({
+                    "time": time,
+                    "level": level,
+                    "target": event.metadata().target(),
+                    "spans": spans,
+                    "message": message,
+                });
+                writeln!(writer, "{line}")
+            }
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogFile {
+    type Writer = &'a LogFile;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self
+    }
+}
+
+/// each write is one whole line from the layer, rotated into place
+impl Write for &LogFile {
+    fn write(&mut self, line: &[u8]) -> io::Result<usize> {
+        let mut open = self.open.lock().unwrap_or_else(|e| e.into_inner());
+        let len = line.len() as u64;
+        if open.size > 0 && open.size + len > self.max_size && self.rotate(&mut open).is_err() {
+            return Ok(line.len());
+        }
+        if open.file.write_all(line).is_ok() {
+            open.size += len;
+        }
+        Ok(line.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
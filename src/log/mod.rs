@@ -0,0 +1,108 @@
+//! what the scanner is doing, for -q, -v and -vv. the library emits
+//! `tracing` events and spans: a `host` span (debug) around each host's
+//! scan, and an event per probe attempt (trace). nothing is written until
+//! a subscriber is set, and events no layer wants are never formatted, so
+//! embedders pay nothing for them unless they hook in their own. these are
+//! the layers the command line writes them out with.
+//!
+//! ```no_run
+//! use port_scanner::log::Console;
+//! use tracing_subscriber::prelude::*;
+//!
+//! tracing_subscriber::registry()
+//!     .with(Console.layer(std::io::stderr, tracing::Level::DEBUG))
+//!     .init();
+//! ```
+
+mod file;
+
+pub use file::{LogFile, LogFormat};
+
+use std::fmt;
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields, MakeWriter};
+use tracing_subscriber::registry::{LookupSpan, SpanRef};
+use tracing_subscriber::Layer;
+
+/// a level as lines show it
+pub fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::ERROR => "error",
+        Level::WARN => "warning",
+        Level::INFO => "info",
+        Level::DEBUG => "debug",
+        _ => "trace",
+    }
+}
+
+/// a span's name and its `key=value` fields, space separated, as the
+/// layer formatting them with `N` recorded them
+fn span_parts<S, N>(span: &SpanRef<'_, S>) -> (&'static str, String)
+where
+    S: for<'a> LookupSpan<'a>,
+    N: 'static,
+{
+    let fields = span
+        .extensions()
+        .get::<FormattedFields<N>>()
+        .map(|f| f.fields.clone())
+        .unwrap_or_default();
+    (span.name(), fields)
+}
+
+/// `host ip=10.0.0.5`
+fn span_text((name, fields): (&str, String)) -> String {
+    if fields.is_empty() {
+        name.to_string()
+    } else {
+        format!("{name} {fields}")
+    }
+}
+
+/// console lines: info as it is, the rest after its level, and debug and
+/// trace with the spans they happened in, e.g.
+/// `debug: [host ip=10.0.0.5] 2 open of 1000 ports`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Console;
+
+impl Console {
+    /// a layer writing events up to `max` to `writer` in this layout
+    pub fn layer<S, W>(self, writer: W, max: Level) -> impl Layer<S>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+    {
+        tracing_subscriber::fmt::layer()
+            .event_format(self)
+            .with_writer(writer)
+            .with_filter(LevelFilter::from_level(max))
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for Console
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let level = *event.metadata().level();
+        if level != Level::INFO {
+            write!(writer, "{}: ", level_name(level))?;
+        }
+        // tracing orders levels by verbosity: debug and trace
+        if level >= Level::DEBUG {
+            for span in ctx.event_scope().into_iter().flat_map(|s| s.from_root()) {
+                write!(writer, "[{}] ", span_text(span_parts::<S, N>(&span)))?;
+            }
+        }
+        ctx.format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
@@ -1,11 +1,42 @@
-use anyhow::{bail, Result};
-use clap::Parser;
-use std::io::{self, Write};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::builder::ValueParser;
+use clap::parser::ValueSource;
+use clap::{ArgGroup, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Read, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn, Level};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::prelude::*;
 
-use port_scanner::{resolve_target, scan_ip, PortRange};
+use port_scanner::{
+    desktop_notify, diff_scans, discover, exclude_hosts, expand_targets_with,
+    log::{Console, LogFile, LogFormat},
+    merged_report, parse_dns_server, parse_exclude_list, parse_target_list, port_histories,
+    probe_port_with, raw_sockets_available,
+    report::{
+        self, ChatReporter, ColorChoice, EmailReporter, HostReport, JsonlReporter, MetricsReporter,
+        MultiReporter, Palette, ReportOptions, Reporter, ScanInfo, ScanSummary, StoreReporter,
+        SyslogReporter, WatchReporter, WebhookReporter,
+    },
+    resolve_ptr_names, serve_metrics, services, socket_limit,
+    tui::Dashboard,
+    Backoff, BannerOptions, Change, ChatTarget, Config, Cron, DiscoverOptions, Engine, Facility,
+    GrpcServer, History, HostRecord, IpFamily, IpNet, Metrics, Policy, PortHistory, PortSpec,
+    PortState, Progress, ProgressSnapshot, Protocol, Proxy, Resolver, RetryPolicy, Rng, SavedHost,
+    SavedScan, ScanError, ScanHost, ScanOutcome, ScanResult, ScanType, Scanner, ScannerBuilder,
+    ServeOptions, Server, ServiceProbes, Services, Sighting, SmtpConfig, Stopped, SyslogTarget,
+    Target, Timing, Transport, Webhook, DEFAULT_CONCURRENCY, DEFAULT_DEAD_AFTER,
+    DEFAULT_MAX_BACKOFF, DEFAULT_MAX_RTT_TIMEOUT, DEFAULT_MIN_RTT_TIMEOUT,
+};
 
 /// a simple tcp port scanner (authorized targets only).
 #[derive(Parser, Debug)]
@@ -13,14 +44,68 @@ use port_scanner::{resolve_target, scan_ip, PortRange};
     name = "port_scanner",
     version = "1.0",
     author = "Mo Elbahwati",
-    about = "simple tcp port scanner (authorized targets only)"
+    about = "simple tcp and udp port scanner (authorized targets only)",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
 )]
+#[command(group(
+    ArgGroup::new("targets")
+        .required(true)
+        .multiple(true)
+        .args(["target", "extra_targets", "target_file", "resume"])
+))]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// ip, domain or cidr network (example: 192.168.1.0/24), repeatable
     #[arg(short = 'H', long, value_name = "HOST")]
-    target: String,
+    target: Vec<Target>,
+
+    /// more targets, same syntax as --target ("-" reads targets from stdin)
+    #[arg(value_name = "TARGET")]
+    extra_targets: Vec<Target>,
+
+    /// read targets from a file, one per line ("#" starts a comment)
+    #[arg(long, value_name = "FILE")]
+    target_file: Option<PathBuf>,
+
+    /// ips or cidr networks to never scan (comma separated, repeatable)
+    #[arg(long, value_name = "ADDRS", value_delimiter = ',', value_parser = IpNet::parse_ip_or_net)]
+    exclude: Vec<IpNet>,
+
+    /// read excluded ips/networks from a file, one per line
+    #[arg(long, value_name = "FILE")]
+    exclude_file: Option<PathBuf>,
 
     #[arg(short = 'p', long, default_value = "1-1000")]
-    ports: PortRange,
+    ports: PortSpec,
+
+    /// scan the N most common ports (tcp, or udp with --udp) instead of --ports
+    #[arg(long, value_name = "N", conflicts_with = "ports")]
+    top_ports: Option<usize>,
+
+    /// ports to leave out of the scan (same syntax as --ports)
+    #[arg(long, value_name = "PORTS")]
+    exclude_ports: Option<PortSpec>,
+
+    /// probe the ports in a random order (results are still listed sorted)
+    #[arg(long, default_value_t = false)]
+    randomize_ports: bool,
+
+    /// scan the hosts (e.g. of a subnet) in a random order, spreading the
+    /// load across the network over time
+    #[arg(long, default_value_t = false)]
+    randomize_hosts: bool,
+
+    /// probe the most commonly open ports of the list first, so results
+    /// come in early while the long tail is still being scanned
+    #[arg(long, default_value_t = false)]
+    common_first: bool,
+
+    /// seed for the random orders, so a scan can be repeated exactly
+    #[arg(long, value_name = "N")]
+    seed: Option<u64>,
 
     #[arg(short = 't', long, default_value_t = 50)]
     timeout_ms: u64,
@@ -28,186 +113,3380 @@ struct Args {
     #[arg(long, default_value_t = 0)]
     retries: u8,
 
+    /// wait this long before each retry instead of retrying at once
+    #[arg(long, value_name = "MS")]
+    retry_backoff_ms: Option<u64>,
+
+    /// double the backoff for every further retry (up to 10s)
+    #[arg(long, default_value_t = false, requires = "retry_backoff_ms")]
+    retry_exponential: bool,
+
+    /// wait a random half to all of each backoff, so retries spread out
+    #[arg(long, default_value_t = false, requires = "retry_backoff_ms")]
+    retry_jitter: bool,
+
+    /// retry ports that answered closed (refused) too; by default only
+    /// silence and errors are retried
+    #[arg(long, default_value_t = false)]
+    retry_refused: bool,
+
+    /// multiply the timeout by this for every retry (e.g. 2 doubles it)
+    #[arg(long, value_name = "FACTOR", default_value_t = 1.0)]
+    retry_timeout_growth: f64,
+
+    /// adapt each host's timeout to its measured round trips, never below
+    /// this (--timeout-ms is used until the first answer)
+    #[arg(long, value_name = "MS")]
+    min_rtt_timeout: Option<u64>,
+
+    /// adapt each host's timeout to its measured round trips, never above this
+    #[arg(long, value_name = "MS")]
+    max_rtt_timeout: Option<u64>,
+
+    /// scan udp instead of tcp: send a protocol request (dns, ntp, snmp, ...)
+    /// and wait for an answer; silence is reported as open|filtered
+    #[arg(long, default_value_t = false, conflicts_with_all = ["tls_probe", "http_probe", "version_detect"])]
+    udp: bool,
+
+    /// how tcp ports are probed: connect (full handshake), or syn (half-open),
+    /// fin, null, xmas or ack on a raw socket (root or CAP_NET_RAW; falls back
+    /// to connect without them). fin/null/xmas report silence as
+    /// open|filtered; ack maps firewall rules as unfiltered/filtered and
+    /// shows every port
+    #[arg(long, value_name = "TYPE", default_value = "connect")]
+    scan_type: ScanType,
+
     #[arg(long, default_value_t = false)]
     parallel: bool,
 
     #[arg(long)]
     threads: Option<usize>,
 
+    /// scanning engine: threads (blocking connects) or async (non-blocking)
+    #[arg(long, default_value = "threads")]
+    engine: Engine,
+
+    /// max in-flight connects for the async engine, or probes for raw scan types
+    #[arg(long, default_value_t = DEFAULT_CONCURRENCY)]
+    concurrency: usize,
+
+    /// halve the concurrency (threads or in-flight probes) while probes go
+    /// unanswered or fail, and ramp back up as answers return
+    #[arg(long, default_value_t = false)]
+    adaptive_concurrency: bool,
+
+    /// hosts scanned at the same time; the default scans them one after
+    /// another
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    parallel_hosts: u16,
+
+    /// the most probes in flight across all the hosts being scanned at
+    /// once (default: --concurrency, or --parallel-hosts when that is
+    /// higher)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    max_in_flight: Option<u64>,
+
+    /// the least concurrency --adaptive-concurrency backs off to
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        requires = "adaptive_concurrency"
+    )]
+    min_concurrency: usize,
+
+    /// timing template, 0-5 or paranoid, sneaky, polite, normal, aggressive,
+    /// insane: sets timeout, retries, concurrency and scan delay together
+    /// (any of those given explicitly wins)
+    #[arg(short = 'T', long, value_name = "TEMPLATE")]
+    timing: Option<Timing>,
+
+    /// a named bundle of ports, timing and probes: quick, web, db, full,
+    /// or a [profiles.NAME] of the config file (any option given
+    /// explicitly wins). defaults to the config's profile, if any
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// least time between two probes, across all hosts and workers
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    scan_delay_ms: u64,
+
+    /// start at most N probes a second, across all hosts and workers
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    max_rate: Option<u32>,
+
     #[arg(long, default_value_t = false)]
     show_closed: bool,
 
+    /// read what each open port sends first and include it in the output
+    #[arg(long, default_value_t = false)]
+    banner: bool,
+
+    /// max bytes to read for a banner
+    #[arg(long, value_name = "N", default_value_t = 256)]
+    banner_bytes: usize,
+
+    /// how long to wait for a banner after connecting
+    #[arg(long, value_name = "MS", default_value_t = 500)]
+    banner_timeout_ms: u64,
+
+    /// try a tls handshake on open ports and report version, cipher and certificate
+    #[arg(long, default_value_t = false)]
+    tls_probe: bool,
+
+    /// how long a tls handshake may take
+    #[arg(long, value_name = "MS", default_value_t = 1000)]
+    tls_timeout_ms: u64,
+
+    /// send GET / to open ports and report status, server header and title
+    #[arg(long, default_value_t = false)]
+    http_probe: bool,
+
+    /// how long an http request may take
+    #[arg(long, value_name = "MS", default_value_t = 2000)]
+    http_timeout_ms: u64,
+
+    /// probe open ports to find out which service and version is listening
+    #[arg(long, default_value_t = false)]
+    version_detect: bool,
+
+    /// how long each version probe waits for a reply
+    #[arg(long, value_name = "MS", default_value_t = 1000)]
+    version_timeout_ms: u64,
+
+    /// extra probe and match rules (nmap-service-probes format), tried
+    /// before the built-in ones
+    #[arg(long, value_name = "FILE", requires = "version_detect")]
+    service_probes: Option<PathBuf>,
+
+    /// service names (/etc/services or nmap-services format, or IANA's
+    /// service-names-port-numbers.csv), used over the built-in excerpt
+    /// for reports and for names in port lists, the subcommands' too
+    #[arg(long, value_name = "FILE")]
+    services_file: Option<PathBuf>,
+
+    /// output format: table, json, jsonl, csv, nmap-xml, grepable, html, md, sarif,
+    /// cef, leef
+    /// (default: table, or guessed from the --output extension)
+    #[arg(long)]
+    format: Option<String>,
+
+    /// write the report to FILE; stdout keeps the table
+    #[arg(short = 'o', long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// save every answer to FILE as the scan goes, so a scan stopped by
+    /// ctrl-c or a crash can be picked up with --resume (the file is
+    /// removed once the scan completes)
+    #[arg(long, value_name = "FILE", conflicts_with = "watch")]
+    checkpoint: Option<PathBuf>,
+
+    /// add the scan (settings, hosts and reported ports) to a sqlite
+    /// database, made if missing, to keep a history; needs a build with
+    /// the sqlite feature
+    #[arg(long, value_name = "FILE")]
+    store: Option<PathBuf>,
+
+    /// carry on with the scan saved in a --checkpoint file, with the
+    /// options it was started with; hosts already done aren't scanned
+    /// again, nor are ports that already answered
+    #[arg(long, value_name = "FILE", exclusive = true)]
+    resume: Option<PathBuf>,
+
+    /// print each open port to stdout the moment it is found, ahead of
+    /// the report
+    #[arg(long, default_value_t = false)]
+    open_as_found: bool,
+
     #[arg(long, default_value_t = false)]
     all_ips: bool,
 
+    #[command(flatten)]
+    email: EmailArgs,
+
+    #[command(flatten)]
+    dns: DnsArgs,
+
+    /// connect through this proxy (http://[user:pass@]host:port or
+    /// socks5://[user:pass@]host[:port]), tunnelling every probe. repeat to
+    /// chain proxies, in order. tcp connect scans only; target names are
+    /// still resolved here, and hosts aren't pinged.
+    #[arg(long, value_name = "URL")]
+    proxy: Vec<Proxy>,
+
+    /// connect through the local tor client (127.0.0.1:9050), each target
+    /// host over its own circuit. names are still resolved here.
+    #[arg(long, default_value_t = false, conflicts_with = "proxy")]
+    tor: bool,
+
+    /// send probes from this local address, on hosts with several
+    #[arg(long, value_name = "IP")]
+    source_ip: Option<IpAddr>,
+
+    /// send probes from this local port (for firewall rules that trust,
+    /// say, 53), or from these ports (same syntax as --ports), moving on
+    /// to the next when probes to one target port would collide. below
+    /// 1024 needs root
+    #[arg(long, value_name = "PORTS")]
+    source_port: Option<PortSpec>,
+
+    /// send probes through this network interface (SO_BINDTODEVICE on
+    /// linux, needing root; elsewhere from the interface's address)
+    #[arg(long, value_name = "NAME")]
+    interface: Option<String>,
+
+    /// how long reaching each proxy of the chain may take
+    #[arg(long, value_name = "MS", default_value_t = 3000)]
+    proxy_timeout_ms: u64,
+
+    /// scan every host, even the ones that don't answer the ping
+    /// (icmp echo when raw sockets are available, else tcp connects to
+    /// 80,443,22,3389) that normally runs first
+    #[arg(long, default_value_t = false)]
+    no_ping: bool,
+
+    /// probe every port of a host even when its first --dead-after probes
+    /// all time out (by default the rest is skipped and reported filtered)
+    #[arg(long, default_value_t = false)]
+    no_skip_dead: bool,
+
+    /// stop scanning a host after this long (e.g. 90s, 15m, 500ms; a bare
+    /// number is seconds) and report the ports it didn't get to as unknown
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    host_timeout: Option<Duration>,
+
+    /// stop the whole scan after this long (same format as --host-timeout),
+    /// write out what was found so far and exit with status 3
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    max_scan_time: Option<Duration>,
+
+    /// scan again every DURATION (e.g. 300s, 1h) until ctrl-c, printing
+    /// only the ports that opened or closed since the scan before, with
+    /// timestamps
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        conflicts_with_all = ["format", "output", "open_as_found"]
+    )]
+    watch: Option<Duration>,
+
+    /// with --watch, serve prometheus metrics on http://ADDR/metrics
+    #[arg(long, value_name = "ADDR", requires = "watch")]
+    metrics: Option<SocketAddr>,
+
+    /// post the findings as json to URL when the scan finishes; with
+    /// --watch, post each port as soon as it opens. repeat for more
+    /// endpoints
+    #[arg(long, value_name = "URL")]
+    webhook: Vec<Webhook>,
+
+    /// sign webhook posts with this secret: the X-Pscan-Signature-256
+    /// header carries sha256= and the hex hmac-sha256 of the body
+    #[arg(long, value_name = "SECRET", requires = "webhook")]
+    webhook_secret: Option<String>,
+
+    /// times to try a webhook post again when it fails, waiting 1s, 2s,
+    /// 4s... in between
+    #[arg(long, value_name = "N", default_value_t = 3, requires = "webhook")]
+    webhook_retries: u8,
+
+    /// also send every finding to syslog (rfc 5424) at udp://HOST[:514],
+    /// tcp://HOST[:514] or a unix socket like /dev/log
+    #[arg(long, value_name = "TARGET")]
+    syslog: Option<SyslogTarget>,
+
+    /// the facility syslog messages are filed under, e.g. daemon or local0
+    #[arg(long, value_name = "NAME", default_value = "user", requires = "syslog")]
+    syslog_facility: Facility,
+
+    /// post a short summary to a slack, discord or teams channel:
+    /// slack:URL, discord:URL or teams:URL (the prefix may be left off for
+    /// their own webhook hosts). repeat for more channels. with --watch,
+    /// only rounds that changed something are posted.
+    #[arg(long, value_name = "TARGET")]
+    chat: Vec<ChatTarget>,
+
+    /// a saved json or jsonl scan to compare with: --chat lists what
+    /// changed since it rather than every open port, and --fail-on change
+    /// fails when anything did
+    #[arg(long, value_name = "FILE")]
+    baseline: Option<PathBuf>,
+
+    /// exit with status 2 when any port is open (open), or when any port
+    /// opened, closed or changed service since --baseline (change); either
+    /// way a scan cut short by ctrl-c or --host-timeout exits with 3
+    #[arg(
+        long,
+        value_name = "WHEN",
+        value_enum,
+        default_value_t = FailOn::None,
+        conflicts_with = "watch"
+    )]
+    fail_on: FailOn,
+
+    /// pop up a desktop notification with the open port count when the
+    /// scan is done, for scans left running in the background
+    #[arg(long, default_value_t = false, conflicts_with = "watch")]
+    notify: bool,
+
+    /// stop scanning a host once this many of its ports are found open;
+    /// the ports not probed by then are reported unknown
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    max_open: Option<u64>,
+
+    /// stop scanning a host at its first open port (--max-open 1), to
+    /// check whether anything is listening at all
+    #[arg(long, default_value_t = false, conflicts_with = "max_open")]
+    first_open: bool,
+
+    /// tcp probes that may go unanswered, without a single reset or
+    /// accept, before a host is taken as down or firewalled and skipped
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_DEAD_AFTER)]
+    dead_after: usize,
+
+    /// how long the ping waits for an answer
+    #[arg(long, value_name = "MS", default_value_t = 1000)]
+    ping_timeout_ms: u64,
+
+    /// look up the reverse dns (ptr) name of every host scanned
+    #[arg(long, default_value_t = false)]
+    resolve_ptr: bool,
+
     #[arg(long, default_value_t = true)]
     progress: bool,
+
+    /// how progress goes to stderr: text (bars on a terminal, a plain
+    /// line every few seconds otherwise) or json, one object a second
+    /// for wrappers and ci logs to parse. json progress stays on with -q
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value = "text",
+        value_parser = ["text", "json"],
+        conflicts_with = "tui"
+    )]
+    progress_format: String,
+
+    /// a live view of the scan on the terminal instead of the progress
+    /// line: each host's progress, the open ports so far and probes a
+    /// second. p pauses, s skips the selected host, q quits; the report
+    /// follows once it closes
+    #[arg(long, default_value_t = false, conflicts_with_all = ["watch", "open_as_found"])]
+    tui: bool,
+
+    /// say more on stderr: -v for what happened to each host, -vv for
+    /// every probe attempt and the error it got
+    #[arg(short = 'v', long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// say nothing on stderr but warnings and errors: no progress bars and
+    /// no per-host notes, just the report
+    #[arg(short = 'q', long, default_value_t = false, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// color port states in the table, --watch, --open-as-found and diff:
+    /// auto (on a terminal, unless NO_COLOR is set), always or never
+    #[arg(long, value_name = "WHEN", default_value = "auto", global = true)]
+    color: ColorChoice,
+
+    /// also log everything, down to each probe attempt, to FILE, whatever
+    /// -q or -v show on the console
+    #[arg(long, value_name = "FILE")]
+    log_file: Option<PathBuf>,
+
+    /// the log file's lines: text, or json with one object per line
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value = "text",
+        requires = "log_file"
+    )]
+    log_format: LogFormat,
+
+    /// start a new log file once it would grow past this (e.g. 512k, 10M,
+    /// 1G; a bare number is bytes), moving the old one to FILE.1
+    #[arg(long, value_name = "SIZE", default_value = "10M", value_parser = parse_size, requires = "log_file")]
+    log_max_size: u64,
+
+    /// how many old log files (FILE.1, FILE.2, ...) to keep
+    #[arg(long, value_name = "N", default_value_t = 5, requires = "log_file")]
+    log_keep: usize,
+
+    /// read defaults (timeout, threads, format, webhooks, authorized
+    /// targets) from this toml file instead of /etc/pscan/config.toml and
+    /// ~/.config/pscan/config.toml (also $PSCAN_CONFIG); the PSCAN_*
+    /// variables win over it, and options given here over both
+    #[arg(long, value_name = "FILE", conflicts_with = "no_config")]
+    config: Option<PathBuf>,
+
+    /// leave the config files unread (the PSCAN_* variables still count)
+    #[arg(long, default_value_t = false)]
+    no_config: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// find which hosts are up, without scanning their ports
+    Discover(DiscoverArgs),
+    /// block until ports are reachable, for ci and container startup scripts
+    Wait(WaitArgs),
+    /// compare two saved scans (json or jsonl reports): ports that opened,
+    /// closed or changed service. exits 1 when anything changed
+    Diff(DiffArgs),
+    /// check hosts against a policy of expected open ports. exits 1 on
+    /// any violation
+    Audit(AuditArgs),
+    /// look through the scan history kept with --store: when ports were
+    /// first and last seen open on each host
+    Query(QueryArgs),
+    /// fold saved scans (json or jsonl reports) into one json report, e.g.
+    /// the shards of a scan run from several machines
+    Merge(MergeArgs),
+    /// scan on a cron schedule in the foreground, logging what drifted
+    /// since the last scan and from a policy
+    Schedule(ScheduleArgs),
+    /// run an http api that takes scan jobs, reports their progress and
+    /// hands back their results as json
+    Serve(ServeArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct DiscoverArgs {
+    /// ip, domain or cidr network, repeatable ("-" reads targets from stdin)
+    #[arg(value_name = "TARGET", required_unless_present = "target_file")]
+    targets: Vec<Target>,
+
+    /// read targets from a file, one per line ("#" starts a comment)
+    #[arg(long, value_name = "FILE")]
+    target_file: Option<PathBuf>,
+
+    /// ips or cidr networks to leave out (comma separated, repeatable)
+    #[arg(long, value_name = "ADDRS", value_delimiter = ',', value_parser = IpNet::parse_ip_or_net)]
+    exclude: Vec<IpNet>,
+
+    /// read excluded ips/networks from a file, one per line
+    #[arg(long, value_name = "FILE")]
+    exclude_file: Option<PathBuf>,
+
+    /// tcp ports to ping; an accept or a reset means the host is up
+    #[arg(short = 'p', long, default_value = "80,443,22,3389")]
+    ports: PortSpec,
+
+    /// how long to wait for an answer
+    #[arg(short = 't', long, default_value_t = 500)]
+    timeout_ms: u64,
+
+    /// hosts pinged over tcp at once
+    #[arg(long, default_value_t = 64)]
+    concurrency: usize,
+
+    /// skip the icmp echo sweep that runs first when raw sockets are available
+    #[arg(long, default_value_t = false)]
+    no_icmp: bool,
+
+    /// arp for hosts on directly connected ethernet subnets first and report
+    /// their mac addresses; far more reliable than pings on a lan
+    /// (linux, root or CAP_NET_RAW)
+    #[arg(long, default_value_t = false)]
+    arp: bool,
+
+    /// print only the ips of hosts that are up, one per line
+    /// (pipe them into a scan with "-")
+    #[arg(long, default_value_t = false)]
+    hosts_only: bool,
+
+    #[arg(long, default_value_t = false)]
+    all_ips: bool,
+
+    #[command(flatten)]
+    dns: DnsArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct WaitArgs {
+    /// host to wait for (ip or domain), repeatable; every one must come up
+    #[arg(short = 'H', long = "host", value_name = "HOST", required = true)]
+    hosts: Vec<Target>,
+
+    /// tcp ports that must accept a connection
+    #[arg(short = 'p', long)]
+    ports: PortSpec,
+
+    /// give up after this long (e.g. 120s, 5m)
+    #[arg(long, default_value = "60s", value_parser = parse_duration)]
+    timeout: Duration,
+
+    /// wait between attempts
+    #[arg(long, default_value = "1s", value_parser = parse_duration)]
+    interval: Duration,
+
+    /// how long each connect may take
+    #[arg(long, default_value = "1s", value_parser = parse_duration)]
+    connect_timeout: Duration,
+
+    /// print nothing; the exit status says whether the ports came up
+    #[arg(short = 'q', long, default_value_t = false)]
+    quiet: bool,
+
+    #[command(flatten)]
+    dns: DnsArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct DiffArgs {
+    /// the earlier scan
+    old: PathBuf,
+
+    /// the later scan
+    new: PathBuf,
+
+    /// table for people, json for scripts
+    #[arg(long, default_value = "table", value_parser = ["table", "json"])]
+    format: String,
+}
+
+#[derive(clap::Args, Debug)]
+struct AuditArgs {
+    /// the policy: expected open ports per host (toml)
+    #[arg(long, value_name = "FILE")]
+    policy: PathBuf,
+
+    /// hosts to audit (ip, domain or cidr network); the ones the policy
+    /// names when left out
+    #[arg(value_name = "TARGET")]
+    targets: Vec<Target>,
+
+    /// check a report saved with --format json or jsonl instead of scanning
+    #[arg(long, value_name = "FILE", conflicts_with = "targets")]
+    scan: Option<PathBuf>,
+
+    /// ports to look for unexpected services on, besides the ones the policy
+    /// names (default: the top 1000)
+    #[arg(short = 'p', long)]
+    ports: Option<PortSpec>,
+
+    /// how long to wait for each connect
+    #[arg(short = 't', long, default_value_t = 1000)]
+    timeout_ms: u64,
+
+    /// probes in flight at once
+    #[arg(long, default_value_t = DEFAULT_CONCURRENCY)]
+    concurrency: usize,
+
+    #[command(flatten)]
+    dns: DnsArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct QueryArgs {
+    /// the database scans were stored in with --store
+    #[arg(long, value_name = "FILE")]
+    db: PathBuf,
+
+    /// only this host (ip, name or cidr network), repeatable
+    #[arg(short = 'H', long = "host", value_name = "HOST")]
+    hosts: Vec<Target>,
+
+    /// only these ports
+    #[arg(short = 'p', long)]
+    ports: Option<PortSpec>,
+
+    /// only scans from this recently, e.g. 30d or 12h
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    since: Option<Duration>,
+
+    /// list every scan of each port instead of a summary
+    #[arg(long)]
+    history: bool,
+
+    /// table for people, json for scripts (always with the full history)
+    #[arg(long, default_value = "table", value_parser = ["table", "json"])]
+    format: String,
+}
+
+#[derive(clap::Args, Debug)]
+struct MergeArgs {
+    /// the scans to merge; when they disagree about a port, the one that
+    /// got to the host last wins
+    #[arg(value_name = "FILE", required = true)]
+    files: Vec<PathBuf>,
+
+    /// write the merged report here instead of to stdout
+    #[arg(short = 'o', long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct ScheduleArgs {
+    /// when to scan: a crontab line in local time ("0 2 * * *" is 02:00
+    /// every day) or @hourly, @daily, @weekly, @monthly
+    #[arg(value_name = "CRON")]
+    cron: Cron,
+
+    /// hosts to scan (ip, domain or cidr network); the ones the policy
+    /// names when left out
+    #[arg(value_name = "TARGET", required_unless_present = "policy")]
+    targets: Vec<Target>,
+
+    /// also check every scan against this policy of expected open ports
+    #[arg(long, value_name = "FILE")]
+    policy: Option<PathBuf>,
+
+    /// ports to scan, besides the ones the policy names (default: the top
+    /// 1000)
+    #[arg(short = 'p', long)]
+    ports: Option<PortSpec>,
+
+    /// how long to wait for each connect
+    #[arg(short = 't', long, default_value_t = 1000)]
+    timeout_ms: u64,
+
+    /// probes in flight at once
+    #[arg(long, default_value_t = DEFAULT_CONCURRENCY)]
+    concurrency: usize,
+
+    /// run this shell command when a scan finds drift, with the drift lines
+    /// on its stdin
+    #[arg(long, value_name = "CMD")]
+    on_drift: Option<String>,
+
+    /// serve prometheus metrics on http://ADDR/metrics
+    #[arg(long, value_name = "ADDR")]
+    metrics: Option<SocketAddr>,
+
+    #[command(flatten)]
+    email: EmailArgs,
+
+    #[command(flatten)]
+    dns: DnsArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct ServeArgs {
+    /// address to listen on; anyone who can reach it can start scans, so
+    /// keep it local or set --token
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+    listen: SocketAddr,
+
+    /// require `Authorization: Bearer TOKEN` on every request
+    #[arg(long, value_name = "TOKEN")]
+    token: Option<String>,
+
+    /// scans running at once; more wait in a queue
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    max_jobs: u16,
+
+    /// scans waiting or running at once; more are refused (503) until
+    /// some finish
+    #[arg(long, value_name = "N", default_value_t = 100, value_parser = clap::value_parser!(u16).range(1..))]
+    max_pending: u16,
+
+    /// also serve the grpc api (proto/pscan.proto) on this address; needs
+    /// a build with --features grpc
+    #[arg(long, value_name = "ADDR")]
+    grpc: Option<SocketAddr>,
+
+    #[command(flatten)]
+    dns: DnsArgs,
+}
+
+/// how target names are resolved (the system resolver unless one is given)
+#[derive(clap::Args, Debug)]
+struct DnsArgs {
+    /// resolve target names with this dns server (ip, optionally :port)
+    /// instead of the system resolver
+    #[arg(long, value_name = "ADDR", value_parser = parse_dns_server, conflicts_with_all = ["dot", "doh"])]
+    dns_server: Option<SocketAddr>,
+
+    /// resolve target names over tls with this server (host[:port], port 853
+    /// by default)
+    #[arg(long, value_name = "HOST", conflicts_with = "doh")]
+    dot: Option<String>,
+
+    /// resolve target names over https with this endpoint
+    /// (https://host[:port][/path])
+    #[arg(long, value_name = "URL")]
+    doh: Option<String>,
+
+    /// only use ipv4: names resolve to their a records, ipv6 targets are refused
+    #[arg(short = '4', long = "ipv4", conflicts_with = "ipv6")]
+    ipv4: bool,
+
+    /// only use ipv6: names resolve to their aaaa records, ipv4 targets are refused
+    #[arg(short = '6', long = "ipv6")]
+    ipv6: bool,
 }
 
-/// small "service hint" list for common ports
-fn service_hint(port: u16) -> &'static str {
-    match port {
-        20 | 21 => "ftp",
-        22 => "ssh",
-        23 => "telnet",
-        25 => "smtp",
-        53 => "dns",
-        80 => "http",
-        110 => "pop3",
-        135 => "msrpc",
-        139 => "netbios",
-        143 => "imap",
-        443 => "https",
-        445 => "smb",
-        3306 => "mysql",
-        3389 => "rdp",
-        5432 => "postgres",
-        6379 => "redis",
-        8000 => "http-alt",
-        8080 => "http-alt",
-        8443 => "https-alt",
-        _ => "",
+impl DnsArgs {
+    fn resolver(&self) -> Result<Resolver> {
+        let resolver = match (&self.dns_server, &self.dot, &self.doh) {
+            (Some(server), _, _) => Resolver::with_server(*server),
+            (_, Some(server), _) => Resolver::over_tls(server)?,
+            (_, _, Some(url)) => Resolver::over_https(url)?,
+            _ => Resolver::system(),
+        };
+        let family = match (self.ipv4, self.ipv6) {
+            (true, _) => IpFamily::V4,
+            (_, true) => IpFamily::V6,
+            _ => IpFamily::Any,
+        };
+        Ok(resolver.family(family))
     }
 }
 
-fn print_results(ip: std::net::IpAddr, results: &[port_scanner::ScanResult], show_closed: bool) {
-    println!();
-    println!("target ip: {ip}");
-    println!("{:<8}  {:<6}  {}", "port", "state", "hint");
-    println!("{:-<8}  {:-<6}  {:-<8}", "", "", "");
+/// mailing the report when something changed, for --watch and schedule
+#[derive(clap::Args, Debug)]
+struct EmailArgs {
+    /// mail the report to ADDR when ports open or close between scans
+    /// (with --watch or schedule); repeat for more recipients
+    #[arg(long, value_name = "ADDR", requires = "smtp_config")]
+    email_to: Vec<String>,
+
+    /// toml file with the mail server in an [smtp] table: host, port,
+    /// tls (starttls, implicit or none), from, username and password
+    #[arg(long, value_name = "FILE")]
+    smtp_config: Option<PathBuf>,
 
-    let mut open_count = 0;
+    /// the mailed report's format: html or md
+    #[arg(long, value_name = "FORMAT", default_value = "html", value_parser = ["html", "md"])]
+    email_format: String,
+}
 
-    for r in results {
-        let state = if r.open { "open" } else { "closed" };
-        if r.open {
-            open_count += 1;
+impl EmailArgs {
+    /// the reporter that mails, when --email-to was given
+    fn reporter(&self, opts: &ReportOptions) -> Result<Option<EmailReporter>> {
+        if self.email_to.is_empty() {
+            return Ok(None);
         }
+        let Some(path) = &self.smtp_config else {
+            bail!("--email-to needs --smtp-config");
+        };
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let smtp = SmtpConfig::parse(&text).map_err(|e| anyhow!("{}: {e}", path.display()))?;
+        let reporter = EmailReporter::new(
+            smtp,
+            self.email_to.clone(),
+            &self.email_format,
+            opts,
+            Box::new(io::stderr()),
+        )
+        .map_err(|e| anyhow!(e))?;
+        Ok(Some(reporter))
+    }
+}
+
+/// --notify: say how the scan went on the desktop; not being able to is
+/// only worth a warning
+fn notify_done(summary: &ScanSummary, expired: bool) {
+    let title = if expired {
+        "pscan: scan stopped at --max-scan-time"
+    } else if summary.cancelled {
+        "pscan: scan cancelled"
+    } else {
+        "pscan: scan complete"
+    };
+    let secs = summary.elapsed.as_secs();
+    let took = match secs {
+        0..=59 => format!("{:.1}s", summary.elapsed.as_secs_f64()),
+        60..=3599 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    };
+    let body = format!(
+        "{} open port{} on {} of {} hosts, in {took}",
+        summary.open_ports,
+        if summary.open_ports == 1 { "" } else { "s" },
+        summary.hosts_with_open,
+        summary.hosts_scanned
+    );
+    if let Err(e) = desktop_notify(title, &body) {
+        warn!("can't show a notification: {e}");
+    }
+}
 
-        if show_closed || r.open {
-            println!("{:<8}  {:<6}  {}", r.port, state, service_hint(r.port));
+/// `500ms`, `90s`, `15m`, `2h` or `30d`; a bare number is seconds
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let n: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{s}' (expected e.g. 500ms, 90s, 15m)"))?;
+    let duration = match unit {
+        "ms" => Duration::from_millis(n),
+        "" | "s" => Duration::from_secs(n),
+        "m" => Duration::from_secs(n * 60),
+        "h" => Duration::from_secs(n * 3600),
+        "d" => Duration::from_secs(n * 86_400),
+        _ => {
+            return Err(format!(
+                "unknown unit '{unit}' in '{s}' (use ms, s, m, h or d)"
+            ))
         }
+    };
+    if duration.is_zero() {
+        return Err("duration must be more than 0".to_string());
+    }
+    Ok(duration)
+}
+
+/// `512k`, `10M` or `1G`, in powers of 1024; a bare number is bytes
+fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let n: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{s}' (expected e.g. 512k, 10M, 1G)"))?;
+    let shift = match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => 0,
+        "k" | "kb" => 10,
+        "m" | "mb" => 20,
+        "g" | "gb" => 30,
+        _ => return Err(format!("unknown unit '{unit}' in '{s}' (use k, M or G)")),
+    };
+    match n.checked_mul(1 << shift) {
+        Some(0) => Err("size must be more than 0".to_string()),
+        Some(size) => Ok(size),
+        None => Err(format!("size '{s}' is too big")),
     }
+}
 
-    println!();
-    println!("open ports found: {open_count}");
+/// lock a mutex, ignoring poisoning (a panicked worker already aborts the scan)
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
 }
 
-fn start_progress_line(
+/// one progress bar: `done` of `total` ports, at `rate` a second
+struct Bar {
+    label: String,
+    done: usize,
     total: usize,
-    scanned: Arc<AtomicUsize>,
+    rate: Option<f64>,
+    eta: Option<Duration>,
+}
+
+impl Bar {
+    /// `823 ports/s  eta 0:01`
+    fn speed(&self) -> String {
+        let rate = self.rate.map_or("--".to_string(), |r| format!("{r:.0}"));
+        let eta = self.eta.map_or("--".to_string(), clock);
+        format!("{rate} ports/s  eta {eta}")
+    }
+
+    /// `label 412/1000  41.2%  823 ports/s  eta 0:01`
+    fn line(&self) -> String {
+        let pct = if self.total == 0 {
+            100.0
+        } else {
+            (self.done as f64 / self.total as f64) * 100.0
+        };
+        format!(
+            "{} {}/{}  {pct:.1}%  {}",
+            self.label,
+            self.done,
+            self.total,
+            self.speed()
+        )
+    }
+
+    /// move `bar` to where this one is
+    fn show(&self, bar: &ProgressBar) {
+        bar.set_length(self.total as u64);
+        bar.set_position(self.done as u64);
+        bar.set_message(self.speed());
+    }
+}
+
+/// `m:ss`, or `h:mm:ss` past the hour, rounding up
+fn clock(d: Duration) -> String {
+    let secs = d.as_secs_f64().ceil() as u64;
+    match secs / 3600 {
+        0 => format!("{}:{:02}", secs / 60, secs % 60),
+        h => format!("{h}:{:02}:{:02}", secs / 60 % 60, secs % 60),
+    }
+}
+
+/// the progress bars on stderr. whatever else goes to the terminal while
+/// they're up is written inside `suspend`, which clears them first.
+fn bars() -> &'static MultiProgress {
+    static BARS: OnceLock<MultiProgress> = OnceLock::new();
+    BARS.get_or_init(|| MultiProgress::with_draw_target(ProgressDrawTarget::stderr()))
+}
+
+/// stderr under the progress bars, for log lines
+#[derive(Clone, Copy)]
+struct UnderBars;
+
+impl Write for UnderBars {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        bars().suspend(|| io::stderr().write(buf))
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        bars().suspend(|| io::stderr().write_all(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+impl MakeWriter<'_> for UnderBars {
+    type Writer = UnderBars;
+
+    fn make_writer(&self) -> UnderBars {
+        *self
+    }
+}
+
+/// how often progress is written when stderr isn't a terminal
+const PLAIN_PROGRESS_EVERY: Duration = Duration::from_secs(5);
+
+/// draw `progress` on stderr until `done` or `cancelled`: a bar for each
+/// host being scanned when `per_host`, and one for the whole run when
+/// `overall`. on a terminal they're indicatif bars, cleared at the end;
+/// otherwise a plain line goes out every few seconds, for logs.
+fn start_progress(
+    progress: Arc<Progress>,
+    per_host: bool,
+    overall: bool,
     done: Arc<AtomicBool>,
     cancelled: Arc<AtomicBool>,
-    started: Instant,
 ) -> std::thread::JoinHandle<()> {
     std::thread::spawn(move || {
+        let tty = io::stderr().is_terminal();
+        let style =
+            ProgressStyle::with_template("{prefix:<15} [{bar:24}] {pos}/{len}  {percent}%  {msg}")
+                .expect("progress template")
+                .progress_chars("#>.");
+        let new_bar = |label: &str| {
+            ProgressBar::no_length()
+                .with_style(style.clone())
+                .with_prefix(label.to_string())
+        };
+        // the run's bar stays last, the hosts' come and go above it
+        let whole = (tty && overall).then(|| bars().add(new_bar("overall")));
+        let mut host_bars: HashMap<IpAddr, ProgressBar> = HashMap::new();
+        let mut written = Instant::now();
         while !done.load(Ordering::Relaxed) && !cancelled.load(Ordering::Relaxed) {
-            let n = scanned.load(Ordering::Relaxed);
-            let pct = if total == 0 {
-                100.0
-            } else {
-                (n as f64 / total as f64) * 100.0
+            let now = progress.snapshot();
+            let hosts: Vec<(IpAddr, Bar)> = match per_host {
+                true => now
+                    .hosts
+                    .iter()
+                    .map(|h| {
+                        let bar = Bar {
+                            label: h.ip.to_string(),
+                            done: h.probes_done,
+                            total: h.probes_total,
+                            rate: h.rate,
+                            eta: h.eta,
+                        };
+                        (h.ip, bar)
+                    })
+                    .collect(),
+                false => Vec::new(),
             };
-
-            eprint!(
-                "\rscanning... {n}/{total} ({pct:.1}%) elapsed: {:?}",
-                started.elapsed()
-            );
-            let _ = io::stderr().flush();
+            let run = overall.then(|| Bar {
+                label: "overall".to_string(),
+                done: now.probes_done,
+                total: now.probes_total,
+                rate: now.rate,
+                eta: now.eta,
+            });
+            if tty {
+                host_bars.retain(|ip, bar| {
+                    let scanning = hosts.iter().any(|(h, _)| h == ip);
+                    if !scanning {
+                        bar.finish_and_clear();
+                        bars().remove(bar);
+                    }
+                    scanning
+                });
+                for (ip, host) in &hosts {
+                    let bar = host_bars.entry(*ip).or_insert_with(|| match &whole {
+                        Some(whole) => bars().insert_before(whole, new_bar(&host.label)),
+                        None => bars().add(new_bar(&host.label)),
+                    });
+                    host.show(bar);
+                }
+                if let (Some(bar), Some(run)) = (&whole, &run) {
+                    run.show(bar);
+                }
+            } else if written.elapsed() >= PLAIN_PROGRESS_EVERY
+                && (!hosts.is_empty() || run.is_some())
+            {
+                let lines: Vec<String> = hosts
+                    .iter()
+                    .map(|(_, b)| b)
+                    .chain(&run)
+                    .map(Bar::line)
+                    .collect();
+                eprintln!("progress: {}", lines.join("; "));
+                written = Instant::now();
+            }
             std::thread::sleep(Duration::from_millis(200));
         }
-        eprintln!();
+        for bar in host_bars.values().chain(&whole) {
+            bar.finish_and_clear();
+            bars().remove(bar);
+        }
     })
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+/// how often --progress-format json writes a record
+const JSON_PROGRESS_EVERY: Duration = Duration::from_secs(1);
 
-    if args.timeout_ms == 0 {
-        bail!("timeout must be at least 1 ms");
-    }
-    let timeout = Duration::from_millis(args.timeout_ms);
+/// write `progress` to stderr as a json line every second until `done` or
+/// `cancelled`, and once more at the end:
+/// `{"type":"progress","time":"...","scanned":412,"total":1000,...}`
+fn start_json_progress(
+    progress: Arc<Progress>,
+    done: Arc<AtomicBool>,
+    cancelled: Arc<AtomicBool>,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut written: Option<Instant> = None;
+        loop {
+            let over = done.load(Ordering::Relaxed) || cancelled.load(Ordering::Relaxed);
+            if over || written.is_none_or(|at| at.elapsed() >= JSON_PROGRESS_EVERY) {
+                let now = progress.snapshot();
+                let current: Vec<_> = now.hosts.iter().map(|h| h.ip).collect();
+                let record = json!({
+                    "type": "progress",
+                    "time": report::util::rfc3339(SystemTime::now()),
+                    "elapsed_ms": now.elapsed.as_millis() as u64,
+                    "scanned": now.probes_done,
+                    "total": now.probes_total,
+                    "percent": (now.fraction() * 1000.0).round() / 10.0,
+                    "rate": now.rate.map(|r| (r * 10.0).round() / 10.0),
+                    "eta_ms": now.eta.map(|e| e.as_millis() as u64),
+                    "hosts_done": now.hosts_done,
+                    "hosts_total": now.hosts_total,
+                    "open_ports": now.open_ports,
+                    "current": current
+                });
+                eprintln!("{record}");
+                written = Some(Instant::now());
+            }
+            if over {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    })
+}
 
-    let default_threads = std::thread::available_parallelism()
-        .map(|n| n.get())
-        .unwrap_or(4);
-    let threads = args.threads.unwrap_or(default_threads).max(1);
+/// gather targets from --target, positional args, --target-file and stdin ("-")
+fn collect_targets<'a>(
+    given: impl IntoIterator<Item = &'a Target>,
+    target_file: Option<&Path>,
+) -> Result<Vec<Target>> {
+    let mut targets = Vec::new();
+    let mut read_stdin = false;
 
-    let ips = resolve_target(&args.target)?;
-    let ips_to_scan = if args.all_ips { ips } else { vec![ips[0]] };
+    for target in given {
+        match target {
+            Target::Host(h) if h == "-" => read_stdin = true,
+            _ => targets.push(target.clone()),
+        }
+    }
 
-    let ports = args.ports.to_vec();
+    if let Some(path) = target_file {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read target file '{}'", path.display()))?;
+        let list = parse_target_list(&text).map_err(|e| anyhow!("{}: {e}", path.display()))?;
+        targets.extend(list);
+    }
 
-    let cancelled = Arc::new(AtomicBool::new(false));
-    {
-        let cancelled = cancelled.clone();
-        ctrlc::set_handler(move || {
-            cancelled.store(true, Ordering::SeqCst);
-        })?;
+    if read_stdin {
+        let mut text = String::new();
+        io::stdin()
+            .read_to_string(&mut text)
+            .context("failed to read targets from stdin")?;
+        let list = parse_target_list(&text).map_err(|e| anyhow!("stdin: {e}"))?;
+        targets.extend(list);
     }
 
-    println!("pscan");
-    println!("  target      : {}", args.target);
-    println!("  ips scanned  : {}", ips_to_scan.len());
-    println!("  ports        : {}-{}", args.ports.start, args.ports.end);
-    println!("  timeout      : {} ms", args.timeout_ms);
-    println!("  retries      : {}", args.retries);
-    println!("  parallel     : {}", args.parallel);
-    if args.parallel {
-        println!("  threads      : {}", threads);
+    if targets.is_empty() {
+        bail!("no targets to scan");
     }
-    println!("  show_closed  : {}", args.show_closed);
 
-    for ip in ips_to_scan {
-        let started = Instant::now();
+    Ok(targets)
+}
 
-        let scanned = Arc::new(AtomicUsize::new(0));
-        let done = Arc::new(AtomicBool::new(false));
+/// gather excluded networks from --exclude and --exclude-file
+fn collect_exclusions(exclude: &[IpNet], exclude_file: Option<&Path>) -> Result<Vec<IpNet>> {
+    let mut excluded = exclude.to_vec();
 
-        let progress_handle = if args.progress {
-            Some(start_progress_line(
-                ports.len(),
-                scanned.clone(),
-                done.clone(),
-                cancelled.clone(),
-                started,
-            ))
-        } else {
-            None
-        };
-
-        let results = scan_ip(
-            ip,
-            &ports,
-            timeout,
-            args.retries,
-            args.parallel,
-            threads,
-            if args.progress {
-                Some(scanned.clone())
-            } else {
-                None
-            },
-            cancelled.clone(),
+    if let Some(path) = exclude_file {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read exclude file '{}'", path.display()))?;
+        let list = parse_exclude_list(&text).map_err(|e| anyhow!("{}: {e}", path.display()))?;
+        excluded.extend(list);
+    }
+
+    Ok(excluded)
+}
+
+/// the report for stdout, plus one for --output when given
+fn create_reporter(
+    args: &Args,
+    opts: &ReportOptions,
+    dashboard: Option<&Dashboard>,
+) -> Result<Box<dyn Reporter>> {
+    let on_stdout = ReportOptions {
+        color: args.color.enabled(io::stdout().is_terminal()),
+        ..opts.clone()
+    };
+    // under --tui the report waits for the screen to close
+    let stdout = || -> Box<dyn Write + Send> {
+        match dashboard {
+            Some(dashboard) => Box::new(dashboard.stdout()),
+            None => Box::new(io::stdout()),
+        }
+    };
+    let Some(path) = &args.output else {
+        let format = args.format.as_deref().unwrap_or("table");
+        return report::create(format, stdout(), &on_stdout).map_err(|e| anyhow!(e));
+    };
+
+    let format = match &args.format {
+        Some(format) => format.as_str(),
+        None => report::format_for_path(path).ok_or_else(|| {
+            anyhow!(
+                "can't tell the format of '{}' from its extension, pass --format",
+                path.display()
+            )
+        })?,
+    };
+    // reject a bad format before creating (and truncating) the file
+    report::create(format, Box::new(io::sink()), opts).map_err(|e| anyhow!(e))?;
+    let file = File::create(path)
+        .with_context(|| format!("failed to create output file '{}'", path.display()))?;
+    let to_file =
+        report::create(format, Box::new(BufWriter::new(file)), opts).map_err(|e| anyhow!(e))?;
+    let to_stdout = report::create("table", stdout(), &on_stdout).map_err(|e| anyhow!(e))?;
+
+    Ok(Box::new(MultiReporter::new(vec![to_stdout, to_file])))
+}
+
+/// one stdout line for an open port as soon as it is found, above the
+/// progress bars on a terminal
+fn print_found(
+    ip: IpAddr,
+    protocol: Protocol,
+    r: &ScanResult,
+    services: &Services,
+    palette: Palette,
+) {
+    let open = palette.state(PortState::Open, "open");
+    let line = match r.service_name_in(services) {
+        Some(name) => format!("{open} {ip} {}/{protocol} {name}", r.port),
+        None => format!("{open} {ip} {}/{protocol}", r.port),
+    };
+    bars().suspend(|| {
+        let mut out = io::stdout().lock();
+        let _ = writeln!(out, "{line}");
+        let _ = out.flush();
+    });
+}
+
+/// what --fail-on exits with EXIT_FAILED for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum FailOn {
+    /// any port found open
+    Open,
+    /// any port opened, closed or changed service since --baseline
+    Change,
+    /// nothing
+    None,
+}
+
+/// exit status when --fail-on found what it was looking for
+const EXIT_FAILED: i32 = 2;
+
+/// exit status when --max-scan-time cut the scan short, or, with
+/// --fail-on, anything else did
+const EXIT_INCOMPLETE: i32 = 3;
+
+/// exit status when a second ctrl-c aborts the scan, as the shell would
+/// have it for SIGINT
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// set `cancelled` at `deadline`, and `stopping` and `expired` with it
+fn cancel_at(
+    deadline: Instant,
+    cancelled: &Arc<AtomicBool>,
+    stopping: &Arc<AtomicBool>,
+    expired: &Arc<AtomicBool>,
+) {
+    let cancelled = cancelled.clone();
+    let stopping = stopping.clone();
+    let expired = expired.clone();
+    std::thread::spawn(move || {
+        // wake up now and then to notice a ctrl-c or the end of the scan
+        while !cancelled.load(Ordering::Relaxed) {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                expired.store(true, Ordering::SeqCst);
+                stopping.store(true, Ordering::SeqCst);
+                cancelled.store(true, Ordering::SeqCst);
+                break;
+            }
+            std::thread::sleep(left.min(Duration::from_millis(100)));
+        }
+    });
+}
+
+/// print where the scan has got to on stderr whenever SIGUSR1 comes in,
+/// as dd does: `kill -USR1 PID`. `found` are the open ports so far.
+#[cfg(unix)]
+fn status_on_sigusr1(progress: Arc<Progress>, found: Arc<Mutex<Vec<String>>>) -> Result<()> {
+    let mut signals = signal_hook::iterator::Signals::new([signal_hook::consts::SIGUSR1])
+        .context("can't handle SIGUSR1")?;
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            print_status(&progress.snapshot(), &lock(&found));
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn status_on_sigusr1(_progress: Arc<Progress>, _found: Arc<Mutex<Vec<String>>>) -> Result<()> {
+    Ok(())
+}
+
+/// ```text
+/// status after 0:12: 4105/8000 probes (51.3%), 2050 probes/s, eta 0:02; 1/2 hosts done, 1 open
+///   10.0.0.6: 2001/4000 (50.0%), 0 open
+///   open: 10.0.0.5 22/tcp ssh
+/// ```
+fn print_status(now: &ProgressSnapshot, found: &[String]) {
+    let mut status = format!(
+        "status after {}: {}/{} probes ({:.1}%), {} probes/s, eta {}; {}/{} hosts done, {} open\n",
+        clock(now.elapsed),
+        now.probes_done,
+        now.probes_total,
+        now.fraction() * 100.0,
+        now.rate.map_or("--".to_string(), |r| format!("{r:.0}")),
+        now.eta.map_or("--".to_string(), clock),
+        now.hosts_done,
+        now.hosts_total,
+        now.open_ports
+    );
+    for host in &now.hosts {
+        status += &format!(
+            "  {}: {}/{} ({:.1}%), {} open\n",
+            host.ip,
+            host.probes_done,
+            host.probes_total,
+            host.fraction() * 100.0,
+            host.open_ports
         );
+    }
+    for port in found {
+        status += &format!("  open: {port}\n");
+    }
+    bars().suspend(|| {
+        let _ = io::stderr().lock().write_all(status.as_bytes());
+    });
+}
 
-        done.store(true, Ordering::Relaxed);
-        if let Some(h) = progress_handle {
-            let _ = h.join();
+/// set `cancelled` on ctrl-c
+fn cancel_on_ctrlc(cancelled: &Arc<AtomicBool>) -> Result<()> {
+    let cancelled = cancelled.clone();
+    ctrlc::set_handler(move || {
+        cancelled.store(true, Ordering::SeqCst);
+    })?;
+    Ok(())
+}
+
+/// the first ctrl-c sets `stopping`: no new probes go out, the ones in
+/// flight finish and the report is written for what was scanned. the
+/// second sets `cancelled` and, when `exit`, leaves straight away; the
+/// --tui has a terminal to give back first, so it only drops the probes
+/// in flight.
+fn stop_on_ctrlc(
+    stopping: &Arc<AtomicBool>,
+    cancelled: &Arc<AtomicBool>,
+    exit: bool,
+) -> Result<()> {
+    let stopping = stopping.clone();
+    let cancelled = cancelled.clone();
+    ctrlc::set_handler(move || {
+        if !stopping.swap(true, Ordering::SeqCst) {
+            info!("stopping: waiting for the probes in flight (ctrl-c again to abort)");
+            return;
         }
+        cancelled.store(true, Ordering::SeqCst);
+        if exit {
+            info!("aborted");
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+    })?;
+    Ok(())
+}
+
+/// drop the hosts that answer neither an icmp echo nor a tcp connect
+fn ping_hosts(
+    hosts: &mut Vec<ScanHost>,
+    timeout: Duration,
+    transport: &Transport,
+    cancelled: &Arc<AtomicBool>,
+) -> Result<()> {
+    let opts = DiscoverOptions {
+        timeout,
+        icmp: raw_sockets_available(),
+        source: transport.source(),
+        interface: transport.interface_name().map(str::to_string),
+        cancelled: cancelled.clone(),
+        ..DiscoverOptions::default()
+    };
+    // discovery knows nothing of zones, so link-local targets given with
+    // one are scanned unpinged
+    let ips: Vec<IpAddr> = hosts
+        .iter()
+        .filter(|h| h.scope_id == 0)
+        .map(|h| h.ip)
+        .collect();
+    let live: HashSet<IpAddr> = discover(&ips, &opts)?.into_iter().map(|h| h.ip).collect();
+    hosts.retain(|h| h.scope_id != 0 || live.contains(&h.ip));
+    Ok(())
+}
+
+/// the discover subcommand: ping every host and list the ones that are up
+fn run_discover(args: &DiscoverArgs, config: &Config) -> Result<()> {
+    if args.timeout_ms == 0 {
+        bail!("timeout must be at least 1 ms");
+    }
+    if args.ports.is_empty() {
+        bail!("no ports to ping");
+    }
+    let targets = collect_targets(&args.targets, args.target_file.as_deref())?;
+    let mut hosts = expand_targets_with(&targets, args.all_ips, &args.dns.resolver()?)?;
+    let excluded = collect_exclusions(&args.exclude, args.exclude_file.as_deref())?;
+    exclude_hosts(&mut hosts, &excluded);
+    if hosts.is_empty() {
+        bail!("no hosts left to ping after exclusions");
+    }
+    config.check_allowed(&hosts)?;
 
+    let cancelled = Arc::new(AtomicBool::new(false));
+    cancel_on_ctrlc(&cancelled)?;
+
+    let privileged = raw_sockets_available();
+    let icmp = !args.no_icmp && privileged;
+    if !args.no_icmp && !icmp {
+        warn!("icmp needs raw sockets (root or CAP_NET_RAW), pinging over tcp only");
+    }
+    if args.arp && !privileged {
+        warn!("arp needs raw sockets (root or CAP_NET_RAW), skipping it");
+    }
+    let opts = DiscoverOptions {
+        ports: args.ports.iter().collect(),
+        timeout: Duration::from_millis(args.timeout_ms),
+        icmp,
+        arp: args.arp && privileged,
+        concurrency: args.concurrency,
+        source: None,
+        interface: None,
+        cancelled: cancelled.clone(),
+    };
+    let ips: Vec<IpAddr> = hosts.iter().map(|h| h.ip).collect();
+    let started = Instant::now();
+    let live = discover(&ips, &opts)?;
+    if cancelled.load(Ordering::Relaxed) {
+        info!("discovery cancelled (results may be incomplete)");
+    } else {
+        info!("discovery complete in {:?}", started.elapsed());
+    }
+
+    let mut out = io::stdout().lock();
+    if args.hosts_only {
+        for host in &live {
+            writeln!(out, "{}", host.ip)?;
+        }
+        return Ok(());
+    }
+    let width = live
+        .iter()
+        .map(|h| h.ip.to_string().len())
+        .max()
+        .unwrap_or(0)
+        .max(15);
+    write!(
+        out,
+        "{:<width$}  {:<18}  {:<10}",
+        "host", "reason", "latency"
+    )?;
+    if args.arp {
+        write!(out, "  mac")?;
+    }
+    writeln!(out)?;
+    write!(out, "{:-<width$}  {:-<18}  {:-<10}", "", "", "")?;
+    if args.arp {
+        write!(out, "  {:-<17}", "")?;
+    }
+    writeln!(out)?;
+    for host in &live {
+        let latency = format!("{:.2} ms", host.latency.as_secs_f64() * 1000.0);
+        write!(
+            out,
+            "{:<width$}  {:<18}  {latency:<10}",
+            host.ip.to_string(),
+            host.reason
+        )?;
+        if let Some(mac) = host.mac {
+            write!(out, "  {mac}")?;
+        }
+        writeln!(out)?;
+    }
+    writeln!(out)?;
+    writeln!(out, "hosts up: {} of {}", live.len(), hosts.len())?;
+    Ok(())
+}
+
+/// the wait subcommand: retry every host:port until all of them accept a
+/// connection or the time runs out
+fn run_wait(args: &WaitArgs, config: &Config) -> Result<()> {
+    if args.ports.is_empty() {
+        bail!("no ports to wait for");
+    }
+    if args.connect_timeout.is_zero() {
+        bail!("--connect-timeout must be above zero");
+    }
+    let started = Instant::now();
+    let deadline = started + args.timeout;
+    let cancelled = Arc::new(AtomicBool::new(false));
+    cancel_on_ctrlc(&cancelled)?;
+
+    let mut pending: Vec<(&Target, u16)> = args
+        .hosts
+        .iter()
+        .flat_map(|t| args.ports.iter().map(move |port| (t, port)))
+        .collect();
+    loop {
+        // a fresh resolver each round: it caches failures, and the name may
+        // only appear once the container it belongs to has started
+        let resolver = args.dns.resolver()?;
+        let mut last_error = None;
+        let mut refused = None;
+        pending.retain(|&(target, port)| {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() || cancelled.load(Ordering::Relaxed) || refused.is_some() {
+                return true;
+            }
+            let hosts = match expand_targets_with(std::slice::from_ref(target), false, &resolver) {
+                Ok(hosts) => hosts,
+                Err(e) => {
+                    last_error = Some(format!("{e:#}"));
+                    return true;
+                }
+            };
+            if let Err(e) = config.check_allowed(&hosts) {
+                refused = Some(e);
+                return true;
+            }
+            // a name that resolves to several addresses is up when any is
+            let up = hosts.iter().any(|host| {
+                let r = probe_port_with(
+                    host.ip,
+                    port,
+                    args.connect_timeout.min(left),
+                    &RetryPolicy::default(),
+                    &cancelled,
+                );
+                r.state == PortState::Open
+            });
+            if up && !args.quiet {
+                info!("{target}:{port} is up after {:.1?}", started.elapsed());
+            }
+            !up
+        });
+        if let Some(e) = refused {
+            return Err(e.into());
+        }
+        if pending.is_empty() {
+            return Ok(());
+        }
         if cancelled.load(Ordering::Relaxed) {
-            eprintln!("scan cancelled (results may be incomplete)");
+            bail!("wait cancelled");
+        }
+        if Instant::now() >= deadline {
+            if args.quiet {
+                std::process::exit(1);
+            }
+            let waiting: Vec<String> = pending
+                .iter()
+                .map(|(target, port)| format!("{target}:{port}"))
+                .collect();
+            let mut msg = format!(
+                "timed out after {:.1?} waiting for {}",
+                started.elapsed(),
+                waiting.join(", ")
+            );
+            if let Some(e) = last_error {
+                msg.push_str(&format!(" (last error: {e})"));
+            }
+            bail!(msg);
+        }
+        // sleep in short slices so ctrl-c is noticed quickly
+        let until = (Instant::now() + args.interval).min(deadline);
+        while !cancelled.load(Ordering::Relaxed) {
+            let left = until.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            std::thread::sleep(left.min(Duration::from_millis(100)));
+        }
+    }
+}
+
+/// read a report written with --format json or jsonl
+fn load_scan(path: &Path) -> Result<SavedScan> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    SavedScan::parse(&text).map_err(|e| anyhow!("{}: {e}", path.display()))
+}
+
+/// the diff subcommand: what changed between two saved scans
+fn run_diff(args: &DiffArgs, palette: Palette) -> Result<()> {
+    let changes = diff_scans(&load_scan(&args.old)?, &load_scan(&args.new)?);
+    let count = |change| changes.iter().filter(|c| c.change == change).count();
+    let (opened, closed, changed) = (
+        count(Change::Opened),
+        count(Change::Closed),
+        count(Change::ServiceChanged),
+    );
+
+    let mut out = io::stdout().lock();
+    if args.format == "json" {
+        let items: Vec<_> = changes
+            .iter()
+            .map(|c| {
+                json!({
+                    "ip": c.ip,
+                    "port": c.port,
+                    "change": c.change.to_string(),
+                    "old_state": c.old_state.map(PortState::as_str),
+                    "new_state": c.new_state.map(PortState::as_str),
+                    "old_service": c.old_service,
+                    "new_service": c.new_service
+                })
+            })
+            .collect();
+        let doc = json!({
+            "opened": opened,
+            "closed": closed,
+            "changed": changed,
+            "changes": items
+        });
+        serde_json::to_writer_pretty(&mut out, &doc)?;
+        writeln!(out)?;
+    } else {
+        let width = changes
+            .iter()
+            .map(|c| c.ip.to_string().len())
+            .max()
+            .unwrap_or(0);
+        for c in &changes {
+            let (mark, detail) = match c.change {
+                Change::Opened => ("+", c.new_service.clone().unwrap_or_default()),
+                Change::Closed => (
+                    "-",
+                    match c.new_state {
+                        Some(state) => format!("now {state}"),
+                        None => "no longer listed".to_string(),
+                    },
+                ),
+                Change::ServiceChanged => (
+                    "~",
+                    format!(
+                        "{} -> {}",
+                        c.old_service.as_deref().unwrap_or("?"),
+                        c.new_service.as_deref().unwrap_or("?")
+                    ),
+                ),
+            };
+            let line = format!(
+                "{mark} {:<width$}  {:<5}  {:<7}  {detail}",
+                c.ip.to_string(),
+                c.port,
+                c.change
+            );
+            writeln!(out, "{}", palette.change(c.change, line.trim_end()))?;
+        }
+        if changes.is_empty() {
+            writeln!(out, "no changes")?;
         } else {
-            eprintln!("scan complete in {:?}", started.elapsed());
+            writeln!(out)?;
+            writeln!(
+                out,
+                "{} change{}: {opened} opened, {closed} closed, {changed} changed service",
+                changes.len(),
+                if changes.len() == 1 { "" } else { "s" }
+            )?;
         }
+    }
+    out.flush()?;
+    // like diff(1), so scripts can tell without reading the output
+    if !changes.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
 
-        print_results(ip, &results, args.show_closed);
+/// the audit subcommand: scan hosts (or read a saved scan) and list every
+/// port that breaks the policy
+fn run_audit(args: &AuditArgs, config: &Config) -> Result<()> {
+    let text = std::fs::read_to_string(&args.policy)
+        .with_context(|| format!("failed to read {}", args.policy.display()))?;
+    let policy = Policy::parse(&text).map_err(|e| anyhow!("{}: {e}", args.policy.display()))?;
 
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let audited: Vec<(ScanHost, Vec<ScanResult>)> = if let Some(path) = &args.scan {
+        let saved = load_scan(path)?;
+        if let Some(scanned) = &saved.ports {
+            let unscanned = policy.ports().difference(scanned);
+            if !unscanned.is_empty() {
+                warn!(
+                    "{} didn't scan {unscanned}, which the policy names",
+                    path.display()
+                );
+            }
+        }
+        saved
+            .hosts
+            .into_iter()
+            .map(|h| (h.host, h.results))
+            .collect()
+    } else {
+        if args.timeout_ms == 0 {
+            bail!("timeout must be at least 1 ms");
+        }
+        let targets = if args.targets.is_empty() {
+            policy.targets()
+        } else {
+            args.targets.clone()
+        };
+        if targets.is_empty() {
+            bail!("the policy names no hosts, give the targets to audit");
+        }
+        let ports = args
+            .ports
+            .clone()
+            .unwrap_or_else(|| PortSpec::top(1000))
+            .union(&policy.ports());
+        cancel_on_ctrlc(&cancelled)?;
+        let scanner = Scanner::builder()
+            .timeout(Duration::from_millis(args.timeout_ms))
+            .engine(Engine::Async)
+            .concurrency(args.concurrency)
+            .cancel_flag(cancelled.clone())
+            .build();
+        scan_targets(&scanner, &targets, &ports, &args.dns, config)?
+    };
+    if cancelled.load(Ordering::Relaxed) {
+        bail!("audit cancelled");
+    }
+
+    let mut out = io::stdout().lock();
+    let (mut checked, mut failing, mut violations) = (0, 0, 0);
+    for (host, results) in &audited {
+        let Some(rule) = policy.rule_for(host) else {
+            info!("{}: no rule in the policy, skipped", host.ip);
+            continue;
+        };
+        checked += 1;
+        let found = rule.check(results);
+        if !found.is_empty() {
+            failing += 1;
+            violations += found.len();
+        }
+        for (port, violation) in found {
+            writeln!(out, "{:<15}  {port:<5}  {violation}", host.ip.to_string())?;
+        }
+    }
+    if violations == 0 {
+        writeln!(out, "hosts audited: {checked}, no violations")?;
+        return Ok(());
+    }
+    writeln!(out)?;
+    writeln!(
+        out,
+        "hosts audited: {checked}, violations: {violations} on {failing} hosts"
+    )?;
+    out.flush()?;
+    std::process::exit(1);
+}
+
+fn run_query(args: &QueryArgs) -> Result<()> {
+    let history = History::open(&args.db)
+        .with_context(|| format!("can't read scan history from {}", args.db.display()))?;
+    let since = match args.since {
+        Some(ago) => Some(
+            SystemTime::now()
+                .checked_sub(ago)
+                .ok_or_else(|| anyhow!("--since {ago:?} reaches back too far"))?,
+        ),
+        None => None,
+    };
+    let records: Vec<HostRecord> = history
+        .hosts(since)
+        .context("failed to read scan history")?
+        .into_iter()
+        .filter(|r| {
+            args.hosts.is_empty() || args.hosts.iter().any(|t| target_matches(t, r.ip, &r.names))
+        })
+        .collect();
+    let ports: Vec<PortHistory> = port_histories(&records)
+        .into_iter()
+        .filter(|h| args.ports.as_ref().is_none_or(|p| p.contains(h.port)))
+        .collect();
+
+    let state = |s: &Sighting| match &s.result {
+        Some(r) => r.state.as_str(),
+        None => "not open",
+    };
+    let service = |s: Option<&Sighting>| {
+        let r = s?.result.as_ref()?;
+        let name = r.service.as_deref()?;
+        Some(match r.version.as_deref() {
+            Some(version) => format!("{name} {version}"),
+            None => name.to_string(),
+        })
+    };
+    let mut out = io::stdout().lock();
+    if args.format == "json" {
+        let items: Vec<_> = ports
+            .iter()
+            .map(|h| {
+                let seen: Vec<_> = h
+                    .seen
+                    .iter()
+                    .map(|s| {
+                        json!({
+                            "scan": s.scan_id,
+                            "started": s.started,
+                            "state": state(s),
+                            "service": service(Some(s))
+                        })
+                    })
+                    .collect();
+                json!({
+                    "ip": h.ip,
+                    "port": h.port,
+                    "first_open": h.first_open().map(|s| s.started.as_str()),
+                    "last_open": h.last_open().map(|s| s.started.as_str()),
+                    "open_in": h.open_count(),
+                    "scans": h.seen.len(),
+                    "state": h.seen.last().map(state),
+                    "service": service(h.last_open()),
+                    "history": seen
+                })
+            })
+            .collect();
+        serde_json::to_writer_pretty(&mut out, &json!({ "ports": items }))?;
+        writeln!(out)?;
+        return Ok(out.flush()?);
+    }
+
+    if ports.is_empty() {
+        writeln!(out, "no open ports in the stored scans")?;
+        return Ok(());
+    }
+    let width = ports
+        .iter()
+        .map(|h| h.ip.to_string().len())
+        .max()
+        .unwrap_or(0)
+        .max(2);
+    if args.history {
+        writeln!(
+            out,
+            "{:<20}  {:<width$}  {:<5}  {:<8}  service",
+            "scanned", "ip", "port", "state"
+        )?;
+        writeln!(
+            out,
+            "{:-<20}  {:-<width$}  {:-<5}  {:-<8}  {:-<8}",
+            "", "", "", "", ""
+        )?;
+        for h in &ports {
+            for s in &h.seen {
+                let line = format!(
+                    "{:<20}  {:<width$}  {:<5}  {:<8}  {}",
+                    s.started,
+                    h.ip.to_string(),
+                    h.port,
+                    state(s),
+                    service(Some(s)).unwrap_or_default()
+                );
+                writeln!(out, "{}", line.trim_end())?;
+            }
+        }
+        return Ok(out.flush()?);
+    }
+    writeln!(
+        out,
+        "{:<width$}  {:<5}  {:<20}  {:<20}  {:<7}  {:<8}  service",
+        "ip", "port", "first open", "last open", "open in", "now"
+    )?;
+    writeln!(
+        out,
+        "{:-<width$}  {:-<5}  {:-<20}  {:-<20}  {:-<7}  {:-<8}  {:-<8}",
+        "", "", "", "", "", "", ""
+    )?;
+    for h in &ports {
+        let line = format!(
+            "{:<width$}  {:<5}  {:<20}  {:<20}  {:<7}  {:<8}  {}",
+            h.ip.to_string(),
+            h.port,
+            h.first_open().map_or("-", |s| &s.started),
+            h.last_open().map_or("-", |s| &s.started),
+            format!("{}/{}", h.open_count(), h.seen.len()),
+            h.seen.last().map_or("-", state),
+            service(h.last_open()).unwrap_or_default()
+        );
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    Ok(out.flush()?)
+}
+
+/// scan `targets` on `ports`, host by host, for audit and schedule; the
+/// config must authorize every host
+fn scan_targets(
+    scanner: &Scanner,
+    targets: &[Target],
+    ports: &PortSpec,
+    dns: &DnsArgs,
+    config: &Config,
+) -> Result<Vec<(ScanHost, Vec<ScanResult>)>> {
+    let hosts = expand_targets_with(targets, false, &dns.resolver()?)?;
+    config.check_allowed(&hosts)?;
+    Ok(hosts
+        .into_iter()
+        .map(|host| {
+            let outcome = scanner
+                .for_host(&host)
+                .scan_host(host.ip, ports.iter(), |_| {});
+            (host, outcome.results)
+        })
+        .collect())
+}
+
+/// the schedule subcommand: scan whenever the cron expression fires, and
+/// log what drifted since the last scan (and from the policy, if given)
+fn run_schedule(args: &ScheduleArgs, config: &Config) -> Result<()> {
+    let policy = match &args.policy {
+        Some(path) => {
+            let text = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            Some(Policy::parse(&text).map_err(|e| anyhow!("{}: {e}", path.display()))?)
+        }
+        None => None,
+    };
+    if args.timeout_ms == 0 {
+        bail!("timeout must be at least 1 ms");
+    }
+    let targets = match (&policy, args.targets.is_empty()) {
+        (Some(policy), true) => policy.targets(),
+        _ => args.targets.clone(),
+    };
+    if targets.is_empty() {
+        bail!("nothing to scan: give targets, or a policy that names hosts");
+    }
+    let mut ports = args.ports.clone().unwrap_or_else(|| PortSpec::top(1000));
+    if let Some(policy) = &policy {
+        ports = ports.union(&policy.ports());
+    }
+
+    let cancelled = Arc::new(AtomicBool::new(false));
+    cancel_on_ctrlc(&cancelled)?;
+    let scanner = Scanner::builder()
+        .timeout(Duration::from_millis(args.timeout_ms))
+        .engine(Engine::Async)
+        .concurrency(args.concurrency)
+        .cancel_flag(cancelled.clone())
+        .build();
+
+    let log = |line: &str| {
+        println!("{} {line}", report::util::rfc3339(SystemTime::now()));
+    };
+    let metrics = match args.metrics {
+        Some(addr) => {
+            let metrics = Arc::new(Metrics::new());
+            let local = serve_metrics(addr, metrics.clone())
+                .with_context(|| format!("can't serve metrics on {addr}"))?;
+            log(&format!("serving metrics on http://{local}/metrics"));
+            Some(metrics)
+        }
+        None => None,
+    };
+    let mut email = args
+        .email
+        .reporter(&ReportOptions::default())?
+        .map(EmailReporter::manual);
+    let mut previous: Option<SavedScan> = None;
+    let mut previous_violations: HashSet<(IpAddr, u16)> = HashSet::new();
+    loop {
+        let Some(next) = args.cron.next_after(SystemTime::now()) else {
+            bail!("'{}' never fires", args.cron);
+        };
+        log(&format!("next scan at {}", report::util::rfc3339(next)));
+        // sleep in slices, so ctrl-c isn't kept waiting
+        while let Ok(left) = next.duration_since(SystemTime::now()) {
+            if cancelled.load(Ordering::Relaxed) {
+                break;
+            }
+            std::thread::sleep(left.min(Duration::from_millis(200)));
+        }
         if cancelled.load(Ordering::Relaxed) {
-            return Ok(());
+            break;
+        }
+
+        let started = SystemTime::now();
+        let scanned = match scan_targets(&scanner, &targets, &ports, &args.dns, config) {
+            Ok(scanned) => scanned,
+            // the allow list won't change its mind by the next run
+            Err(e) if matches!(e.downcast_ref(), Some(ScanError::NotAuthorized { .. })) => {
+                return Err(e);
+            }
+            // a lookup failing tonight shouldn't end the schedule
+            Err(e) => {
+                log(&format!("scan failed: {e:#}"));
+                continue;
+            }
+        };
+        if cancelled.load(Ordering::Relaxed) {
+            break;
+        }
+        if let Some(metrics) = &metrics {
+            for (host, results) in &scanned {
+                metrics.record_host(host, results);
+            }
+            metrics.record_scan();
+        }
+        let scan = SavedScan {
+            ports: Some(ports.clone()),
+            started: Some(report::util::rfc3339(started)),
+            hosts: scanned
+                .into_iter()
+                .map(|(host, results)| SavedHost {
+                    host,
+                    results,
+                    elapsed: Duration::ZERO,
+                    incomplete: false,
+                    finished: None,
+                })
+                .collect(),
+            ..SavedScan::default()
+        };
+        let open: usize = scan
+            .hosts
+            .iter()
+            .map(|h| h.results.iter().filter(|r| r.is_open()).count())
+            .sum();
+        log(&format!(
+            "scanned {} host{} in {:.1}s: {open} open ports",
+            scan.hosts.len(),
+            if scan.hosts.len() == 1 { "" } else { "s" },
+            started.elapsed().unwrap_or_default().as_secs_f64()
+        ));
+
+        let mut drift = Vec::new();
+        if let Some(previous) = &previous {
+            for c in diff_scans(previous, &scan) {
+                let service = match c.change {
+                    Change::Closed => None,
+                    _ => c.new_service,
+                };
+                drift.push(
+                    format!(
+                        "{} {} {} {}",
+                        c.change,
+                        c.ip,
+                        c.port,
+                        service.unwrap_or_default()
+                    )
+                    .trim_end()
+                    .to_string(),
+                );
+            }
+        }
+        let mut violations = HashSet::new();
+        for host in &scan.hosts {
+            let Some(rule) = policy.as_ref().and_then(|p| p.rule_for(&host.host)) else {
+                continue;
+            };
+            for (port, violation) in rule.check(&host.results) {
+                violations.insert((host.host.ip, port));
+                // a violation is drift the first time it shows up
+                if !previous_violations.contains(&(host.host.ip, port)) {
+                    drift.push(format!("violation {} {port} {violation}", host.host.ip));
+                }
+            }
+        }
+        if previous.is_some() {
+            log(&format!(
+                "{} change{} since the last scan",
+                drift.len(),
+                if drift.len() == 1 { "" } else { "s" }
+            ));
+        } else {
+            log("first scan, the next ones are compared with it");
+        }
+        for line in &drift {
+            log(line);
+        }
+        if let Some(email) = &mut email {
+            if let Err(e) = render_round(email, args, &targets, &ports, started, &scan) {
+                log(&format!("can't render the report to mail: {e}"));
+            }
+        }
+        if !drift.is_empty() {
+            if let Some(command) = &args.on_drift {
+                if let Err(e) = run_hook(command, &drift) {
+                    log(&format!("--on-drift command failed: {e:#}"));
+                }
+            }
+            if let Some(email) = &mut email {
+                let subject = format!(
+                    "pscan: {} change{} since the last scan",
+                    drift.len(),
+                    if drift.len() == 1 { "" } else { "s" }
+                );
+                if let Err(e) = email.mail(&subject) {
+                    log(&format!("can't mail the report: {e}"));
+                }
+            }
+        }
+        previous = Some(scan);
+        previous_violations = violations;
+    }
+    log("schedule stopped");
+    Ok(())
+}
+
+/// hand one scheduled scan to the email reporter, which keeps it as the
+/// report to mail
+fn render_round(
+    email: &mut EmailReporter,
+    args: &ScheduleArgs,
+    targets: &[Target],
+    ports: &PortSpec,
+    started: SystemTime,
+    scan: &SavedScan,
+) -> io::Result<()> {
+    email.begin(&ScanInfo {
+        command: std::env::args().collect::<Vec<_>>().join(" "),
+        targets: targets.iter().map(|t| t.to_string()).collect(),
+        host_count: scan.hosts.len(),
+        excluded_hosts: 0,
+        ports: ports.to_string(),
+        port_count: ports.len(),
+        excluded_ports: None,
+        timeout_ms: args.timeout_ms,
+        retries: 0,
+        engine: Engine::Async,
+        concurrency: args.concurrency,
+        started,
+    })?;
+    let mut summary = ScanSummary {
+        targets: targets.len(),
+        hosts_total: scan.hosts.len(),
+        elapsed: started.elapsed().unwrap_or_default(),
+        ..ScanSummary::default()
+    };
+    for host in &scan.hosts {
+        let report = HostReport {
+            host: &host.host,
+            results: &host.results,
+            elapsed: host.elapsed,
+            incomplete: false,
+        };
+        email.host(&report)?;
+        summary.hosts_scanned += 1;
+        summary.open_ports += report.open_count();
+        if report.open_count() > 0 {
+            summary.hosts_with_open += 1;
+        }
+    }
+    email.finish(&summary)
+}
+
+/// run `command` with the shell, the drift lines on its stdin
+fn run_hook(command: &str, lines: &[String]) -> Result<()> {
+    use std::process::{Command as Process, Stdio};
+    #[cfg(unix)]
+    let (shell, flag) = ("sh", "-c");
+    #[cfg(not(unix))]
+    let (shell, flag) = ("cmd", "/C");
+    let mut child = Process::new(shell)
+        .arg(flag)
+        .arg(command)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // a command that doesn't read its input isn't an error
+        let _ = stdin.write_all((lines.join("\n") + "\n").as_bytes());
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("{command}: {status}");
+    }
+    Ok(())
+}
+
+/// the serve subcommand: the scan api, until ctrl-c
+fn run_serve(args: &ServeArgs, config: &Config) -> Result<()> {
+    let opts = ServeOptions {
+        token: args.token.clone(),
+        max_jobs: usize::from(args.max_jobs),
+        max_pending: usize::from(args.max_pending),
+        resolver: args.dns.resolver()?,
+        allow: config.allow.clone(),
+    };
+    let grpc = match args.grpc {
+        Some(addr) => Some(
+            GrpcServer::bind(addr, opts.clone())
+                .with_context(|| format!("can't serve grpc on {addr}"))?,
+        ),
+        None => None,
+    };
+    let server = Server::bind(args.listen, opts)
+        .with_context(|| format!("can't listen on {}", args.listen))?;
+    let exposed = std::iter::once(args.listen)
+        .chain(args.grpc)
+        .any(|addr| !addr.ip().is_loopback());
+    if args.token.is_none() && exposed {
+        warn!("listening beyond this machine without --token");
+    }
+    info!("serving the scan api on http://{}", server.local_addr()?);
+    let stop = Arc::new(AtomicBool::new(false));
+    let waker = server.waker()?;
+    {
+        let (stop, waker) = (stop.clone(), waker.clone());
+        ctrlc::set_handler(move || {
+            stop.store(true, Ordering::SeqCst);
+            waker.wake();
+        })?;
+    }
+    let grpc = match grpc {
+        Some(grpc) => {
+            info!("serving the grpc api on {}", grpc.local_addr()?);
+            let stop = stop.clone();
+            Some(std::thread::spawn(move || {
+                let served = grpc.run(&stop);
+                stop.store(true, Ordering::SeqCst);
+                waker.wake();
+                served
+            }))
+        }
+        None => None,
+    };
+    let served = server.run(&stop);
+    // either one failing stops both
+    stop.store(true, Ordering::SeqCst);
+    if let Some(grpc) = grpc {
+        grpc.join()
+            .map_err(|_| anyhow!("the grpc server panicked"))??;
+    }
+    served?;
+    Ok(())
+}
+
+/// the merge subcommand: one json report out of several saved scans, each
+/// host and port saying which file it came from
+fn run_merge(args: &MergeArgs) -> Result<()> {
+    let scans = args
+        .files
+        .iter()
+        .map(|path| load_scan(path))
+        .collect::<Result<Vec<_>>>()?;
+    let files: Vec<String> = args
+        .files
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+    let doc = merged_report(&scans, &files);
+
+    match &args.output {
+        Some(path) => std::fs::write(path, doc)
+            .with_context(|| format!("failed to write {}", path.display()))?,
+        None => io::stdout().lock().write_all(doc.as_bytes())?,
+    }
+    Ok(())
+}
+
+/// whether a host with this ip and names is the one `target` names, or is
+/// in its network
+fn target_matches(target: &Target, ip: IpAddr, names: &[String]) -> bool {
+    match target {
+        Target::Host(name) => name.parse::<IpAddr>() == Ok(ip) || names.iter().any(|n| n == name),
+        Target::Network(net) => net.contains(ip),
+    }
+}
+
+/// the config files, each overriding the one before: --config FILE (or
+/// $PSCAN_CONFIG), else the default ones that exist, none with
+/// --no-config. the PSCAN_* variables go over them all
+fn load_config(args: &Args) -> Result<Config> {
+    let paths = match (&args.config, std::env::var_os("PSCAN_CONFIG")) {
+        _ if args.no_config => Vec::new(),
+        (Some(path), _) => vec![path.clone()],
+        (None, Some(path)) if !path.is_empty() => vec![PathBuf::from(path)],
+        (None, _) => Config::default_paths()
+            .into_iter()
+            .filter(|path| path.exists())
+            .collect(),
+    };
+    let files = paths.iter().try_fold(Config::default(), |config, path| {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let layer = Config::parse(&text).map_err(|e| anyhow!("{}: {e}", path.display()))?;
+        anyhow::Ok(config.merge(layer))
+    })?;
+    let env = Config::from_env().map_err(|e| anyhow!(e))?;
+    Ok(files.merge(env))
+}
+
+/// the --services-file on `argv`, over the built-in names. clap needs
+/// the names to parse port lists, so this is a first pass with the port
+/// lists taken as plain strings; when that pass fails the real one says why.
+fn load_services(argv: &[OsString]) -> Result<Arc<Services>> {
+    let first = port_lists(Args::command(), &ValueParser::string()).try_get_matches_from(argv);
+    let Some(path) = first
+        .ok()
+        .and_then(|matches| matches.get_one::<PathBuf>("services_file").cloned())
+    else {
+        return Ok(Arc::new(Services::builtin()));
+    };
+    let text = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let mut services = Services::builtin();
+    services.extend(Services::parse(&text).map_err(|e| anyhow!("{}: {e}", path.display()))?);
+    Ok(Arc::new(services))
+}
+
+/// the command line, with port lists naming ports from `services`
+fn command_with(services: &Arc<Services>) -> clap::Command {
+    let services = services.clone();
+    let parser = ValueParser::new(move |s: &str| PortSpec::parse_with(s, &services));
+    port_lists(Args::command(), &parser)
+}
+
+/// `command` with every port list, its subcommands' too, parsed by `parser`
+fn port_lists(mut command: clap::Command, parser: &ValueParser) -> clap::Command {
+    for id in ["ports", "exclude_ports", "source_port"] {
+        if command.get_arguments().any(|arg| arg.get_id() == id) {
+            command = command.mut_arg(id, |arg| arg.value_parser(parser.clone()));
+        }
+    }
+    let subcommands: Vec<String> = command
+        .get_subcommands()
+        .map(|sub| sub.get_name().to_string())
+        .collect();
+    for name in subcommands {
+        command = command.mut_subcommand(name, |sub| port_lists(sub, parser));
+    }
+    command
+}
+
+/// fill in what the config files set, leaving alone anything given on the
+/// command line; the config comes back for what only it can say
+fn apply_config(args: &mut Args, matches: &ArgMatches) -> Result<Config> {
+    let config = load_config(args)?;
+    let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    if let (Some(ms), false) = (config.timeout_ms, given("timeout_ms")) {
+        args.timeout_ms = ms;
+    }
+    if let (Some(n), false) = (config.threads, given("threads")) {
+        args.threads = Some(n);
+        if !given("parallel") {
+            args.parallel = n > 1;
+        }
+    }
+    // a format guessed from the --output extension wins, and --watch
+    // prints its own lines
+    let format_given = given("format") || given("output") || args.watch.is_some();
+    if let (Some(format), false) = (&config.format, format_given) {
+        args.format = Some(format.clone());
+    }
+    if args.webhook.is_empty() {
+        args.webhook = config.webhooks.clone();
+    }
+    Ok(config)
+}
+
+/// fill in what `--profile` sets, over what the config files set but
+/// leaving alone anything given on the command line
+fn apply_profile(args: &mut Args, matches: &ArgMatches, config: &Config) -> Result<()> {
+    let Some(name) = args.profile.as_ref().or(config.profile.as_ref()) else {
+        return Ok(());
+    };
+    let profile = config.profile(name).map_err(|e| anyhow!(e))?;
+    let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    if !given("ports") && !given("top_ports") {
+        if let Some(ports) = profile.ports {
+            args.ports = ports;
+        }
+        args.top_ports = profile.top_ports;
+    }
+    // -T on the command line wins over the profile's template, and over
+    // what the profile sets next to it
+    if !given("timing") {
+        if let Some(timing) = profile.timing {
+            args.timing = Some(timing);
+            apply_timing(args, matches);
+        }
+        if let (Some(ms), false) = (profile.timeout_ms, given("timeout_ms")) {
+            args.timeout_ms = ms;
+        }
+        if let (Some(n), false) = (profile.retries, given("retries")) {
+            args.retries = n;
+        }
+    }
+    args.banner |= profile.banner;
+    args.tls_probe |= profile.tls_probe;
+    args.http_probe |= profile.http_probe;
+    args.version_detect |= profile.version_detect;
+    Ok(())
+}
+
+/// fill in what `-T` sets, leaving alone anything given on the command line
+fn apply_timing(args: &mut Args, matches: &ArgMatches) {
+    let Some(timing) = args.timing else {
+        return;
+    };
+    let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    if !given("timeout_ms") {
+        args.timeout_ms = timing.timeout().as_millis() as u64;
+    }
+    if !given("retries") {
+        args.retries = timing.retries();
+    }
+    if !given("scan_delay_ms") {
+        args.scan_delay_ms = timing.scan_delay().as_millis() as u64;
+    }
+    if !given("concurrency") {
+        args.concurrency = timing.concurrency();
+    }
+    // the thread engine gets the same concurrency as worker threads
+    if !given("threads") {
+        args.threads = Some(timing.concurrency());
+    }
+    if !given("parallel") {
+        args.parallel = args.threads.unwrap_or(1) > 1;
+    }
+}
+
+fn main() -> Result<()> {
+    let started = Instant::now();
+    let argv: Vec<OsString> = std::env::args_os().collect();
+    let mut services = load_services(&argv)?;
+    let mut matches = command_with(&services).get_matches_from(&argv);
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    // a resumed scan runs again with the command line it was started with
+    let resumed = match args.resume.take() {
+        Some(path) => {
+            let saved = load_scan(&path)?;
+            if saved.command.is_empty() {
+                bail!("{} isn't a --checkpoint file", path.display());
+            }
+            let argv: Vec<OsString> = saved.command.iter().map(OsString::from).collect();
+            services = load_services(&argv)?;
+            matches = command_with(&services)
+                .try_get_matches_from(&argv)
+                .with_context(|| format!("can't resume {}", path.display()))?;
+            args = Args::from_arg_matches(&matches)?;
+            args.checkpoint = Some(path);
+            Some(saved)
+        }
+        None => None,
+    };
+    let config = apply_config(&mut args, &matches)?;
+    apply_timing(&mut args, &matches);
+    apply_profile(&mut args, &matches, &config)?;
+    let dashboard = start_logging(&args, &services)?;
+    if (args.quiet && args.progress_format != "json") || args.tui {
+        args.progress = false;
+    }
+    match &args.command {
+        Some(Command::Discover(discover_args)) => run_discover(discover_args, &config),
+        Some(Command::Wait(wait_args)) => run_wait(wait_args, &config),
+        Some(Command::Diff(diff_args)) => {
+            let palette = Palette::new(args.color.enabled(io::stdout().is_terminal()));
+            run_diff(diff_args, palette)
         }
+        Some(Command::Audit(audit_args)) => run_audit(audit_args, &config),
+        Some(Command::Query(query_args)) => run_query(query_args),
+        Some(Command::Merge(merge_args)) => run_merge(merge_args),
+        Some(Command::Schedule(schedule_args)) => run_schedule(schedule_args, &config),
+        Some(Command::Serve(serve_args)) => run_serve(serve_args, &config),
+        None => run_scan(
+            &args,
+            &config,
+            &services,
+            resumed.as_ref(),
+            dashboard,
+            started,
+        ),
     }
+}
+
+/// send messages to stderr, or to the --tui screen, and to --log-file;
+/// the screen comes back when there is one
+fn start_logging(args: &Args, services: &Arc<Services>) -> Result<Option<Dashboard>> {
+    let level = match (args.quiet, args.verbose) {
+        (true, _) => Level::WARN,
+        (false, 0) => Level::INFO,
+        (false, 1) => Level::DEBUG,
+        (false, _) => Level::TRACE,
+    };
+    // --tui shows messages on its screen
+    let dashboard = args.tui.then(|| Dashboard::new(services.clone()));
+    let screen = dashboard
+        .clone()
+        .map(|dashboard| Console.layer(dashboard, level));
+    let stderr = dashboard.is_none().then(|| Console.layer(UnderBars, level));
+    let file = match &args.log_file {
+        Some(path) => Some(
+            LogFile::create(path, args.log_format, args.log_max_size, args.log_keep)
+                .with_context(|| format!("can't open {}", path.display()))?
+                .layer(),
+        ),
+        None => None,
+    };
+    tracing_subscriber::registry()
+        .with(screen)
+        .with(stderr)
+        .with(file)
+        .try_init()
+        .map_err(|e| anyhow!(e))?;
+    Ok(dashboard)
+}
 
+/// refuse option values the scan can't run with, before anything is
+/// resolved or probed
+fn check_scan_args(args: &Args) -> Result<()> {
+    if args.timeout_ms == 0 {
+        bail!("timeout must be at least 1 ms");
+    }
+    if !(1.0..=100.0).contains(&args.retry_timeout_growth) {
+        bail!("--retry-timeout-growth must be between 1 and 100");
+    }
+    if args.min_rtt_timeout == Some(0) || args.max_rtt_timeout == Some(0) {
+        bail!("rtt timeouts must be at least 1 ms");
+    }
+    if let (Some(min), Some(max)) = (args.min_rtt_timeout, args.max_rtt_timeout) {
+        if min > max {
+            bail!("--min-rtt-timeout ({min}) is above --max-rtt-timeout ({max})");
+        }
+    }
+    if args.banner && (args.banner_bytes == 0 || args.banner_timeout_ms == 0) {
+        bail!("banner-bytes and banner-timeout-ms must be at least 1");
+    }
+    if args.tls_probe && args.tls_timeout_ms == 0 {
+        bail!("tls-timeout-ms must be at least 1");
+    }
+    if args.http_probe && args.http_timeout_ms == 0 {
+        bail!("http-timeout-ms must be at least 1");
+    }
+    if args.version_detect && args.version_timeout_ms == 0 {
+        bail!("version-timeout-ms must be at least 1");
+    }
+    if args.udp && args.scan_type.is_raw() {
+        bail!(
+            "--scan-type {} is for tcp and can't be used with --udp",
+            args.scan_type
+        );
+    }
+    let proxied = args.tor || !args.proxy.is_empty();
+    if proxied && (args.udp || args.scan_type.is_raw()) {
+        bail!("--proxy and --tor only tunnel tcp connect scans");
+    }
+    if proxied && args.proxy_timeout_ms == 0 {
+        bail!("proxy-timeout-ms must be at least 1");
+    }
+    if !args.no_ping && !proxied && args.ping_timeout_ms == 0 {
+        bail!("ping-timeout-ms must be at least 1");
+    }
+    if args.watch.is_some_and(|every| every.is_zero()) {
+        bail!("--watch needs an interval above zero");
+    }
+    if args.open_as_found
+        && args.output.is_none()
+        && args.format.as_deref().unwrap_or("table") != "table"
+    {
+        bail!("--open-as-found prints to stdout, where the report goes too; add --output FILE");
+    }
+    if !args.email.email_to.is_empty() && args.watch.is_none() {
+        bail!("--email-to mails what changed between scans, so it needs --watch");
+    }
     Ok(())
 }
+
+/// the built-in service probes, and those of --service-probes, for
+/// --version-detect
+fn load_service_probes(args: &Args) -> Result<Option<Arc<ServiceProbes>>> {
+    if !args.version_detect {
+        return Ok(None);
+    }
+    let mut probes = ServiceProbes::builtin();
+    if let Some(path) = &args.service_probes {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let extra = ServiceProbes::parse(&text).map_err(|e| anyhow!("{}: {e}", path.display()))?;
+        probes.extend(extra);
+    }
+    Ok(Some(Arc::new(probes)))
+}
+
+/// the ports of --ports or --top-ports, less --exclude-ports
+fn scan_ports(args: &Args, protocol: Protocol) -> Result<PortSpec> {
+    let port_spec = match args.top_ports {
+        Some(n) => {
+            let known = match protocol {
+                Protocol::Tcp => services::TOP_PORTS.len(),
+                Protocol::Udp => services::TOP_UDP_PORTS.len(),
+            };
+            if n == 0 || n > known {
+                bail!("top-ports must be between 1 and {known}");
+            }
+            match protocol {
+                Protocol::Tcp => PortSpec::top(n),
+                Protocol::Udp => PortSpec::top_udp(n),
+            }
+        }
+        None => args.ports.clone(),
+    };
+    let port_spec = match &args.exclude_ports {
+        Some(excluded) => port_spec.difference(excluded),
+        None => port_spec,
+    };
+    if port_spec.is_empty() {
+        bail!("no ports left to scan after exclusions");
+    }
+    Ok(port_spec)
+}
+
+/// how probes leave: directly, or through --proxy or --tor, from
+/// --source-ip, --source-port and --interface. it is tried out first.
+fn build_transport(args: &Args) -> Result<Transport> {
+    let proxied = args.tor || !args.proxy.is_empty();
+    let mut transport = Transport::direct();
+    if proxied {
+        transport = if args.tor {
+            Transport::tor()
+        } else {
+            Transport::chain(args.proxy.clone())
+        };
+        transport = transport.hop_timeout(Duration::from_millis(args.proxy_timeout_ms));
+    }
+    if let Some(source) = args.source_ip {
+        transport = transport.source_ip(source);
+    }
+    if let Some(ports) = &args.source_port {
+        transport = transport.source_ports(ports.iter());
+    }
+    if let Some(name) = &args.interface {
+        transport = transport.interface(name.clone());
+    }
+    let checked = transport.check();
+    if args.tor {
+        checked.context("can't use tor (is it running?)")?;
+    } else if proxied {
+        checked.context("the proxy chain doesn't work")?;
+    } else {
+        checked?;
+    }
+    Ok(transport)
+}
+
+/// the --baseline scan, checked against what it is compared for
+fn load_baseline(args: &Args) -> Result<Option<SavedScan>> {
+    match &args.baseline {
+        Some(_) if args.chat.is_empty() && args.fail_on != FailOn::Change => {
+            bail!("--baseline is only compared with for --chat and --fail-on change")
+        }
+        Some(path) => Ok(Some(load_scan(path)?)),
+        None if args.fail_on == FailOn::Change => {
+            bail!("--fail-on change needs a --baseline scan to compare with")
+        }
+        None => Ok(None),
+    }
+}
+
+/// every place the scan reports to: stdout or --output (or the --watch
+/// lines), then --store, --metrics, --syslog, --webhook, --email-to and
+/// --chat
+fn build_reporter(
+    args: &Args,
+    report_opts: &ReportOptions,
+    dashboard: Option<&Dashboard>,
+    palette: Palette,
+    baseline: Option<&SavedScan>,
+) -> Result<Box<dyn Reporter>> {
+    let reporter: Box<dyn Reporter> = if args.watch.is_some() {
+        let on_stdout = ReportOptions {
+            color: palette.is_on(),
+            ..report_opts.clone()
+        };
+        Box::new(WatchReporter::new(Box::new(io::stdout()), &on_stdout))
+    } else {
+        create_reporter(args, report_opts, dashboard)?
+    };
+    let reporter = match &args.store {
+        Some(path) => {
+            let store = StoreReporter::open(path, report_opts)
+                .with_context(|| format!("can't store scans in {}", path.display()))?;
+            Box::new(MultiReporter::new(vec![reporter, Box::new(store)]))
+        }
+        None => reporter,
+    };
+    let reporter = match args.metrics {
+        Some(addr) => {
+            let metrics = Arc::new(Metrics::new());
+            let local = serve_metrics(addr, metrics.clone())
+                .with_context(|| format!("can't serve metrics on {addr}"))?;
+            info!("serving metrics on http://{local}/metrics");
+            Box::new(MultiReporter::new(vec![
+                reporter,
+                Box::new(MetricsReporter::new(metrics)),
+            ]))
+        }
+        None => reporter,
+    };
+    let reporter = match &args.syslog {
+        Some(target) => {
+            let to_syslog = SyslogReporter::connect(target, args.syslog_facility, report_opts)
+                .with_context(|| format!("can't send to syslog at {target}"))?;
+            Box::new(MultiReporter::new(vec![reporter, Box::new(to_syslog)]))
+        }
+        None => reporter,
+    };
+    let reporter = if args.webhook.is_empty() {
+        reporter
+    } else {
+        let mut reporters = vec![reporter];
+        for webhook in &args.webhook {
+            let mut webhook = webhook.clone().retries(args.webhook_retries);
+            if let Some(secret) = &args.webhook_secret {
+                webhook = webhook.secret(secret);
+            }
+            let mut to_webhook = WebhookReporter::new(webhook, report_opts);
+            if args.watch.is_some() {
+                to_webhook = to_webhook.watch(Box::new(io::stderr()));
+            }
+            reporters.push(Box::new(to_webhook));
+        }
+        Box::new(MultiReporter::new(reporters))
+    };
+    let reporter = match args.email.reporter(report_opts)? {
+        Some(to_email) => Box::new(MultiReporter::new(vec![reporter, Box::new(to_email)])),
+        None => reporter,
+    };
+    if args.chat.is_empty() {
+        return Ok(reporter);
+    }
+    let mut to_chat = ChatReporter::new(args.chat.clone(), report_opts);
+    if let Some(baseline) = baseline {
+        to_chat = to_chat.baseline(baseline.clone());
+    }
+    if args.watch.is_some() {
+        to_chat = to_chat.watch(Box::new(io::stderr()));
+    }
+    Ok(Box::new(MultiReporter::new(vec![
+        reporter,
+        Box::new(to_chat),
+    ])))
+}
+
+/// the --checkpoint file, with every port as json lines so it can be read
+/// back like a saved scan; a resumed scan adds to the file it came from
+fn open_checkpoint(
+    args: &Args,
+    targets: &[Target],
+    resumed: bool,
+    report_opts: &ReportOptions,
+) -> Result<Option<Mutex<JsonlReporter>>> {
+    let Some(path) = &args.checkpoint else {
+        return Ok(None);
+    };
+    if targets.iter().any(|t| t.to_string() == "-") {
+        bail!("--checkpoint can't keep targets read from stdin, use --target-file")
+    }
+    let mut file = if resumed {
+        File::options().append(true).open(path)
+    } else {
+        File::create(path)
+    }
+    .map(BufWriter::new)
+    .with_context(|| format!("failed to open {}", path.display()))?;
+    if !resumed {
+        let argv: Vec<String> = std::env::args().collect();
+        writeln!(file, "{}", json!({ "type": "checkpoint", "args": argv }))?;
+        file.flush()?;
+    }
+    let opts = ReportOptions {
+        show_closed: true,
+        ..report_opts.clone()
+    };
+    Ok(Some(Mutex::new(JsonlReporter::new(Box::new(file), &opts))))
+}
+
+/// what the scanner takes from the command line alone: retries, pacing,
+/// timeouts, the probes on open ports and when to stop
+fn scanner_options(args: &Args, mut builder: ScannerBuilder) -> ScannerBuilder {
+    let backoff = match args.retry_backoff_ms.map(Duration::from_millis) {
+        None => Backoff::None,
+        Some(base) if args.retry_exponential => Backoff::Exponential {
+            base,
+            max: DEFAULT_MAX_BACKOFF.max(base),
+        },
+        Some(delay) => Backoff::Fixed(delay),
+    };
+    builder = builder
+        .timeout(Duration::from_millis(args.timeout_ms))
+        .retry_policy(RetryPolicy {
+            retries: args.retries,
+            backoff,
+            jitter: args.retry_jitter,
+            timeout_growth: args.retry_timeout_growth,
+            retry_refused: args.retry_refused,
+        })
+        .engine(args.engine)
+        .scan_delay(Duration::from_millis(args.scan_delay_ms));
+    if let Some(rate) = args.max_rate {
+        builder = builder.max_rate(rate);
+    }
+    if args.adaptive_concurrency {
+        builder = builder.adaptive_concurrency(args.min_concurrency);
+    }
+    if args.min_rtt_timeout.is_some() || args.max_rtt_timeout.is_some() {
+        let min = args
+            .min_rtt_timeout
+            .map_or(DEFAULT_MIN_RTT_TIMEOUT, Duration::from_millis);
+        let max = args
+            .max_rtt_timeout
+            .map_or(DEFAULT_MAX_RTT_TIMEOUT, Duration::from_millis);
+        builder = builder.adaptive_timeout(min, max);
+    }
+    if args.banner {
+        builder = builder.banner(BannerOptions {
+            max_bytes: args.banner_bytes,
+            timeout: Duration::from_millis(args.banner_timeout_ms),
+        });
+    }
+    if args.tls_probe {
+        builder = builder.tls_probe(Duration::from_millis(args.tls_timeout_ms));
+    }
+    if args.http_probe {
+        builder = builder.http_probe(Duration::from_millis(args.http_timeout_ms));
+    }
+    let max_open = if args.first_open {
+        Some(1)
+    } else {
+        args.max_open
+    };
+    if let Some(n) = max_open {
+        builder = builder.max_open(usize::try_from(n).unwrap_or(usize::MAX));
+    }
+    if let Some(limit) = args.host_timeout {
+        builder = builder.host_timeout(limit);
+    }
+    if !args.no_skip_dead && !args.udp {
+        builder = builder.skip_dead_hosts(args.dead_after);
+    }
+    builder
+}
+
+/// say why a host's scan stopped early; whether it was cut short, so the
+/// results can't pass for complete
+fn log_stopped(ip: IpAddr, stopped: Option<Stopped>, dead_after: usize) -> bool {
+    match stopped {
+        Some(Stopped::HostDown) => info!(
+            "{ip}: no answer to the first {dead_after} probes, skipped the other ports \
+             (down or firewalled? --no-skip-dead scans them anyway)"
+        ),
+        Some(Stopped::HostTimeout) => {
+            info!("{ip}: --host-timeout reached, ports not yet answered are reported unknown");
+            return true;
+        }
+        Some(Stopped::EnoughOpen) => {
+            info!("{ip}: enough open ports found, ports not yet probed are reported unknown")
+        }
+        Some(Stopped::Skipped) => {
+            info!("{ip}: skipped, ports not yet probed are reported unknown");
+            return true;
+        }
+        _ => {}
+    }
+    false
+}
+
+/// the scan itself, when no subcommand is given: once, or every --watch
+/// interval
+fn run_scan(
+    args: &Args,
+    config: &Config,
+    services: &Arc<Services>,
+    resumed: Option<&SavedScan>,
+    dashboard: Option<Dashboard>,
+    started: Instant,
+) -> Result<()> {
+    check_scan_args(args)?;
+    let service_probes = load_service_probes(args)?;
+    let default_threads = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4);
+    let threads = args.threads.unwrap_or(default_threads).max(1);
+
+    let targets = collect_targets(
+        args.target.iter().chain(&args.extra_targets),
+        args.target_file.as_deref(),
+    )?;
+    let mut resolver = args.dns.resolver()?;
+    // names resolve to addresses the source can reach
+    if let (Some(source), IpFamily::Any) = (args.source_ip, resolver.ip_family()) {
+        resolver = resolver.family(match source {
+            IpAddr::V4(_) => IpFamily::V4,
+            IpAddr::V6(_) => IpFamily::V6,
+        });
+    }
+    let mut hosts = expand_targets_with(&targets, args.all_ips, &resolver)?;
+
+    let excluded = collect_exclusions(&args.exclude, args.exclude_file.as_deref())?;
+    let before_exclusion = hosts.len();
+    exclude_hosts(&mut hosts, &excluded);
+    let excluded_hosts = before_exclusion - hosts.len();
+    if hosts.is_empty() {
+        bail!("no hosts left to scan after exclusions");
+    }
+    config.check_allowed(&hosts)?;
+    let host_count = hosts.len();
+    if let Some(source) = args.source_ip {
+        if let Some(other) = hosts.iter().find(|h| h.ip.is_ipv4() != source.is_ipv4()) {
+            bail!(
+                "can't reach {} from --source-ip {source}: different ip family",
+                other.ip
+            );
+        }
+    }
+
+    let protocol = if args.udp {
+        Protocol::Udp
+    } else {
+        Protocol::Tcp
+    };
+    let proxied = args.tor || !args.proxy.is_empty();
+    let scan_type = if args.scan_type.is_raw() && !raw_sockets_available() {
+        warn!(
+            "{} scan needs raw sockets (root or CAP_NET_RAW), using a connect scan",
+            args.scan_type
+        );
+        ScanType::Connect
+    } else {
+        args.scan_type
+    };
+    let port_spec = scan_ports(args, protocol)?;
+    let mut rng = args.seed.map_or_else(Rng::from_clock, Rng::new);
+    let mut probe_order = if args.randomize_ports {
+        port_spec.shuffled(&mut rng)
+    } else {
+        port_spec.to_vec()
+    };
+    if args.common_first {
+        services::common_first(&mut probe_order, protocol == Protocol::Udp);
+    }
+    let transport = build_transport(args)?;
+
+    // ctrl-c stops the scan gently, a second one at once
+    let stopping = Arc::new(AtomicBool::new(false));
+    let cancelled = Arc::new(AtomicBool::new(false));
+    stop_on_ctrlc(&stopping, &cancelled, dashboard.is_none())?;
+    // a stop either way, once the scan is out of probes or its report
+    let interrupted = || stopping.load(Ordering::Relaxed) || cancelled.load(Ordering::Relaxed);
+    let expired = Arc::new(AtomicBool::new(false));
+    if let Some(limit) = args.max_scan_time {
+        cancel_at(started + limit, &cancelled, &stopping, &expired);
+    }
+
+    // the ping would go out directly, around the proxy
+    if !args.no_ping && !proxied {
+        ping_hosts(
+            &mut hosts,
+            Duration::from_millis(args.ping_timeout_ms),
+            &transport,
+            &stopping,
+        )?;
+        if expired.load(Ordering::Relaxed) {
+            info!("--max-scan-time reached while pinging hosts, nothing scanned");
+            std::process::exit(EXIT_INCOMPLETE);
+        }
+        if interrupted() {
+            bail!("cancelled while pinging hosts");
+        }
+        let down = host_count - hosts.len();
+        if hosts.is_empty() {
+            bail!("no host answered the ping (--no-ping scans them anyway)");
+        }
+        if down > 0 {
+            info!(
+                "{down} of {host_count} hosts didn't answer the ping, skipping them \
+                 (--no-ping scans them anyway)"
+            );
+        }
+    }
+
+    if args.resolve_ptr {
+        resolve_ptr_names(&mut hosts, &resolver);
+    }
+    if args.randomize_hosts {
+        rng.shuffle(&mut hosts);
+    }
+
+    let concurrency = match args.engine {
+        _ if scan_type.is_raw() => args.concurrency,
+        Engine::Threads if args.parallel => threads,
+        Engine::Threads => 1,
+        Engine::Async => args.concurrency,
+    };
+    // past the descriptor limit connects fail, and those ports would be
+    // misreported; raw scans share one socket
+    let concurrency = match socket_limit() {
+        Some(limit) if concurrency > limit && !scan_type.is_raw() => {
+            warn!(
+                "{concurrency} probes at once would run out of file descriptors, \
+                 using {limit} (raise ulimit -n for more)"
+            );
+            limit
+        }
+        _ => concurrency,
+    };
+
+    let report_opts = ReportOptions {
+        // an ack scan never finds open ports; its answer is the other states
+        show_closed: args.show_closed || scan_type == ScanType::Ack,
+        banners: args.banner,
+        tls: args.tls_probe,
+        http: args.http_probe,
+        versions: args.version_detect,
+        ptr: args.resolve_ptr,
+        protocol,
+        scan_type,
+        color: false,
+        services: services.clone(),
+    };
+    let palette = Palette::new(args.color.enabled(io::stdout().is_terminal()));
+    let baseline = load_baseline(args)?;
+    let reporter = build_reporter(
+        args,
+        &report_opts,
+        dashboard.as_ref(),
+        palette,
+        baseline.as_ref(),
+    )?;
+    let reporter = Mutex::new(reporter);
+    let write_error: Mutex<Option<io::Error>> = Mutex::new(None);
+    let mut checkpoint = open_checkpoint(args, &targets, resumed.is_some(), &report_opts)?;
+
+    // every host and port counts into it, for the bars and --tui
+    let progress = Arc::new(Progress::new());
+    // `ip port/protocol service` of each open port this round, for SIGUSR1
+    let found: Arc<Mutex<Vec<String>>> = Arc::default();
+    if !args.tui {
+        status_on_sigusr1(progress.clone(), found.clone())?;
+    }
+
+    let mut builder = scanner_options(args, Scanner::builder())
+        .protocol(protocol)
+        .scan_type(scan_type)
+        .concurrency(concurrency)
+        .cancel_flag(cancelled.clone())
+        .stop_flag(stopping.clone())
+        .transport(transport)
+        .track(progress.clone());
+    let parallel_hosts = usize::from(args.parallel_hosts).min(hosts.len());
+    let shared_cap = match args.max_in_flight {
+        Some(cap) => Some(usize::try_from(cap).unwrap_or(usize::MAX)),
+        // hosts side by side share what one host would have had
+        None if parallel_hosts > 1 => Some(concurrency.max(parallel_hosts)),
+        None => None,
+    };
+    if let Some(cap) = shared_cap {
+        builder = builder.max_in_flight(cap);
+    }
+    if let Some(probes) = service_probes {
+        builder = builder.version_detect(probes, Duration::from_millis(args.version_timeout_ms));
+    }
+    let scanner = builder.build();
+    let mut screen = match &dashboard {
+        Some(dashboard) => Some(
+            dashboard
+                .show(&scanner, progress.clone())
+                .context("can't start --tui (it needs a terminal)")?,
+        ),
+        None => None,
+    };
+
+    // --watch goes round again; every other scan is a single round
+    loop {
+        progress.start(hosts.len(), port_spec.len());
+        lock(&found).clear();
+        let run_started = Instant::now();
+        let info = ScanInfo {
+            command: std::env::args().collect::<Vec<_>>().join(" "),
+            targets: targets.iter().map(|t| t.to_string()).collect(),
+            host_count,
+            excluded_hosts,
+            ports: match args.top_ports {
+                Some(n) => format!("top {n}"),
+                None => port_spec.to_string(),
+            },
+            port_count: port_spec.len(),
+            excluded_ports: args.exclude_ports.as_ref().map(|p| p.to_string()),
+            timeout_ms: args.timeout_ms,
+            retries: args.retries,
+            engine: args.engine,
+            concurrency,
+            started: SystemTime::now(),
+        };
+        lock(&reporter)
+            .begin(&info)
+            .context("failed to write report")?;
+
+        let summary = ScanSummary {
+            targets: targets.len(),
+            hosts_total: host_count,
+            ..ScanSummary::default()
+        };
+
+        // hosts go to the --parallel-hosts workers one at a time; a single
+        // worker takes them in order
+        let parallel = parallel_hosts > 1;
+        let next_host = AtomicUsize::new(0);
+        let summary = Mutex::new(summary);
+        // for --fail-on: this round's hosts, and whether any was cut short
+        // by running out of time or being skipped
+        let seen: Mutex<Vec<SavedHost>> = Mutex::new(Vec::new());
+        let cut_short = AtomicBool::new(false);
+        // side by side, one bar covers every host; json lines cover the
+        // whole run either way
+        let json_progress = args.progress_format == "json";
+        let run_done = Arc::new(AtomicBool::new(false));
+        let run_progress = match args.progress {
+            true if json_progress => Some(start_json_progress(
+                progress.clone(),
+                run_done.clone(),
+                cancelled.clone(),
+            )),
+            true if parallel => Some(start_progress(
+                progress.clone(),
+                false,
+                true,
+                run_done.clone(),
+                cancelled.clone(),
+            )),
+            _ => None,
+        };
+
+        let note_found = |ip: IpAddr, r: &ScanResult| {
+            if r.is_open() {
+                let port = format!("{ip} {}/{protocol}", r.port);
+                lock(&found).push(match r.service_name_in(services) {
+                    Some(name) => format!("{port} {name}"),
+                    None => port,
+                });
+            }
+        };
+        let scan_hosts = || -> Result<()> {
+            while let Some(host) = hosts.get(next_host.fetch_add(1, Ordering::Relaxed)) {
+                if interrupted() {
+                    break;
+                }
+                let started = Instant::now();
+                let done = Arc::new(AtomicBool::new(false));
+                // several hosts report at once, so say which one
+                let which = if parallel {
+                    format!("{}: ", host.ip)
+                } else {
+                    String::new()
+                };
+
+                // what a resumed checkpoint already has for this host
+                let before = resumed.and_then(|saved| saved.host(host.ip));
+                let answered: HashSet<u16> = before
+                    .map(|h| h.results.iter().map(|r| r.port).collect())
+                    .unwrap_or_default();
+                let skip = dashboard
+                    .as_ref()
+                    .map(|d| d.host_started(host.ip, probe_order.len()));
+                if let Some(before) = before {
+                    progress.carry(host.ip, answered.len());
+                    for r in &before.results {
+                        if let Some(dashboard) = &dashboard {
+                            dashboard.result(host.ip, r);
+                        }
+                        note_found(host.ip, r);
+                        if args.open_as_found && r.is_open() {
+                            print_found(host.ip, protocol, r, services, palette);
+                        }
+                        lock(&reporter)
+                            .result(host, r)
+                            .context("failed to write report")?;
+                    }
+                }
+
+                // one at a time, the host's bar goes over the whole run's
+                let progress_handle = if args.progress && !parallel && !json_progress {
+                    Some(start_progress(
+                        progress.clone(),
+                        true,
+                        hosts.len() > 1,
+                        done.clone(),
+                        cancelled.clone(),
+                    ))
+                } else {
+                    None
+                };
+
+                let outcome = if before.is_some_and(|h| !h.incomplete) {
+                    ScanOutcome {
+                        results: Vec::new(),
+                        stopped: None,
+                    }
+                } else {
+                    let ports = probe_order
+                        .iter()
+                        .copied()
+                        .filter(|port| !answered.contains(port));
+                    let scanner = match skip {
+                        Some(skip) => scanner.for_host(host).with_skip_flag(skip),
+                        None => scanner.for_host(host),
+                    };
+                    scanner.scan_host(host.ip, ports, |r| {
+                        // once cancelled, the ports left come back unprobed
+                        // and must stay out of the checkpoint; once stopped,
+                        // only the ones in flight still get an answer
+                        let unprobed = cancelled.load(Ordering::SeqCst)
+                            || (stopping.load(Ordering::SeqCst) && r.state == PortState::Unknown);
+                        let saved = match &checkpoint {
+                            Some(c) if !unprobed => lock(c).result(host, r),
+                            _ => Ok(()),
+                        };
+                        if args.open_as_found && r.is_open() {
+                            print_found(host.ip, protocol, r, services, palette);
+                        }
+                        if let Some(dashboard) = &dashboard {
+                            dashboard.result(host.ip, r);
+                        }
+                        note_found(host.ip, r);
+                        if let Err(e) = saved.and_then(|_| lock(&reporter).result(host, r)) {
+                            // output is gone (e.g. a closed pipe), no point scanning on
+                            lock(&write_error).get_or_insert(e);
+                            cancelled.store(true, Ordering::SeqCst);
+                        }
+                    })
+                };
+                if let Some(e) = lock(&write_error).take() {
+                    return Err(e).context("failed to write report");
+                }
+                let mut results = outcome.results;
+                if let Some(before) = before {
+                    results.extend(before.results.iter().cloned());
+                    results.sort_by_key(|r| r.port);
+                }
+
+                done.store(true, Ordering::Relaxed);
+                if let Some(h) = progress_handle {
+                    let _ = h.join();
+                }
+                if let Some(dashboard) = &dashboard {
+                    dashboard.host_finished(host.ip, outcome.stopped);
+                }
+                if log_stopped(host.ip, outcome.stopped, args.dead_after) {
+                    cut_short.store(true, Ordering::Relaxed);
+                }
+
+                let incomplete = interrupted();
+                if expired.load(Ordering::Relaxed) {
+                    info!("{which}--max-scan-time reached, stopping (results are incomplete)");
+                } else if cancelled.load(Ordering::Relaxed) {
+                    info!("{which}scan cancelled (results may be incomplete)");
+                } else if incomplete {
+                    info!(
+                        "{which}scan stopped, ports not yet probed are reported unknown \
+                         (results are incomplete)"
+                    );
+                } else {
+                    info!("{which}scan complete in {:?}", started.elapsed());
+                }
+
+                let report = HostReport {
+                    host,
+                    results: &results,
+                    elapsed: started.elapsed(),
+                    incomplete,
+                };
+                lock(&reporter)
+                    .host(&report)
+                    .context("failed to write report")?;
+                if let Some(c) = &checkpoint {
+                    if !incomplete {
+                        lock(c)
+                            .host(&report)
+                            .context("failed to write checkpoint")?;
+                    }
+                }
+
+                if baseline.is_some() && args.fail_on == FailOn::Change {
+                    lock(&seen).push(SavedHost {
+                        host: host.clone(),
+                        results: results.clone(),
+                        elapsed: report.elapsed,
+                        incomplete,
+                        finished: None,
+                    });
+                }
+
+                let open = report.open_count();
+                let mut summary = lock(&summary);
+                summary.hosts_scanned += 1;
+                summary.open_ports += open;
+                if open > 0 {
+                    summary.hosts_with_open += 1;
+                }
+
+                if incomplete {
+                    summary.cancelled = true;
+                    break;
+                }
+            }
+            Ok(())
+        };
+        let scanned_all = if parallel {
+            std::thread::scope(|s| {
+                let workers: Vec<_> = (0..parallel_hosts).map(|_| s.spawn(scan_hosts)).collect();
+                workers
+                    .into_iter()
+                    .try_for_each(|w| w.join().expect("host worker panicked"))
+            })
+        } else {
+            scan_hosts()
+        };
+        run_done.store(true, Ordering::Relaxed);
+        if let Some(h) = run_progress {
+            let _ = h.join();
+        }
+        // the rest of the report, and the messages shown, go to the
+        // terminal as usual
+        drop(screen.take());
+        scanned_all?;
+
+        let mut summary = summary.into_inner().unwrap_or_else(|e| e.into_inner());
+        summary.elapsed = run_started.elapsed();
+        lock(&reporter)
+            .finish(&summary)
+            .context("failed to write report")?;
+        if args.notify {
+            notify_done(&summary, expired.load(Ordering::Relaxed));
+        }
+
+        if let Some(path) = &args.checkpoint {
+            if interrupted() {
+                info!(
+                    "progress saved, carry on with: port_scanner --resume {}",
+                    path.display()
+                );
+            } else {
+                // a finished scan has nothing left to resume
+                drop(checkpoint.take());
+                std::fs::remove_file(path)
+                    .with_context(|| format!("failed to remove {}", path.display()))?;
+            }
+        }
+
+        if expired.load(Ordering::Relaxed) {
+            // the report is written; say why it stopped short to whatever
+            // runs the scan, like a ci job
+            drop(reporter);
+            std::process::exit(EXIT_INCOMPLETE);
+        }
+        let incomplete = summary.cancelled || cut_short.load(Ordering::Relaxed);
+        let seen = seen.into_inner().unwrap_or_else(|e| e.into_inner());
+        let status = fail_on_status(
+            args,
+            &summary,
+            incomplete,
+            baseline.as_ref(),
+            &port_spec,
+            seen,
+        );
+        if status != 0 {
+            drop(reporter);
+            std::process::exit(status);
+        }
+        let Some(every) = args.watch else {
+            return Ok(());
+        };
+        // sleep in short slices so ctrl-c is noticed quickly
+        let next_round = run_started + every;
+        while !interrupted() && Instant::now() < next_round {
+            std::thread::sleep(
+                next_round
+                    .saturating_duration_since(Instant::now())
+                    .min(Duration::from_millis(100)),
+            );
+        }
+        if interrupted() {
+            return Ok(());
+        }
+    }
+}
+
+/// the exit status --fail-on asks for after a round: EXIT_FAILED for open
+/// ports (or ports changed since the --baseline), EXIT_INCOMPLETE when the
+/// round didn't finish, else 0
+fn fail_on_status(
+    args: &Args,
+    summary: &ScanSummary,
+    incomplete: bool,
+    baseline: Option<&SavedScan>,
+    ports: &PortSpec,
+    seen: Vec<SavedHost>,
+) -> i32 {
+    if args.fail_on == FailOn::None {
+        return 0;
+    }
+    let failed = match baseline {
+        Some(baseline) if args.fail_on == FailOn::Change => {
+            let scan = SavedScan {
+                ports: Some(ports.clone()),
+                hosts: seen,
+                ..SavedScan::default()
+            };
+            let changes = diff_scans(baseline, &scan).len();
+            if changes > 0 {
+                info!(
+                    "{changes} port{} changed since the baseline",
+                    if changes == 1 { "" } else { "s" }
+                );
+            }
+            changes > 0
+        }
+        _ => summary.open_ports > 0,
+    };
+    if incomplete {
+        EXIT_INCOMPLETE
+    } else if failed {
+        EXIT_FAILED
+    } else {
+        0
+    }
+}
@@ -0,0 +1,199 @@
+//! several saved scans folded into one, e.g. the shards of a scan run from
+//! different machines
+
+use crate::report::json::{ms, Host, Port, Probes};
+use crate::{PortSpec, PortState, SavedHost, SavedScan, ScanHost, ScanResult};
+use serde::Serialize;
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// a host in the merged scan
+#[derive(Debug, Clone)]
+pub struct MergedHost {
+    /// the names from every scan that had the host
+    pub host: ScanHost,
+    /// each port with the index of the scan its result came from
+    pub results: Vec<(ScanResult, usize)>,
+    /// the longest any one scan spent on the host
+    pub elapsed: Duration,
+    /// no scan finished the host
+    pub incomplete: bool,
+    /// the indexes of the scans that had the host
+    pub sources: Vec<usize>,
+}
+
+/// every host in `scans`, by ip. when scans disagree about a port, the one
+/// that got to the host last wins (scans that don't say when count as
+/// oldest, and later ones in `scans` break ties), but an answer always beats
+/// no answer. a port the winning scan covered without listing isn't open
+/// any more and is left out.
+pub fn merge_scans(scans: &[SavedScan]) -> Vec<MergedHost> {
+    let mut ips: Vec<IpAddr> = scans
+        .iter()
+        .flat_map(|s| &s.hosts)
+        .map(|h| h.host.ip)
+        .collect();
+    ips.sort();
+    ips.dedup();
+
+    ips.into_iter()
+        .map(|ip| {
+            let found: Vec<(usize, &SavedHost)> = scans
+                .iter()
+                .enumerate()
+                .filter_map(|(i, s)| Some((i, s.host(ip)?)))
+                .collect();
+            let mut host = found[0].1.host.clone();
+            for (_, h) in &found[1..] {
+                for name in &h.host.names {
+                    if !host.names.contains(name) {
+                        host.names.push(name.clone());
+                    }
+                }
+                host.ptr = host.ptr.or_else(|| h.host.ptr.clone());
+            }
+
+            let mut ports: Vec<u16> = found
+                .iter()
+                .flat_map(|(_, h)| h.results.iter().map(|r| r.port))
+                .collect();
+            ports.sort_unstable();
+            ports.dedup();
+            let results = ports
+                .into_iter()
+                .filter_map(|port| {
+                    // the newest scan that has something to say about the port
+                    let (i, h) = found
+                        .iter()
+                        .filter(|(i, h)| {
+                            find(h, port).is_some() || (!h.incomplete && scans[*i].covers(port))
+                        })
+                        .max_by_key(|(i, h)| {
+                            // covered without being listed is an answer too
+                            let answered =
+                                find(h, port).is_none_or(|r| r.state != PortState::Unknown);
+                            (answered, seen(&scans[*i], h), *i)
+                        })?;
+                    Some((find(h, port)?.clone(), *i))
+                })
+                .collect();
+
+            MergedHost {
+                host,
+                results,
+                elapsed: found
+                    .iter()
+                    .map(|(_, h)| h.elapsed)
+                    .max()
+                    .unwrap_or_default(),
+                incomplete: found.iter().all(|(_, h)| h.incomplete),
+                sources: found.iter().map(|&(i, _)| i).collect(),
+            }
+        })
+        .collect()
+}
+
+fn find(host: &SavedHost, port: u16) -> Option<&ScanResult> {
+    host.results.iter().find(|r| r.port == port)
+}
+
+/// when a scan got to a host; rfc 3339 times in utc sort as text
+fn seen<'a>(scan: &'a SavedScan, host: &'a SavedHost) -> Option<&'a str> {
+    host.finished.as_deref().or(scan.started.as_deref())
+}
+
+/// the merged report: `merge_scans` of `scans` as a json report, each host
+/// and port saying which of `files` it came from
+pub fn merged_report(scans: &[SavedScan], files: &[String]) -> String {
+    #[derive(Serialize)]
+    struct Document<'a> {
+        scanner: &'static str,
+        version: &'static str,
+        started: Option<&'a str>,
+        finished: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        parameters: Option<Parameters>,
+        merged_from: Vec<Source<'a>>,
+        hosts: Vec<Host>,
+        summary: Summary,
+    }
+    #[derive(Serialize)]
+    struct Parameters {
+        ports: String,
+    }
+    #[derive(Serialize)]
+    struct Source<'a> {
+        file: &'a str,
+        started: Option<&'a str>,
+        hosts: usize,
+    }
+    #[derive(Serialize)]
+    struct Summary {
+        hosts_scanned: usize,
+        hosts_with_open: usize,
+        open_ports: usize,
+    }
+
+    let merged = merge_scans(scans);
+    let hosts: Vec<Host> = merged
+        .iter()
+        .map(|h| {
+            let ports = h
+                .results
+                .iter()
+                .map(|(r, i)| Port {
+                    source: Some(files[*i].clone()),
+                    ..Port::new(r, r.service_name(), Probes::found(r))
+                })
+                .collect();
+            Host {
+                ptr: h.host.ptr.clone().map(Some),
+                elapsed_ms: ms(h.elapsed),
+                incomplete: h.incomplete,
+                open_count: h.results.iter().filter(|(r, _)| r.is_open()).count(),
+                sources: h.sources.iter().map(|&i| files[i].clone()).collect(),
+                ports,
+                ..Host::new(&h.host, false)
+            }
+        })
+        .collect();
+    let doc = Document {
+        scanner: "pscan",
+        version: env!("CARGO_PKG_VERSION"),
+        started: scans.iter().filter_map(|s| s.started.as_deref()).min(),
+        finished: scans
+            .iter()
+            .flat_map(|s| &s.hosts)
+            .filter_map(|h| h.finished.as_deref())
+            .max(),
+        // the ports every scan covered between them, when they all say
+        parameters: scans
+            .iter()
+            .map(|s| s.ports.as_ref())
+            .collect::<Option<Vec<_>>>()
+            .map(|all| Parameters {
+                ports: all
+                    .into_iter()
+                    .fold(PortSpec::default(), |a, p| a.union(p))
+                    .to_string(),
+            }),
+        merged_from: scans
+            .iter()
+            .zip(files)
+            .map(|(scan, file)| Source {
+                file,
+                started: scan.started.as_deref(),
+                hosts: scan.hosts.len(),
+            })
+            .collect(),
+        summary: Summary {
+            hosts_scanned: hosts.len(),
+            hosts_with_open: hosts.iter().filter(|h| h.open_count > 0).count(),
+            open_ports: hosts.iter().map(|h| h.open_count).sum(),
+        },
+        hosts,
+    };
+    let mut text = serde_json::to_string_pretty(&doc).expect("a report always serializes");
+    text.push('\n');
+    text
+}
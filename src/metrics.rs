@@ -0,0 +1,177 @@
+//! prometheus metrics for the long running modes (--watch, schedule and
+//! serve): scans and probes counted, open ports as of the last scan, and
+//! how long answered probes took
+
+use crate::{PortState, ScanHost, ScanResult};
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// the content type of `Metrics::render`
+pub const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// upper bounds of the probe_duration_seconds buckets
+const BUCKETS: [f64; 12] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+const STATES: [PortState; 7] = [
+    PortState::Open,
+    PortState::Closed,
+    PortState::Filtered,
+    PortState::Error,
+    PortState::OpenFiltered,
+    PortState::Unfiltered,
+    PortState::Unknown,
+];
+
+/// what the scans so far add up to; cheap to share between threads
+#[derive(Default)]
+pub struct Metrics {
+    inner: Mutex<Counts>,
+}
+
+#[derive(Default)]
+struct Counts {
+    scans: u64,
+    /// by state, in the order of STATES
+    probes: [u64; 7],
+    /// per bucket, not cumulative; the last one is +Inf
+    durations: [u64; BUCKETS.len() + 1],
+    duration_sum: f64,
+    /// as of the last finished scan
+    open: BTreeSet<(IpAddr, u16)>,
+    /// found by the scan under way
+    opening: BTreeSet<(IpAddr, u16)>,
+}
+
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// count a host's ports toward the scan under way
+    pub fn record_host(&self, host: &ScanHost, results: &[ScanResult]) {
+        let mut counts = lock(&self.inner);
+        for r in results {
+            if r.state == PortState::Unknown {
+                // never probed
+                continue;
+            }
+            if let Some(i) = STATES.iter().position(|&s| s == r.state) {
+                counts.probes[i] += 1;
+            }
+            if let Some(latency) = r.latency {
+                counts.observe(latency);
+            }
+            if r.is_open() {
+                counts.opening.insert((host.ip, r.port));
+            }
+        }
+    }
+
+    /// the scan under way is over: its open ports become the ones reported
+    pub fn record_scan(&self) {
+        let mut counts = lock(&self.inner);
+        counts.scans += 1;
+        counts.open = std::mem::take(&mut counts.opening);
+    }
+
+    /// everything, in the prometheus text format
+    pub fn render(&self) -> String {
+        let counts = lock(&self.inner);
+        let mut out = String::new();
+        out += "# HELP pscan_scans_total Scans finished.\n";
+        out += "# TYPE pscan_scans_total counter\n";
+        let _ = writeln!(out, "pscan_scans_total {}", counts.scans);
+
+        out += "# HELP pscan_probes_total Ports probed, by the state found.\n";
+        out += "# TYPE pscan_probes_total counter\n";
+        for (state, n) in STATES.iter().zip(counts.probes) {
+            let _ = writeln!(out, "pscan_probes_total{{state=\"{state}\"}} {n}");
+        }
+
+        out += "# HELP pscan_open_ports Ports open in the last finished scan.\n";
+        out += "# TYPE pscan_open_ports gauge\n";
+        for (ip, port) in &counts.open {
+            let _ = writeln!(out, "pscan_open_ports{{host=\"{ip}\",port=\"{port}\"}} 1");
+        }
+
+        out += "# HELP pscan_probe_duration_seconds Time to an answer (open or closed).\n";
+        out += "# TYPE pscan_probe_duration_seconds histogram\n";
+        let mut total = 0;
+        for (i, n) in counts.durations.iter().enumerate() {
+            total += n;
+            let le = match BUCKETS.get(i) {
+                Some(bound) => bound.to_string(),
+                None => "+Inf".to_string(),
+            };
+            let _ = writeln!(
+                out,
+                "pscan_probe_duration_seconds_bucket{{le=\"{le}\"}} {total}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "pscan_probe_duration_seconds_sum {}",
+            counts.duration_sum
+        );
+        let _ = writeln!(out, "pscan_probe_duration_seconds_count {total}");
+        out
+    }
+}
+
+impl Counts {
+    fn observe(&mut self, latency: Duration) {
+        let secs = latency.as_secs_f64();
+        let bucket = BUCKETS
+            .iter()
+            .position(|&bound| secs <= bound)
+            .unwrap_or(BUCKETS.len());
+        self.durations[bucket] += 1;
+        self.duration_sum += secs;
+    }
+}
+
+/// answer `GET /metrics` on `addr` from a thread of its own, for as long as
+/// the process runs; returns the address it listens on
+pub fn serve_metrics(addr: SocketAddr, metrics: Arc<Metrics>) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local = listener.local_addr()?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let _ = answer(stream, &metrics);
+        }
+    });
+    Ok(local)
+}
+
+fn answer(stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // the headers don't matter
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+    let path = request.split_whitespace().nth(1).unwrap_or("");
+    let (status, body) = match path.split('?').next() {
+        Some("/metrics") => ("200 OK", metrics.render()),
+        _ => ("404 Not Found", "try /metrics\n".to_string()),
+    };
+    let mut out = &stream;
+    write!(
+        out,
+        "HTTP/1.1 {status}\r\nContent-Type: {METRICS_CONTENT_TYPE}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
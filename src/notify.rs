@@ -0,0 +1,17 @@
+//! desktop notifications, for --notify, through notify-rust: d-bus on
+//! linux and the bsds, the notification center on macos, toasts on
+//! windows
+
+use notify_rust::Notification;
+use std::io;
+
+/// pop up `title` with `body` on this machine's desktop
+pub fn desktop_notify(title: &str, body: &str) -> io::Result<()> {
+    Notification::new()
+        .appname("pscan")
+        .summary(title)
+        .body(body)
+        .show()
+        .map(drop)
+        .map_err(|e| io::Error::other(format!("the notification failed: {e}")))
+}
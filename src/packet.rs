@@ -0,0 +1,205 @@
+//! building and parsing the tcp segments the raw scan types send and read,
+//! and the icmp echoes and arp requests host discovery sends
+
+use crate::iface::MacAddr;
+use std::net::{IpAddr, Ipv4Addr};
+
+pub(crate) const FIN: u8 = 0x01;
+pub(crate) const SYN: u8 = 0x02;
+pub(crate) const RST: u8 = 0x04;
+pub(crate) const PSH: u8 = 0x08;
+pub(crate) const ACK: u8 = 0x10;
+pub(crate) const URG: u8 = 0x20;
+
+const TCP_HEADER: usize = 20;
+
+const ECHO_REQUEST: u8 = 8;
+const ECHO_REPLY: u8 = 0;
+const ECHO_REQUEST_V6: u8 = 128;
+const ECHO_REPLY_V6: u8 = 129;
+
+/// one probe segment
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TcpProbe {
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub seq: u32,
+    pub ack: u32,
+    pub flags: u8,
+}
+
+/// the fields of a reply the scan cares about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TcpReply {
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub seq: u32,
+    pub ack: u32,
+    pub flags: u8,
+}
+
+/// a tcp header (no ip header; the kernel adds it) with a checksum over
+/// the `src` -> `dst` pseudo header. syns carry an mss option like a real
+/// stack's would.
+pub(crate) fn tcp_segment(src: IpAddr, dst: IpAddr, probe: &TcpProbe) -> Vec<u8> {
+    let options: &[u8] = if probe.flags & SYN != 0 {
+        &[2, 4, 0x05, 0xb4] // mss 1460
+    } else {
+        &[]
+    };
+    let len = TCP_HEADER + options.len();
+
+    let mut seg = Vec::with_capacity(len);
+    seg.extend_from_slice(&probe.src_port.to_be_bytes());
+    seg.extend_from_slice(&probe.dst_port.to_be_bytes());
+    seg.extend_from_slice(&probe.seq.to_be_bytes());
+    seg.extend_from_slice(&probe.ack.to_be_bytes());
+    seg.push(((len / 4) as u8) << 4);
+    seg.push(probe.flags);
+    seg.extend_from_slice(&1024u16.to_be_bytes()); // window
+    seg.extend_from_slice(&[0, 0]); // checksum, filled in below
+    seg.extend_from_slice(&[0, 0]); // urgent pointer
+    seg.extend_from_slice(options);
+
+    let sum = checksum(src, dst, &seg);
+    seg[16..18].copy_from_slice(&sum.to_be_bytes());
+    seg
+}
+
+/// an icmp echo request, or an icmpv6 one when `v6` is set. the kernel
+/// fills in the icmpv6 checksum, which needs the pseudo header.
+pub(crate) fn icmp_echo(v6: bool, id: u16, seq: u16) -> Vec<u8> {
+    let kind = if v6 { ECHO_REQUEST_V6 } else { ECHO_REQUEST };
+    let mut msg = vec![kind, 0, 0, 0];
+    msg.extend_from_slice(&id.to_be_bytes());
+    msg.extend_from_slice(&seq.to_be_bytes());
+    msg.extend_from_slice(b"pscan ping");
+    if !v6 {
+        let mut sum = 0;
+        add_words(&mut sum, &msg);
+        msg[2..4].copy_from_slice(&fold(sum).to_be_bytes());
+    }
+    msg
+}
+
+/// the id and sequence number of an echo reply
+pub(crate) fn parse_echo_reply(v6: bool, msg: &[u8]) -> Option<(u16, u16)> {
+    let reply = if v6 { ECHO_REPLY_V6 } else { ECHO_REPLY };
+    if msg.len() < 8 || msg[0] != reply {
+        return None;
+    }
+    let id = u16::from_be_bytes([msg[4], msg[5]]);
+    let seq = u16::from_be_bytes([msg[6], msg[7]]);
+    Some((id, seq))
+}
+
+/// ethertype of arp frames
+pub(crate) const ETH_P_ARP: u16 = 0x0806;
+
+const ARP_REQUEST: u16 = 1;
+const ARP_REPLY: u16 = 2;
+
+/// a broadcast ethernet frame asking who has `target`
+pub(crate) fn arp_request(src_mac: MacAddr, src_ip: Ipv4Addr, target: Ipv4Addr) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(60);
+    frame.extend_from_slice(&MacAddr::BROADCAST.0);
+    frame.extend_from_slice(&src_mac.0);
+    frame.extend_from_slice(&ETH_P_ARP.to_be_bytes());
+    // ethernet / ipv4, 6 and 4 byte addresses
+    frame.extend_from_slice(&[0, 1, 0x08, 0x00, 6, 4]);
+    frame.extend_from_slice(&ARP_REQUEST.to_be_bytes());
+    frame.extend_from_slice(&src_mac.0);
+    frame.extend_from_slice(&src_ip.octets());
+    frame.extend_from_slice(&[0; 6]);
+    frame.extend_from_slice(&target.octets());
+    // pad to the ethernet minimum
+    frame.resize(60, 0);
+    frame
+}
+
+/// the sender of an arp reply frame
+pub(crate) fn parse_arp_reply(frame: &[u8]) -> Option<(Ipv4Addr, MacAddr)> {
+    let arp = frame.get(14..42)?;
+    if frame[12..14] != ETH_P_ARP.to_be_bytes()
+        || arp[..6] != [0, 1, 0x08, 0x00, 6, 4]
+        || arp[6..8] != ARP_REPLY.to_be_bytes()
+    {
+        return None;
+    }
+    let mac = MacAddr(arp[8..14].try_into().ok()?);
+    let ip: [u8; 4] = arp[14..18].try_into().ok()?;
+    Some((Ipv4Addr::from(ip), mac))
+}
+
+/// add big-endian 16-bit words to a ones' complement sum
+fn add_words(sum: &mut u32, bytes: &[u8]) {
+    for pair in bytes.chunks(2) {
+        let word = match pair {
+            [a, b] => u16::from_be_bytes([*a, *b]),
+            [a] => u16::from_be_bytes([*a, 0]),
+            _ => 0,
+        };
+        *sum += word as u32;
+    }
+}
+
+fn fold(mut sum: u32) -> u16 {
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// the tcp checksum: ones' complement sum over the pseudo header and segment
+pub(crate) fn checksum(src: IpAddr, dst: IpAddr, segment: &[u8]) -> u16 {
+    let mut sum: u32 = 0;
+    let mut add = |bytes: &[u8]| add_words(&mut sum, bytes);
+
+    match (src, dst) {
+        (IpAddr::V4(s), IpAddr::V4(d)) => {
+            add(&s.octets());
+            add(&d.octets());
+            add(&[0, 6]);
+            add(&(segment.len() as u16).to_be_bytes());
+        }
+        (s, d) => {
+            let v6 = |ip: IpAddr| match ip {
+                IpAddr::V4(v4) => v4.to_ipv6_mapped().octets(),
+                IpAddr::V6(v6) => v6.octets(),
+            };
+            add(&v6(s));
+            add(&v6(d));
+            add(&(segment.len() as u32).to_be_bytes());
+            add(&[0, 0, 0, 6]);
+        }
+    }
+    add(segment);
+    fold(sum)
+}
+
+/// what follows the header of an ipv4 packet, as a v4 raw socket delivers
+/// it (v6 raw sockets leave the header off)
+pub(crate) fn ipv4_payload(packet: &[u8]) -> Option<&[u8]> {
+    let (&first, _) = packet.split_first()?;
+    if first >> 4 != 4 {
+        return None;
+    }
+    let ihl = (first & 0x0f) as usize * 4;
+    packet.get(ihl..)
+}
+
+/// the fields of a tcp header
+pub(crate) fn parse_tcp(seg: &[u8]) -> Option<TcpReply> {
+    if seg.len() < TCP_HEADER {
+        return None;
+    }
+    let u16_at = |i: usize| u16::from_be_bytes([seg[i], seg[i + 1]]);
+    let u32_at = |i: usize| u32::from_be_bytes([seg[i], seg[i + 1], seg[i + 2], seg[i + 3]]);
+    Some(TcpReply {
+        src_port: u16_at(0),
+        dst_port: u16_at(2),
+        seq: u32_at(4),
+        ack: u32_at(8),
+        flags: seg[13],
+    })
+}
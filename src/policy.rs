@@ -0,0 +1,223 @@
+//! expected open ports per host, and what breaks them
+
+use crate::config::{error_at, parse_toml};
+use crate::{PortSpec, PortState, ScanHost, ScanResult, Target};
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::IpAddr;
+use toml::Spanned;
+
+/// which ports a host should have open, from a toml policy file:
+///
+/// ```toml
+/// [default]            # hosts not listed below
+/// open = [22]
+///
+/// [hosts."203.0.113.10"]
+/// open = [80, 443]     # must be open, and nothing else may be
+/// allow = "8000-8100"  # may be open, needn't be
+///
+/// [hosts."203.0.113.0/28"]
+/// open = []
+/// ```
+///
+/// hosts are ips, names or cidr networks; an ip or name wins over a
+/// network, and a smaller network over a bigger one.
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    pub default: Option<HostPolicy>,
+    pub hosts: Vec<(Target, HostPolicy)>,
+}
+
+/// the rule for one host
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostPolicy {
+    /// ports that must be open; any other open port is a violation
+    pub open: PortSpec,
+    /// ports that may be open without having to be
+    pub allow: PortSpec,
+}
+
+/// how a host breaks its policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+    /// open, but neither expected nor allowed
+    UnexpectedOpen,
+    /// expected open, but found in this state (None when a saved scan
+    /// doesn't list the port)
+    NotOpen(Option<PortState>),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::UnexpectedOpen => f.write_str("unexpected open port"),
+            Violation::NotOpen(Some(state)) => write!(f, "expected open, found {state}"),
+            Violation::NotOpen(None) => f.write_str("expected open, not found"),
+        }
+    }
+}
+
+/// a policy file as written, checked by `Policy::parse`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    default: Option<RuleTable>,
+    #[serde(default)]
+    hosts: BTreeMap<Spanned<String>, RuleTable>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleTable {
+    open: Option<Spanned<PortList>>,
+    allow: Option<Spanned<PortList>>,
+}
+
+impl Policy {
+    pub fn parse(text: &str) -> Result<Policy, String> {
+        let file: PolicyFile = parse_toml(text)?;
+        let mut policy = Policy::default();
+        if let Some(rule) = file.default {
+            policy.default = Some(host_policy("default", rule, text)?);
+        }
+        for (host, rule) in file.hosts {
+            let section = format!("hosts.\"{}\"", host.get_ref());
+            let target = host
+                .get_ref()
+                .parse()
+                .map_err(|e| error_at(text, host.span(), &format!("{section}: {e}")))?;
+            policy
+                .hosts
+                .push((target, host_policy(&section, rule, text)?));
+        }
+        Ok(policy)
+    }
+
+    /// the hosts the policy names, to scan when no targets are given
+    pub fn targets(&self) -> Vec<Target> {
+        self.hosts
+            .iter()
+            .map(|(target, _)| target.clone())
+            .collect()
+    }
+
+    /// every port some rule mentions
+    pub fn ports(&self) -> PortSpec {
+        self.default
+            .iter()
+            .chain(self.hosts.iter().map(|(_, rule)| rule))
+            .fold(PortSpec::default(), |all, rule| {
+                all.union(&rule.open).union(&rule.allow)
+            })
+    }
+
+    /// the rule for `host`, if any applies
+    pub fn rule_for(&self, host: &ScanHost) -> Option<&HostPolicy> {
+        let exact = self.hosts.iter().find(|(target, _)| match target {
+            Target::Host(name) => {
+                name.parse::<IpAddr>() == Ok(host.ip) || host.names.iter().any(|n| n == name)
+            }
+            Target::Network(_) => false,
+        });
+        let network = || {
+            self.hosts
+                .iter()
+                .filter_map(|(target, rule)| match target {
+                    Target::Network(net) if net.contains(host.ip) => Some((net.prefix(), rule)),
+                    _ => None,
+                })
+                .max_by_key(|&(prefix, _)| prefix)
+                .map(|(_, rule)| rule)
+        };
+        exact
+            .map(|(_, rule)| rule)
+            .or_else(network)
+            .or(self.default.as_ref())
+    }
+}
+
+impl HostPolicy {
+    /// every way `results` break this rule, by port. a port missing from
+    /// `results` counts as not open.
+    pub fn check(&self, results: &[ScanResult]) -> Vec<(u16, Violation)> {
+        let mut violations: Vec<(u16, Violation)> = results
+            .iter()
+            .filter(|r| r.is_open() && !self.open.contains(r.port) && !self.allow.contains(r.port))
+            .map(|r| (r.port, Violation::UnexpectedOpen))
+            .collect();
+        for port in self.open.iter() {
+            let state = results.iter().find(|r| r.port == port).map(|r| r.state);
+            if state != Some(PortState::Open) {
+                violations.push((port, Violation::NotOpen(state)));
+            }
+        }
+        violations.sort_by_key(|&(port, _)| port);
+        violations
+    }
+}
+
+fn host_policy(section: &str, rule: RuleTable, text: &str) -> Result<HostPolicy, String> {
+    let ports = |key: &str, list: Option<Spanned<PortList>>| match list {
+        Some(list) => list
+            .get_ref()
+            .spec()
+            .map_err(|e| error_at(text, list.span(), &format!("{section}.{key}: {e}"))),
+        None => Ok(PortSpec::default()),
+    };
+    Ok(HostPolicy {
+        open: ports("open", rule.open)?,
+        allow: ports("allow", rule.allow)?,
+    })
+}
+
+/// `[22, 443, "8000-8100"]` or `"22,443,8000-8100"`, as written
+pub(crate) struct PortList(String);
+
+impl PortList {
+    pub(crate) fn spec(&self) -> Result<PortSpec, String> {
+        if self.0.trim().is_empty() {
+            return Ok(PortSpec::default());
+        }
+        self.0.parse::<PortSpec>().map_err(|e| e.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for PortList {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        /// one entry of the list: a port or a range
+        #[derive(Deserialize)]
+        #[serde(untagged, expecting = "a port number or a string of ports")]
+        enum Item {
+            Port(i64),
+            Spec(String),
+        }
+
+        struct Visitor;
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = PortList;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a port list (a list of ports or a string)")
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<PortList, E> {
+                Ok(PortList(s.to_string()))
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<PortList, A::Error> {
+                let mut items = Vec::new();
+                while let Some(item) = seq.next_element()? {
+                    items.push(match item {
+                        Item::Port(port) => port.to_string(),
+                        Item::Spec(spec) => spec,
+                    });
+                }
+                Ok(PortList(items.join(",")))
+            }
+        }
+        d.deserialize_any(Visitor)
+    }
+}
@@ -0,0 +1,277 @@
+use crate::services::{self, Services};
+use crate::{Protocol, Rng, ScanError};
+use std::fmt;
+use std::str::FromStr;
+
+/// represents a port range like 1-1000
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PortRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl PortRange {
+    /// expand the range into a vector of ports to scan
+    pub fn to_vec(self) -> Vec<u16> {
+        (self.start..=self.end).collect()
+    }
+}
+
+/// parse `PortRange` from a string like "1-1000"
+impl FromStr for PortRange {
+    type Err = ScanError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_range(s).map_err(ScanError::InvalidPortSpec)
+    }
+}
+
+fn parse_range(s: &str) -> Result<PortRange, String> {
+    let parts: Vec<&str> = s.split('-').collect();
+    if parts.len() != 2 {
+        return Err("ports must be in format start-end (example: 1-1000)".to_string());
+    }
+
+    let start: u16 = parts[0]
+        .trim()
+        .parse()
+        .map_err(|_| "start port must be a number".to_string())?;
+
+    let end: u16 = parts[1]
+        .trim()
+        .parse()
+        .map_err(|_| "end port must be a number".to_string())?;
+
+    if start == 0 || end == 0 {
+        return Err("port range must be between 1 and 65535".to_string());
+    }
+    if start > end {
+        return Err("start port must be <= end port".to_string());
+    }
+
+    Ok(PortRange { start, end })
+}
+
+impl fmt::Display for PortRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
+/// a set of ports like "22,80,443,8000-8100".
+/// stored as sorted, non-overlapping ranges so duplicates collapse
+/// and iteration is always in ascending order.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PortSpec {
+    ranges: Vec<PortRange>,
+}
+
+impl PortSpec {
+    /// build a spec from arbitrary ranges, merging overlaps
+    pub fn from_ranges(ranges: impl IntoIterator<Item = PortRange>) -> Self {
+        let mut ranges: Vec<PortRange> = ranges.into_iter().collect();
+        ranges.sort_by_key(|r| r.start);
+
+        let mut merged: Vec<PortRange> = Vec::with_capacity(ranges.len());
+        for r in ranges {
+            match merged.last_mut() {
+                Some(last) if r.start as u32 <= last.end as u32 + 1 => {
+                    last.end = last.end.max(r.end);
+                }
+                _ => merged.push(r),
+            }
+        }
+
+        PortSpec { ranges: merged }
+    }
+
+    /// every port, 1-65535
+    pub fn all() -> Self {
+        PortRange {
+            start: 1,
+            end: u16::MAX,
+        }
+        .into()
+    }
+
+    /// the `n` most common tcp ports (see `services::TOP_PORTS`)
+    pub fn top(n: usize) -> Self {
+        PortSpec::from_ranges(
+            services::top_ports(n)
+                .iter()
+                .map(|&p| PortRange { start: p, end: p }),
+        )
+    }
+
+    /// the `n` most common udp ports (see `services::TOP_UDP_PORTS`)
+    pub fn top_udp(n: usize) -> Self {
+        PortSpec::from_ranges(
+            services::top_udp_ports(n)
+                .iter()
+                .map(|&p| PortRange { start: p, end: p }),
+        )
+    }
+
+    /// the normalized ranges in ascending order
+    pub fn ranges(&self) -> &[PortRange] {
+        &self.ranges
+    }
+
+    /// number of distinct ports
+    pub fn len(&self) -> usize {
+        self.ranges
+            .iter()
+            .map(|r| (r.end - r.start) as usize + 1)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn contains(&self, port: u16) -> bool {
+        self.ranges.iter().any(|r| r.start <= port && port <= r.end)
+    }
+
+    /// ports in either spec
+    pub fn union(&self, other: &PortSpec) -> PortSpec {
+        PortSpec::from_ranges(self.ranges.iter().chain(other.ranges.iter()).copied())
+    }
+
+    /// ports in `self` that are not in `other`
+    pub fn difference(&self, other: &PortSpec) -> PortSpec {
+        let mut out = Vec::new();
+
+        for r in &self.ranges {
+            // walk the excluded ranges that overlap r, keeping the gaps
+            let mut next = r.start as u32;
+            for x in &other.ranges {
+                if x.end < r.start || x.start > r.end {
+                    continue;
+                }
+                if (x.start as u32) > next {
+                    out.push(PortRange {
+                        start: next as u16,
+                        end: x.start - 1,
+                    });
+                }
+                next = next.max(x.end as u32 + 1);
+            }
+            if next <= r.end as u32 {
+                out.push(PortRange {
+                    start: next as u16,
+                    end: r.end,
+                });
+            }
+        }
+
+        PortSpec { ranges: out }
+    }
+
+    /// iterate ports in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = u16> + '_ {
+        self.ranges.iter().flat_map(|r| r.start..=r.end)
+    }
+
+    /// expand the spec into a vector of ports to scan
+    pub fn to_vec(&self) -> Vec<u16> {
+        self.iter().collect()
+    }
+
+    /// every port of the spec, in an order drawn from `rng`
+    pub fn shuffled(&self, rng: &mut Rng) -> Vec<u16> {
+        let mut ports = self.to_vec();
+        rng.shuffle(&mut ports);
+        ports
+    }
+}
+
+impl From<PortRange> for PortSpec {
+    fn from(r: PortRange) -> Self {
+        PortSpec { ranges: vec![r] }
+    }
+}
+
+/// parse a single port like "80"
+fn parse_single_port(s: &str) -> Result<u16, String> {
+    let port: u16 = s
+        .parse()
+        .map_err(|_| format!("invalid port '{s}' (expected a number between 1 and 65535)"))?;
+    if port == 0 {
+        return Err("port range must be between 1 and 65535".to_string());
+    }
+    Ok(port)
+}
+
+/// parse `PortSpec` from a string like "22,80,443,8000-8100" or "ssh,https".
+/// numeric entries win; anything else is looked up as a service name.
+/// "all" or a lone "-" means every port.
+impl FromStr for PortSpec {
+    type Err = ScanError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PortSpec::parse_with(s, services::builtin_table())
+    }
+}
+
+impl PortSpec {
+    /// parse like `from_str`, looking service names up in `services`
+    /// instead of the built-in table
+    pub fn parse_with(s: &str, services: &Services) -> Result<PortSpec, ScanError> {
+        parse_spec(s, services).map_err(ScanError::InvalidPortSpec)
+    }
+}
+
+fn parse_spec(s: &str, services: &Services) -> Result<PortSpec, String> {
+    let s = s.trim();
+    if s == "-" || s.eq_ignore_ascii_case("all") {
+        return Ok(PortSpec::all());
+    }
+
+    let mut ranges = Vec::new();
+
+    for token in s.split(',') {
+        let token = token.trim();
+        if token.is_empty() {
+            return Err("empty entry in port list".to_string());
+        }
+
+        let numeric = token.starts_with(|c: char| c.is_ascii_digit());
+        let range = if numeric && token.contains('-') {
+            parse_range(token)?
+        } else {
+            let port = if numeric {
+                parse_single_port(token)?
+            } else {
+                services
+                    .port_for(token, Protocol::Tcp)
+                    .or_else(|| services.port_for(token, Protocol::Udp))
+                    .ok_or_else(|| format!("unknown port or service name '{token}'"))?
+            };
+            PortRange {
+                start: port,
+                end: port,
+            }
+        };
+        ranges.push(range);
+    }
+
+    Ok(PortSpec::from_ranges(ranges))
+}
+
+impl fmt::Display for PortSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, r) in self.ranges.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{r}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+//! named scan profiles: the ports, timing and follow-up probes of a
+//! recurring kind of scan, picked with --profile
+
+use crate::config::error_at;
+use crate::policy::PortList;
+use crate::{PortSpec, Timing};
+use serde::Deserialize;
+use std::ops::Range;
+use toml::Spanned;
+
+/// the profiles every build has, by name
+pub const BUILTIN_PROFILES: [&str; 4] = ["quick", "web", "db", "full"];
+
+/// what a profile sets; anything left out keeps the config's or the
+/// built-in default. in a config file:
+///
+/// ```toml
+/// [profiles.mail]
+/// ports = [25, 110, 143, 465, 587, 993, 995]
+/// timing = "polite"     # a -T template
+/// timeout_ms = 1500     # wins over the template's
+/// retries = 2
+/// probes = ["banner", "tls", "version"]  # and "http"
+/// ```
+///
+/// `top_ports = 100` takes the most common ports instead of `ports`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub ports: Option<PortSpec>,
+    pub top_ports: Option<usize>,
+    pub timing: Option<Timing>,
+    pub timeout_ms: Option<u64>,
+    pub retries: Option<u8>,
+    pub banner: bool,
+    pub tls_probe: bool,
+    pub http_probe: bool,
+    pub version_detect: bool,
+}
+
+impl Profile {
+    /// one of `BUILTIN_PROFILES`: quick (the 100 most common ports, fast),
+    /// web (web server ports with http and tls probes), db (database
+    /// ports with banners and version detection) and full (every port)
+    pub fn builtin(name: &str) -> Option<Profile> {
+        let ports = |spec: &str| Some(spec.parse().expect("built-in port lists parse"));
+        match name {
+            "quick" => Some(Profile {
+                top_ports: Some(100),
+                timing: Some(Timing::Aggressive),
+                ..Profile::default()
+            }),
+            "web" => Some(Profile {
+                ports: ports(
+                    "80,443,591,2082,2083,3000,4443,5000,8000,8008,8080,8081,8443,8888,9000,9443",
+                ),
+                timing: Some(Timing::Normal),
+                tls_probe: true,
+                http_probe: true,
+                ..Profile::default()
+            }),
+            "db" => Some(Profile {
+                ports: ports(
+                    "1433,1521,2483,3306,5432,5984,6379,7000,7474,8086,9042,9200,11211,27017,28015",
+                ),
+                timing: Some(Timing::Normal),
+                banner: true,
+                version_detect: true,
+                ..Profile::default()
+            }),
+            "full" => Some(Profile {
+                ports: ports("1-65535"),
+                timing: Some(Timing::Aggressive),
+                ..Profile::default()
+            }),
+            _ => None,
+        }
+    }
+
+    /// a `[profiles.NAME]` table of the config file `text`
+    pub(crate) fn from_table(
+        name: &str,
+        table: ProfileTable,
+        text: &str,
+    ) -> Result<Profile, String> {
+        let section = format!("profiles.{name}");
+        let fail = |span: Range<usize>, what: String| error_at(text, span, &what);
+        let number = |key: &str, n: &Spanned<i64>, max: u64| {
+            u64::try_from(*n.get_ref())
+                .ok()
+                .filter(|n| (1..=max).contains(n))
+                .ok_or_else(|| {
+                    let what = format!(
+                        "{section}.{key} {} must be between 1 and {max}",
+                        n.get_ref()
+                    );
+                    fail(n.span(), what)
+                })
+        };
+        let mut profile = Profile::default();
+        if let Some(ports) = &table.ports {
+            let spec = ports.get_ref().spec();
+            profile.ports =
+                Some(spec.map_err(|e| fail(ports.span(), format!("{section}.ports: {e}")))?);
+        }
+        if let Some(n) = &table.top_ports {
+            profile.top_ports = Some(number("top_ports", n, u16::MAX as u64)? as usize);
+        }
+        if let Some(timing) = &table.timing {
+            let template = match timing.get_ref() {
+                Template::Name(name) => name.parse(),
+                Template::Level(n) => n.to_string().parse(),
+            };
+            profile.timing =
+                Some(template.map_err(|e| fail(timing.span(), format!("{section}.timing: {e}")))?);
+        }
+        if let Some(ms) = &table.timeout_ms {
+            profile.timeout_ms = Some(number("timeout_ms", ms, u32::MAX as u64)?);
+        }
+        if let Some(n) = &table.retries {
+            profile.retries = Some(u8::try_from(*n.get_ref()).map_err(|_| {
+                let what = format!("{section}.retries {} is out of range", n.get_ref());
+                fail(n.span(), what)
+            })?);
+        }
+        for (i, probe) in table.probes.iter().enumerate() {
+            let flag = match probe.get_ref().as_str() {
+                "banner" => &mut profile.banner,
+                "tls" => &mut profile.tls_probe,
+                "http" => &mut profile.http_probe,
+                "version" => &mut profile.version_detect,
+                other => {
+                    return Err(fail(
+                        probe.span(),
+                        format!(
+                            "{section}.probes[{i}]: unknown probe '{other}' (expected banner, \
+                             tls, http or version)"
+                        ),
+                    ))
+                }
+            };
+            *flag = true;
+        }
+        if profile.ports.is_some() && profile.top_ports.is_some() {
+            return Err(format!("{section}: give ports or top_ports, not both"));
+        }
+        Ok(profile)
+    }
+}
+
+/// a `[profiles.NAME]` table as written, checked by `Profile::from_table`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ProfileTable {
+    ports: Option<Spanned<PortList>>,
+    top_ports: Option<Spanned<i64>>,
+    timing: Option<Spanned<Template>>,
+    timeout_ms: Option<Spanned<i64>>,
+    retries: Option<Spanned<i64>>,
+    #[serde(default)]
+    probes: Vec<Spanned<String>>,
+}
+
+/// a -T template, by name or number
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "a template name or number")]
+enum Template {
+    Name(String),
+    Level(i64),
+}
@@ -0,0 +1,244 @@
+//! how far a scan of many hosts has got, for whatever shows it: the
+//! command line's progress bars, --tui, the scan api.
+
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// a scan's progress over all its hosts. scanners given it with
+/// `ScannerBuilder::track` count each host and port in as they go, from
+/// whichever thread; frontends take a `snapshot` whenever they redraw.
+///
+/// ```no_run
+/// use port_scanner::{Progress, Scanner};
+/// use std::sync::Arc;
+///
+/// let progress = Arc::new(Progress::new());
+/// progress.start(1, 1000);
+/// let scanner = Scanner::builder().track(progress.clone()).build();
+/// std::thread::spawn(move || loop {
+///     let now = progress.snapshot();
+///     eprintln!("{}/{} probes", now.probes_done, now.probes_total);
+///     std::thread::sleep(std::time::Duration::from_secs(1));
+/// });
+/// scanner.scan("10.0.0.5".parse().unwrap(), 1..=1000);
+/// ```
+#[derive(Debug, Default)]
+pub struct Progress {
+    probes_done: AtomicUsize,
+    open_ports: AtomicUsize,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    started: Instant,
+    hosts_total: usize,
+    ports_per_host: usize,
+    hosts_done: usize,
+    /// hosts being scanned, in the order they started
+    running: Vec<Arc<HostTally>>,
+    /// ports done before the scan, see `carry`
+    carried: Vec<(IpAddr, usize)>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            started: Instant::now(),
+            hosts_total: 0,
+            ports_per_host: 0,
+            hosts_done: 0,
+            running: Vec::new(),
+            carried: Vec::new(),
+        }
+    }
+}
+
+/// one host's count, held by the scanner copy scanning it
+#[derive(Debug)]
+pub(crate) struct HostTally {
+    ip: IpAddr,
+    started: Instant,
+    total: usize,
+    done: AtomicUsize,
+    open: AtomicUsize,
+}
+
+/// where a scan has got to
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgressSnapshot {
+    pub hosts_total: usize,
+    pub hosts_done: usize,
+    pub probes_total: usize,
+    pub probes_done: usize,
+    pub open_ports: usize,
+    pub elapsed: Duration,
+    /// probes finished a second since the start; None in the first
+    /// second, with too little to go on
+    pub rate: Option<f64>,
+    /// time left at that rate
+    pub eta: Option<Duration>,
+    /// the hosts being scanned right now, in the order they started
+    pub hosts: Vec<HostProgress>,
+}
+
+/// where one host's scan has got to
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostProgress {
+    pub ip: IpAddr,
+    pub probes_done: usize,
+    pub probes_total: usize,
+    pub open_ports: usize,
+    pub elapsed: Duration,
+    pub rate: Option<f64>,
+    pub eta: Option<Duration>,
+}
+
+impl ProgressSnapshot {
+    /// how much is done, from 0 to 1; 1 when there was nothing to do
+    pub fn fraction(&self) -> f64 {
+        fraction(self.probes_done, self.probes_total)
+    }
+}
+
+impl HostProgress {
+    pub fn fraction(&self) -> f64 {
+        fraction(self.probes_done, self.probes_total)
+    }
+}
+
+fn fraction(done: usize, total: usize) -> f64 {
+    if total == 0 {
+        1.0
+    } else {
+        done as f64 / total as f64
+    }
+}
+
+/// the rate `done` probes in `elapsed` make, and the time the rest take at it
+fn pace(done: usize, total: usize, elapsed: Duration) -> (Option<f64>, Option<Duration>) {
+    // the first moment's burst says little about the rate
+    let secs = elapsed.as_secs_f64();
+    let rate = (secs >= 1.0).then(|| done as f64 / secs);
+    let eta = if done >= total {
+        Some(Duration::ZERO)
+    } else {
+        rate.filter(|&r| r > 0.0)
+            .map(|r| Duration::from_secs_f64((total - done) as f64 / r))
+    };
+    (rate, eta)
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Progress::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// start counting afresh for `hosts` hosts of `ports` ports each, e.g.
+    /// for each round of a watch
+    pub fn start(&self, hosts: usize, ports: usize) {
+        let mut state = self.state();
+        *state = State {
+            hosts_total: hosts,
+            ports_per_host: ports,
+            ..State::default()
+        };
+        self.probes_done.store(0, Ordering::Relaxed);
+        self.open_ports.store(0, Ordering::Relaxed);
+    }
+
+    /// `ports` of `ip` that were done before the scan, e.g. by the run a
+    /// checkpoint was saved from. they count towards the totals now, and
+    /// towards the host's own once its scan starts; a host carried in full
+    /// is done.
+    pub fn carry(&self, ip: IpAddr, ports: usize) {
+        self.probes_done.fetch_add(ports, Ordering::Relaxed);
+        let mut state = self.state();
+        if ports >= state.ports_per_host {
+            state.hosts_done += 1;
+        } else {
+            state.carried.push((ip, ports));
+        }
+    }
+
+    pub(crate) fn host_started(&self, ip: IpAddr) -> Arc<HostTally> {
+        let mut state = self.state();
+        let carried = match state.carried.iter().position(|&(c, _)| c == ip) {
+            Some(i) => state.carried.swap_remove(i).1,
+            None => 0,
+        };
+        let tally = Arc::new(HostTally {
+            ip,
+            started: Instant::now(),
+            total: state.ports_per_host,
+            done: AtomicUsize::new(carried),
+            open: AtomicUsize::new(0),
+        });
+        state.running.push(tally.clone());
+        tally
+    }
+
+    pub(crate) fn port_done(&self, host: Option<&HostTally>, open: bool) {
+        self.probes_done.fetch_add(1, Ordering::Relaxed);
+        if open {
+            self.open_ports.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some(host) = host {
+            host.done.fetch_add(1, Ordering::Relaxed);
+            if open {
+                host.open.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub(crate) fn host_finished(&self, host: &Arc<HostTally>) {
+        let mut state = self.state();
+        if let Some(i) = state.running.iter().position(|h| Arc::ptr_eq(h, host)) {
+            state.running.remove(i);
+            state.hosts_done += 1;
+        }
+    }
+
+    pub fn snapshot(&self) -> ProgressSnapshot {
+        let state = self.state();
+        let probes_total = state.hosts_total * state.ports_per_host;
+        let probes_done = self.probes_done.load(Ordering::Relaxed).min(probes_total);
+        let elapsed = state.started.elapsed();
+        let (rate, eta) = pace(probes_done, probes_total, elapsed);
+        let hosts = state
+            .running
+            .iter()
+            .map(|h| {
+                let probes_done = h.done.load(Ordering::Relaxed).min(h.total);
+                let elapsed = h.started.elapsed();
+                let (rate, eta) = pace(probes_done, h.total, elapsed);
+                HostProgress {
+                    ip: h.ip,
+                    probes_done,
+                    probes_total: h.total,
+                    open_ports: h.open.load(Ordering::Relaxed),
+                    elapsed,
+                    rate,
+                    eta,
+                }
+            })
+            .collect();
+        ProgressSnapshot {
+            hosts_total: state.hosts_total,
+            hosts_done: state.hosts_done,
+            probes_total,
+            probes_done,
+            open_ports: self.open_ports.load(Ordering::Relaxed),
+            elapsed,
+            rate,
+            eta,
+            hosts,
+        }
+    }
+}
@@ -0,0 +1,520 @@
+//! scan types that craft their own tcp packets on a raw socket
+
+use crate::engine::{enrich, record, scan_async, scan_threads, OnResult};
+use crate::packet::{self, TcpProbe, TcpReply, ACK, FIN, PSH, RST, SYN, URG};
+use crate::rng::random_u32;
+use crate::{Engine, PortState, ScanResult, ScanType, Scanner};
+use rayon::prelude::*;
+use std::io;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+/// whether this process may open raw sockets (root or CAP_NET_RAW)
+pub fn raw_sockets_available() -> bool {
+    #[cfg(unix)]
+    {
+        sys::RawSocket::open("127.0.0.1".parse().unwrap(), libc::IPPROTO_TCP).is_ok()
+    }
+    #[cfg(not(unix))]
+    {
+        false
+    }
+}
+
+/// tcp flags a probe of this type carries
+fn probe_flags(scan_type: ScanType) -> u8 {
+    match scan_type {
+        ScanType::Connect | ScanType::Syn => SYN,
+        ScanType::Fin => FIN,
+        ScanType::Null => 0,
+        ScanType::Xmas => FIN | PSH | URG,
+        ScanType::Ack => ACK,
+    }
+}
+
+/// what a reply says about the port, None for replies that don't count
+fn classify(scan_type: ScanType, flags: u8) -> Option<PortState> {
+    match scan_type {
+        ScanType::Connect | ScanType::Syn => {
+            if flags & RST != 0 {
+                Some(PortState::Closed)
+            } else if flags & (SYN | ACK) == SYN | ACK {
+                Some(PortState::Open)
+            } else {
+                None
+            }
+        }
+        // rfc 793: a closed port resets anything but a rst, while an open
+        // one drops segments without syn, rst or ack
+        ScanType::Fin | ScanType::Null | ScanType::Xmas => {
+            (flags & RST != 0).then_some(PortState::Closed)
+        }
+        // open and closed ports both reset an unexpected ack
+        ScanType::Ack => (flags & RST != 0).then_some(PortState::Unfiltered),
+    }
+}
+
+/// the state of a port that never answered
+fn no_response(scan_type: ScanType) -> PortState {
+    match scan_type {
+        ScanType::Connect | ScanType::Syn | ScanType::Ack => PortState::Filtered,
+        // silence can't tell open from dropped
+        ScanType::Fin | ScanType::Null | ScanType::Xmas => PortState::OpenFiltered,
+    }
+}
+
+/// the local address the kernel would use to reach `dst`, needed for the
+/// checksum. a udp connect picks the route without sending anything.
+fn source_for(mut dst: SocketAddr) -> io::Result<IpAddr> {
+    let local: SocketAddr = match dst {
+        SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+        SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+    };
+    let socket = UdpSocket::bind(local)?;
+    dst.set_port(9);
+    socket.connect(dst)?;
+    Ok(socket.local_addr()?.ip())
+}
+
+/// raw probes with up to `concurrency` outstanding, read back on the same
+/// socket. without the privileges for that it falls back to the
+/// scanner's connect engine.
+pub(crate) fn scan_raw<I>(
+    scanner: &Scanner,
+    ip: IpAddr,
+    ports: I,
+    on_result: OnResult,
+) -> Vec<ScanResult>
+where
+    I: IntoIterator<Item = u16>,
+    I::IntoIter: Send,
+{
+    #[cfg(unix)]
+    {
+        let setup = sys::RawSocket::open(ip, libc::IPPROTO_TCP).and_then(|s| {
+            if let Some(name) = scanner.transport.interface_name() {
+                s.bind_device(name)?;
+            }
+            let src = match scanner.transport.source_for(ip) {
+                Some(local) => {
+                    s.bind(local)?;
+                    local
+                }
+                None => source_for(scanner.addr(ip, 0))?,
+            };
+            Ok((s, src))
+        });
+        if let Ok((socket, src)) = setup {
+            return scan_with(scanner, &socket, src, ip, ports.into_iter(), on_result);
+        }
+    }
+    match scanner.engine {
+        Engine::Threads => scan_threads(scanner, ip, ports, on_result),
+        Engine::Async => scan_async(scanner, ip, ports, on_result),
+    }
+}
+
+#[cfg(unix)]
+fn scan_with(
+    scanner: &Scanner,
+    socket: &sys::RawSocket,
+    src: IpAddr,
+    ip: IpAddr,
+    mut ports: impl Iterator<Item = u16>,
+    on_result: OnResult,
+) -> Vec<ScanResult> {
+    use crate::retry::RetryQueue;
+    use crate::timing::Slot;
+    use std::collections::HashMap;
+    use std::io::ErrorKind;
+    use std::time::Instant;
+
+    // poll in short slices so cancellation is noticed quickly
+    const POLL_SLICE: Duration = Duration::from_millis(100);
+    // how soon to look again when other hosts hold the whole shared cap
+    const CAP_WAIT: Duration = Duration::from_millis(5);
+
+    struct Pending<'s> {
+        attempt: u8,
+        sent: Instant,
+        /// its place under the cap shared across hosts
+        _slot: Slot<'s>,
+    }
+
+    let scan_type = scanner.scan_type;
+    let concurrency = scanner.concurrency.max(1);
+    let secret = random_u32();
+    // nothing is bound, so a fixed source port can't collide
+    let src_port = match scanner.transport.source_port_list().first() {
+        Some(&port) => port,
+        None => 40_000 + (random_u32() % 20_000) as u16,
+    };
+    // per-port sequence numbers, so stray or spoofed replies don't count
+    let seq_for = |port: u16| secret ^ (port as u32).wrapping_mul(0x9e37_79b1);
+
+    let retry = &scanner.retry;
+    let mut pending: HashMap<u16, Pending> = HashMap::with_capacity(concurrency);
+    let mut retry_queue = RetryQueue::default();
+    let mut results = Vec::new();
+    let mut buf = vec![0u8; 2048];
+    // open ports that still need banners, tls, http or version probes; those
+    // take a while, so they run once the raw loop is done
+    let follow_up = scanner.banner.is_some()
+        || scanner.tls.is_some()
+        || scanner.http.is_some()
+        || scanner.version.is_some();
+    let mut found = Vec::new();
+
+    let mut finish = |results: &mut Vec<ScanResult>, port: u16, state: PortState, latency| {
+        let result = ScanResult {
+            latency,
+            ..ScanResult::new(port, state)
+        };
+        if follow_up && result.is_open() {
+            // counted now so --max-open can stop the scan; it is counted
+            // again once recorded, by when that no longer matters
+            scanner.found_open();
+            found.push(result);
+        } else {
+            record(scanner, on_result, results, result);
+        }
+    };
+
+    // stopped gently, the probes outstanding still get their answer
+    while !scanner.stopped() || (scanner.draining() && !pending.is_empty()) {
+        // top up the outstanding probes, as fast as the scan delay allows
+        let mut paced = None;
+        while pending.len() < scanner.window() && !scanner.stopped() {
+            let next = retry_queue
+                .pop()
+                .or_else(|| ports.next().map(|port| (port, 0)));
+            let Some((port, attempt)) = next else {
+                break;
+            };
+            let Some(slot) = scanner.try_shared_slot() else {
+                retry_queue.push_front(port, attempt);
+                paced = Some(CAP_WAIT);
+                break;
+            };
+            if let Err(wait) = scanner.pacer.try_take() {
+                retry_queue.push_front(port, attempt);
+                paced = Some(wait);
+                break;
+            }
+            let probe = TcpProbe {
+                src_port,
+                dst_port: port,
+                seq: seq_for(port),
+                ack: seq_for(port),
+                flags: probe_flags(scan_type),
+            };
+            match socket.send(scanner.addr(ip, 0), &packet::tcp_segment(src, ip, &probe)) {
+                Ok(()) => {
+                    let sent = Instant::now();
+                    pending.insert(
+                        port,
+                        Pending {
+                            attempt,
+                            sent,
+                            _slot: slot,
+                        },
+                    );
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock && !pending.is_empty() => {
+                    // send buffer full: let some replies come back first
+                    retry_queue.push_front(port, attempt);
+                    break;
+                }
+                Err(e) => finish(
+                    &mut results,
+                    port,
+                    PortState::from_error_kind(e.kind()),
+                    None,
+                ),
+            }
+        }
+
+        // the next probe the scan delay or a retry backoff holds back
+        let held = paced.or(retry_queue.next_due());
+        if pending.is_empty() {
+            if let Some(wait) = held {
+                std::thread::sleep(wait.min(POLL_SLICE));
+            } else if retry_queue.is_empty() {
+                break;
+            }
+            continue;
+        }
+
+        let timeout = scanner.probe_timeout();
+        let expires = |p: &Pending| p.sent + retry.timeout(timeout, p.attempt);
+        let now = Instant::now();
+        let wait = pending
+            .values()
+            .map(|p| expires(p).saturating_duration_since(now))
+            .min()
+            .unwrap_or(POLL_SLICE)
+            .min(held.unwrap_or(POLL_SLICE));
+        match socket.wait(wait) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        }
+
+        // everything that arrived; most of it is other traffic
+        loop {
+            let (from, reply): (IpAddr, TcpReply) = match socket.recv(&mut buf) {
+                Ok((from, seg)) => match packet::parse_tcp(seg) {
+                    Some(reply) => (from, reply),
+                    None => continue,
+                },
+                Err(e) if e.kind() == ErrorKind::InvalidData => continue,
+                Err(_) => break,
+            };
+            if from != ip || reply.dst_port != src_port {
+                continue;
+            }
+            let port = reply.src_port;
+            let seq = seq_for(port);
+            // a syn-ack or rst acknowledges seq + 1 (or seq, for probes
+            // without syn or fin); the rst to an ack takes its seq from our ack
+            let ours = reply.ack.wrapping_sub(seq) <= 1
+                || (reply.flags & RST != 0 && reply.flags & ACK == 0 && reply.seq == seq);
+            if !pending.contains_key(&port) || !ours {
+                continue;
+            }
+            let Some(state) = classify(scan_type, reply.flags) else {
+                continue;
+            };
+            let p = pending.remove(&port).expect("checked above");
+            let rtt = p.sent.elapsed();
+            scanner.answered(rtt);
+            finish(&mut results, port, state, Some(rtt));
+        }
+
+        // probes that timed out go again or get the no-answer state
+        let now = Instant::now();
+        let expired: Vec<u16> = pending
+            .iter()
+            .filter(|(_, p)| now >= expires(p))
+            .map(|(&port, _)| port)
+            .collect();
+        for port in expired {
+            let p = pending.remove(&port).expect("collected above");
+            scanner.unanswered(no_response(scan_type));
+            if p.attempt < retry.retries {
+                retry_queue.schedule(retry, port, p.attempt + 1);
+            } else {
+                finish(&mut results, port, no_response(scan_type), None);
+            }
+        }
+    }
+
+    // stopped: whatever never got an answer is reported as filtered, or
+    // unknown when the host ran out of time or enough ports were open
+    let unanswered: Vec<u16> = pending
+        .into_keys()
+        .chain(retry_queue.into_ports())
+        .chain(ports)
+        .collect();
+    for port in unanswered {
+        finish(&mut results, port, scanner.skipped_state(), None);
+    }
+
+    found.par_iter_mut().for_each(|r| enrich(scanner, ip, r));
+    for result in found {
+        record(scanner, on_result, &mut results, result);
+    }
+
+    results.sort_by_key(|r| r.port);
+    results
+}
+
+/// send an icmp echo request to every host and collect the replies that
+/// come back within `timeout` of each request. fails when there are no
+/// raw sockets to do it with.
+pub(crate) fn echo_sweep(
+    hosts: &[IpAddr],
+    timeout: Duration,
+    cancelled: &AtomicBool,
+) -> io::Result<Vec<(IpAddr, Duration)>> {
+    #[cfg(unix)]
+    {
+        let mut answered = Vec::new();
+        for v6 in [false, true] {
+            let family: Vec<IpAddr> = hosts
+                .iter()
+                .copied()
+                .filter(|ip| ip.is_ipv6() == v6)
+                .collect();
+            if let Some(&first) = family.first() {
+                answered.extend(sweep_family(first, &family, timeout, cancelled)?);
+            }
+        }
+        Ok(answered)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (hosts, timeout, cancelled);
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+#[cfg(unix)]
+fn sweep_family(
+    first: IpAddr,
+    hosts: &[IpAddr],
+    timeout: Duration,
+    cancelled: &AtomicBool,
+) -> io::Result<Vec<(IpAddr, Duration)>> {
+    use std::collections::HashMap;
+    use std::io::ErrorKind;
+    use std::sync::atomic::Ordering;
+    use std::time::Instant;
+
+    let v6 = first.is_ipv6();
+    let protocol = if v6 {
+        libc::IPPROTO_ICMPV6
+    } else {
+        libc::IPPROTO_ICMP
+    };
+    let socket = sys::RawSocket::open(first, protocol)?;
+    let id = random_u32() as u16;
+
+    let mut sent: HashMap<IpAddr, Instant> = HashMap::with_capacity(hosts.len());
+    let mut answered = Vec::new();
+    let mut buf = vec![0u8; 2048];
+    let mut drain = |sent: &mut HashMap<IpAddr, Instant>,
+                     answered: &mut Vec<(IpAddr, Duration)>| loop {
+        match socket.recv(&mut buf) {
+            Ok((from, msg)) => {
+                if packet::parse_echo_reply(v6, msg).is_some_and(|(reply_id, _)| reply_id == id) {
+                    if let Some(at) = sent.remove(&from) {
+                        answered.push((from, at.elapsed()));
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::InvalidData => {}
+            Err(_) => break,
+        }
+    };
+
+    for (seq, &ip) in hosts.iter().enumerate() {
+        if cancelled.load(Ordering::Relaxed) {
+            return Ok(answered);
+        }
+        let echo = packet::icmp_echo(v6, id, seq as u16);
+        loop {
+            match socket.send(SocketAddr::new(ip, 0), &echo) {
+                Ok(()) => {
+                    sent.insert(ip, Instant::now());
+                    break;
+                }
+                // send buffer full: give it a moment
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    let _ = socket.wait(Duration::from_millis(10));
+                    drain(&mut sent, &mut answered);
+                }
+                // unreachable and the like: that host just isn't up
+                Err(_) => break,
+            }
+        }
+        drain(&mut sent, &mut answered);
+    }
+
+    // wait out the last requests' timeout
+    let deadline = Instant::now() + timeout;
+    while !sent.is_empty() && !cancelled.load(Ordering::Relaxed) {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        match socket.wait(left.min(Duration::from_millis(100))) {
+            Ok(()) => drain(&mut sent, &mut answered),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(answered)
+}
+
+#[cfg(unix)]
+mod sys {
+    use crate::packet;
+    use crate::transport::Readable;
+    use socket2::{Domain, Protocol, Socket, Type};
+    use std::io;
+    use std::mem::MaybeUninit;
+    use std::net::{IpAddr, SocketAddr};
+    use std::time::Duration;
+
+    const RECV_BUFFER: usize = 4 << 20;
+
+    /// a non-blocking raw socket for one ip protocol (tcp, icmp or icmpv6)
+    pub struct RawSocket {
+        socket: Socket,
+        readable: Readable,
+        v6: bool,
+    }
+
+    impl RawSocket {
+        pub fn open(ip: IpAddr, protocol: libc::c_int) -> io::Result<RawSocket> {
+            let domain = Domain::for_address(SocketAddr::new(ip, 0));
+            let socket = Socket::new(domain, Type::RAW, Some(Protocol::from(protocol)))?;
+            socket.set_nonblocking(true)?;
+            // every packet of the protocol on the host lands here, not just
+            // our replies; the default buffer drops some of a large batch
+            socket.set_recv_buffer_size(RECV_BUFFER)?;
+            Ok(RawSocket {
+                readable: Readable::new(&socket)?,
+                socket,
+                v6: ip.is_ipv6(),
+            })
+        }
+
+        /// send through this interface only
+        pub fn bind_device(&self, name: &str) -> io::Result<()> {
+            crate::transport::bind_device(&self.socket, name)
+        }
+
+        /// send from this local address only
+        pub fn bind(&self, local: IpAddr) -> io::Result<()> {
+            self.socket.bind(&SocketAddr::new(local, 0).into())
+        }
+
+        /// send to `dst`, whose port is ignored (raw sockets take the
+        /// protocol instead) but whose ipv6 zone picks the interface
+        pub fn send(&self, dst: SocketAddr, segment: &[u8]) -> io::Result<()> {
+            self.socket.send_to(segment, &dst.into()).map(drop)
+        }
+
+        /// wait until something new is readable, or `wait` passes; read
+        /// until WouldBlock first
+        pub fn wait(&self, wait: Duration) -> io::Result<()> {
+            self.readable.wait(wait)
+        }
+
+        /// the next packet's sender and its tcp or icmp part (ipv4 reads
+        /// include the ip header, which is skipped). Err(InvalidData) for a
+        /// packet that can't be read, Err(WouldBlock) once nothing is left
+        pub fn recv<'a>(&self, buf: &'a mut [u8]) -> io::Result<(IpAddr, &'a [u8])> {
+            // SAFETY: recv_from only writes initialised bytes into the
+            // buffer, and only the n it reports are read back
+            let uninit = unsafe { &mut *(buf as *mut [u8] as *mut [MaybeUninit<u8>]) };
+            let (n, from) = self.socket.recv_from(uninit)?;
+            let packet = &buf[..n];
+            let src = from
+                .as_socket()
+                .map(|addr| addr.ip())
+                .ok_or(io::ErrorKind::InvalidData)?;
+            let payload = if self.v6 {
+                Some(packet)
+            } else {
+                packet::ipv4_payload(packet)
+            };
+            payload
+                .map(|payload| (src, payload))
+                .ok_or_else(|| io::ErrorKind::InvalidData.into())
+        }
+    }
+}
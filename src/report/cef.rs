@@ -0,0 +1,192 @@
+use super::util::rfc3339;
+use super::{HostReport, ReportOptions, Reporter, ScanInfo};
+use crate::services::Services;
+use crate::{Protocol, ScanHost, ScanResult};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dialect {
+    Cef,
+    Leef,
+}
+
+/// one siem event line per open port (every port with show_closed), in
+/// arcsight's cef or qradar's leef 1.0, e.g.
+/// `CEF:0|pscan|pscan|0.1.0|port-open|port open|5|rt=1714557600000 dst=10.0.0.5 dpt=22 proto=TCP app=ssh act=open ...`.
+/// the scan's targets, ports and start time ride along as extensions.
+pub struct CefReporter {
+    out: Box<dyn Write + Send>,
+    dialect: Dialect,
+    show_closed: bool,
+    versions: bool,
+    protocol: Protocol,
+    services: Arc<Services>,
+    targets: String,
+    ports: String,
+    started: SystemTime,
+}
+
+impl CefReporter {
+    /// arcsight common event format
+    pub fn new(out: Box<dyn Write + Send>, opts: &ReportOptions) -> Self {
+        CefReporter {
+            out,
+            dialect: Dialect::Cef,
+            show_closed: opts.show_closed,
+            versions: opts.versions,
+            protocol: opts.protocol,
+            services: opts.services.clone(),
+            targets: String::new(),
+            ports: String::new(),
+            started: SystemTime::now(),
+        }
+    }
+
+    /// qradar's log event extended format, tab separated
+    pub fn leef(out: Box<dyn Write + Send>, opts: &ReportOptions) -> Self {
+        CefReporter {
+            dialect: Dialect::Leef,
+            ..CefReporter::new(out, opts)
+        }
+    }
+
+    fn event(&self, host: &ScanHost, r: &ScanResult) -> String {
+        let state = r.state.to_string();
+        let event_id = format!("port-{}", state.replace('|', "-"));
+        let severity = if r.is_open() { 5 } else { 2 };
+        let version = self
+            .versions
+            .then(|| r.service.as_ref().map(|m| m.summary()))
+            .flatten()
+            .filter(|v| !v.is_empty());
+        match self.dialect {
+            Dialect::Cef => {
+                let mut ext = vec![
+                    ("rt", epoch_ms(SystemTime::now()).to_string()),
+                    ("start", epoch_ms(self.started).to_string()),
+                    ("dst", host.ip.to_string()),
+                ];
+                if let Some(name) = hostname(host) {
+                    ext.push(("dhost", name.to_string()));
+                }
+                ext.push(("dpt", r.port.to_string()));
+                ext.push(("proto", self.protocol.to_string().to_uppercase()));
+                if let Some(service) = r.service_name_in(&self.services) {
+                    ext.push(("app", service.to_string()));
+                }
+                ext.push(("act", state.clone()));
+                if let Some(ms) = r.latency_ms() {
+                    ext.push(("cn1Label", "latencyMs".to_string()));
+                    ext.push(("cn1", format!("{}", ms.round() as u64)));
+                }
+                ext.push(("cs1Label", "scanTargets".to_string()));
+                ext.push(("cs1", self.targets.clone()));
+                ext.push(("cs2Label", "scanPorts".to_string()));
+                ext.push(("cs2", self.ports.clone()));
+                if let Some(version) = version {
+                    ext.push(("cs3Label", "version".to_string()));
+                    ext.push(("cs3", version));
+                }
+                let ext: Vec<String> = ext
+                    .into_iter()
+                    .map(|(key, value)| format!("{key}={}", cef_value(&value)))
+                    .collect();
+                format!(
+                    "CEF:0|pscan|pscan|{}|{event_id}|port {}|{severity}|{}",
+                    env!("CARGO_PKG_VERSION"),
+                    cef_header(&state),
+                    ext.join(" ")
+                )
+            }
+            Dialect::Leef => {
+                let mut attrs = vec![
+                    ("devTime", rfc3339(SystemTime::now())),
+                    ("devTimeFormat", "yyyy-MM-dd'T'HH:mm:ssX".to_string()),
+                    ("cat", "port".to_string()),
+                    ("sev", severity.to_string()),
+                    ("dst", host.ip.to_string()),
+                ];
+                if let Some(name) = hostname(host) {
+                    attrs.push(("dstHostName", name.to_string()));
+                }
+                attrs.push(("dstPort", r.port.to_string()));
+                attrs.push(("proto", self.protocol.to_string().to_uppercase()));
+                attrs.push(("state", state));
+                if let Some(service) = r.service_name_in(&self.services) {
+                    attrs.push(("service", service.to_string()));
+                }
+                if let Some(ms) = r.latency_ms() {
+                    attrs.push(("latencyMs", format!("{}", ms.round() as u64)));
+                }
+                if let Some(version) = version {
+                    attrs.push(("version", version));
+                }
+                attrs.push(("scanStart", rfc3339(self.started)));
+                attrs.push(("scanTargets", self.targets.clone()));
+                attrs.push(("scanPorts", self.ports.clone()));
+                let attrs: Vec<String> = attrs
+                    .into_iter()
+                    .map(|(key, value)| format!("{key}={}", leef_value(&value)))
+                    .collect();
+                format!(
+                    "LEEF:1.0|pscan|pscan|{}|{event_id}|{}",
+                    env!("CARGO_PKG_VERSION"),
+                    attrs.join("\t")
+                )
+            }
+        }
+    }
+}
+
+/// the first real hostname, as given or looked up
+fn hostname(host: &ScanHost) -> Option<&str> {
+    host.ptr.as_deref().or_else(|| {
+        host.names
+            .iter()
+            .find(|n| n.parse::<IpAddr>().is_err() && !n.contains('/'))
+            .map(String::as_str)
+    })
+}
+
+fn epoch_ms(t: SystemTime) -> u128 {
+    t.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis()
+}
+
+/// header fields escape `\` and `|`
+fn cef_header(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+/// extension values escape `\` and `=`, and write line breaks as `\n`
+fn cef_value(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace("\r\n", "\\n")
+        .replace(['\r', '\n'], "\\n")
+}
+
+/// leef has no escapes: tabs would split the attribute, so they go
+fn leef_value(s: &str) -> String {
+    s.replace(['\t', '\r', '\n'], " ")
+}
+
+impl Reporter for CefReporter {
+    fn begin(&mut self, info: &ScanInfo) -> io::Result<()> {
+        self.targets = info.targets.join(",");
+        self.ports = info.ports.clone();
+        self.started = info.started;
+        Ok(())
+    }
+
+    fn host(&mut self, report: &HostReport) -> io::Result<()> {
+        for r in report.results {
+            if self.show_closed || r.is_open() {
+                writeln!(self.out, "{}", self.event(report.host, r))?;
+            }
+        }
+        self.out.flush()
+    }
+}
@@ -0,0 +1,210 @@
+use super::{HostReport, ReportOptions, Reporter, ScanInfo, ScanSummary};
+use crate::saved::recorded_ports;
+use crate::services::Services;
+use crate::{diff_scans, Change, ChatTarget, PortSpec, PortState, Protocol, SavedHost, SavedScan};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::sync::Arc;
+
+/// lines of ports or changes a message lists before it sums up the rest
+const MAX_LINES: usize = 15;
+
+/// posts a compact summary to chat channels: what was scanned, what's
+/// open, and what changed against the baseline, e.g.
+///
+/// ```text
+/// pscan: 10.0.0.0/24, top 1000 ports: 3 open ports on 2 of 256 hosts (41.2s)
+/// changed since the baseline: 1 opened, 1 closed
+///   opened 10.0.0.5 8080/tcp http-proxy
+///   closed 10.0.0.7 23/tcp telnet
+/// ```
+///
+/// the baseline is a saved scan when one is given, else the first scan.
+/// with --watch every later round is compared with the one before and only
+/// posted when something changed; those failures go to `log` rather than
+/// ending the watch.
+pub struct ChatReporter {
+    targets: Vec<ChatTarget>,
+    protocol: Protocol,
+    services: Arc<Services>,
+    scanned: String,
+    ports: Option<PortSpec>,
+    hosts: Vec<SavedHost>,
+    baseline: Option<SavedScan>,
+    /// what the baseline is, for the message
+    since: &'static str,
+    /// Some for --watch
+    log: Option<Box<dyn Write + Send>>,
+}
+
+impl ChatReporter {
+    pub fn new(targets: Vec<ChatTarget>, opts: &ReportOptions) -> Self {
+        ChatReporter {
+            targets,
+            protocol: opts.protocol,
+            services: opts.services.clone(),
+            scanned: String::new(),
+            ports: None,
+            hosts: Vec::new(),
+            baseline: None,
+            since: "the baseline",
+            log: None,
+        }
+    }
+
+    /// compare the (first) scan with this one
+    pub fn baseline(mut self, scan: SavedScan) -> Self {
+        self.baseline = Some(scan);
+        self
+    }
+
+    /// for --watch: post only the rounds that changed something
+    pub fn watch(mut self, log: Box<dyn Write + Send>) -> Self {
+        self.log = Some(log);
+        self
+    }
+
+    fn message(&self, summary: &ScanSummary, scan: &SavedScan) -> Option<String> {
+        let mut text = format!(
+            "pscan: {}: {} open port{} on {} of {} hosts ({:.1}s){}",
+            self.scanned,
+            summary.open_ports,
+            plural(summary.open_ports),
+            summary.hosts_with_open,
+            summary.hosts_scanned,
+            summary.elapsed.as_secs_f64(),
+            if summary.cancelled { ", cancelled" } else { "" }
+        );
+        let mut lines = Vec::new();
+        match &self.baseline {
+            Some(baseline) => {
+                let changes = diff_scans(baseline, scan);
+                if changes.is_empty() && self.log.is_some() {
+                    return None;
+                }
+                let count = |change| changes.iter().filter(|c| c.change == change).count();
+                let counts: Vec<String> = [
+                    (Change::Opened, "opened"),
+                    (Change::Closed, "closed"),
+                    (Change::ServiceChanged, "changed service"),
+                ]
+                .into_iter()
+                .filter(|&(change, _)| count(change) > 0)
+                .map(|(change, what)| format!("{} {what}", count(change)))
+                .collect();
+                if counts.is_empty() {
+                    let _ = write!(text, "\nno changes since {}", self.since);
+                } else {
+                    let _ = write!(
+                        text,
+                        "\nchanged since {}: {}",
+                        self.since,
+                        counts.join(", ")
+                    );
+                }
+                for c in &changes {
+                    let service = match c.change {
+                        Change::Closed => c.old_service.as_deref(),
+                        _ => c.new_service.as_deref(),
+                    };
+                    let line = format!(
+                        "{} {} {}/{} {}",
+                        c.change,
+                        c.ip,
+                        c.port,
+                        self.protocol,
+                        service.unwrap_or_default()
+                    );
+                    lines.push(line.trim_end().to_string());
+                }
+            }
+            None => {
+                for host in &scan.hosts {
+                    for r in host.results.iter().filter(|r| r.is_open()) {
+                        let line = format!(
+                            "open {} {}/{} {}",
+                            host.host.ip,
+                            r.port,
+                            self.protocol,
+                            r.service_name_in(&self.services).unwrap_or_default()
+                        );
+                        lines.push(line.trim_end().to_string());
+                    }
+                }
+            }
+        }
+        for line in lines.iter().take(MAX_LINES) {
+            let _ = write!(text, "\n  {line}");
+        }
+        if lines.len() > MAX_LINES {
+            let _ = write!(text, "\n  … and {} more", lines.len() - MAX_LINES);
+        }
+        Some(text)
+    }
+}
+
+fn plural(n: usize) -> &'static str {
+    if n == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
+
+impl Reporter for ChatReporter {
+    fn begin(&mut self, info: &ScanInfo) -> io::Result<()> {
+        let ports = if info.ports.starts_with("top ") {
+            format!("{} ports", info.ports)
+        } else {
+            format!("ports {}", info.ports)
+        };
+        self.scanned = format!("{}, {ports}", info.targets.join(" "));
+        self.ports = recorded_ports(&info.ports);
+        self.hosts.clear();
+        Ok(())
+    }
+
+    fn host(&mut self, report: &HostReport) -> io::Result<()> {
+        self.hosts.push(SavedHost {
+            host: report.host.clone(),
+            // unprobed ports say nothing about what changed
+            results: report
+                .results
+                .iter()
+                .filter(|r| r.state != PortState::Unknown)
+                .cloned()
+                .collect(),
+            elapsed: report.elapsed,
+            incomplete: report.incomplete,
+            finished: None,
+        });
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &ScanSummary) -> io::Result<()> {
+        let scan = SavedScan {
+            ports: self.ports.take(),
+            hosts: std::mem::take(&mut self.hosts),
+            ..SavedScan::default()
+        };
+        let message = self.message(summary, &scan);
+        if self.log.is_some() && !summary.cancelled {
+            // the next round is compared with this one
+            self.baseline = Some(scan);
+            self.since = "the last scan";
+        }
+        let Some(message) = message else {
+            return Ok(());
+        };
+        for target in &self.targets {
+            if let Err(e) = target.post(&message) {
+                let e = io::Error::other(format!("{target}: {e}"));
+                match &mut self.log {
+                    Some(log) => writeln!(log, "can't post to the {e}")?,
+                    None => return Err(e),
+                }
+            }
+        }
+        Ok(())
+    }
+}
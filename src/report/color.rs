@@ -0,0 +1,102 @@
+use crate::{Change, PortState};
+use std::fmt;
+use std::str::FromStr;
+
+/// --color: whether human readable output is colored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    /// on a terminal, unless NO_COLOR is set or TERM is dumb
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// whether to color output going to a terminal (or not)
+    pub fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            // https://no-color.org: set and not empty means no color
+            ColorChoice::Auto => {
+                is_terminal
+                    && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                    && std::env::var_os("TERM").is_none_or(|t| t != "dumb")
+            }
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            other => Err(format!(
+                "unknown color choice '{other}' (expected auto, always or never)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ColorChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ColorChoice::Auto => "auto",
+            ColorChoice::Always => "always",
+            ColorChoice::Never => "never",
+        })
+    }
+}
+
+/// the colors port states and changes are shown in, so the table, --watch,
+/// --open-as-found and diff all agree: green open, red closed, yellow
+/// filtered. with color off text is left as it is. pad text before
+/// painting it, as the escape codes count towards `{:<n}` widths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Palette {
+    on: bool,
+}
+
+impl Palette {
+    pub fn new(on: bool) -> Self {
+        Palette { on }
+    }
+
+    pub fn is_on(self) -> bool {
+        self.on
+    }
+
+    /// `text` in the color of `state`
+    pub fn state(self, state: PortState, text: &str) -> String {
+        let code = match state {
+            PortState::Open => "32",
+            PortState::Closed => "31",
+            PortState::Filtered | PortState::OpenFiltered => "33",
+            PortState::Error => "35",
+            PortState::Unfiltered | PortState::Unknown => return text.to_string(),
+        };
+        self.paint(code, text)
+    }
+
+    /// `text` in the color of a port that opened, closed or changed service
+    pub fn change(self, change: Change, text: &str) -> String {
+        match change {
+            Change::Opened => self.state(PortState::Open, text),
+            Change::Closed => self.state(PortState::Closed, text),
+            Change::ServiceChanged => self.paint("33", text),
+        }
+    }
+
+    fn paint(self, code: &str, text: &str) -> String {
+        if self.on {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text.to_string()
+        }
+    }
+}
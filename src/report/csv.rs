@@ -0,0 +1,133 @@
+use super::util::{csv_field, rfc3339};
+use super::{HostReport, ReportOptions, Reporter};
+use crate::services::Services;
+use std::io::{self, Write};
+use std::sync::Arc;
+
+/// one row per port: host, ip, port, state, service, latency_ms
+/// (plus banner, tls, http and version columns when those probes ran)
+pub struct CsvReporter {
+    out: Box<dyn Write + Send>,
+    show_closed: bool,
+    banners: bool,
+    tls: bool,
+    http: bool,
+    versions: bool,
+    services: Arc<Services>,
+    ptr: bool,
+    wrote_header: bool,
+}
+
+impl CsvReporter {
+    pub fn new(out: Box<dyn Write + Send>, opts: &ReportOptions) -> Self {
+        CsvReporter {
+            out,
+            show_closed: opts.show_closed,
+            banners: opts.banners,
+            tls: opts.tls,
+            http: opts.http,
+            versions: opts.versions,
+            services: opts.services.clone(),
+            ptr: opts.ptr,
+            wrote_header: false,
+        }
+    }
+}
+
+impl Reporter for CsvReporter {
+    fn host(&mut self, report: &HostReport) -> io::Result<()> {
+        if !self.wrote_header {
+            write!(self.out, "host,ip,port,state,service,latency_ms")?;
+            if self.banners {
+                write!(self.out, ",banner")?;
+            }
+            if self.tls {
+                write!(
+                    self.out,
+                    ",tls_version,tls_cipher,tls_subject,tls_issuer,tls_sans,tls_not_after"
+                )?;
+            }
+            if self.http {
+                write!(self.out, ",http_status,http_server,http_title")?;
+            }
+            if self.versions {
+                write!(self.out, ",product,version,info")?;
+            }
+            if self.ptr {
+                write!(self.out, ",ptr")?;
+            }
+            writeln!(self.out)?;
+            self.wrote_header = true;
+        }
+
+        // several targets can share an ip; keep them all in one field
+        let host = csv_field(&report.host.names.join(";"));
+        let ip = report.host.ip.to_string();
+        let ptr = csv_field(report.host.ptr.as_deref().unwrap_or(""));
+
+        for r in report.results {
+            if !(self.show_closed || r.is_open()) {
+                continue;
+            }
+            let latency = r
+                .latency_ms()
+                .map(|ms| format!("{ms:.3}"))
+                .unwrap_or_default();
+            write!(
+                self.out,
+                "{host},{ip},{},{},{},{latency}",
+                r.port,
+                r.state,
+                csv_field(r.service_name_in(&self.services).unwrap_or(""))
+            )?;
+            if self.banners {
+                write!(
+                    self.out,
+                    ",{}",
+                    csv_field(r.banner.as_deref().unwrap_or(""))
+                )?;
+            }
+            if self.tls {
+                let tls = r.tls.as_ref();
+                let cert = tls.and_then(|t| t.certificate.as_ref());
+                write!(
+                    self.out,
+                    ",{},{},{},{},{},{}",
+                    csv_field(tls.map_or("", |t| t.version.as_str())),
+                    csv_field(tls.map_or("", |t| t.cipher.as_str())),
+                    csv_field(cert.map_or("", |c| c.subject.as_str())),
+                    csv_field(cert.map_or("", |c| c.issuer.as_str())),
+                    csv_field(&cert.map(|c| c.sans.join(";")).unwrap_or_default()),
+                    cert.and_then(|c| c.not_after)
+                        .map(rfc3339)
+                        .unwrap_or_default()
+                )?;
+            }
+            if self.http {
+                let http = r.http.as_ref();
+                write!(
+                    self.out,
+                    ",{},{},{}",
+                    http.map(|h| h.status.to_string()).unwrap_or_default(),
+                    csv_field(http.and_then(|h| h.server.as_deref()).unwrap_or("")),
+                    csv_field(http.and_then(|h| h.title.as_deref()).unwrap_or(""))
+                )?;
+            }
+            if self.versions {
+                let service = r.service.as_ref();
+                write!(
+                    self.out,
+                    ",{},{},{}",
+                    csv_field(service.and_then(|m| m.product.as_deref()).unwrap_or("")),
+                    csv_field(service.and_then(|m| m.version.as_deref()).unwrap_or("")),
+                    csv_field(service.and_then(|m| m.info.as_deref()).unwrap_or(""))
+                )?;
+            }
+            if self.ptr {
+                write!(self.out, ",{ptr}")?;
+            }
+            writeln!(self.out)?;
+        }
+        self.out.flush()
+    }
+}
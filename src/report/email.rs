@@ -0,0 +1,156 @@
+use super::util::SharedBuf;
+use super::{create, HostReport, ReportOptions, Reporter, ScanInfo, ScanSummary};
+use crate::SmtpConfig;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, Write};
+use std::net::IpAddr;
+
+/// for --watch: renders every round as an html or markdown report and
+/// mails it when ports opened or closed since the round before. the first
+/// round is the baseline and isn't sent. a mail that can't be sent is
+/// written to `log` rather than ending the watch.
+pub struct EmailReporter {
+    smtp: SmtpConfig,
+    to: Vec<String>,
+    format: &'static str,
+    opts: ReportOptions,
+    log: Box<dyn Write + Send>,
+    buf: SharedBuf,
+    inner: Option<Box<dyn Reporter>>,
+    /// the report of the last finished round
+    last: String,
+    /// mail on changes by ourselves, rather than only when asked
+    auto: bool,
+    /// open ports per host, once the first round is over
+    open: Option<HashMap<IpAddr, BTreeSet<u16>>>,
+    opening: HashMap<IpAddr, BTreeSet<u16>>,
+    opened: usize,
+    closed: usize,
+}
+
+impl EmailReporter {
+    /// `format` is `html` or `md`
+    pub fn new(
+        smtp: SmtpConfig,
+        to: Vec<String>,
+        format: &str,
+        opts: &ReportOptions,
+        log: Box<dyn Write + Send>,
+    ) -> Result<Self, String> {
+        let format = match format {
+            "html" => "html",
+            "md" => "md",
+            other => {
+                return Err(format!(
+                    "can't mail '{other}' reports (expected html or md)"
+                ))
+            }
+        };
+        Ok(EmailReporter {
+            smtp,
+            to,
+            format,
+            opts: opts.clone(),
+            log,
+            buf: SharedBuf::default(),
+            inner: None,
+            last: String::new(),
+            auto: true,
+            open: None,
+            opening: HashMap::new(),
+            opened: 0,
+            closed: 0,
+        })
+    }
+
+    /// only mail when `mail` is called, for callers that judge what
+    /// changed themselves
+    pub fn manual(mut self) -> Self {
+        self.auto = false;
+        self
+    }
+
+    /// mail the last finished round's report
+    pub fn mail(&mut self, subject: &str) -> io::Result<()> {
+        self.smtp
+            .send(&self.to, subject, content_type(self.format), &self.last)
+    }
+}
+
+/// the content type a report of `format` is mailed as
+fn content_type(format: &str) -> &'static str {
+    match format {
+        "html" => "text/html",
+        _ => "text/plain",
+    }
+}
+
+impl Reporter for EmailReporter {
+    fn begin(&mut self, info: &ScanInfo) -> io::Result<()> {
+        self.buf.take();
+        self.opened = 0;
+        self.closed = 0;
+        let mut inner = create(self.format, Box::new(self.buf.clone()), &self.opts)
+            .map_err(io::Error::other)?;
+        inner.begin(info)?;
+        self.inner = Some(inner);
+        Ok(())
+    }
+
+    fn host(&mut self, report: &HostReport) -> io::Result<()> {
+        if let Some(inner) = &mut self.inner {
+            inner.host(report)?;
+        }
+        // a cancelled scan leaves ports unanswered, which would read as closed
+        if report.incomplete {
+            return Ok(());
+        }
+        let now: BTreeSet<u16> = report
+            .results
+            .iter()
+            .filter(|r| r.is_open())
+            .map(|r| r.port)
+            .collect();
+        if let Some(open) = &self.open {
+            let empty = BTreeSet::new();
+            let before = open.get(&report.host.ip).unwrap_or(&empty);
+            self.opened += now.difference(before).count();
+            self.closed += before.difference(&now).count();
+        }
+        self.opening.insert(report.host.ip, now);
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &ScanSummary) -> io::Result<()> {
+        if let Some(mut inner) = self.inner.take() {
+            inner.finish(summary)?;
+        }
+        self.last = self.buf.take();
+        let opening = std::mem::take(&mut self.opening);
+        let Some(open) = &mut self.open else {
+            self.open = Some(opening);
+            return Ok(());
+        };
+        open.extend(opening);
+        if !self.auto || self.opened + self.closed == 0 {
+            return Ok(());
+        }
+        let subject = match (self.opened, self.closed) {
+            (n, 0) => format!("pscan: {n} port{} opened", plural(n)),
+            (0, n) => format!("pscan: {n} port{} closed", plural(n)),
+            (o, c) => format!("pscan: {o} port{} opened, {c} closed", plural(o)),
+        };
+        if let Err(e) = self.mail(&subject) {
+            writeln!(self.log, "can't mail the report: {e}")?;
+        }
+        Ok(())
+    }
+}
+
+fn plural(n: usize) -> &'static str {
+    if n == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
@@ -0,0 +1,131 @@
+use super::util::ctime;
+use super::{HostReport, ReportOptions, Reporter, ScanInfo, ScanSummary};
+use crate::services::Services;
+use crate::{PortState, Protocol};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// nmap's -oG layout: one tab-separated "Host:" line per host with every
+/// port inline, e.g. `Host: 10.0.0.5 () Ports: 22/open/tcp//ssh///, 443/open/tcp//https///`
+pub struct GrepableReporter {
+    out: Box<dyn Write + Send>,
+    show_closed: bool,
+    protocol: Protocol,
+    services: Arc<Services>,
+    hosts_up: usize,
+}
+
+impl GrepableReporter {
+    pub fn new(out: Box<dyn Write + Send>, opts: &ReportOptions) -> Self {
+        GrepableReporter {
+            out,
+            show_closed: opts.show_closed,
+            protocol: opts.protocol,
+            services: opts.services.clone(),
+            hosts_up: 0,
+        }
+    }
+}
+
+impl Reporter for GrepableReporter {
+    fn begin(&mut self, info: &ScanInfo) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "# pscan {} scan initiated {} as: {}",
+            env!("CARGO_PKG_VERSION"),
+            ctime(info.started),
+            info.command
+        )?;
+        self.out.flush()
+    }
+
+    fn host(&mut self, report: &HostReport) -> io::Result<()> {
+        self.hosts_up += 1;
+
+        // the ptr name, else the first real hostname, as nmap shows them
+        let name = report
+            .host
+            .ptr
+            .as_deref()
+            .or_else(|| {
+                report
+                    .host
+                    .names
+                    .iter()
+                    .find(|n| n.parse::<IpAddr>().is_err() && !n.contains('/'))
+                    .map(String::as_str)
+            })
+            .unwrap_or("");
+        let ip = report.host.ip;
+
+        let ports: Vec<String> = report
+            .results
+            .iter()
+            .filter(|r| self.show_closed || r.is_open())
+            .map(|r| {
+                // the detected version (or failing that the banner) goes in
+                // the version field; like nmap, '/' becomes '|' so the
+                // field layout survives
+                let detected = r.service.as_ref().map(|m| m.summary());
+                let version = detected
+                    .as_deref()
+                    .filter(|v| !v.is_empty())
+                    .or(r.banner.as_deref())
+                    .unwrap_or("")
+                    .replace('/', "|")
+                    .replace(',', ";");
+                format!(
+                    "{}/{}/{}//{}//{version}/",
+                    r.port,
+                    r.state,
+                    self.protocol,
+                    r.service_name_in(&self.services).unwrap_or("")
+                )
+            })
+            .collect();
+
+        writeln!(self.out, "Host: {ip} ({name})\tStatus: Up")?;
+        write!(self.out, "Host: {ip} ({name})\tPorts: {}", ports.join(", "))?;
+        if !self.show_closed {
+            // nmap reports only the most common hidden state;
+            // reversed so ties go to closed, then filtered
+            let ignored = [
+                PortState::Closed,
+                PortState::Filtered,
+                PortState::OpenFiltered,
+                PortState::Unfiltered,
+                PortState::Error,
+                PortState::Unknown,
+            ]
+            .into_iter()
+            .rev()
+            .map(|s| (s, report.count(s)))
+            .filter(|(_, n)| *n > 0)
+            .max_by_key(|(_, n)| *n);
+            if let Some((state, n)) = ignored {
+                write!(self.out, "\tIgnored State: {state} ({n})")?;
+            }
+        }
+        writeln!(self.out)?;
+        self.out.flush()
+    }
+
+    fn finish(&mut self, summary: &ScanSummary) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "# pscan done at {} -- {} IP addresses ({} hosts up) scanned in {:.2} seconds{}",
+            ctime(SystemTime::now()),
+            summary.hosts_total,
+            self.hosts_up,
+            summary.elapsed.as_secs_f64(),
+            if summary.cancelled {
+                " (cancelled, results are incomplete)"
+            } else {
+                ""
+            }
+        )?;
+        self.out.flush()
+    }
+}
@@ -0,0 +1,213 @@
+use super::util::{rfc3339, xml_escape};
+use super::{HostReport, ReportOptions, Reporter, ScanInfo, ScanSummary};
+use crate::services::Services;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::sync::Arc;
+
+const STYLE: &str = "body{font-family:system-ui,sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:1.5em}\
+th,td{border:1px solid #ccc;padding:4px 10px;text-align:left}\
+th{background:#f0f0f0;cursor:pointer;user-select:none}\
+th.sorted-asc::after{content:' \\25B2'}th.sorted-desc::after{content:' \\25BC'}\
+.open{color:#1a7f37;font-weight:bold}.closed{color:#cf222e}\
+.filtered,.error{color:#9a6700}\
+dl{display:grid;grid-template-columns:max-content auto;gap:2px 12px}dt{font-weight:bold}";
+
+// click a header to sort by that column; numeric columns sort numerically
+const SCRIPT: &str = "document.querySelectorAll('table.ports').forEach(function(t){\
+t.querySelectorAll('th').forEach(function(th,i){th.addEventListener('click',function(){\
+var asc=!th.classList.contains('sorted-asc');\
+t.querySelectorAll('th').forEach(function(h){h.classList.remove('sorted-asc','sorted-desc')});\
+th.classList.add(asc?'sorted-asc':'sorted-desc');\
+var body=t.tBodies[0];var rows=Array.prototype.slice.call(body.rows);\
+rows.sort(function(a,b){var x=a.cells[i].textContent,y=b.cells[i].textContent;\
+var nx=parseFloat(x),ny=parseFloat(y);\
+var c=(!isNaN(nx)&&!isNaN(ny))?nx-ny:x.localeCompare(y);return asc?c:-c});\
+rows.forEach(function(r){body.appendChild(r)})})})});";
+
+/// a standalone html page: metadata, summary and a sortable table per host
+pub struct HtmlReporter {
+    out: Box<dyn Write + Send>,
+    show_closed: bool,
+    banners: bool,
+    tls: bool,
+    http: bool,
+    versions: bool,
+    services: Arc<Services>,
+    info: Option<ScanInfo>,
+    /// already-rendered host sections
+    sections: Vec<String>,
+}
+
+impl HtmlReporter {
+    pub fn new(out: Box<dyn Write + Send>, opts: &ReportOptions) -> Self {
+        HtmlReporter {
+            out,
+            show_closed: opts.show_closed,
+            banners: opts.banners,
+            tls: opts.tls,
+            http: opts.http,
+            versions: opts.versions,
+            services: opts.services.clone(),
+            info: None,
+            sections: Vec::new(),
+        }
+    }
+}
+
+impl Reporter for HtmlReporter {
+    fn begin(&mut self, info: &ScanInfo) -> io::Result<()> {
+        self.info = Some(info.clone());
+        Ok(())
+    }
+
+    fn host(&mut self, report: &HostReport) -> io::Result<()> {
+        let host = report.host;
+        let mut s = String::new();
+
+        let _ = writeln!(
+            s,
+            "<h2>{} <small>({})</small></h2>",
+            host.ip,
+            xml_escape(&host.all_names().join(", "))
+        );
+        let _ = writeln!(
+            s,
+            "<p>{} open port(s), scanned in {:.2}s{}</p>",
+            report.open_count(),
+            report.elapsed.as_secs_f64(),
+            if report.incomplete {
+                " <strong>(incomplete)</strong>"
+            } else {
+                ""
+            }
+        );
+        let _ = writeln!(
+            s,
+            "<table class=\"ports\"><thead><tr><th>port</th><th>state</th><th>service</th><th>latency (ms)</th>{}{}{}{}</tr></thead><tbody>",
+            if self.banners { "<th>banner</th>" } else { "" },
+            if self.tls { "<th>tls</th>" } else { "" },
+            if self.http { "<th>http</th>" } else { "" },
+            if self.versions { "<th>version</th>" } else { "" }
+        );
+        for r in report.results {
+            if !(self.show_closed || r.is_open()) {
+                continue;
+            }
+            let latency = r
+                .latency_ms()
+                .map(|ms| format!("{ms:.2}"))
+                .unwrap_or_default();
+            let banner = if self.banners {
+                format!(
+                    "<td><code>{}</code></td>",
+                    xml_escape(r.banner.as_deref().unwrap_or(""))
+                )
+            } else {
+                String::new()
+            };
+            let tls = if self.tls {
+                let summary = r.tls.as_ref().map(|t| t.summary()).unwrap_or_default();
+                format!("<td>{}</td>", xml_escape(&summary))
+            } else {
+                String::new()
+            };
+            let http = if self.http {
+                let summary = r.http.as_ref().map(|h| h.summary()).unwrap_or_default();
+                format!("<td>{}</td>", xml_escape(&summary))
+            } else {
+                String::new()
+            };
+            let version = if self.versions {
+                let summary = r.service.as_ref().map(|m| m.summary()).unwrap_or_default();
+                format!("<td>{}</td>", xml_escape(&summary))
+            } else {
+                String::new()
+            };
+            let _ = writeln!(
+                s,
+                "<tr><td>{}</td><td class=\"{}\">{}</td><td>{}</td><td>{latency}</td>{banner}{tls}{http}{version}</tr>",
+                r.port,
+                r.state,
+                r.state,
+                r.service_name_in(&self.services).unwrap_or("")
+            );
+        }
+        let _ = writeln!(s, "</tbody></table>");
+
+        self.sections.push(s);
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &ScanSummary) -> io::Result<()> {
+        let out = &mut self.out;
+        writeln!(out, "<!DOCTYPE html>")?;
+        writeln!(out, "<html lang=\"en\"><head><meta charset=\"utf-8\">")?;
+        writeln!(out, "<title>pscan report</title>")?;
+        writeln!(out, "<style>{STYLE}</style></head><body>")?;
+        writeln!(out, "<h1>pscan report</h1>")?;
+
+        writeln!(out, "<dl>")?;
+        if let Some(info) = &self.info {
+            let row = |k: &str, v: &str| format!("<dt>{k}</dt><dd>{}</dd>", xml_escape(v));
+            writeln!(out, "{}", row("started", &rfc3339(info.started)))?;
+            writeln!(out, "{}", row("targets", &info.targets.join(", ")))?;
+            writeln!(
+                out,
+                "{}",
+                row(
+                    "ports",
+                    &format!("{} ({} total)", info.ports, info.port_count)
+                )
+            )?;
+            if let Some(excluded) = &info.excluded_ports {
+                writeln!(out, "{}", row("excluded ports", excluded))?;
+            }
+            writeln!(
+                out,
+                "{}",
+                row("timeout", &format!("{} ms", info.timeout_ms))
+            )?;
+            writeln!(out, "{}", row("retries", &info.retries.to_string()))?;
+            writeln!(
+                out,
+                "{}",
+                row(
+                    "engine",
+                    &format!("{} (concurrency {})", info.engine, info.concurrency)
+                )
+            )?;
+        }
+        writeln!(
+            out,
+            "<dt>hosts scanned</dt><dd>{}/{}</dd>",
+            summary.hosts_scanned, summary.hosts_total
+        )?;
+        writeln!(
+            out,
+            "<dt>hosts with open ports</dt><dd>{}</dd>",
+            summary.hosts_with_open
+        )?;
+        writeln!(out, "<dt>open ports</dt><dd>{}</dd>", summary.open_ports)?;
+        writeln!(
+            out,
+            "<dt>elapsed</dt><dd>{:.2}s{}</dd>",
+            summary.elapsed.as_secs_f64(),
+            if summary.cancelled {
+                " (cancelled)"
+            } else {
+                ""
+            }
+        )?;
+        writeln!(out, "</dl>")?;
+
+        for section in &self.sections {
+            out.write_all(section.as_bytes())?;
+        }
+
+        writeln!(out, "<script>{SCRIPT}</script>")?;
+        writeln!(out, "</body></html>")?;
+        out.flush()
+    }
+}
@@ -0,0 +1,362 @@
+use super::util::rfc3339;
+use super::{HostReport, ReportOptions, Reporter, ScanInfo, ScanSummary};
+use crate::services::Services;
+use crate::{CertInfo, HttpInfo, ScanHost, ScanResult, ServiceMatch, TlsInfo};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// one json document for the whole run, written when the scan finishes
+pub struct JsonReporter {
+    out: Box<dyn Write + Send>,
+    show_closed: bool,
+    probes: Probes,
+    services: Arc<Services>,
+    ptr: bool,
+    info: Option<ScanInfo>,
+    hosts: Vec<Host>,
+}
+
+impl JsonReporter {
+    pub fn new(out: Box<dyn Write + Send>, opts: &ReportOptions) -> Self {
+        JsonReporter {
+            out,
+            show_closed: opts.show_closed,
+            probes: Probes::of(opts),
+            services: opts.services.clone(),
+            ptr: opts.ptr,
+            info: None,
+            hosts: Vec::new(),
+        }
+    }
+}
+
+/// the whole report
+#[derive(Serialize)]
+struct Document<'a> {
+    scanner: &'static str,
+    version: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    started: Option<String>,
+    finished: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parameters: Option<Parameters>,
+    hosts: &'a [Host],
+    summary: Summary,
+}
+
+#[derive(Serialize)]
+struct Parameters {
+    targets: Vec<String>,
+    ports: String,
+    port_count: usize,
+    excluded_ports: Option<String>,
+    excluded_hosts: usize,
+    timeout_ms: u64,
+    retries: u8,
+    engine: String,
+    concurrency: usize,
+    show_closed: bool,
+}
+
+#[derive(Serialize)]
+struct Summary {
+    targets: usize,
+    hosts_total: usize,
+    hosts_scanned: usize,
+    hosts_with_open: usize,
+    open_ports: usize,
+    elapsed_ms: f64,
+    cancelled: bool,
+}
+
+/// a host as the json report writes it, and as saved scans read it back
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Host {
+    pub ip: IpAddr,
+    #[serde(default)]
+    pub names: Vec<String>,
+    /// only there when reverse dns names were looked up
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ptr: Option<Option<String>>,
+    #[serde(default)]
+    pub elapsed_ms: f64,
+    #[serde(default)]
+    pub incomplete: bool,
+    #[serde(default)]
+    pub open_count: usize,
+    /// the files a merged report took the host from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<String>,
+    #[serde(default)]
+    pub ports: Vec<Port>,
+}
+
+/// a port, in json reports and on json lines. the probe results are only
+/// there when the probe was asked for, and null when it found nothing.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Port {
+    pub port: u16,
+    pub state: String,
+    pub service: Option<String>,
+    pub latency_ms: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub banner: Option<Option<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<Option<Tls>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http: Option<Option<Http>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<Option<Version>>,
+    /// the file a merged report took the port from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Tls {
+    pub version: String,
+    pub cipher: String,
+    #[serde(flatten)]
+    pub certificate: Option<Cert>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Cert {
+    pub subject: String,
+    #[serde(default)]
+    pub issuer: String,
+    #[serde(default)]
+    pub sans: Vec<String>,
+    #[serde(default)]
+    pub not_before: Option<String>,
+    #[serde(default)]
+    pub not_after: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Http {
+    pub status: u16,
+    pub server: Option<String>,
+    pub title: Option<String>,
+}
+
+/// a version detection match; the service name is the port's own
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Version {
+    pub product: Option<String>,
+    pub version: Option<String>,
+    pub info: Option<String>,
+    pub os: Option<String>,
+    pub hostname: Option<String>,
+}
+
+/// which probe results a port carries
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Probes {
+    pub banners: bool,
+    pub tls: bool,
+    pub http: bool,
+    pub versions: bool,
+}
+
+impl Probes {
+    pub(crate) fn of(opts: &ReportOptions) -> Probes {
+        Probes {
+            banners: opts.banners,
+            tls: opts.tls,
+            http: opts.http,
+            versions: opts.versions,
+        }
+    }
+
+    /// the ones `r` has results for
+    pub(crate) fn found(r: &ScanResult) -> Probes {
+        Probes {
+            banners: r.banner.is_some(),
+            tls: r.tls.is_some(),
+            http: r.http.is_some(),
+            versions: r.service.is_some(),
+        }
+    }
+}
+
+/// milliseconds, to the microsecond
+pub(crate) fn ms(d: Duration) -> f64 {
+    (d.as_secs_f64() * 1_000_000.0).round() / 1000.0
+}
+
+impl Port {
+    pub(crate) fn new(r: &ScanResult, service: Option<&str>, probes: Probes) -> Port {
+        Port {
+            port: r.port,
+            state: r.state.as_str().to_string(),
+            service: service.map(str::to_string),
+            latency_ms: r.latency.map(ms),
+            banner: probes.banners.then(|| r.banner.clone()),
+            tls: probes.tls.then(|| r.tls.as_ref().map(Tls::new)),
+            http: probes.http.then(|| r.http.as_ref().map(Http::new)),
+            version: probes
+                .versions
+                .then(|| r.service.as_ref().map(Version::new)),
+            source: None,
+        }
+    }
+
+    /// the result back; reports don't say which protocol the ports were
+    /// scanned with, or when certificates expire
+    pub(crate) fn result(self) -> Result<ScanResult, String> {
+        let state = self.state.parse()?;
+        let service = self.version.flatten().map(|v| ServiceMatch {
+            service: self.service.unwrap_or_default(),
+            product: v.product,
+            version: v.version,
+            info: v.info,
+            os: v.os,
+            hostname: v.hostname,
+        });
+        Ok(ScanResult {
+            latency: self
+                .latency_ms
+                .and_then(|ms| Duration::try_from_secs_f64(ms / 1000.0).ok()),
+            banner: self.banner.flatten(),
+            tls: self.tls.flatten().map(|t| TlsInfo {
+                version: t.version,
+                cipher: t.cipher,
+                certificate: t.certificate.map(|c| CertInfo {
+                    subject: c.subject,
+                    issuer: c.issuer,
+                    sans: c.sans,
+                    not_before: None,
+                    not_after: None,
+                }),
+            }),
+            http: self.http.flatten().map(|h| HttpInfo {
+                status: h.status,
+                server: h.server,
+                title: h.title,
+            }),
+            service,
+            ..ScanResult::new(self.port, state)
+        })
+    }
+}
+
+impl Tls {
+    fn new(tls: &TlsInfo) -> Tls {
+        Tls {
+            version: tls.version.clone(),
+            cipher: tls.cipher.clone(),
+            certificate: tls.certificate.as_ref().map(|c| Cert {
+                subject: c.subject.clone(),
+                issuer: c.issuer.clone(),
+                sans: c.sans.clone(),
+                not_before: c.not_before.map(rfc3339),
+                not_after: c.not_after.map(rfc3339),
+            }),
+        }
+    }
+}
+
+impl Http {
+    fn new(http: &HttpInfo) -> Http {
+        Http {
+            status: http.status,
+            server: http.server.clone(),
+            title: http.title.clone(),
+        }
+    }
+}
+
+impl Version {
+    fn new(m: &ServiceMatch) -> Version {
+        Version {
+            product: m.product.clone(),
+            version: m.version.clone(),
+            info: m.info.clone(),
+            os: m.os.clone(),
+            hostname: m.hostname.clone(),
+        }
+    }
+}
+
+impl Host {
+    /// `host` without its ports
+    pub(crate) fn new(host: &ScanHost, ptr: bool) -> Host {
+        Host {
+            ip: host.ip,
+            names: host.names.clone(),
+            ptr: ptr.then(|| host.ptr.clone()),
+            elapsed_ms: 0.0,
+            incomplete: false,
+            open_count: 0,
+            sources: Vec::new(),
+            ports: Vec::new(),
+        }
+    }
+}
+
+impl Reporter for JsonReporter {
+    fn begin(&mut self, info: &ScanInfo) -> io::Result<()> {
+        self.info = Some(info.clone());
+        Ok(())
+    }
+
+    fn host(&mut self, report: &HostReport) -> io::Result<()> {
+        let ports = report
+            .results
+            .iter()
+            .filter(|r| self.show_closed || r.is_open())
+            .map(|r| Port::new(r, r.service_name_in(&self.services), self.probes))
+            .collect();
+        self.hosts.push(Host {
+            elapsed_ms: ms(report.elapsed),
+            incomplete: report.incomplete,
+            open_count: report.open_count(),
+            ports,
+            ..Host::new(report.host, self.ptr)
+        });
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &ScanSummary) -> io::Result<()> {
+        let info = self.info.as_ref();
+        let finished = match info {
+            Some(info) => info.started + summary.elapsed,
+            None => SystemTime::now(),
+        };
+        let doc = Document {
+            scanner: "pscan",
+            version: env!("CARGO_PKG_VERSION"),
+            started: info.map(|info| rfc3339(info.started)),
+            finished: rfc3339(finished),
+            parameters: info.map(|info| Parameters {
+                targets: info.targets.clone(),
+                ports: info.ports.clone(),
+                port_count: info.port_count,
+                excluded_ports: info.excluded_ports.clone(),
+                excluded_hosts: info.excluded_hosts,
+                timeout_ms: info.timeout_ms,
+                retries: info.retries,
+                engine: info.engine.to_string(),
+                concurrency: info.concurrency,
+                show_closed: self.show_closed,
+            }),
+            hosts: &self.hosts,
+            summary: Summary {
+                targets: summary.targets,
+                hosts_total: summary.hosts_total,
+                hosts_scanned: summary.hosts_scanned,
+                hosts_with_open: summary.hosts_with_open,
+                open_ports: summary.open_ports,
+                elapsed_ms: ms(summary.elapsed),
+                cancelled: summary.cancelled,
+            },
+        };
+        serde_json::to_writer_pretty(&mut self.out, &doc)?;
+        writeln!(self.out)?;
+        self.out.flush()
+    }
+}
@@ -0,0 +1,157 @@
+use super::json::{ms, Port, Probes};
+use super::util::rfc3339;
+use super::{HostReport, ReportOptions, Reporter, ScanInfo, ScanSummary};
+use crate::services::Services;
+use crate::{ScanHost, ScanResult};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// json lines: one object per finding, written the moment it is found.
+/// every line carries a "type" of start, port, host or summary.
+pub struct JsonlReporter {
+    out: Box<dyn Write + Send>,
+    show_closed: bool,
+    probes: Probes,
+    services: Arc<Services>,
+    ptr: bool,
+}
+
+/// one line, as written and as saved scans read it back. a --checkpoint
+/// file starts with the command line it was started with.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub(crate) enum Line {
+    Checkpoint {
+        args: Vec<String>,
+    },
+    Start {
+        time: Option<String>,
+        #[serde(default)]
+        targets: Vec<String>,
+        #[serde(default)]
+        hosts: usize,
+        ports: Option<String>,
+        #[serde(default)]
+        port_count: usize,
+    },
+    Port {
+        time: Option<String>,
+        #[serde(flatten)]
+        host: LineHost,
+        #[serde(flatten)]
+        port: Box<Port>,
+    },
+    Host {
+        time: Option<String>,
+        #[serde(flatten)]
+        host: LineHost,
+        #[serde(default)]
+        open_count: usize,
+        #[serde(default)]
+        elapsed_ms: f64,
+        #[serde(default)]
+        incomplete: bool,
+    },
+    Summary {
+        time: Option<String>,
+        hosts_scanned: usize,
+        hosts_total: usize,
+        hosts_with_open: usize,
+        open_ports: usize,
+        elapsed_ms: f64,
+        cancelled: bool,
+    },
+    /// anything a later version adds
+    #[serde(other)]
+    Other,
+}
+
+/// the host a port or host line is about
+#[derive(Serialize, Deserialize)]
+pub(crate) struct LineHost {
+    pub ip: IpAddr,
+    #[serde(default)]
+    pub names: Vec<String>,
+    /// only there when reverse dns names were looked up
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ptr: Option<Option<String>>,
+}
+
+impl JsonlReporter {
+    pub fn new(out: Box<dyn Write + Send>, opts: &ReportOptions) -> Self {
+        JsonlReporter {
+            out,
+            show_closed: opts.show_closed,
+            probes: Probes::of(opts),
+            services: opts.services.clone(),
+            ptr: opts.ptr,
+        }
+    }
+
+    fn line(&mut self, line: &Line) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, line)?;
+        writeln!(self.out)?;
+        self.out.flush()
+    }
+
+    fn host_of(&self, host: &ScanHost) -> LineHost {
+        LineHost {
+            ip: host.ip,
+            names: host.names.clone(),
+            ptr: self.ptr.then(|| host.ptr.clone()),
+        }
+    }
+}
+
+fn now() -> Option<String> {
+    Some(rfc3339(SystemTime::now()))
+}
+
+impl Reporter for JsonlReporter {
+    fn begin(&mut self, info: &ScanInfo) -> io::Result<()> {
+        self.line(&Line::Start {
+            time: Some(rfc3339(info.started)),
+            targets: info.targets.clone(),
+            hosts: info.host_count,
+            ports: Some(info.ports.clone()),
+            port_count: info.port_count,
+        })
+    }
+
+    fn result(&mut self, host: &ScanHost, r: &ScanResult) -> io::Result<()> {
+        if !(self.show_closed || r.is_open()) {
+            return Ok(());
+        }
+        let port = Box::new(Port::new(r, r.service_name_in(&self.services), self.probes));
+        self.line(&Line::Port {
+            time: now(),
+            host: self.host_of(host),
+            port,
+        })
+    }
+
+    fn host(&mut self, report: &HostReport) -> io::Result<()> {
+        self.line(&Line::Host {
+            time: now(),
+            host: self.host_of(report.host),
+            open_count: report.open_count(),
+            elapsed_ms: ms(report.elapsed),
+            incomplete: report.incomplete,
+        })
+    }
+
+    fn finish(&mut self, summary: &ScanSummary) -> io::Result<()> {
+        self.line(&Line::Summary {
+            time: now(),
+            hosts_scanned: summary.hosts_scanned,
+            hosts_total: summary.hosts_total,
+            hosts_with_open: summary.hosts_with_open,
+            open_ports: summary.open_ports,
+            elapsed_ms: ms(summary.elapsed),
+            cancelled: summary.cancelled,
+        })
+    }
+}
@@ -0,0 +1,170 @@
+use super::util::{md_cell, rfc3339};
+use super::{HostReport, ReportOptions, Reporter, ScanInfo, ScanSummary};
+use crate::services::Services;
+use std::io::{self, Write};
+use std::sync::Arc;
+
+/// github-flavored markdown: a table per host and a summary section
+pub struct MarkdownReporter {
+    out: Box<dyn Write + Send>,
+    show_closed: bool,
+    banners: bool,
+    tls: bool,
+    http: bool,
+    versions: bool,
+    services: Arc<Services>,
+}
+
+impl MarkdownReporter {
+    pub fn new(out: Box<dyn Write + Send>, opts: &ReportOptions) -> Self {
+        MarkdownReporter {
+            out,
+            show_closed: opts.show_closed,
+            banners: opts.banners,
+            tls: opts.tls,
+            http: opts.http,
+            versions: opts.versions,
+            services: opts.services.clone(),
+        }
+    }
+}
+
+impl Reporter for MarkdownReporter {
+    fn begin(&mut self, info: &ScanInfo) -> io::Result<()> {
+        let out = &mut self.out;
+        writeln!(out, "# pscan report")?;
+        writeln!(out)?;
+        writeln!(out, "- **started:** {}", rfc3339(info.started))?;
+        writeln!(out, "- **targets:** {}", md_cell(&info.targets.join(", ")))?;
+        writeln!(
+            out,
+            "- **ports:** {} ({} total)",
+            md_cell(&info.ports),
+            info.port_count
+        )?;
+        if let Some(excluded) = &info.excluded_ports {
+            writeln!(out, "- **excluded ports:** {}", md_cell(excluded))?;
+        }
+        writeln!(
+            out,
+            "- **timeout:** {} ms, **retries:** {}",
+            info.timeout_ms, info.retries
+        )?;
+        out.flush()
+    }
+
+    fn host(&mut self, report: &HostReport) -> io::Result<()> {
+        let out = &mut self.out;
+        let host = report.host;
+
+        writeln!(out)?;
+        writeln!(
+            out,
+            "## {} ({})",
+            host.ip,
+            md_cell(&host.all_names().join(", "))
+        )?;
+        writeln!(out)?;
+
+        let shown: Vec<_> = report
+            .results
+            .iter()
+            .filter(|r| self.show_closed || r.is_open())
+            .collect();
+        if shown.is_empty() {
+            writeln!(out, "_no open ports_")?;
+        } else {
+            let mut header = "| port | state | service | latency (ms) |".to_string();
+            let mut rule = "| ---: | --- | --- | ---: |".to_string();
+            if self.banners {
+                header += " banner |";
+                rule += " --- |";
+            }
+            if self.tls {
+                header += " tls |";
+                rule += " --- |";
+            }
+            if self.http {
+                header += " http |";
+                rule += " --- |";
+            }
+            if self.versions {
+                header += " version |";
+                rule += " --- |";
+            }
+            writeln!(out, "{header}")?;
+            writeln!(out, "{rule}")?;
+            for r in shown {
+                let latency = r
+                    .latency_ms()
+                    .map(|ms| format!("{ms:.2}"))
+                    .unwrap_or_default();
+                write!(
+                    out,
+                    "| {} | {} | {} | {latency} |",
+                    r.port,
+                    r.state,
+                    r.service_name_in(&self.services).unwrap_or("")
+                )?;
+                if self.banners {
+                    let banner = r.banner.as_deref().map(md_cell).unwrap_or_default();
+                    write!(out, " {banner} |")?;
+                }
+                if self.tls {
+                    let tls = r.tls.as_ref().map(|t| md_cell(&t.summary()));
+                    write!(out, " {} |", tls.unwrap_or_default())?;
+                }
+                if self.http {
+                    let http = r.http.as_ref().map(|h| md_cell(&h.summary()));
+                    write!(out, " {} |", http.unwrap_or_default())?;
+                }
+                if self.versions {
+                    let version = r.service.as_ref().map(|m| md_cell(&m.summary()));
+                    write!(out, " {} |", version.unwrap_or_default())?;
+                }
+                writeln!(out)?;
+            }
+        }
+
+        if report.incomplete {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "> scan of this host was cancelled; results are incomplete"
+            )?;
+        }
+        out.flush()
+    }
+
+    fn finish(&mut self, summary: &ScanSummary) -> io::Result<()> {
+        let out = &mut self.out;
+        writeln!(out)?;
+        writeln!(out, "## summary")?;
+        writeln!(out)?;
+        writeln!(out, "| | |")?;
+        writeln!(out, "| --- | ---: |")?;
+        writeln!(out, "| targets | {} |", summary.targets)?;
+        writeln!(
+            out,
+            "| hosts scanned | {}/{} |",
+            summary.hosts_scanned, summary.hosts_total
+        )?;
+        writeln!(
+            out,
+            "| hosts with open ports | {} |",
+            summary.hosts_with_open
+        )?;
+        writeln!(out, "| open ports | {} |", summary.open_ports)?;
+        writeln!(
+            out,
+            "| elapsed | {:.2}s{} |",
+            summary.elapsed.as_secs_f64(),
+            if summary.cancelled {
+                " (cancelled)"
+            } else {
+                ""
+            }
+        )?;
+        out.flush()
+    }
+}
@@ -0,0 +1,27 @@
+use super::{HostReport, Reporter, ScanSummary};
+use crate::Metrics;
+use std::io;
+use std::sync::Arc;
+
+/// adds every scan to a `Metrics`, for a /metrics endpoint to serve
+pub struct MetricsReporter {
+    metrics: Arc<Metrics>,
+}
+
+impl MetricsReporter {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        MetricsReporter { metrics }
+    }
+}
+
+impl Reporter for MetricsReporter {
+    fn host(&mut self, report: &HostReport) -> io::Result<()> {
+        self.metrics.record_host(report.host, report.results);
+        Ok(())
+    }
+
+    fn finish(&mut self, _summary: &ScanSummary) -> io::Result<()> {
+        self.metrics.record_scan();
+        Ok(())
+    }
+}
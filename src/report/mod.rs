@@ -0,0 +1,240 @@
+use crate::services::Services;
+use crate::{Engine, PortState, Protocol, ScanHost, ScanResult, ScanType};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+mod cef;
+mod chat;
+mod color;
+mod csv;
+mod email;
+mod grepable;
+mod html;
+pub(crate) mod json;
+pub(crate) mod jsonl;
+mod markdown;
+mod metrics;
+mod multi;
+mod nmap_xml;
+mod sarif;
+mod store;
+mod syslog;
+mod table;
+pub mod util;
+mod watch;
+mod webhook;
+
+pub use cef::CefReporter;
+pub use chat::ChatReporter;
+pub use color::{ColorChoice, Palette};
+pub use csv::CsvReporter;
+pub use email::EmailReporter;
+pub use grepable::GrepableReporter;
+pub use html::HtmlReporter;
+pub use json::JsonReporter;
+pub use jsonl::JsonlReporter;
+pub use markdown::MarkdownReporter;
+pub use metrics::MetricsReporter;
+pub use multi::MultiReporter;
+pub use nmap_xml::NmapXmlReporter;
+pub use sarif::SarifReporter;
+pub use store::StoreReporter;
+pub(crate) use store::{open_database, Db};
+pub use syslog::SyslogReporter;
+pub use table::TableReporter;
+pub use watch::WatchReporter;
+pub use webhook::WebhookReporter;
+
+/// scan settings, known before the first probe goes out
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScanInfo {
+    /// the command line that started the scan
+    pub command: String,
+    /// targets as written by the user
+    pub targets: Vec<String>,
+    /// hosts that will be scanned after dedupe and exclusions
+    pub host_count: usize,
+    /// hosts dropped by exclusions
+    pub excluded_hosts: usize,
+    /// port selection as given, e.g. "22,80,8000-8100" or "top 100"
+    pub ports: String,
+    pub port_count: usize,
+    pub excluded_ports: Option<String>,
+    pub timeout_ms: u64,
+    pub retries: u8,
+    pub engine: Engine,
+    pub concurrency: usize,
+    pub started: SystemTime,
+}
+
+/// everything known about one host once its scan is done
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct HostReport<'a> {
+    pub host: &'a ScanHost,
+    pub results: &'a [ScanResult],
+    pub elapsed: Duration,
+    /// the scan was cancelled before every port got an answer
+    pub incomplete: bool,
+}
+
+impl HostReport<'_> {
+    pub fn count(&self, state: PortState) -> usize {
+        self.results.iter().filter(|r| r.state == state).count()
+    }
+
+    pub fn open_count(&self) -> usize {
+        self.count(PortState::Open)
+    }
+}
+
+/// totals for the whole run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScanSummary {
+    pub targets: usize,
+    pub hosts_total: usize,
+    pub hosts_scanned: usize,
+    pub hosts_with_open: usize,
+    pub open_ports: usize,
+    pub elapsed: Duration,
+    pub cancelled: bool,
+}
+
+/// an output format. reporters own their writer and get called
+/// in order: `begin`, then `result`/`host` while scanning, then `finish`.
+pub trait Reporter: Send {
+    /// called once before any host is scanned
+    fn begin(&mut self, _info: &ScanInfo) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// called for every port as soon as it finishes, for streaming formats
+    fn result(&mut self, _host: &ScanHost, _result: &ScanResult) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// called once per host after all of its ports are done
+    fn host(&mut self, report: &HostReport) -> io::Result<()>;
+
+    /// called once after the last host
+    fn finish(&mut self, _summary: &ScanSummary) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// options shared by every format; each reporter uses what applies to it
+#[derive(Debug, Clone)]
+pub struct ReportOptions {
+    /// include closed, filtered and errored ports, not just open ones
+    pub show_closed: bool,
+    /// add a banner column/field (set when banners were collected)
+    pub banners: bool,
+    /// add tls details (set when tls probing was enabled)
+    pub tls: bool,
+    /// add http details (set when http probing was enabled)
+    pub http: bool,
+    /// add product/version details (set when version detection was enabled)
+    pub versions: bool,
+    /// add the reverse dns name (set when ptr records were looked up)
+    pub ptr: bool,
+    /// which protocol the ports were scanned with
+    pub protocol: Protocol,
+    /// how tcp ports were probed
+    pub scan_type: ScanType,
+    /// color port states, for the table and --watch on a terminal
+    pub color: bool,
+    /// the names ports are reported with when nothing was detected on them
+    pub services: Arc<Services>,
+}
+
+impl Default for ReportOptions {
+    fn default() -> Self {
+        ReportOptions {
+            show_closed: false,
+            banners: false,
+            tls: false,
+            http: false,
+            versions: false,
+            ptr: false,
+            protocol: Protocol::default(),
+            scan_type: ScanType::default(),
+            color: false,
+            services: Arc::new(Services::builtin()),
+        }
+    }
+}
+
+type Constructor = fn(Box<dyn Write + Send>, &ReportOptions) -> Box<dyn Reporter>;
+
+/// every known format, keyed by its --format name
+const REGISTRY: &[(&str, Constructor)] = &[
+    ("table", |out, opts| Box::new(TableReporter::new(out, opts))),
+    ("json", |out, opts| Box::new(JsonReporter::new(out, opts))),
+    ("jsonl", |out, opts| Box::new(JsonlReporter::new(out, opts))),
+    ("csv", |out, opts| Box::new(CsvReporter::new(out, opts))),
+    ("nmap-xml", |out, opts| {
+        Box::new(NmapXmlReporter::new(out, opts))
+    }),
+    ("grepable", |out, opts| {
+        Box::new(GrepableReporter::new(out, opts))
+    }),
+    ("html", |out, opts| Box::new(HtmlReporter::new(out, opts))),
+    ("md", |out, opts| Box::new(MarkdownReporter::new(out, opts))),
+    ("sarif", |out, opts| Box::new(SarifReporter::new(out, opts))),
+    ("cef", |out, opts| Box::new(CefReporter::new(out, opts))),
+    ("leef", |out, opts| Box::new(CefReporter::leef(out, opts))),
+];
+
+/// names accepted by `create`
+pub fn formats() -> impl Iterator<Item = &'static str> {
+    REGISTRY.iter().map(|(name, _)| *name)
+}
+
+/// file extensions that imply a format, for --output without --format
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("txt", "table"),
+    ("json", "json"),
+    ("jsonl", "jsonl"),
+    ("ndjson", "jsonl"),
+    ("csv", "csv"),
+    ("xml", "nmap-xml"),
+    ("gnmap", "grepable"),
+    ("html", "html"),
+    ("htm", "html"),
+    ("md", "md"),
+    ("markdown", "md"),
+    ("sarif", "sarif"),
+    ("cef", "cef"),
+    ("leef", "leef"),
+];
+
+/// guess the format from a file name, e.g. "scan.json" -> "json"
+pub fn format_for_path(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?;
+    EXTENSIONS
+        .iter()
+        .find(|(e, _)| e.eq_ignore_ascii_case(ext))
+        .map(|(_, format)| *format)
+}
+
+/// build the reporter registered under `format`
+pub fn create(
+    format: &str,
+    out: Box<dyn Write + Send>,
+    opts: &ReportOptions,
+) -> Result<Box<dyn Reporter>, String> {
+    REGISTRY
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(format))
+        .map(|(_, make)| make(out, opts))
+        .ok_or_else(|| {
+            format!(
+                "unknown format '{format}' (expected one of: {})",
+                formats().collect::<Vec<_>>().join(", ")
+            )
+        })
+}
@@ -0,0 +1,37 @@
+use super::{HostReport, Reporter, ScanInfo, ScanSummary};
+use crate::{ScanHost, ScanResult};
+use std::io;
+
+/// forwards every call to several reporters, e.g. a table on stdout
+/// plus a json file. stops at the first reporter that fails.
+pub struct MultiReporter {
+    reporters: Vec<Box<dyn Reporter>>,
+}
+
+impl MultiReporter {
+    pub fn new(reporters: Vec<Box<dyn Reporter>>) -> Self {
+        MultiReporter { reporters }
+    }
+}
+
+impl Reporter for MultiReporter {
+    fn begin(&mut self, info: &ScanInfo) -> io::Result<()> {
+        self.reporters.iter_mut().try_for_each(|r| r.begin(info))
+    }
+
+    fn result(&mut self, host: &ScanHost, result: &ScanResult) -> io::Result<()> {
+        self.reporters
+            .iter_mut()
+            .try_for_each(|r| r.result(host, result))
+    }
+
+    fn host(&mut self, report: &HostReport) -> io::Result<()> {
+        self.reporters.iter_mut().try_for_each(|r| r.host(report))
+    }
+
+    fn finish(&mut self, summary: &ScanSummary) -> io::Result<()> {
+        self.reporters
+            .iter_mut()
+            .try_for_each(|r| r.finish(summary))
+    }
+}
@@ -0,0 +1,272 @@
+use super::util::{ctime, unix_secs, xml_escape};
+use super::{HostReport, ReportOptions, Reporter, ScanInfo, ScanSummary};
+use crate::services::Services;
+use crate::{PortState, Protocol, ScanType};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// nmap's xml output (xmloutputversion 1.05), so tools that import
+/// nmap results can read ours unchanged
+pub struct NmapXmlReporter {
+    out: Box<dyn Write + Send>,
+    show_closed: bool,
+    protocol: Protocol,
+    services: Arc<Services>,
+    scan_type: ScanType,
+    started: SystemTime,
+    wrote_header: bool,
+    hosts_up: usize,
+}
+
+impl NmapXmlReporter {
+    pub fn new(out: Box<dyn Write + Send>, opts: &ReportOptions) -> Self {
+        NmapXmlReporter {
+            out,
+            show_closed: opts.show_closed,
+            protocol: opts.protocol,
+            services: opts.services.clone(),
+            scan_type: opts.scan_type,
+            started: SystemTime::now(),
+            wrote_header: false,
+            hosts_up: 0,
+        }
+    }
+
+    fn header(&mut self, info: Option<&ScanInfo>) -> io::Result<()> {
+        if self.wrote_header {
+            return Ok(());
+        }
+        self.wrote_header = true;
+
+        let (args, services, count) = match info {
+            Some(info) => {
+                self.started = info.started;
+                (info.command.as_str(), info.ports.as_str(), info.port_count)
+            }
+            None => ("", "", 0),
+        };
+
+        let scan_type = match self.protocol {
+            Protocol::Tcp => self.scan_type.as_str(),
+            Protocol::Udp => "udp",
+        };
+        let out = &mut self.out;
+        writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(out, "<!DOCTYPE nmaprun>")?;
+        writeln!(
+            out,
+            "<nmaprun scanner=\"pscan\" args=\"{}\" start=\"{}\" startstr=\"{}\" version=\"{}\" xmloutputversion=\"1.05\">",
+            xml_escape(args),
+            unix_secs(self.started),
+            ctime(self.started),
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(
+            out,
+            "<scaninfo type=\"{scan_type}\" protocol=\"{}\" numservices=\"{count}\" services=\"{}\"/>",
+            self.protocol,
+            xml_escape(services)
+        )?;
+        out.flush()
+    }
+}
+
+/// nmap's state name and reason for one of our port states
+fn nmap_state(
+    state: PortState,
+    protocol: Protocol,
+    scan_type: ScanType,
+) -> (&'static str, &'static str) {
+    match (state, protocol) {
+        (PortState::Open, Protocol::Tcp) => ("open", "syn-ack"),
+        (PortState::Open, Protocol::Udp) => ("open", "udp-response"),
+        (PortState::Closed, Protocol::Tcp) if scan_type.is_raw() => ("closed", "reset"),
+        (PortState::Closed, Protocol::Tcp) => ("closed", "conn-refused"),
+        (PortState::Closed, Protocol::Udp) => ("closed", "port-unreach"),
+        (PortState::Filtered, _) => ("filtered", "no-response"),
+        (PortState::Error, _) => ("filtered", "error"),
+        (PortState::OpenFiltered, _) => ("open|filtered", "no-response"),
+        (PortState::Unfiltered, _) => ("unfiltered", "reset"),
+        (PortState::Unknown, _) => ("unknown", "not-probed"),
+    }
+}
+
+impl Reporter for NmapXmlReporter {
+    fn begin(&mut self, info: &ScanInfo) -> io::Result<()> {
+        self.header(Some(info))
+    }
+
+    fn host(&mut self, report: &HostReport) -> io::Result<()> {
+        self.header(None)?;
+        self.hosts_up += 1;
+
+        let end = SystemTime::now();
+        let start = end.checked_sub(report.elapsed).unwrap_or(end);
+        let host = report.host;
+        let addrtype = match host.ip {
+            IpAddr::V4(_) => "ipv4",
+            IpAddr::V6(_) => "ipv6",
+        };
+
+        let out = &mut self.out;
+        writeln!(
+            out,
+            "<host starttime=\"{}\" endtime=\"{}\"><status state=\"up\" reason=\"user-set\" reason_ttl=\"0\"/>",
+            unix_secs(start),
+            unix_secs(end)
+        )?;
+        writeln!(
+            out,
+            "<address addr=\"{}\" addrtype=\"{addrtype}\"/>",
+            host.ip
+        )?;
+
+        // nmap only lists real hostnames, not the ip or network we expanded
+        let names: Vec<&String> = host
+            .names
+            .iter()
+            .filter(|n| n.parse::<IpAddr>().is_err() && !n.contains('/'))
+            .collect();
+        if names.is_empty() && host.ptr.is_none() {
+            writeln!(out, "<hostnames>\n</hostnames>")?;
+        } else {
+            writeln!(out, "<hostnames>")?;
+            for name in names {
+                writeln!(
+                    out,
+                    "<hostname name=\"{}\" type=\"user\"/>",
+                    xml_escape(name)
+                )?;
+            }
+            if let Some(ptr) = &host.ptr {
+                writeln!(out, "<hostname name=\"{}\" type=\"PTR\"/>", xml_escape(ptr))?;
+            }
+            writeln!(out, "</hostnames>")?;
+        }
+
+        writeln!(out, "<ports>")?;
+        if !self.show_closed {
+            // like nmap, fold uninteresting ports into extraports counts
+            for state in [
+                PortState::Closed,
+                PortState::Filtered,
+                PortState::OpenFiltered,
+                PortState::Unfiltered,
+                PortState::Error,
+                PortState::Unknown,
+            ] {
+                let count = report.count(state);
+                if count == 0 {
+                    continue;
+                }
+                let (name, reason) = nmap_state(state, self.protocol, self.scan_type);
+                writeln!(
+                    out,
+                    "<extraports state=\"{name}\" count=\"{count}\">\n<extrareasons reason=\"{reason}\" count=\"{count}\"/>\n</extraports>"
+                )?;
+            }
+        }
+        for r in report.results {
+            if !(self.show_closed || r.is_open()) {
+                continue;
+            }
+            let (name, reason) = nmap_state(r.state, self.protocol, self.scan_type);
+            write!(
+                out,
+                "<port protocol=\"{}\" portid=\"{}\"><state state=\"{name}\" reason=\"{reason}\" reason_ttl=\"0\"/>",
+                self.protocol,
+                r.port
+            )?;
+            if let Some(m) = &r.service {
+                write!(out, "<service name=\"{}\"", xml_escape(&m.service))?;
+                let fields = [
+                    ("product", &m.product),
+                    ("version", &m.version),
+                    ("extrainfo", &m.info),
+                    ("ostype", &m.os),
+                    ("hostname", &m.hostname),
+                ];
+                for (attr, value) in fields {
+                    if let Some(value) = value {
+                        write!(out, " {attr}=\"{}\"", xml_escape(value))?;
+                    }
+                }
+                write!(out, " method=\"probed\" conf=\"10\"/>")?;
+            } else if let Some(service) = self.services.name_for(r.port, self.protocol) {
+                write!(
+                    out,
+                    "<service name=\"{service}\" method=\"table\" conf=\"3\"/>"
+                )?;
+            }
+            if let Some(tls) = &r.tls {
+                write!(
+                    out,
+                    "<script id=\"ssl-cert\" output=\"{}\"/>",
+                    xml_escape(&tls.summary())
+                )?;
+            }
+            if let Some(http) = &r.http {
+                // nmap's http-title and http-server-header scripts
+                if let Some(title) = &http.title {
+                    write!(
+                        out,
+                        "<script id=\"http-title\" output=\"{}\"/>",
+                        xml_escape(title)
+                    )?;
+                }
+                if let Some(server) = &http.server {
+                    write!(
+                        out,
+                        "<script id=\"http-server-header\" output=\"{}\"/>",
+                        xml_escape(server)
+                    )?;
+                }
+            }
+            if let Some(banner) = &r.banner {
+                // the same shape nmap's banner script produces
+                write!(
+                    out,
+                    "<script id=\"banner\" output=\"{}\"/>",
+                    xml_escape(banner)
+                )?;
+            }
+            writeln!(out, "</port>")?;
+        }
+        writeln!(out, "</ports>")?;
+        writeln!(out, "</host>")?;
+        out.flush()
+    }
+
+    fn finish(&mut self, summary: &ScanSummary) -> io::Result<()> {
+        self.header(None)?;
+
+        let end = SystemTime::now();
+        let exit = if summary.cancelled {
+            "error"
+        } else {
+            "success"
+        };
+        let out = &mut self.out;
+        writeln!(out, "<runstats>")?;
+        writeln!(
+            out,
+            "<finished time=\"{}\" timestr=\"{}\" elapsed=\"{:.2}\" summary=\"pscan done: {} IP addresses ({} hosts up) scanned in {:.2} seconds\" exit=\"{exit}\"/>",
+            unix_secs(end),
+            ctime(end),
+            summary.elapsed.as_secs_f64(),
+            summary.hosts_total,
+            self.hosts_up,
+            summary.elapsed.as_secs_f64()
+        )?;
+        writeln!(
+            out,
+            "<hosts up=\"{}\" down=\"0\" total=\"{}\"/>",
+            self.hosts_up, summary.hosts_total
+        )?;
+        writeln!(out, "</runstats>")?;
+        writeln!(out, "</nmaprun>")?;
+        out.flush()
+    }
+}
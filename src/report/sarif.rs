@@ -0,0 +1,123 @@
+use super::util::rfc3339;
+use super::{HostReport, ReportOptions, Reporter, ScanInfo, ScanSummary};
+use crate::services::Services;
+use crate::Protocol;
+use serde_json::{json, Value};
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+const RULE_ID: &str = "PSCAN001";
+
+/// sarif 2.1.0 for code scanning / vulnerability management tools.
+/// every open port is one result, located at tcp://ip:port (or udp://)
+/// on its host.
+pub struct SarifReporter {
+    out: Box<dyn Write + Send>,
+    protocol: Protocol,
+    services: Arc<Services>,
+    info: Option<ScanInfo>,
+    results: Vec<Value>,
+}
+
+impl SarifReporter {
+    pub fn new(out: Box<dyn Write + Send>, opts: &ReportOptions) -> Self {
+        SarifReporter {
+            out,
+            protocol: opts.protocol,
+            services: opts.services.clone(),
+            info: None,
+            results: Vec::new(),
+        }
+    }
+}
+
+impl Reporter for SarifReporter {
+    fn begin(&mut self, info: &ScanInfo) -> io::Result<()> {
+        self.info = Some(info.clone());
+        Ok(())
+    }
+
+    fn host(&mut self, report: &HostReport) -> io::Result<()> {
+        let host = report.host;
+        let proto = self.protocol;
+
+        for r in report.results.iter().filter(|r| r.is_open()) {
+            let addr = SocketAddr::new(host.ip, r.port);
+            let service = r.service_name_in(&self.services);
+            let message = match service {
+                Some(s) => format!("port {}/{proto} ({s}) is open on {}", r.port, host.ip),
+                None => format!("port {}/{proto} is open on {}", r.port, host.ip),
+            };
+
+            self.results.push(json!({
+                "ruleId": RULE_ID,
+                "level": "warning",
+                "message": {"text": message},
+                "locations": [{
+                    "physicalLocation": {"artifactLocation": {"uri": format!("{proto}://{addr}")}},
+                    "logicalLocations": [{"name": format!("{}/{proto}", r.port), "kind": "port"}]
+                }],
+                "partialFingerprints": {"openPort/v1": format!("{addr}/{proto}")},
+                "properties": {
+                    "ip": host.ip,
+                    "port": r.port,
+                    "service": service,
+                    "banner": r.banner,
+                    "tls": r.tls.as_ref().map(|t| t.summary()),
+                    "http": r.http.as_ref().map(|h| h.summary()),
+                    "version": r.service.as_ref().map(|m| m.summary()),
+                    "hostnames": host.all_names()
+                }
+            }));
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &ScanSummary) -> io::Result<()> {
+        let (command, started) = match &self.info {
+            Some(info) => (info.command.clone(), info.started),
+            None => (String::new(), SystemTime::now() - summary.elapsed),
+        };
+        let ended = started + summary.elapsed;
+        let proto = self.protocol;
+        let answered = match proto {
+            Protocol::Tcp => "accepted a connection",
+            Protocol::Udp => "answered a probe",
+        };
+
+        let doc = json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": "pscan",
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "rules": [{
+                            "id": RULE_ID,
+                            "name": "OpenPort",
+                            "shortDescription": {"text": format!("open {proto} port")},
+                            "fullDescription": {
+                                "text": format!("a {proto} port {answered}; confirm it is expected to be exposed")
+                            },
+                            "defaultConfiguration": {"level": "warning"}
+                        }]
+                    }
+                },
+                "invocations": [{
+                    "executionSuccessful": !summary.cancelled,
+                    "commandLine": command,
+                    "startTimeUtc": rfc3339(started),
+                    "endTimeUtc": rfc3339(ended)
+                }],
+                "results": self.results
+            }]
+        });
+        let out = &mut self.out;
+        serde_json::to_writer_pretty(&mut *out, &doc)?;
+        writeln!(out)?;
+        out.flush()
+    }
+}
@@ -0,0 +1,240 @@
+use super::{HostReport, ReportOptions, Reporter, ScanInfo};
+use crate::services::Services;
+use crate::{Protocol, ScanType};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+#[cfg(feature = "sqlite")]
+use {super::util::rfc3339, super::ScanSummary, rusqlite::params};
+
+/// the schema, one step per version; a database is brought up to date by
+/// running the steps past its `user_version`. only ever add steps.
+#[cfg(feature = "sqlite")]
+const MIGRATIONS: &[&str] = &["
+    CREATE TABLE scans (
+        id INTEGER PRIMARY KEY,
+        started TEXT NOT NULL,
+        finished TEXT,
+        command TEXT NOT NULL,
+        targets TEXT NOT NULL,
+        ports TEXT NOT NULL,
+        port_count INTEGER NOT NULL,
+        protocol TEXT NOT NULL,
+        scan_type TEXT NOT NULL,
+        host_count INTEGER NOT NULL,
+        hosts_scanned INTEGER,
+        open_ports INTEGER,
+        elapsed_ms REAL,
+        cancelled INTEGER
+    );
+    CREATE TABLE hosts (
+        id INTEGER PRIMARY KEY,
+        scan_id INTEGER NOT NULL REFERENCES scans(id) ON DELETE CASCADE,
+        ip TEXT NOT NULL,
+        names TEXT NOT NULL,
+        ptr TEXT,
+        elapsed_ms REAL NOT NULL,
+        incomplete INTEGER NOT NULL,
+        open_count INTEGER NOT NULL
+    );
+    CREATE INDEX hosts_by_scan ON hosts(scan_id);
+    CREATE INDEX hosts_by_ip ON hosts(ip);
+    CREATE TABLE ports (
+        host_id INTEGER NOT NULL REFERENCES hosts(id) ON DELETE CASCADE,
+        port INTEGER NOT NULL,
+        state TEXT NOT NULL,
+        service TEXT,
+        version TEXT,
+        banner TEXT,
+        latency_ms REAL
+    );
+    CREATE INDEX ports_by_host ON ports(host_id);
+    CREATE INDEX ports_by_port ON ports(port, state);
+"];
+
+/// an open database; without the `sqlite` feature there never is one
+#[cfg(feature = "sqlite")]
+pub(crate) type Db = rusqlite::Connection;
+#[cfg(not(feature = "sqlite"))]
+pub(crate) type Db = std::convert::Infallible;
+
+/// appends every scan to a sqlite database (--store): a row for the scan,
+/// one per host and one per reported port
+#[cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+pub struct StoreReporter {
+    db: Db,
+    show_closed: bool,
+    protocol: Protocol,
+    services: Arc<Services>,
+    scan_type: ScanType,
+    scan_id: Option<i64>,
+    info: Option<ScanInfo>,
+}
+
+impl StoreReporter {
+    /// open (or create) the database at `path` and bring its schema up to
+    /// date
+    pub fn open(path: &Path, opts: &ReportOptions) -> io::Result<Self> {
+        Ok(StoreReporter {
+            db: open_database(path)?,
+            show_closed: opts.show_closed,
+            protocol: opts.protocol,
+            services: opts.services.clone(),
+            scan_type: opts.scan_type,
+            scan_id: None,
+            info: None,
+        })
+    }
+}
+
+/// open the database at `path` with its schema brought up to date
+#[cfg(feature = "sqlite")]
+pub(crate) fn open_database(path: &Path) -> io::Result<Db> {
+    let db = Db::open(path).map_err(io::Error::other)?;
+    migrate(&db)?;
+    Ok(db)
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub(crate) fn open_database(_path: &Path) -> io::Result<Db> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "this build has no sqlite (rebuild with --features sqlite)",
+    ))
+}
+
+#[cfg(feature = "sqlite")]
+fn migrate(db: &Db) -> io::Result<()> {
+    let version: i64 = db
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(io::Error::other)?;
+    let version = usize::try_from(version).unwrap_or(0);
+    if version > MIGRATIONS.len() {
+        return Err(io::Error::other(format!(
+            "the database has schema version {version}, newer than this build knows ({})",
+            MIGRATIONS.len()
+        )));
+    }
+    for (at, step) in MIGRATIONS.iter().enumerate().skip(version) {
+        // each step lands whole, with the version that records it
+        let step = |tx: &rusqlite::Transaction| {
+            tx.execute_batch(step)?;
+            tx.pragma_update(None, "user_version", at + 1)
+        };
+        let tx = db.unchecked_transaction().map_err(io::Error::other)?;
+        step(&tx)
+            .and_then(|()| tx.commit())
+            .map_err(io::Error::other)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+fn count(n: usize) -> i64 {
+    i64::try_from(n).unwrap_or(i64::MAX)
+}
+
+#[cfg(feature = "sqlite")]
+impl StoreReporter {
+    /// the host's row and its ports', in one transaction
+    fn insert_host(&self, scan_id: i64, report: &HostReport) -> rusqlite::Result<()> {
+        let host = report.host;
+        let tx = self.db.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO hosts (scan_id, ip, names, ptr, elapsed_ms, incomplete, open_count) \
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![
+                scan_id,
+                host.ip.to_string(),
+                host.names.join(","),
+                host.ptr,
+                report.elapsed.as_secs_f64() * 1000.0,
+                report.incomplete,
+                count(report.open_count()),
+            ],
+        )?;
+        let host_id = tx.last_insert_rowid();
+        let mut insert = tx.prepare(
+            "INSERT INTO ports (host_id, port, state, service, version, banner, latency_ms) \
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )?;
+        for r in report
+            .results
+            .iter()
+            .filter(|r| self.show_closed || r.is_open())
+        {
+            let version = r.service.as_ref().map(|m| m.summary());
+            insert.execute(params![
+                host_id,
+                r.port,
+                r.state.as_str(),
+                r.service_name_in(&self.services),
+                version.filter(|v| !v.is_empty()),
+                r.banner,
+                r.latency_ms(),
+            ])?;
+        }
+        drop(insert);
+        tx.commit()
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Reporter for StoreReporter {
+    fn begin(&mut self, info: &ScanInfo) -> io::Result<()> {
+        self.db
+            .execute(
+                "INSERT INTO scans (started, command, targets, ports, port_count, protocol, \
+                 scan_type, host_count) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    rfc3339(info.started),
+                    info.command,
+                    info.targets.join(","),
+                    info.ports,
+                    count(info.port_count),
+                    self.protocol.as_str(),
+                    self.scan_type.as_str(),
+                    count(info.host_count),
+                ],
+            )
+            .map_err(io::Error::other)?;
+        self.scan_id = Some(self.db.last_insert_rowid());
+        self.info = Some(info.clone());
+        Ok(())
+    }
+
+    fn host(&mut self, report: &HostReport) -> io::Result<()> {
+        let Some(scan_id) = self.scan_id else {
+            return Ok(());
+        };
+        self.insert_host(scan_id, report).map_err(io::Error::other)
+    }
+
+    fn finish(&mut self, summary: &ScanSummary) -> io::Result<()> {
+        let (Some(scan_id), Some(info)) = (self.scan_id, &self.info) else {
+            return Ok(());
+        };
+        self.db
+            .execute(
+                "UPDATE scans SET finished = ?, hosts_scanned = ?, open_ports = ?, \
+                 elapsed_ms = ?, cancelled = ? WHERE id = ?",
+                params![
+                    rfc3339(info.started + summary.elapsed),
+                    count(summary.hosts_scanned),
+                    count(summary.open_ports),
+                    summary.elapsed.as_secs_f64() * 1000.0,
+                    summary.cancelled,
+                    scan_id,
+                ],
+            )
+            .map(drop)
+            .map_err(io::Error::other)
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+impl Reporter for StoreReporter {
+    fn host(&mut self, _report: &HostReport) -> io::Result<()> {
+        match self.db {}
+    }
+}
@@ -0,0 +1,116 @@
+use super::util::rfc3339;
+use super::{HostReport, ReportOptions, Reporter, ScanSummary};
+use crate::services::Services;
+use crate::syslog::{local_hostname, syslog_message, Severity, SyslogSender};
+use crate::{Facility, PortState, Protocol, SyslogTarget};
+use std::io;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// structured data id for our params; 32473 is the enterprise number set
+/// aside for examples (rfc 5612), as we have none of our own
+const SD_ID: &str = "pscan@32473";
+
+/// one rfc 5424 message per finding, e.g.
+/// `<13>1 2024-05-01T10:00:00Z box pscan 4242 port [pscan@32473 ip="10.0.0.5"
+/// port="22" proto="tcp" state="open" service="ssh"] 10.0.0.5 22/tcp open ssh`,
+/// and a `scan` message with the totals at the end. open ports are notices,
+/// everything else informational.
+pub struct SyslogReporter {
+    sender: SyslogSender,
+    facility: Facility,
+    hostname: String,
+    show_closed: bool,
+    protocol: Protocol,
+    services: Arc<Services>,
+}
+
+impl SyslogReporter {
+    pub fn connect(
+        target: &SyslogTarget,
+        facility: Facility,
+        opts: &ReportOptions,
+    ) -> io::Result<Self> {
+        Ok(SyslogReporter {
+            sender: SyslogSender::connect(target)?,
+            facility,
+            hostname: local_hostname().unwrap_or_default(),
+            show_closed: opts.show_closed,
+            protocol: opts.protocol,
+            services: opts.services.clone(),
+        })
+    }
+
+    fn send(
+        &mut self,
+        severity: Severity,
+        msgid: &str,
+        params: &[(&str, String)],
+        text: &str,
+    ) -> io::Result<()> {
+        let message = syslog_message(
+            self.facility,
+            severity,
+            &rfc3339(SystemTime::now()),
+            &self.hostname,
+            msgid,
+            (SD_ID, params),
+            text,
+        );
+        self.sender.send(&message)
+    }
+}
+
+impl Reporter for SyslogReporter {
+    fn host(&mut self, report: &HostReport) -> io::Result<()> {
+        let ip = report.host.ip;
+        for r in report.results {
+            if r.state == PortState::Unknown || !(r.is_open() || self.show_closed) {
+                continue;
+            }
+            let mut params = vec![
+                ("ip", ip.to_string()),
+                ("port", r.port.to_string()),
+                ("proto", self.protocol.to_string()),
+                ("state", r.state.to_string()),
+            ];
+            let mut text = format!("{ip} {}/{} {}", r.port, self.protocol, r.state);
+            if let Some(name) = r.service_name_in(&self.services) {
+                params.push(("service", name.to_string()));
+                text = format!("{text} {name}");
+            }
+            if let Some(ms) = r.latency_ms() {
+                params.push(("latency_ms", format!("{ms:.1}")));
+            }
+            let severity = if r.is_open() {
+                Severity::Notice
+            } else {
+                Severity::Informational
+            };
+            self.send(severity, "port", &params, &text)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &ScanSummary) -> io::Result<()> {
+        let params = [
+            ("hosts", summary.hosts_scanned.to_string()),
+            ("hosts_with_open", summary.hosts_with_open.to_string()),
+            ("open_ports", summary.open_ports.to_string()),
+            ("elapsed_ms", summary.elapsed.as_millis().to_string()),
+            ("cancelled", summary.cancelled.to_string()),
+        ];
+        let text = format!(
+            "scan {}: {} open ports on {} of {} hosts",
+            if summary.cancelled {
+                "cancelled"
+            } else {
+                "complete"
+            },
+            summary.open_ports,
+            summary.hosts_with_open,
+            summary.hosts_scanned
+        );
+        self.send(Severity::Informational, "scan", &params, &text)
+    }
+}
@@ -0,0 +1,203 @@
+use super::{HostReport, Palette, ReportOptions, Reporter, ScanInfo, ScanSummary};
+use crate::services::Services;
+use crate::{Engine, PortState, Protocol, ScanType};
+use std::io::{self, Write};
+use std::sync::Arc;
+
+/// the human readable table printed by default
+pub struct TableReporter {
+    out: Box<dyn Write + Send>,
+    show_closed: bool,
+    banners: bool,
+    protocol: Protocol,
+    services: Arc<Services>,
+    scan_type: ScanType,
+    palette: Palette,
+}
+
+impl TableReporter {
+    pub fn new(out: Box<dyn Write + Send>, opts: &ReportOptions) -> Self {
+        TableReporter {
+            out,
+            show_closed: opts.show_closed,
+            banners: opts.banners,
+            protocol: opts.protocol,
+            services: opts.services.clone(),
+            scan_type: opts.scan_type,
+            palette: Palette::new(opts.color),
+        }
+    }
+}
+
+impl Reporter for TableReporter {
+    fn begin(&mut self, info: &ScanInfo) -> io::Result<()> {
+        let out = &mut self.out;
+        writeln!(out, "pscan")?;
+        if info.targets.len() <= 5 {
+            for target in &info.targets {
+                writeln!(out, "  target       : {target}")?;
+            }
+        } else {
+            writeln!(out, "  targets      : {}", info.targets.len())?;
+        }
+        writeln!(out, "  ips scanned  : {}", info.host_count)?;
+        if info.excluded_hosts > 0 {
+            writeln!(out, "  ips excluded : {}", info.excluded_hosts)?;
+        }
+        writeln!(
+            out,
+            "  ports        : {} ({} total)",
+            info.ports, info.port_count
+        )?;
+        if let Some(excluded) = &info.excluded_ports {
+            writeln!(out, "  excluded     : {excluded}")?;
+        }
+        writeln!(out, "  timeout      : {} ms", info.timeout_ms)?;
+        writeln!(out, "  retries      : {}", info.retries)?;
+        if self.protocol != Protocol::Tcp {
+            writeln!(out, "  protocol     : {}", self.protocol)?;
+        }
+        if self.scan_type != ScanType::Connect {
+            writeln!(out, "  scan type    : {}", self.scan_type)?;
+        }
+        writeln!(out, "  engine       : {}", info.engine)?;
+        match info.engine {
+            Engine::Threads => {
+                writeln!(out, "  parallel     : {}", info.concurrency > 1)?;
+                if info.concurrency > 1 {
+                    writeln!(out, "  threads      : {}", info.concurrency)?;
+                }
+            }
+            Engine::Async => writeln!(out, "  concurrency  : {}", info.concurrency)?,
+        }
+        writeln!(out, "  show_closed  : {}", self.show_closed)?;
+        if self.banners {
+            writeln!(out, "  banners      : true")?;
+        }
+        out.flush()
+    }
+
+    fn host(&mut self, report: &HostReport) -> io::Result<()> {
+        let out = &mut self.out;
+        let host = report.host;
+
+        writeln!(out)?;
+        if host.names.len() == 1 && host.names[0] == host.ip.to_string() {
+            writeln!(out, "target ip: {}", host.ip)?;
+        } else {
+            writeln!(out, "target ip: {} ({})", host.ip, host.names.join(", "))?;
+        }
+        if let Some(ptr) = &host.ptr {
+            writeln!(out, "ptr      : {ptr}")?;
+        }
+        // udp and the fin/null/xmas scans add "open|filtered", ack "unfiltered"
+        let sw = match (self.protocol, self.scan_type) {
+            (Protocol::Udp, _) | (_, ScanType::Fin | ScanType::Null | ScanType::Xmas) => 13,
+            (_, ScanType::Ack) => 10,
+            _ => 8,
+        };
+        if self.banners {
+            writeln!(
+                out,
+                "{:<8}  {:<sw$}  {:<10}  {:<12}  banner",
+                "port", "state", "latency", "hint"
+            )?;
+            writeln!(
+                out,
+                "{:-<8}  {:-<sw$}  {:-<10}  {:-<12}  {:-<8}",
+                "", "", "", "", ""
+            )?;
+        } else {
+            writeln!(
+                out,
+                "{:<8}  {:<sw$}  {:<10}  hint",
+                "port", "state", "latency"
+            )?;
+            writeln!(out, "{:-<8}  {:-<sw$}  {:-<10}  {:-<8}", "", "", "", "")?;
+        }
+
+        for r in report.results {
+            if !(self.show_closed || r.is_open()) {
+                continue;
+            }
+            let latency = match r.latency_ms() {
+                Some(ms) => format!("{ms:.2} ms"),
+                None => "-".to_string(),
+            };
+            let hint = r.service_name_in(&self.services).unwrap_or("");
+            let state = self.palette.state(r.state, &format!("{:<sw$}", r.state));
+            if self.banners {
+                writeln!(
+                    out,
+                    "{:<8}  {state}  {:<10}  {:<12}  {}",
+                    r.port,
+                    latency,
+                    hint,
+                    r.banner.as_deref().unwrap_or("")
+                )?;
+            } else {
+                writeln!(out, "{:<8}  {state}  {:<10}  {}", r.port, latency, hint)?;
+            }
+            if let Some(tls) = &r.tls {
+                writeln!(out, "{:<8}  tls: {}", "", tls.summary())?;
+            }
+            if let Some(http) = &r.http {
+                writeln!(out, "{:<8}  http: {}", "", http.summary())?;
+            }
+            if let Some(service) = r.service.as_ref().map(|m| m.summary()) {
+                if !service.is_empty() {
+                    writeln!(out, "{:<8}  version: {service}", "")?;
+                }
+            }
+        }
+
+        writeln!(out)?;
+        writeln!(out, "open ports found: {}", report.open_count())?;
+        let filtered = report.count(PortState::Filtered);
+        if filtered > 0 {
+            writeln!(out, "filtered ports  : {filtered}")?;
+        }
+        let unanswered = report.count(PortState::OpenFiltered);
+        if unanswered > 0 {
+            writeln!(out, "open|filtered   : {unanswered}")?;
+        }
+        let unfiltered = report.count(PortState::Unfiltered);
+        if unfiltered > 0 {
+            writeln!(out, "unfiltered      : {unfiltered}")?;
+        }
+        let unknown = report.count(PortState::Unknown);
+        if unknown > 0 {
+            writeln!(out, "unknown         : {unknown} (not probed)")?;
+        }
+        if report.incomplete {
+            writeln!(
+                out,
+                "incomplete      : the scan stopped before every port was probed"
+            )?;
+        }
+        out.flush()
+    }
+
+    fn finish(&mut self, summary: &ScanSummary) -> io::Result<()> {
+        if summary.hosts_total <= 1 {
+            return Ok(());
+        }
+
+        let out = &mut self.out;
+        writeln!(out)?;
+        writeln!(out, "summary")?;
+        writeln!(out, "  targets             : {}", summary.targets)?;
+        writeln!(
+            out,
+            "  hosts scanned       : {}/{}",
+            summary.hosts_scanned, summary.hosts_total
+        )?;
+        writeln!(out, "  hosts with open     : {}", summary.hosts_with_open)?;
+        writeln!(out, "  open ports (total)  : {}", summary.open_ports)?;
+        writeln!(out, "  elapsed             : {:?}", summary.elapsed)?;
+        if summary.cancelled {
+            writeln!(out, "  cancelled           : yes (results are incomplete)")?;
+        }
+        out.flush()
+    }
+}
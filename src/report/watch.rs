@@ -0,0 +1,74 @@
+use super::util::rfc3339;
+use super::{HostReport, Palette, ReportOptions, Reporter};
+use crate::services::Services;
+use crate::{PortState, Protocol};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// for --watch: remembers every host's ports from one scan to the next and
+/// prints only what changed, one timestamped line each, e.g.
+/// `2024-05-01T10:00:00Z opened 10.0.0.5 8080/tcp http-proxy`.
+/// the first scan of a host lists its open ports as `open`.
+pub struct WatchReporter {
+    out: Box<dyn Write + Send>,
+    protocol: Protocol,
+    services: Arc<Services>,
+    palette: Palette,
+    seen: HashMap<IpAddr, BTreeMap<u16, PortState>>,
+}
+
+impl WatchReporter {
+    pub fn new(out: Box<dyn Write + Send>, opts: &ReportOptions) -> Self {
+        WatchReporter {
+            out,
+            protocol: opts.protocol,
+            services: opts.services.clone(),
+            palette: Palette::new(opts.color),
+            seen: HashMap::new(),
+        }
+    }
+}
+
+impl Reporter for WatchReporter {
+    fn host(&mut self, report: &HostReport) -> io::Result<()> {
+        // a cancelled scan leaves ports unanswered, which would read as closed
+        if report.incomplete {
+            return Ok(());
+        }
+        let now = rfc3339(SystemTime::now());
+        let ip = report.host.ip;
+        let first = !self.seen.contains_key(&ip);
+        let before = self.seen.entry(ip).or_default();
+        for r in report.results {
+            // an unprobed port says nothing about whether it changed
+            if r.state == PortState::Unknown {
+                continue;
+            }
+            let was_open = before.insert(r.port, r.state) == Some(PortState::Open);
+            let change = match (was_open, r.is_open()) {
+                (false, true) if first => "open",
+                (false, true) => "opened",
+                (true, false) => "closed",
+                _ => continue,
+            };
+            let change = if r.is_open() {
+                self.palette.state(PortState::Open, change)
+            } else {
+                self.palette.state(PortState::Closed, change)
+            };
+            write!(self.out, "{now} {change} {ip} {}/{}", r.port, self.protocol)?;
+            if r.is_open() {
+                if let Some(name) = r.service_name_in(&self.services) {
+                    write!(self.out, " {name}")?;
+                }
+            } else if r.state != PortState::Closed {
+                write!(self.out, " (now {})", r.state)?;
+            }
+            writeln!(self.out)?;
+        }
+        self.out.flush()
+    }
+}
@@ -0,0 +1,162 @@
+use super::util::rfc3339;
+use super::{HostReport, ReportOptions, Reporter, ScanInfo, ScanSummary};
+use crate::services::Services;
+use crate::{Protocol, ScanHost, ScanResult, Webhook};
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// posts findings to a `Webhook`: a `scan.finished` payload with every host
+/// that has open ports once the scan is over. with --watch the first round
+/// posts that as the baseline, and each port found open after it is posted
+/// as `port.opened` the moment its host is done, from a thread of its own
+/// so retries don't hold up the scan; those failures go to `log`.
+pub struct WebhookReporter {
+    webhook: Arc<Webhook>,
+    protocol: Protocol,
+    services: Arc<Services>,
+    started: SystemTime,
+    /// open hosts of the round under way
+    hosts: Vec<Value>,
+    watch: Option<Watch>,
+}
+
+struct Watch {
+    queue: Sender<String>,
+    /// open ports per host, once the first round is over
+    open: Option<HashMap<IpAddr, BTreeSet<u16>>>,
+    opening: HashMap<IpAddr, BTreeSet<u16>>,
+}
+
+impl WebhookReporter {
+    pub fn new(webhook: Webhook, opts: &ReportOptions) -> Self {
+        WebhookReporter {
+            webhook: Arc::new(webhook),
+            protocol: opts.protocol,
+            services: opts.services.clone(),
+            started: SystemTime::now(),
+            hosts: Vec::new(),
+            watch: None,
+        }
+    }
+
+    /// for --watch: after the first round, post ports as they open
+    pub fn watch(mut self, mut log: Box<dyn Write + Send>) -> Self {
+        let (queue, deliveries) = mpsc::channel::<String>();
+        let webhook = self.webhook.clone();
+        std::thread::spawn(move || {
+            for body in deliveries {
+                if let Err(e) = webhook.deliver(&body) {
+                    let _ = writeln!(log, "webhook {webhook} failed: {e}");
+                }
+            }
+        });
+        self.watch = Some(Watch {
+            queue,
+            open: None,
+            opening: HashMap::new(),
+        });
+        self
+    }
+}
+
+fn port_opened(
+    host: &ScanHost,
+    result: &ScanResult,
+    protocol: Protocol,
+    services: &Services,
+) -> String {
+    json!({
+        "event": "port.opened",
+        "scanner": "pscan",
+        "time": rfc3339(SystemTime::now()),
+        "ip": host.ip,
+        "names": host.names,
+        "port": result.port,
+        "protocol": protocol.to_string(),
+        "service": result.service_name_in(services)
+    })
+    .to_string()
+}
+
+impl Reporter for WebhookReporter {
+    fn begin(&mut self, info: &ScanInfo) -> io::Result<()> {
+        self.started = info.started;
+        self.hosts.clear();
+        Ok(())
+    }
+
+    fn host(&mut self, report: &HostReport) -> io::Result<()> {
+        let host = report.host;
+        let open: Vec<_> = report.results.iter().filter(|r| r.is_open()).collect();
+        if !open.is_empty() {
+            let ports: Vec<Value> = open
+                .iter()
+                .map(|r| json!({"port": r.port, "service": r.service_name_in(&self.services)}))
+                .collect();
+            self.hosts
+                .push(json!({"ip": host.ip, "names": host.names, "ports": ports}));
+        }
+
+        let Some(watch) = &mut self.watch else {
+            return Ok(());
+        };
+        // a cancelled scan leaves ports unanswered, which would read as closed
+        if report.incomplete {
+            return Ok(());
+        }
+        if let Some(before) = &watch.open {
+            let before = before.get(&host.ip);
+            for r in &open {
+                if !before.is_some_and(|ports| ports.contains(&r.port)) {
+                    // the worker only goes away with us
+                    let _ = watch
+                        .queue
+                        .send(port_opened(host, r, self.protocol, &self.services));
+                }
+            }
+        }
+        let now = open.iter().map(|r| r.port).collect();
+        watch.opening.insert(host.ip, now);
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &ScanSummary) -> io::Result<()> {
+        let body = json!({
+            "event": "scan.finished",
+            "scanner": "pscan",
+            "time": rfc3339(self.started + summary.elapsed),
+            "summary": {
+                "hosts_total": summary.hosts_total,
+                "hosts_scanned": summary.hosts_scanned,
+                "hosts_with_open": summary.hosts_with_open,
+                "open_ports": summary.open_ports,
+                "elapsed_ms": summary.elapsed.as_millis() as u64,
+                "cancelled": summary.cancelled
+            },
+            "hosts": self.hosts
+        })
+        .to_string();
+        let Some(watch) = &mut self.watch else {
+            // the scan is over, so there's nothing to hold up: deliver it
+            // here and fail the run when it can't be
+            return self
+                .webhook
+                .deliver(&body)
+                .map_err(|e| io::Error::other(format!("webhook {}: {e}", self.webhook)));
+        };
+        let opening = std::mem::take(&mut watch.opening);
+        match &mut watch.open {
+            Some(open) => open.extend(opening),
+            None => {
+                watch.open = Some(opening);
+                let _ = watch.queue.send(body);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+//! how ports that didn't answer get tried again
+
+use crate::rng::random_u32;
+use crate::PortState;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// longest exponential backoff, when none is given
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// how long to wait before each retry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backoff {
+    /// retry straight away
+    #[default]
+    None,
+    /// the same wait before every retry
+    Fixed(Duration),
+    /// `base` before the first retry, doubling for each one after, up to `max`
+    Exponential { base: Duration, max: Duration },
+}
+
+/// retries for ports that didn't answer: how many, how far apart, and how
+/// long each may wait for an answer. the default retries nothing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// extra attempts after the first
+    pub retries: u8,
+    pub backoff: Backoff,
+    /// wait somewhere between half and all of each backoff, so probes
+    /// retried together don't stay in lockstep
+    pub jitter: bool,
+    /// each retry's timeout is the one before times this; 1 keeps the
+    /// timeout as it is
+    pub timeout_growth: f64,
+    /// retry ports that answered closed too, not just the ones that
+    /// didn't answer or failed
+    pub retry_refused: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 0,
+            backoff: Backoff::None,
+            jitter: false,
+            timeout_growth: 1.0,
+            retry_refused: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// `retries` extra attempts, straight away and with the same timeout
+    pub fn new(retries: u8) -> Self {
+        RetryPolicy {
+            retries,
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// whether an attempt that ended in `state` is worth another. an open
+    /// port or a refusal is a definite answer; silence and errors aren't
+    pub fn should_retry(&self, state: PortState) -> bool {
+        match state {
+            PortState::Open | PortState::Unknown => false,
+            PortState::Closed | PortState::Unfiltered => self.retry_refused,
+            PortState::Filtered | PortState::OpenFiltered | PortState::Error => true,
+        }
+    }
+
+    /// the wait before retry `retry` (1 for the first)
+    pub fn delay(&self, retry: u8) -> Duration {
+        let delay = match self.backoff {
+            Backoff::None => Duration::ZERO,
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { base, max } => {
+                let doublings = u32::from(retry.saturating_sub(1)).min(31);
+                base.saturating_mul(1 << doublings).min(max)
+            }
+        };
+        if self.jitter && !delay.is_zero() {
+            let half = delay / 2;
+            half + half.mul_f64(f64::from(random_u32()) / f64::from(u32::MAX))
+        } else {
+            delay
+        }
+    }
+
+    /// the timeout of attempt `attempt` (0 for the first), given the
+    /// first one's
+    pub fn timeout(&self, first: Duration, attempt: u8) -> Duration {
+        let growth = self.timeout_growth.max(1.0).powi(i32::from(attempt));
+        Duration::try_from_secs_f64(first.as_secs_f64() * growth).unwrap_or(Duration::MAX)
+    }
+
+    /// sleep out the backoff before retry `retry`. false when `cancelled`
+    /// was set meanwhile.
+    pub(crate) fn wait(&self, retry: u8, cancelled: &AtomicBool) -> bool {
+        // sleep in short slices so cancellation is noticed quickly
+        const SLICE: Duration = Duration::from_millis(100);
+        let until = Instant::now() + self.delay(retry);
+        loop {
+            if cancelled.load(Ordering::Relaxed) {
+                return false;
+            }
+            let left = until.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return true;
+            }
+            thread::sleep(left.min(SLICE));
+        }
+    }
+}
+
+/// ports waiting for another attempt, for the engines that interleave
+/// their probes: (port, attempt) pairs, some held back by the backoff
+#[derive(Debug, Default)]
+pub(crate) struct RetryQueue {
+    ready: VecDeque<(u16, u8)>,
+    later: Vec<(Instant, u16, u8)>,
+}
+
+impl RetryQueue {
+    /// queue attempt `attempt` of `port`, once the policy's backoff is over
+    pub fn schedule(&mut self, policy: &RetryPolicy, port: u16, attempt: u8) {
+        let delay = policy.delay(attempt);
+        if delay.is_zero() {
+            self.ready.push_back((port, attempt));
+        } else {
+            self.later.push((Instant::now() + delay, port, attempt));
+        }
+    }
+
+    /// put back an attempt that couldn't start yet, to go first
+    pub fn push_front(&mut self, port: u16, attempt: u8) {
+        self.ready.push_front((port, attempt));
+    }
+
+    /// the next attempt that is due
+    pub fn pop(&mut self) -> Option<(u16, u8)> {
+        if !self.later.is_empty() {
+            let now = Instant::now();
+            let (mut due, later): (Vec<_>, Vec<_>) =
+                self.later.drain(..).partition(|&(at, ..)| at <= now);
+            self.later = later;
+            due.sort_by_key(|&(at, ..)| at);
+            self.ready
+                .extend(due.into_iter().map(|(_, port, attempt)| (port, attempt)));
+        }
+        self.ready.pop_front()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ready.is_empty() && self.later.is_empty()
+    }
+
+    /// how long until the next held-back attempt is due, if any is
+    pub fn next_due(&self) -> Option<Duration> {
+        let now = Instant::now();
+        self.later
+            .iter()
+            .map(|&(at, ..)| at.saturating_duration_since(now))
+            .min()
+    }
+
+    /// every port still waiting
+    pub fn into_ports(self) -> impl Iterator<Item = u16> {
+        self.ready
+            .into_iter()
+            .map(|(port, _)| port)
+            .chain(self.later.into_iter().map(|(_, port, _)| port))
+    }
+}
@@ -0,0 +1,51 @@
+//! a small seedable random number generator, for shuffling the scan order
+//! reproducibly, and the operating system's random numbers for what must
+//! not be guessed
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// a random number from the operating system, for dns ids, source ports,
+/// sequence numbers and jitter
+pub(crate) fn random_u32() -> u32 {
+    getrandom::u32().expect("the operating system has no random numbers")
+}
+
+/// splitmix64: tiny, fast and plenty for shuffling. the same seed always
+/// gives the same numbers.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    /// seeded from the clock and the process id
+    pub fn from_clock() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Rng(nanos ^ (u64::from(std::process::id()) << 32))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// a number in `0..n`; `n` must not be 0
+    pub fn below(&mut self, n: u64) -> u64 {
+        ((u128::from(self.next_u64()) * u128::from(n)) >> 64) as u64
+    }
+
+    /// put `items` in a random order (fisher-yates)
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i as u64 + 1) as usize;
+            items.swap(i, j);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! scans read back from the json and json lines reports
+
+use crate::report::json::{Host, Port};
+use crate::report::jsonl::{Line, LineHost};
+use crate::{PortSpec, ScanHost, ScanResult};
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// a scan loaded from a report written with `--format json` or `jsonl`
+#[derive(Debug, Clone, Default)]
+pub struct SavedScan {
+    /// the command line a --checkpoint file was started with; empty for
+    /// reports
+    pub command: Vec<String>,
+    /// the ports that were scanned, when the report says
+    pub ports: Option<PortSpec>,
+    /// when the scan started, rfc 3339 in utc, when the report says
+    pub started: Option<String>,
+    pub hosts: Vec<SavedHost>,
+}
+
+/// one host of a saved scan, with the ports the report kept (only the open
+/// ones, unless it was written with --show-closed)
+#[derive(Debug, Clone)]
+pub struct SavedHost {
+    pub host: ScanHost,
+    pub results: Vec<ScanResult>,
+    pub elapsed: Duration,
+    /// the scan was cancelled before every port got an answer (or, in json
+    /// lines, the host's closing line was never written)
+    pub incomplete: bool,
+    /// when the host was done, rfc 3339 in utc: its own line in json lines,
+    /// the end of the scan in json
+    pub finished: Option<String>,
+}
+
+impl SavedScan {
+    /// read a json report, or a json lines one. certificate dates aren't
+    /// read back.
+    pub fn parse(text: &str) -> Result<SavedScan, String> {
+        let text = text.trim_start();
+        // a json report is one object over many lines; json lines start
+        // with a whole object, with a "type", on the first
+        let first_line = text.lines().next().unwrap_or("");
+        let first = serde_json::from_str::<serde_json::Value>(first_line);
+        if first.is_ok_and(|line| line.get("type").is_some()) {
+            parse_jsonl(text)
+        } else {
+            parse_json(text)
+        }
+    }
+
+    /// the host with this ip
+    pub fn host(&self, ip: IpAddr) -> Option<&SavedHost> {
+        self.hosts.iter().find(|h| h.host.ip == ip)
+    }
+
+    /// whether `port` was scanned; true when the report doesn't say
+    pub fn covers(&self, port: u16) -> bool {
+        self.ports.as_ref().is_none_or(|ports| ports.contains(port))
+    }
+}
+
+/// the port selection as a report records it: a spec, or "top N"
+pub(crate) fn recorded_ports(text: &str) -> Option<PortSpec> {
+    match text.strip_prefix("top ") {
+        Some(n) => Some(PortSpec::top(n.parse().ok()?)),
+        None => text.parse().ok(),
+    }
+}
+
+/// what a json report keeps that a saved scan needs
+#[derive(Deserialize)]
+struct Report {
+    started: Option<String>,
+    finished: Option<String>,
+    parameters: Option<Parameters>,
+    hosts: Vec<Host>,
+}
+
+#[derive(Deserialize)]
+struct Parameters {
+    ports: Option<String>,
+}
+
+fn parse_json(text: &str) -> Result<SavedScan, String> {
+    let doc: Report = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let hosts = doc
+        .hosts
+        .into_iter()
+        .map(|h| {
+            Ok(SavedHost {
+                host: ScanHost {
+                    ip: h.ip,
+                    names: h.names,
+                    ptr: h.ptr.flatten(),
+                    scope_id: 0,
+                },
+                results: h
+                    .ports
+                    .into_iter()
+                    .map(Port::result)
+                    .collect::<Result<_, _>>()?,
+                elapsed: millis(h.elapsed_ms),
+                incomplete: h.incomplete,
+                finished: doc.finished.clone(),
+            })
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(SavedScan {
+        command: Vec::new(),
+        ports: doc
+            .parameters
+            .and_then(|p| p.ports)
+            .and_then(|p| recorded_ports(&p)),
+        started: doc.started,
+        hosts,
+    })
+}
+
+fn parse_jsonl(text: &str) -> Result<SavedScan, String> {
+    let mut scan = SavedScan::default();
+    for (n, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let at_line = |e: String| format!("line {}: {e}", n + 1);
+        match serde_json::from_str(line).map_err(|e| at_line(e.to_string()))? {
+            Line::Start { time, ports, .. } => {
+                scan.ports = ports.and_then(|p| recorded_ports(&p));
+                scan.started = time;
+            }
+            Line::Checkpoint { args } => scan.command = args,
+            Line::Port { host, port, .. } => {
+                let result = port.result().map_err(at_line)?;
+                saved_host(&mut scan, host).results.push(result);
+            }
+            Line::Host {
+                time,
+                host,
+                elapsed_ms,
+                incomplete,
+                ..
+            } => {
+                let saved = saved_host(&mut scan, host);
+                saved.elapsed = millis(elapsed_ms);
+                saved.incomplete = incomplete;
+                saved.finished = time;
+            }
+            Line::Summary { .. } | Line::Other => {}
+        }
+    }
+    Ok(scan)
+}
+
+/// the host a line is about. port lines come before their host's line,
+/// and hosts scanned side by side interleave.
+fn saved_host(scan: &mut SavedScan, host: LineHost) -> &mut SavedHost {
+    let at = match scan.hosts.iter().position(|h| h.host.ip == host.ip) {
+        Some(at) => at,
+        None => {
+            scan.hosts.push(SavedHost {
+                host: ScanHost {
+                    ip: host.ip,
+                    names: host.names,
+                    ptr: host.ptr.flatten(),
+                    scope_id: 0,
+                },
+                results: Vec::new(),
+                elapsed: Duration::ZERO,
+                incomplete: true,
+                finished: None,
+            });
+            scan.hosts.len() - 1
+        }
+    };
+    &mut scan.hosts[at]
+}
+
+fn millis(ms: f64) -> Duration {
+    Duration::try_from_secs_f64(ms / 1000.0).unwrap_or_default()
+}
@@ -0,0 +1,884 @@
+use crate::engine::{scan_async, scan_threads, OnResult};
+use crate::progress::HostTally;
+use crate::raw::scan_raw;
+use crate::timing::{Congestion, Pacer, RttEstimator, Slot};
+use crate::{
+    BannerOptions, Engine, PortState, Progress, Protocol, RetryPolicy, ScanError, ScanHost,
+    ScanResult, ScanType, ServiceProbes, Timing, Transport,
+};
+use rayon::ThreadPool;
+use std::net::{IpAddr, SocketAddr, SocketAddrV6};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, debug_span};
+
+/// a configured port scanner. build one with `Scanner::builder()`
+/// and reuse it for as many ips as needed.
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    pub(crate) timeout: Duration,
+    pub(crate) retry: RetryPolicy,
+    pub(crate) protocol: Protocol,
+    pub(crate) scan_type: ScanType,
+    pub(crate) engine: Engine,
+    pub(crate) concurrency: usize,
+    pub(crate) banner: Option<BannerOptions>,
+    /// handshake timeout, when open ports get a tls probe
+    pub(crate) tls: Option<Duration>,
+    /// request timeout, when open ports get an http probe
+    pub(crate) http: Option<Duration>,
+    /// rules and per-probe timeout, when open ports get version detection
+    pub(crate) version: Option<(Arc<ServiceProbes>, Duration)>,
+    pub(crate) transport: Transport,
+    /// spaces probes out by the scan delay; shared with every `for_host` copy
+    pub(crate) pacer: Arc<Pacer>,
+    /// adapts the timeout to the host, when enabled; each `scan` starts afresh
+    pub(crate) rtt: Option<Arc<RttEstimator>>,
+    /// narrows the concurrency while probes go unanswered, when enabled;
+    /// each `scan` starts afresh
+    pub(crate) congestion: Option<Arc<Congestion>>,
+    /// caps the probes in flight across every host, when set: a window
+    /// that never moves, shared with every `for_host` copy
+    pub(crate) shared_cap: Option<Arc<Congestion>>,
+    /// gives up on hosts that never answer, when enabled; each `scan`
+    /// starts afresh
+    pub(crate) liveness: Option<Arc<DeadHostCheck>>,
+    pub(crate) host_timeout: Option<Duration>,
+    /// when the host being scanned runs out of time, see `host_timeout`
+    pub(crate) deadline: Option<Instant>,
+    pub(crate) max_open: Option<usize>,
+    /// open ports found on the host being scanned, for `max_open`; each
+    /// `scan` starts afresh
+    pub(crate) opened: Arc<AtomicUsize>,
+    /// sni / host name for probes, see `for_host`
+    pub(crate) server_name: Option<String>,
+    /// ipv6 zone (interface index) for link-local hosts, see `for_host`
+    pub(crate) scope_id: u32,
+    pub(crate) progress: Option<Arc<AtomicUsize>>,
+    /// counts every host and port in, when set; shared with every copy
+    pub(crate) tracker: Option<Arc<Progress>>,
+    /// the host being scanned, in `tracker`; each `scan` starts afresh
+    pub(crate) tracked: Option<Arc<HostTally>>,
+    pub(crate) cancelled: Arc<AtomicBool>,
+    /// stops the scan once the probes in flight are done, see
+    /// `ScannerBuilder::stop_flag`
+    pub(crate) stop: Arc<AtomicBool>,
+    /// stops the scan of the host this copy is for, see `with_skip_flag`
+    pub(crate) skip: Option<Arc<AtomicBool>>,
+    /// the thread engine's workers: given to the builder, or started on
+    /// the first scan that needs them; shared with every copy
+    pub(crate) pool: Arc<Mutex<Option<Arc<ThreadPool>>>>,
+}
+
+/// tcp probes that go unanswered before the command line gives up on a host
+pub const DEFAULT_DEAD_AFTER: usize = 100;
+
+/// why a host's scan ended before every port was probed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stopped {
+    /// the cancel flag was set
+    Cancelled,
+    /// the stop flag was set: the probes in flight finished and the
+    /// ports never probed are unknown; see `ScannerBuilder::stop_flag`
+    Interrupted,
+    /// the first probes all went unanswered, so the host looks down or
+    /// firewalled; see `ScannerBuilder::skip_dead_hosts`
+    HostDown,
+    /// the host used up its time; see `ScannerBuilder::host_timeout`
+    HostTimeout,
+    /// enough open ports were found; see `ScannerBuilder::max_open`
+    EnoughOpen,
+    /// the host's skip flag was set; see `Scanner::with_skip_flag`
+    Skipped,
+}
+
+/// what scanning one host came to
+#[derive(Debug, Clone)]
+pub struct ScanOutcome {
+    /// every port, sorted by port; the ones never probed are filtered,
+    /// or unknown after a host timeout, once enough were open or once
+    /// the scan was stopped
+    pub results: Vec<ScanResult>,
+    /// None when every port was probed
+    pub stopped: Option<Stopped>,
+}
+
+/// builder for `Scanner`
+#[derive(Debug, Clone)]
+pub struct ScannerBuilder {
+    timeout: Duration,
+    retry: RetryPolicy,
+    protocol: Protocol,
+    scan_type: ScanType,
+    engine: Engine,
+    concurrency: Option<usize>,
+    banner: Option<BannerOptions>,
+    tls: Option<Duration>,
+    http: Option<Duration>,
+    version: Option<(Arc<ServiceProbes>, Duration)>,
+    transport: Transport,
+    scan_delay: Duration,
+    max_rate: Option<u32>,
+    rtt_bounds: Option<(Duration, Duration)>,
+    min_concurrency: Option<usize>,
+    max_in_flight: Option<usize>,
+    dead_after: Option<usize>,
+    host_timeout: Option<Duration>,
+    max_open: Option<usize>,
+    progress: Option<Arc<AtomicUsize>>,
+    tracker: Option<Arc<Progress>>,
+    cancelled: Option<Arc<AtomicBool>>,
+    stop: Option<Arc<AtomicBool>>,
+    pool: Option<Arc<ThreadPool>>,
+}
+
+impl Default for ScannerBuilder {
+    fn default() -> Self {
+        ScannerBuilder {
+            timeout: Duration::from_millis(50),
+            retry: RetryPolicy::default(),
+            protocol: Protocol::Tcp,
+            scan_type: ScanType::Connect,
+            engine: Engine::Threads,
+            concurrency: None,
+            banner: None,
+            tls: None,
+            http: None,
+            version: None,
+            transport: Transport::direct(),
+            scan_delay: Duration::ZERO,
+            max_rate: None,
+            rtt_bounds: None,
+            min_concurrency: None,
+            max_in_flight: None,
+            dead_after: None,
+            host_timeout: None,
+            max_open: None,
+            progress: None,
+            tracker: None,
+            cancelled: None,
+            stop: None,
+            pool: None,
+        }
+    }
+}
+
+impl ScannerBuilder {
+    /// connect timeout per attempt (default 50ms)
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// extra attempts for ports that stayed silent or failed (default 0);
+    /// see `RetryPolicy::should_retry`
+    pub fn retries(mut self, retries: u8) -> Self {
+        self.retry.retries = retries;
+        self
+    }
+
+    /// how retries are spaced out and timed, `retries` included
+    /// (default: straight away, with the same timeout)
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// probe tcp (connect) or udp (payload and wait for a reply); default tcp.
+    /// tls, http and version probes only run for tcp.
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// how tcp ports are probed (default connect). the raw types send
+    /// their own packets with up to `concurrency` probes outstanding and
+    /// fall back to connect when raw sockets can't be opened.
+    pub fn scan_type(mut self, scan_type: ScanType) -> Self {
+        self.scan_type = scan_type;
+        self
+    }
+
+    /// which engine drives the probes (default threads)
+    pub fn engine(mut self, engine: Engine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    /// worker threads for the thread engine, in-flight connects for the
+    /// async engine. 1 scans sequentially on the calling thread.
+    /// defaults to the available parallelism (threads) or
+    /// `DEFAULT_CONCURRENCY` (async). either way it is kept under
+    /// `socket_limit()`, except for raw scans.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency.max(1));
+        self
+    }
+
+    /// read a banner from every open port (off by default)
+    pub fn banner(mut self, opts: BannerOptions) -> Self {
+        self.banner = Some(opts);
+        self
+    }
+
+    /// try a tls handshake on every open port and record what it
+    /// reveals (off by default)
+    pub fn tls_probe(mut self, timeout: Duration) -> Self {
+        self.tls = Some(timeout);
+        self
+    }
+
+    /// send `GET /` to every open port that does not speak tls and
+    /// record the status, server header and title (off by default)
+    pub fn http_probe(mut self, timeout: Duration) -> Self {
+        self.http = Some(timeout);
+        self
+    }
+
+    /// run `probes` against every open port to find out what is really
+    /// listening there; `timeout` applies to each probe (off by default)
+    pub fn version_detect(mut self, probes: Arc<ServiceProbes>, timeout: Duration) -> Self {
+        self.version = Some((probes, timeout));
+        self
+    }
+
+    /// how tcp connections are made (default direct). through a proxy,
+    /// every probe is a blocking tunnel request whatever the engine, and
+    /// raw scan types and udp aren't possible.
+    pub fn transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// the least time between the starts of two probes, across all
+    /// workers and hosts of the scan, retries included (default none)
+    pub fn scan_delay(mut self, delay: Duration) -> Self {
+        self.scan_delay = delay;
+        self
+    }
+
+    /// start at most `per_second` probes a second, across all workers and
+    /// hosts of the scan, retries included (default unlimited)
+    pub fn max_rate(mut self, per_second: u32) -> Self {
+        self.max_rate = Some(per_second).filter(|&r| r > 0);
+        self
+    }
+
+    /// derive each host's timeout from the round trips of its answered
+    /// probes, within `min`..=`max`. `timeout` is used until the first
+    /// answer comes back (off by default)
+    pub fn adaptive_timeout(mut self, min: Duration, max: Duration) -> Self {
+        self.rtt_bounds = Some((min.min(max), max));
+        self
+    }
+
+    /// back off from `concurrency` while probes go unanswered or fail,
+    /// halving down to `min` at most once per timeout, and ramp back up
+    /// as answers come in (off by default)
+    pub fn adaptive_concurrency(mut self, min: usize) -> Self {
+        self.min_concurrency = Some(min.max(1));
+        self
+    }
+
+    /// at most `cap` probes in flight across every host this scanner (and
+    /// its `for_host` copies) scans at the same time, so several hosts can
+    /// be scanned side by side under one limit. each host still keeps to
+    /// its own `concurrency`. no cap by default.
+    pub fn max_in_flight(mut self, cap: usize) -> Self {
+        self.max_in_flight = Some(cap.max(1));
+        self
+    }
+
+    /// give up on a host once its first `probes` tcp probes all time out
+    /// without a single answer (not even a reset): it is most likely down
+    /// or behind a firewall that drops everything. its other ports are
+    /// reported filtered without being probed (off by default)
+    pub fn skip_dead_hosts(mut self, probes: usize) -> Self {
+        self.dead_after = Some(probes.max(1));
+        self
+    }
+
+    /// stop probing a host once `n` of its ports are found open, for
+    /// "is anything listening at all" checks; the ports not probed by
+    /// then are reported unknown (off by default)
+    pub fn max_open(mut self, n: usize) -> Self {
+        self.max_open = Some(n.max(1));
+        self
+    }
+
+    /// the most time one host may take; once it is up, probing stops and
+    /// the ports that never got an answer are reported unknown (off by
+    /// default)
+    pub fn host_timeout(mut self, limit: Duration) -> Self {
+        self.host_timeout = Some(limit);
+        self
+    }
+
+    /// set timeout, retries, concurrency and scan delay from a template.
+    /// any of them set after this call wins over the template.
+    pub fn timing(self, timing: Timing) -> Self {
+        self.timeout(timing.timeout())
+            .retries(timing.retries())
+            .concurrency(timing.concurrency())
+            .scan_delay(timing.scan_delay())
+    }
+
+    /// counter bumped once per finished port
+    pub fn progress(mut self, counter: Arc<AtomicUsize>) -> Self {
+        self.progress = Some(counter);
+        self
+    }
+
+    /// count every host scanned, and each of its ports, into `progress`
+    pub fn track(mut self, progress: Arc<Progress>) -> Self {
+        self.tracker = Some(progress);
+        self
+    }
+
+    /// shared flag that stops the scan when set
+    pub fn cancel_flag(mut self, cancelled: Arc<AtomicBool>) -> Self {
+        self.cancelled = Some(cancelled);
+        self
+    }
+
+    /// shared flag that stops the scan gently when set: no new probes go
+    /// out, the ones in flight finish and the ports never probed come
+    /// back unknown. the cancel flag still stops it on the spot.
+    pub fn stop_flag(mut self, stop: Arc<AtomicBool>) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    /// run the thread engine's probes on `pool` instead of workers the
+    /// scanner starts itself, so several scanners can share one. the pool's
+    /// size caps the probes in flight, whatever the `concurrency`.
+    pub fn thread_pool(mut self, pool: Arc<ThreadPool>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    pub fn build(self) -> Scanner {
+        let concurrency = self.concurrency.unwrap_or_else(|| match self.engine {
+            _ if self.scan_type.is_raw() => crate::DEFAULT_CONCURRENCY,
+            Engine::Threads => std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(4),
+            Engine::Async => crate::DEFAULT_CONCURRENCY,
+        });
+        // raw scans share one socket; everything else holds one per probe
+        let fd_limit = crate::socket_limit().filter(|_| !self.scan_type.is_raw());
+        let concurrency = concurrency.min(fd_limit.unwrap_or(usize::MAX));
+        let max_in_flight = self
+            .max_in_flight
+            .map(|cap| cap.min(fd_limit.unwrap_or(usize::MAX)));
+
+        Scanner {
+            timeout: self.timeout,
+            retry: self.retry,
+            protocol: self.protocol,
+            scan_type: self.scan_type,
+            engine: self.engine,
+            concurrency,
+            banner: self.banner,
+            tls: self.tls,
+            http: self.http,
+            version: self.version,
+            transport: self.transport,
+            pacer: Arc::new(Pacer::new(self.scan_delay, self.max_rate)),
+            rtt: self
+                .rtt_bounds
+                .map(|(min, max)| Arc::new(RttEstimator::new(min, max, self.timeout))),
+            congestion: self
+                .min_concurrency
+                .map(|min| Arc::new(Congestion::new(min, concurrency))),
+            shared_cap: max_in_flight.map(|cap| Arc::new(Congestion::new(cap, cap))),
+            liveness: self.dead_after.map(|n| Arc::new(DeadHostCheck::new(n))),
+            host_timeout: self.host_timeout,
+            deadline: None,
+            max_open: self.max_open,
+            opened: Arc::default(),
+            server_name: None,
+            scope_id: 0,
+            progress: self.progress,
+            tracker: self.tracker,
+            tracked: None,
+            cancelled: self.cancelled.unwrap_or_default(),
+            stop: self.stop.unwrap_or_default(),
+            skip: None,
+            pool: Arc::new(Mutex::new(self.pool)),
+        }
+    }
+}
+
+impl Scanner {
+    pub fn builder() -> ScannerBuilder {
+        ScannerBuilder::default()
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn retries(&self) -> u8 {
+        self.retry.retries
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry
+    }
+
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    pub fn scan_type(&self) -> ScanType {
+        self.scan_type
+    }
+
+    pub fn engine(&self) -> Engine {
+        self.engine
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    pub fn scan_delay(&self) -> Duration {
+        self.pacer.delay()
+    }
+
+    /// bounds of the adaptive timeout, None when the timeout is fixed
+    pub fn rtt_bounds(&self) -> Option<(Duration, Duration)> {
+        self.rtt.as_ref().map(|rtt| rtt.bounds())
+    }
+
+    /// how long the next probe waits for an answer
+    pub(crate) fn probe_timeout(&self) -> Duration {
+        match &self.rtt {
+            Some(rtt) => rtt.timeout(),
+            None => self.timeout,
+        }
+    }
+
+    /// the least concurrency adaptive backoff may go down to, None when
+    /// the concurrency is fixed
+    pub fn min_concurrency(&self) -> Option<usize> {
+        self.congestion.as_ref().map(|c| c.min())
+    }
+
+    /// the most probes in flight across every host at once, None when
+    /// only each host's concurrency counts
+    pub fn max_in_flight(&self) -> Option<usize> {
+        self.shared_cap.as_ref().map(|c| c.min())
+    }
+
+    /// probes that go unanswered before a host is given up on, None when
+    /// dead hosts are scanned in full
+    pub fn dead_after(&self) -> Option<usize> {
+        self.liveness.as_ref().map(|l| l.after)
+    }
+
+    /// the most time one host may take, None when unlimited
+    pub fn host_timeout(&self) -> Option<Duration> {
+        self.host_timeout
+    }
+
+    /// open ports after which a host's scan stops, None when every port
+    /// is probed regardless
+    pub fn max_open(&self) -> Option<usize> {
+        self.max_open
+    }
+
+    /// whether the scan should stop probing: cancelled or stopped, out of
+    /// time, enough open ports found, or the host is given up on
+    pub(crate) fn stopped(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+            || self.stop.load(Ordering::Relaxed)
+            || self.skipped()
+            || self.out_of_time()
+            || self.enough_open()
+            || self.liveness.as_ref().is_some_and(|l| l.is_dead())
+    }
+
+    /// stopped gently rather than cancelled: the engines start nothing
+    /// new but wait for the probes they have in flight
+    pub(crate) fn draining(&self) -> bool {
+        self.stop.load(Ordering::Relaxed) && !self.cancelled.load(Ordering::Relaxed)
+    }
+
+    fn skipped(&self) -> bool {
+        self.skip
+            .as_ref()
+            .is_some_and(|s| s.load(Ordering::Relaxed))
+    }
+
+    fn out_of_time(&self) -> bool {
+        self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+
+    fn enough_open(&self) -> bool {
+        self.max_open
+            .is_some_and(|n| self.opened.load(Ordering::Relaxed) >= n)
+    }
+
+    /// an open port was found on the host being scanned
+    pub(crate) fn found_open(&self) {
+        self.opened.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// what ports the scan stopped before answering are reported as:
+    /// unknown when the host ran out of time, enough ports were open, it
+    /// was skipped or the scan was stopped, filtered otherwise
+    pub(crate) fn skipped_state(&self) -> PortState {
+        if (self.out_of_time()
+            || self.enough_open()
+            || self.skipped()
+            || self.stop.load(Ordering::Relaxed))
+            && !self.cancelled.load(Ordering::Relaxed)
+        {
+            PortState::Unknown
+        } else {
+            PortState::Filtered
+        }
+    }
+
+    /// probes that may be in flight right now
+    pub(crate) fn window(&self) -> usize {
+        match &self.congestion {
+            Some(congestion) => congestion.limit(),
+            None => self.concurrency,
+        }
+    }
+
+    /// wait for room in the congestion window and under the cap shared
+    /// across hosts, when there are such; None when cancelled first
+    pub(crate) fn slot(&self) -> Option<(Slot<'_>, Slot<'_>)> {
+        let own = match &self.congestion {
+            Some(congestion) => congestion.acquire(&self.cancelled)?,
+            None => Slot(None),
+        };
+        let shared = match &self.shared_cap {
+            Some(cap) => cap.acquire(&self.cancelled)?,
+            None => Slot(None),
+        };
+        Some((own, shared))
+    }
+
+    /// a place under the cap shared across hosts if there is room right
+    /// now, for the engines that count their own window
+    pub(crate) fn try_shared_slot(&self) -> Option<Slot<'_>> {
+        match &self.shared_cap {
+            Some(cap) => cap.try_acquire(),
+            None => Some(Slot(None)),
+        }
+    }
+
+    /// a probe got an answer after `rtt`
+    pub(crate) fn answered(&self, rtt: Duration) {
+        if let Some(estimator) = &self.rtt {
+            estimator.observe(rtt);
+        }
+        if let Some(congestion) = &self.congestion {
+            congestion.answered();
+        }
+        if let Some(liveness) = &self.liveness {
+            liveness.answered();
+        }
+    }
+
+    /// a probe ended in `state` without an answer. silence that is an
+    /// answer in itself (open|filtered) isn't taken as congestion
+    pub(crate) fn unanswered(&self, state: PortState) {
+        if let (Some(congestion), PortState::Filtered | PortState::Error) =
+            (&self.congestion, state)
+        {
+            congestion.dropped(self.probe_timeout());
+        }
+        if let (Some(liveness), PortState::Filtered) = (&self.liveness, state) {
+            liveness.timed_out();
+        }
+    }
+
+    /// most probes started per second, None when unlimited
+    pub fn max_rate(&self) -> Option<u32> {
+        self.pacer.rate()
+    }
+
+    /// banner settings, None when banners are not collected
+    pub fn banner(&self) -> Option<&BannerOptions> {
+        self.banner.as_ref()
+    }
+
+    /// a copy of this scanner for `host`: probes that send a name
+    /// (tls sni, the http host header) use its first hostname, and a
+    /// link-local host's zone picks the interface
+    pub fn for_host(&self, host: &ScanHost) -> Scanner {
+        let name = host
+            .names
+            .iter()
+            .find(|n| n.parse::<IpAddr>().is_err() && !n.contains('/'));
+        Scanner {
+            server_name: name.cloned(),
+            scope_id: host.scope_id,
+            ..self.clone()
+        }
+    }
+
+    /// where to send probes for `port` on `ip`
+    pub(crate) fn addr(&self, ip: IpAddr, port: u16) -> SocketAddr {
+        match ip {
+            IpAddr::V6(v6) if self.scope_id != 0 => {
+                SocketAddrV6::new(v6, port, 0, self.scope_id).into()
+            }
+            _ => SocketAddr::new(ip, port),
+        }
+    }
+
+    /// the flag that cancels this scanner's scans when set
+    pub fn cancel_flag(&self) -> &Arc<AtomicBool> {
+        &self.cancelled
+    }
+
+    /// the flag that stops this scanner's scans gently when set, see
+    /// `ScannerBuilder::stop_flag`
+    pub fn stop_flag(&self) -> &Arc<AtomicBool> {
+        &self.stop
+    }
+
+    /// a copy of this scanner whose scans stop when `skip` is set, leaving
+    /// the ports not yet probed unknown; the other hosts carry on
+    pub fn with_skip_flag(self, skip: Arc<AtomicBool>) -> Scanner {
+        Scanner {
+            skip: Some(skip),
+            ..self
+        }
+    }
+
+    /// hold back every probe not yet started, on every host scanned with
+    /// this scanner or a copy of it, until unpaused. probes in flight
+    /// still finish.
+    pub fn set_paused(&self, paused: bool) {
+        self.pacer.set_paused(paused);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.pacer.is_paused()
+    }
+
+    /// scan a set of ports on one ip, results sorted by port
+    pub fn scan<I>(&self, ip: IpAddr, ports: I) -> Vec<ScanResult>
+    where
+        I: IntoIterator<Item = u16>,
+        I::IntoIter: Send,
+    {
+        self.scan_each(ip, ports, |_| {})
+    }
+
+    /// `scan`, failing instead of making do: when the worker threads
+    /// can't be started (`scan` probes one port at a time then), or when
+    /// the scan is cancelled before it starts
+    pub fn try_scan<I>(&self, ip: IpAddr, ports: I) -> Result<Vec<ScanResult>, ScanError>
+    where
+        I: IntoIterator<Item = u16>,
+        I::IntoIter: Send,
+    {
+        self.try_scan_host(ip, ports, |_| {})
+            .map(|outcome| outcome.results)
+    }
+
+    /// like `scan`, but `on_result` sees every port as soon as it finishes
+    /// (in completion order, possibly from worker threads). the sorted
+    /// results are still returned at the end.
+    pub fn scan_each<I, F>(&self, ip: IpAddr, ports: I, on_result: F) -> Vec<ScanResult>
+    where
+        I: IntoIterator<Item = u16>,
+        I::IntoIter: Send,
+        F: Fn(&ScanResult) + Sync,
+    {
+        self.scan_host(ip, ports, on_result).results
+    }
+
+    /// `scan_host`, failing the way `try_scan` does
+    pub fn try_scan_host<I, F>(
+        &self,
+        ip: IpAddr,
+        ports: I,
+        on_result: F,
+    ) -> Result<ScanOutcome, ScanError>
+    where
+        I: IntoIterator<Item = u16>,
+        I::IntoIter: Send,
+        F: Fn(&ScanResult) + Sync,
+    {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(ScanError::Cancelled);
+        }
+        if self.uses_pool() {
+            self.thread_pool()?;
+        }
+        Ok(self.scan_host(ip, ports, on_result))
+    }
+
+    /// `scan_each`, also telling whether (and why) the scan stopped
+    /// before every port was probed
+    pub fn scan_host<I, F>(&self, ip: IpAddr, ports: I, on_result: F) -> ScanOutcome
+    where
+        I: IntoIterator<Item = u16>,
+        I::IntoIter: Send,
+        F: Fn(&ScanResult) + Sync,
+    {
+        let _span = debug_span!("host", %ip).entered();
+        let started = Instant::now();
+        // another host, another distance and another path
+        let scanner = Scanner {
+            rtt: self.rtt.as_ref().map(|r| Arc::new(r.fresh())),
+            congestion: self.congestion.as_ref().map(|c| Arc::new(c.fresh())),
+            liveness: self.liveness.as_ref().map(|l| Arc::new(l.fresh())),
+            deadline: self.host_timeout.map(|limit| Instant::now() + limit),
+            opened: Arc::default(),
+            tracked: self.tracker.as_ref().map(|p| p.host_started(ip)),
+            ..self.clone()
+        };
+        let results = scanner.dispatch(ip, ports, &on_result);
+        if let (Some(progress), Some(host)) = (&scanner.tracker, &scanner.tracked) {
+            progress.host_finished(host);
+        }
+        let unprobed = results.iter().any(|r| r.state == PortState::Unknown);
+        let open = results.iter().filter(|r| r.is_open()).count();
+        let stopped = if scanner.cancelled.load(Ordering::Relaxed) {
+            Some(Stopped::Cancelled)
+        } else if scanner.stop.load(Ordering::Relaxed) {
+            Some(Stopped::Interrupted)
+        } else if scanner.skipped() {
+            Some(Stopped::Skipped)
+        } else if scanner.liveness.as_ref().is_some_and(|l| l.is_dead()) {
+            Some(Stopped::HostDown)
+        } else if unprobed && scanner.max_open.is_some_and(|n| open >= n) {
+            Some(Stopped::EnoughOpen)
+        } else if unprobed {
+            Some(Stopped::HostTimeout)
+        } else {
+            None
+        };
+        debug!(
+            "{open} open of {} ports in {:.1?}{}",
+            results.len(),
+            started.elapsed(),
+            match stopped {
+                Some(Stopped::Cancelled) => ", cancelled",
+                Some(Stopped::Interrupted) => ", stopped",
+                Some(Stopped::HostDown) => ", stopped: looks down",
+                Some(Stopped::HostTimeout) => ", stopped: out of time",
+                Some(Stopped::EnoughOpen) => ", stopped: enough open",
+                Some(Stopped::Skipped) => ", skipped",
+                None => "",
+            }
+        );
+        ScanOutcome { results, stopped }
+    }
+
+    /// whether scans go through the thread engine's workers, rather than
+    /// event loops, a raw socket or the calling thread alone
+    fn uses_pool(&self) -> bool {
+        let threads = self.engine == Engine::Threads;
+        self.concurrency > 1 && (threads || !self.transport.is_direct())
+    }
+
+    /// the thread engine's workers, started on first use: one per probe
+    /// in flight, up to `concurrency` for each host, or the cap shared
+    /// between hosts scanned side by side. a failed start is tried again
+    /// on the next scan.
+    pub(crate) fn thread_pool(&self) -> Result<Arc<ThreadPool>, ScanError> {
+        let mut pool = self.pool.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(pool) = &*pool {
+            return Ok(pool.clone());
+        }
+        let cap = self.shared_cap.as_ref().map_or(0, |c| c.min());
+        let built = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.concurrency.max(cap))
+            .build()
+            .map(Arc::new)
+            .map_err(|e| ScanError::PoolBuild(e.to_string()))?;
+        Ok(pool.insert(built).clone())
+    }
+
+    fn dispatch<I>(&self, ip: IpAddr, ports: I, on_result: OnResult) -> Vec<ScanResult>
+    where
+        I: IntoIterator<Item = u16>,
+        I::IntoIter: Send,
+    {
+        if !self.transport.is_direct() {
+            // tunnels are opened one blocking request at a time
+            return scan_threads(self, ip, ports, on_result);
+        }
+        if self.protocol == Protocol::Tcp && self.scan_type.is_raw() {
+            return scan_raw(self, ip, ports, on_result);
+        }
+        match self.engine {
+            Engine::Threads => scan_threads(self, ip, ports, on_result),
+            Engine::Async => scan_async(self, ip, ports, on_result),
+        }
+    }
+
+    /// scan on a background thread and stream results through a channel
+    /// as they finish. the channel closes once every port is done.
+    pub fn scan_stream<I>(&self, ip: IpAddr, ports: I) -> mpsc::Receiver<ScanResult>
+    where
+        I: IntoIterator<Item = u16>,
+        I::IntoIter: Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let scanner = self.clone();
+        let ports = ports.into_iter();
+
+        std::thread::spawn(move || {
+            scanner.scan_each(ip, ports, |r| {
+                // a dropped receiver just means nobody is listening anymore
+                let _ = tx.send(r.clone());
+            });
+        });
+
+        rx
+    }
+}
+
+/// notices a host that never answers: `after` timeouts before the first
+/// answer mark it dead
+#[derive(Debug)]
+pub(crate) struct DeadHostCheck {
+    after: usize,
+    timeouts: AtomicUsize,
+    answered: AtomicBool,
+    dead: AtomicBool,
+}
+
+impl DeadHostCheck {
+    fn new(after: usize) -> Self {
+        DeadHostCheck {
+            after,
+            timeouts: AtomicUsize::new(0),
+            answered: AtomicBool::new(false),
+            dead: AtomicBool::new(false),
+        }
+    }
+
+    fn fresh(&self) -> Self {
+        DeadHostCheck::new(self.after)
+    }
+
+    fn answered(&self) {
+        self.answered.store(true, Ordering::Relaxed);
+    }
+
+    fn timed_out(&self) {
+        let timeouts = self.timeouts.fetch_add(1, Ordering::Relaxed) + 1;
+        if timeouts >= self.after && !self.answered.load(Ordering::Relaxed) {
+            self.dead.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn is_dead(&self) -> bool {
+        self.dead.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,799 @@
+//! a small http api for running scans (the serve subcommand): submit scan
+//! jobs, poll their progress, fetch their results as json and cancel them
+
+use crate::report::json::ms;
+use crate::report::util::{rfc3339, SharedBuf};
+use crate::report::{self, HostReport, ReportOptions, Reporter, ScanInfo, ScanSummary};
+use crate::websocket::{self, Message};
+use crate::{
+    check_allowed, expand_targets_with, Engine, Metrics, PortSpec, Progress, Resolver, ScanHost,
+    ScanResult, Scanner, Target, DEFAULT_CONCURRENCY, METRICS_CONTENT_TYPE,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
+use subtle::ConstantTimeEq;
+
+/// finished jobs kept for fetching before the oldest are dropped
+const KEEP_JOBS: usize = 100;
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+const MAX_BODY_BYTES: usize = 64 * 1024;
+/// how long a client gets to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// how often an event stream reports progress
+const PROGRESS_EVERY: Duration = Duration::from_millis(500);
+/// connections answered at once (event streams hold theirs for as long
+/// as they follow a job); the rest wait in the listen backlog
+const MAX_CONNECTIONS: usize = 64;
+
+/// how the api server runs
+#[derive(Debug, Clone)]
+pub struct ServeOptions {
+    /// when set, every request needs `Authorization: Bearer <token>`
+    pub token: Option<String>,
+    /// jobs scanning at once; the rest wait their turn
+    pub max_jobs: usize,
+    /// jobs waiting or scanning at once; more are refused with a 503
+    pub max_pending: usize,
+    /// how job targets are looked up
+    pub resolver: Resolver,
+    /// the only targets jobs may scan, when set (`[targets] allow` in the
+    /// config)
+    pub allow: Option<Vec<Target>>,
+}
+
+impl Default for ServeOptions {
+    fn default() -> Self {
+        ServeOptions {
+            token: None,
+            max_jobs: 1,
+            max_pending: 100,
+            resolver: Resolver::system(),
+            allow: None,
+        }
+    }
+}
+
+/// the api server, bound and ready to `run`
+pub struct Server {
+    listener: TcpListener,
+    state: Arc<State>,
+}
+
+struct State {
+    opts: ServeOptions,
+    jobs: Mutex<VecDeque<Arc<Job>>>,
+    next_id: AtomicUsize,
+    queue: Mutex<mpsc::Sender<Arc<Job>>>,
+    metrics: Arc<Metrics>,
+}
+
+/// one submitted scan
+struct Job {
+    id: usize,
+    targets: Vec<Target>,
+    ports: PortSpec,
+    timeout: Duration,
+    concurrency: usize,
+    show_closed: bool,
+    created: SystemTime,
+    cancelled: Arc<AtomicBool>,
+    progress: Arc<Progress>,
+    state: Mutex<JobState>,
+    /// the event streams following the job
+    watchers: Mutex<Vec<mpsc::Sender<String>>>,
+}
+
+#[derive(Default)]
+struct JobState {
+    status: Status,
+    info: Option<ScanInfo>,
+    started: Option<Instant>,
+    finished: Option<SystemTime>,
+    /// how long it ran, once it's over
+    elapsed: Duration,
+    hosts_total: usize,
+    hosts: Vec<DoneHost>,
+    error: Option<String>,
+}
+
+struct DoneHost {
+    host: ScanHost,
+    results: Vec<ScanResult>,
+    elapsed: Duration,
+    incomplete: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Status {
+    #[default]
+    Queued,
+    Running,
+    Done,
+    Cancelled,
+    Failed,
+}
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::Queued => "queued",
+            Status::Running => "running",
+            Status::Done => "done",
+            Status::Cancelled => "cancelled",
+            Status::Failed => "failed",
+        }
+    }
+
+    fn is_over(self) -> bool {
+        matches!(self, Status::Done | Status::Cancelled | Status::Failed)
+    }
+}
+
+/// lock a mutex, ignoring poisoning (a panicked job is already failed)
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Server {
+    pub fn bind(addr: SocketAddr, opts: ServeOptions) -> io::Result<Server> {
+        let listener = TcpListener::bind(addr)?;
+        let (queue, jobs) = mpsc::channel::<Arc<Job>>();
+        let jobs = Arc::new(Mutex::new(jobs));
+        let metrics = Arc::new(Metrics::new());
+        for _ in 0..opts.max_jobs.max(1) {
+            let jobs = jobs.clone();
+            let opts = opts.clone();
+            let metrics = metrics.clone();
+            std::thread::spawn(move || loop {
+                let Ok(job) = lock(&jobs).recv() else {
+                    return;
+                };
+                run_job(&job, &opts, &metrics);
+            });
+        }
+        Ok(Server {
+            listener,
+            state: Arc::new(State {
+                opts,
+                jobs: Mutex::new(VecDeque::new()),
+                next_id: AtomicUsize::new(1),
+                queue: Mutex::new(queue),
+                metrics,
+            }),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// what wakes `run` from waiting on a connection once `stop` is set
+    pub fn waker(&self) -> io::Result<ServerWaker> {
+        let mut addr = self.listener.local_addr()?;
+        if addr.ip().is_unspecified() {
+            addr.set_ip(match addr {
+                SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+            });
+        }
+        Ok(ServerWaker(addr))
+    }
+
+    /// answer requests until `stop` is set, on a pool of `MAX_CONNECTIONS`
+    /// threads. accept blocks, so whoever sets `stop` calls `ServerWaker::wake`
+    /// after. running jobs are cancelled on the way out.
+    pub fn run(self, stop: &AtomicBool) -> io::Result<()> {
+        let (connections, accepted) = mpsc::sync_channel::<TcpStream>(0);
+        let accepted = Arc::new(Mutex::new(accepted));
+        for _ in 0..MAX_CONNECTIONS {
+            let accepted = accepted.clone();
+            let state = self.state.clone();
+            std::thread::spawn(move || loop {
+                let Ok(stream) = lock(&accepted).recv() else {
+                    return;
+                };
+                let _ = handle(stream, &state);
+            });
+        }
+        while !stop.load(Ordering::SeqCst) {
+            match self.listener.accept() {
+                Ok(_) if stop.load(Ordering::SeqCst) => break,
+                Ok((stream, _)) => {
+                    // waits for a free handler
+                    let _ = connections.send(stream);
+                }
+                // the client gave up before we got to it
+                Err(e) if e.kind() == io::ErrorKind::ConnectionAborted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        for job in lock(&self.state.jobs).iter() {
+            job.cancelled.store(true, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+}
+
+/// wakes a `Server::run` blocked in accept with a connection of its own
+#[derive(Debug, Clone)]
+pub struct ServerWaker(SocketAddr);
+
+impl ServerWaker {
+    pub fn wake(&self) {
+        let _ = TcpStream::connect_timeout(&self.0, Duration::from_secs(1));
+    }
+}
+
+/// whether the token a client `given` is `token`, compared in constant time
+pub(crate) fn token_matches(token: &str, given: Option<&str>) -> bool {
+    given.is_some_and(|given| bool::from(given.as_bytes().ct_eq(token.as_bytes())))
+}
+
+impl Job {
+    /// hand an event to every stream still following the job
+    fn publish(&self, event: String) {
+        lock(&self.watchers).retain(|w| w.send(event.clone()).is_ok());
+    }
+}
+
+fn run_job(job: &Job, opts: &ServeOptions, metrics: &Metrics) {
+    {
+        let mut state = lock(&job.state);
+        // cancelled while it waited
+        if state.status != Status::Queued {
+            return;
+        }
+        state.status = Status::Running;
+        state.started = Some(Instant::now());
+    }
+    let finish = |status: Status, error: Option<String>| {
+        let mut state = lock(&job.state);
+        state.status = status;
+        state.error = error;
+        state.finished = Some(SystemTime::now());
+        state.elapsed = state.started.map(|s| s.elapsed()).unwrap_or_default();
+    };
+    let hosts = expand_targets_with(&job.targets, false, &opts.resolver)
+        .and_then(|hosts| check_allowed(&hosts, opts.allow.as_deref()).map(|()| hosts));
+    let hosts = match hosts {
+        Ok(hosts) => hosts,
+        Err(e) => return finish(Status::Failed, Some(format!("{e:#}"))),
+    };
+    {
+        let mut state = lock(&job.state);
+        state.hosts_total = hosts.len();
+        job.progress.start(hosts.len(), job.ports.len());
+        state.info = Some(ScanInfo {
+            command: "port_scanner serve".to_string(),
+            targets: job.targets.iter().map(|t| t.to_string()).collect(),
+            host_count: hosts.len(),
+            excluded_hosts: 0,
+            ports: job.ports.to_string(),
+            port_count: job.ports.len(),
+            excluded_ports: None,
+            timeout_ms: job.timeout.as_millis() as u64,
+            retries: 0,
+            engine: Engine::Async,
+            concurrency: job.concurrency,
+            started: SystemTime::now(),
+        });
+    }
+    let scanner = Scanner::builder()
+        .timeout(job.timeout)
+        .engine(Engine::Async)
+        .concurrency(job.concurrency)
+        .track(job.progress.clone())
+        .cancel_flag(job.cancelled.clone())
+        .build();
+    for host in hosts {
+        if job.cancelled.load(Ordering::Relaxed) {
+            break;
+        }
+        let started = Instant::now();
+        let outcome = scanner
+            .for_host(&host)
+            .scan_host(host.ip, job.ports.iter(), |result| {
+                if job.show_closed || result.is_open() {
+                    job.publish(port_event(&host, result));
+                }
+            });
+        let event = json!({
+            "event": "host",
+            "ip": host.ip,
+            "open_ports": outcome.results.iter().filter(|r| r.is_open()).count(),
+            "elapsed_ms": started.elapsed().as_millis() as u64,
+            "incomplete": outcome.stopped.is_some()
+        });
+        job.publish(event.to_string());
+        metrics.record_host(&host, &outcome.results);
+        lock(&job.state).hosts.push(DoneHost {
+            host,
+            incomplete: outcome.stopped.is_some(),
+            results: outcome.results,
+            elapsed: started.elapsed(),
+        });
+    }
+    metrics.record_scan();
+    if job.cancelled.load(Ordering::Relaxed) {
+        finish(Status::Cancelled, None);
+    } else {
+        finish(Status::Done, None);
+    }
+}
+
+/// one port as an event: `{"event": "port", "ip": "10.0.0.5", "port": 22,
+/// "state": "open", ...}`
+fn port_event(host: &ScanHost, result: &ScanResult) -> String {
+    json!({
+        "event": "port",
+        "ip": host.ip,
+        "names": host.names,
+        "port": result.port,
+        "state": result.state.as_str(),
+        "service": result.service_name(),
+        "latency_ms": result.latency.map(ms)
+    })
+    .to_string()
+}
+
+/// an http request, as far as the api cares
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    /// Sec-WebSocket-Key, when the client asks for a websocket
+    websocket_key: Option<String>,
+    body: Vec<u8>,
+}
+
+/// what goes back: a status and a body (json but for /metrics), or a
+/// job's event stream
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+    events: Option<Arc<Job>>,
+}
+
+impl Response {
+    fn json(status: u16, body: String) -> Response {
+        Response {
+            status,
+            content_type: "application/json",
+            body,
+            events: None,
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        Response::json(status, json!({ "error": message }).to_string() + "\n")
+    }
+}
+
+fn handle(stream: TcpStream, state: &State) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let response = match read_request(&stream) {
+        Ok(request) => match route(&request, state) {
+            Response {
+                events: Some(job), ..
+            } => {
+                let key = request.websocket_key.as_deref().unwrap_or_default();
+                return stream_events(stream, &job, key);
+            }
+            response => response,
+        },
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => Response::error(400, &e.to_string()),
+        Err(e) if e.kind() == io::ErrorKind::FileTooLarge => Response::error(413, &e.to_string()),
+        Err(e) => return Err(e),
+    };
+    let reason = match response.status {
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Error",
+    };
+    let mut out = io::BufWriter::new(&stream);
+    write!(
+        out,
+        "HTTP/1.1 {} {reason}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.body
+    )?;
+    out.flush()
+}
+
+fn bad_request(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what.to_string())
+}
+
+fn read_request(stream: &TcpStream) -> io::Result<Request> {
+    let mut reader = BufReader::new(stream.take((MAX_HEADER_BYTES + MAX_BODY_BYTES) as u64));
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(bad_request("malformed request line"));
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+
+    let mut header_bytes = line.len();
+    let mut length = 0;
+    let mut authorization = None;
+    let mut websocket_key = None;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(bad_request("request ended in its headers"));
+        }
+        header_bytes += line.len();
+        if header_bytes > MAX_HEADER_BYTES {
+            return Err(bad_request("headers too large"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            return Err(bad_request("malformed header"));
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            length = value
+                .parse()
+                .map_err(|_| bad_request("bad content-length"))?;
+        } else if name.eq_ignore_ascii_case("authorization") {
+            authorization = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case("sec-websocket-key") {
+            websocket_key = Some(value.to_string());
+        }
+    }
+    if length > MAX_BODY_BYTES {
+        return Err(io::Error::new(
+            io::ErrorKind::FileTooLarge,
+            format!("body too large (at most {MAX_BODY_BYTES} bytes)"),
+        ));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Request {
+        method,
+        path,
+        authorization,
+        websocket_key,
+        body,
+    })
+}
+
+fn route(request: &Request, state: &State) -> Response {
+    let (path, query) = request.path.split_once('?').unwrap_or((&request.path, ""));
+    if let Some(token) = &state.opts.token {
+        let mut given = request
+            .authorization
+            .as_deref()
+            .and_then(|a| a.strip_prefix("Bearer "));
+        // browsers can't set headers on a websocket, so it may come in
+        // the query instead
+        if request.websocket_key.is_some() {
+            given = given.or_else(|| query.split('&').find_map(|p| p.strip_prefix("token=")));
+        }
+        if !token_matches(token, given) {
+            return Response::error(401, "missing or wrong bearer token");
+        }
+    }
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let method = request.method.as_str();
+    match segments[..] {
+        ["metrics"] if method == "GET" => Response {
+            content_type: METRICS_CONTENT_TYPE,
+            ..Response::json(200, state.metrics.render())
+        },
+        ["scans"] => match method {
+            "GET" => list_jobs(state),
+            "POST" => submit(state, &request.body),
+            _ => Response::error(405, "use GET or POST"),
+        },
+        ["scans", id] | ["scans", id, "results" | "events"] => {
+            let Some(job) = id
+                .parse::<usize>()
+                .ok()
+                .and_then(|id| lock(&state.jobs).iter().find(|j| j.id == id).cloned())
+            else {
+                return Response::error(404, "no such scan");
+            };
+            match (method, segments.len()) {
+                ("GET", 2) => Response::json(200, job_json(&job).to_string() + "\n"),
+                ("DELETE", 2) => cancel(&job),
+                ("GET", 3) if segments[2] == "results" => Response::json(200, results_json(&job)),
+                ("GET", 3) if request.websocket_key.is_none() => {
+                    Response::error(400, "events come over a websocket")
+                }
+                ("GET", 3) => Response {
+                    events: Some(job),
+                    ..Response::json(101, String::new())
+                },
+                _ => Response::error(405, "use GET or DELETE"),
+            }
+        }
+        _ => Response::error(404, "no such endpoint"),
+    }
+}
+
+fn list_jobs(state: &State) -> Response {
+    let jobs: Vec<Value> = lock(&state.jobs).iter().map(|job| job_json(job)).collect();
+    Response::json(200, json!({ "scans": jobs }).to_string() + "\n")
+}
+
+/// `{"targets": ["10.0.0.0/24"], "ports": "1-1024", "timeout_ms": 1000,
+/// "concurrency": 500, "show_closed": false}`; everything but targets is
+/// optional, ports defaulting to the top 1000
+fn submit(state: &State, body: &[u8]) -> Response {
+    let job = match parse_job(body) {
+        Ok(job) => job,
+        Err(e) => return Response::error(400, &e),
+    };
+    let job = {
+        let mut jobs = lock(&state.jobs);
+        let pending = jobs
+            .iter()
+            .filter(|j| !lock(&j.state).status.is_over())
+            .count();
+        if pending >= state.opts.max_pending {
+            return Response::error(
+                503,
+                &format!("{pending} scans are already waiting or running, try again later"),
+            );
+        }
+        let job = Arc::new(Job {
+            id: state.next_id.fetch_add(1, Ordering::Relaxed),
+            ..job
+        });
+        jobs.push_back(job.clone());
+        // forget the oldest finished jobs
+        while jobs.len() > KEEP_JOBS {
+            match jobs.iter().position(|j| lock(&j.state).status.is_over()) {
+                Some(at) => {
+                    jobs.remove(at);
+                }
+                None => break,
+            }
+        }
+        job
+    };
+    if lock(&state.queue).send(job.clone()).is_err() {
+        return Response::error(500, "the scan workers are gone");
+    }
+    Response::json(202, job_json(&job).to_string() + "\n")
+}
+
+/// a job as it is submitted
+#[derive(Deserialize)]
+struct JobRequest {
+    targets: Vec<String>,
+    ports: Option<String>,
+    timeout_ms: Option<u64>,
+    concurrency: Option<u64>,
+    #[serde(default)]
+    show_closed: bool,
+}
+
+fn parse_job(body: &[u8]) -> Result<Job, String> {
+    let request: JobRequest = serde_json::from_slice(body).map_err(|e| e.to_string())?;
+    let targets = request
+        .targets
+        .iter()
+        .map(|t| t.parse::<Target>())
+        .collect::<Result<Vec<_>, _>>()?;
+    if targets.is_empty() {
+        return Err("\"targets\" is empty".to_string());
+    }
+    let ports = match request.ports {
+        None => PortSpec::top(1000),
+        Some(ports) => ports.parse::<PortSpec>().map_err(|e| e.to_string())?,
+    };
+    let number = |key: &str, n: Option<u64>, default: u64, max: u64| match n {
+        None => Ok(default),
+        Some(n) if (1..=max).contains(&n) => Ok(n),
+        Some(_) => Err(format!("\"{key}\" must be a number from 1 to {max}")),
+    };
+    let timeout_ms = number("timeout_ms", request.timeout_ms, 1000, 60_000)?;
+    let concurrency = number(
+        "concurrency",
+        request.concurrency,
+        DEFAULT_CONCURRENCY as u64,
+        10_000,
+    )?;
+    let show_closed = request.show_closed;
+    Ok(Job {
+        id: 0,
+        targets,
+        ports,
+        timeout: Duration::from_millis(timeout_ms),
+        concurrency: concurrency as usize,
+        show_closed,
+        created: SystemTime::now(),
+        cancelled: Arc::default(),
+        progress: Arc::default(),
+        state: Mutex::default(),
+        watchers: Mutex::default(),
+    })
+}
+
+fn cancel(job: &Job) -> Response {
+    {
+        let mut state = lock(&job.state);
+        if state.status.is_over() {
+            return Response::error(
+                409,
+                &format!("the scan is already {}", state.status.as_str()),
+            );
+        }
+        job.cancelled.store(true, Ordering::SeqCst);
+        // a queued job never starts; a running one stops at its next probe
+        if state.status == Status::Queued {
+            state.status = Status::Cancelled;
+            state.finished = Some(SystemTime::now());
+        }
+    }
+    Response::json(202, job_json(job).to_string() + "\n")
+}
+
+/// a job's status and progress
+fn job_json(job: &Job) -> Value {
+    let state = lock(&job.state);
+    let now = job.progress.snapshot();
+    let progress = match state.status {
+        Status::Done => 1.0,
+        _ if now.probes_total == 0 => 0.0,
+        _ => (now.fraction() * 1000.0).round() / 1000.0,
+    };
+    // only while it runs
+    let (rate, eta) = match state.status {
+        Status::Running => (now.rate, now.eta),
+        _ => (None, None),
+    };
+    let open_ports: usize = state
+        .hosts
+        .iter()
+        .map(|h| h.results.iter().filter(|r| r.is_open()).count())
+        .sum();
+    json!({
+        "id": job.id,
+        "status": state.status.as_str(),
+        "targets": job.targets.iter().map(Target::to_string).collect::<Vec<_>>(),
+        "ports": job.ports.to_string(),
+        "created": rfc3339(job.created),
+        "finished": state.finished.map(rfc3339),
+        "hosts_total": state.hosts_total,
+        "hosts_done": state.hosts.len(),
+        "probes_total": now.probes_total,
+        "probes_done": now.probes_done,
+        "progress": progress,
+        "probes_per_sec": rate.map(|r| (r * 10.0).round() / 10.0),
+        "eta_ms": eta.map(|e| e.as_millis() as u64),
+        "open_ports": open_ports,
+        "error": state.error
+    })
+}
+
+/// the hosts finished so far, as the json report would have them
+fn results_json(job: &Job) -> String {
+    let state = lock(&job.state);
+    let buf = SharedBuf::default();
+    let opts = ReportOptions {
+        show_closed: job.show_closed,
+        ..ReportOptions::default()
+    };
+    let mut reporter = report::JsonReporter::new(Box::new(buf.clone()), &opts);
+    if let Some(info) = &state.info {
+        let _ = reporter.begin(info);
+    }
+    for h in &state.hosts {
+        let _ = reporter.host(&HostReport {
+            host: &h.host,
+            results: &h.results,
+            elapsed: h.elapsed,
+            incomplete: h.incomplete,
+        });
+    }
+    let with_open = state
+        .hosts
+        .iter()
+        .filter(|h| h.results.iter().any(ScanResult::is_open))
+        .count();
+    let _ = reporter.finish(&ScanSummary {
+        targets: job.targets.len(),
+        hosts_total: state.hosts_total,
+        hosts_scanned: state.hosts.len(),
+        hosts_with_open: with_open,
+        open_ports: state
+            .hosts
+            .iter()
+            .map(|h| h.results.iter().filter(|r| r.is_open()).count())
+            .sum(),
+        elapsed: match state.started {
+            Some(started) if !state.status.is_over() => started.elapsed(),
+            _ => state.elapsed,
+        },
+        cancelled: state.status == Status::Cancelled,
+    });
+    drop(reporter);
+    buf.take()
+}
+
+/// follow a job over a websocket until it's over: its status first, then
+/// each port and host as it finishes, progress every half second, and its
+/// final status as `done` before the close
+fn stream_events(mut stream: TcpStream, job: &Job, key: &str) -> io::Result<()> {
+    let (events, incoming) = mpsc::channel();
+    lock(&job.watchers).push(events);
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        websocket::accept_key(key)
+    )?;
+    stream.set_read_timeout(None)?;
+    let (out, mut input) = websocket::split(stream.try_clone()?)?;
+    let out = Arc::new(Mutex::new(out));
+    let send = |event: &str| lock(&out).send(Message::text(event));
+    let status = |event: &str| json!({ "event": event, "scan": job_json(job) }).to_string();
+
+    // the client only ever pings or closes
+    let closed = Arc::new(AtomicBool::new(false));
+    {
+        let (out, closed) = (out.clone(), closed.clone());
+        std::thread::spawn(move || {
+            while let Ok(message) = input.read() {
+                match message {
+                    Message::Ping(payload) => {
+                        let _ = lock(&out).send(Message::Pong(payload));
+                    }
+                    Message::Close(_) => break,
+                    _ => {}
+                }
+            }
+            closed.store(true, Ordering::SeqCst);
+        });
+    }
+
+    let result = (|| {
+        send(&status("progress"))?;
+        let mut reported = Instant::now();
+        while !closed.load(Ordering::SeqCst) {
+            if let Ok(event) = incoming.recv_timeout(PROGRESS_EVERY) {
+                send(&event)?;
+            }
+            // everything published before the job ended is queued by now
+            if lock(&job.state).status.is_over() {
+                for event in incoming.try_iter() {
+                    send(&event)?;
+                }
+                send(&status("done"))?;
+                break;
+            }
+            if reported.elapsed() >= PROGRESS_EVERY {
+                send(&status("progress"))?;
+                reported = Instant::now();
+            }
+        }
+        lock(&out).close()
+    })();
+    let _ = stream.shutdown(std::net::Shutdown::Both);
+    result
+}
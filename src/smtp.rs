@@ -0,0 +1,193 @@
+//! sending a report by email, for --email-to: lettre's smtp client with
+//! starttls or implicit tls (rustls) and AUTH PLAIN, configured by a toml
+//! file
+
+use crate::config::{error_at, parse_toml};
+use lettre::message::header::{ContentTransferEncoding, ContentType};
+use lettre::message::{Body, Mailbox};
+use lettre::transport::smtp::authentication::{Credentials, Mechanism};
+use lettre::transport::smtp::extension::ClientId;
+use lettre::{Message, SmtpTransport, Transport};
+use serde::Deserialize;
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use toml::Spanned;
+
+/// how long the server may take to answer any one command
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// how the connection to the server is protected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    /// plain at first, then STARTTLS before anything else is said
+    StartTls,
+    /// tls from the start, usually on port 465
+    Implicit,
+    /// never: only for a relay on this machine or network, and never
+    /// with a username and password
+    None,
+}
+
+/// the mail server, from the `[smtp]` table of a toml file:
+///
+/// ```toml
+/// [smtp]
+/// host = "smtp.example.com"
+/// port = 587              # 465 with tls = "implicit", 25 with "none"
+/// tls = "starttls"        # or "implicit" or "none"
+/// from = "pscan@example.com"
+/// username = "pscan"      # both or neither
+/// password = "..."
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub from: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+/// a file holding an `[smtp]` table, as written
+#[derive(Deserialize)]
+struct SmtpFile {
+    smtp: Option<SmtpTable>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SmtpTable {
+    host: Option<String>,
+    port: Option<Spanned<i64>>,
+    tls: Option<Spanned<String>>,
+    from: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl SmtpConfig {
+    pub fn parse(text: &str) -> Result<SmtpConfig, String> {
+        let file: SmtpFile = parse_toml(text)?;
+        let smtp = file.smtp.ok_or("no [smtp] table")?;
+        let tls = match &smtp.tls {
+            None => SmtpTls::StartTls,
+            Some(tls) => match tls.get_ref().as_str() {
+                "starttls" => SmtpTls::StartTls,
+                "implicit" => SmtpTls::Implicit,
+                "none" => SmtpTls::None,
+                other => {
+                    return Err(error_at(
+                        text,
+                        tls.span(),
+                        &format!("smtp.tls '{other}' must be starttls, implicit or none"),
+                    ))
+                }
+            },
+        };
+        let port = match &smtp.port {
+            Some(n) => u16::try_from(*n.get_ref())
+                .ok()
+                .filter(|&p| p > 0)
+                .ok_or_else(|| {
+                    error_at(
+                        text,
+                        n.span(),
+                        &format!("smtp.port {} is out of range", n.get_ref()),
+                    )
+                })?,
+            None => match tls {
+                SmtpTls::StartTls => 587,
+                SmtpTls::Implicit => 465,
+                SmtpTls::None => 25,
+            },
+        };
+        if smtp.username.is_some() != smtp.password.is_some() {
+            return Err("smtp: give both username and password, or neither".to_string());
+        }
+        if let (SmtpTls::None, Some(_), Some(tls)) = (tls, &smtp.username, &smtp.tls) {
+            return Err(error_at(
+                text,
+                tls.span(),
+                "smtp.tls \"none\" would send the password in the clear; use starttls or implicit",
+            ));
+        }
+        Ok(SmtpConfig {
+            host: smtp.host.ok_or("smtp.host is missing")?,
+            port,
+            tls,
+            from: smtp.from.ok_or("smtp.from is missing")?,
+            username: smtp.username,
+            password: smtp.password,
+        })
+    }
+
+    /// mail `body` to every address in `to`. `content_type` is e.g.
+    /// `text/html`; the body goes out as utf-8, base64 encoded.
+    pub fn send(
+        &self,
+        to: &[String],
+        subject: &str,
+        content_type: &str,
+        body: &str,
+    ) -> io::Result<()> {
+        let message = self.message(to, subject, content_type, body)?;
+        let builder = match self.tls {
+            SmtpTls::Implicit => SmtpTransport::relay(&self.host).map_err(io::Error::other)?,
+            SmtpTls::StartTls => {
+                SmtpTransport::starttls_relay(&self.host).map_err(io::Error::other)?
+            }
+            SmtpTls::None => SmtpTransport::builder_dangerous(&self.host),
+        };
+        let mut builder = builder
+            .port(self.port)
+            .hello_name(ClientId::Domain("pscan".to_string()))
+            .timeout(Some(TIMEOUT));
+        if let (Some(user), Some(password)) = (&self.username, &self.password) {
+            builder = builder
+                .credentials(Credentials::new(user.clone(), password.clone()))
+                .authentication(vec![Mechanism::Plain]);
+        }
+        builder
+            .build()
+            .send(&message)
+            .map(drop)
+            .map_err(|e| io::Error::other(format!("the mail server: {e}")))
+    }
+
+    fn message(
+        &self,
+        to: &[String],
+        subject: &str,
+        content_type: &str,
+        body: &str,
+    ) -> io::Result<Message> {
+        let address = |a: &str| {
+            a.parse::<Mailbox>()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("'{a}': {e}")))
+        };
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let domain = self.from.rsplit_once('@').map_or("pscan", |(_, d)| d);
+        let content_type = ContentType::parse(&format!("{content_type}; charset=utf-8"))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut builder = Message::builder()
+            .from(address(&self.from)?)
+            .subject(subject)
+            .message_id(Some(format!(
+                "<{}.{}.pscan@{domain}>",
+                secs.as_millis(),
+                std::process::id()
+            )))
+            .header(content_type);
+        for rcpt in to {
+            builder = builder.to(address(rcpt)?);
+        }
+        let body = Body::new_with_encoding(body.to_string(), ContentTransferEncoding::Base64)
+            .map_err(|_| io::Error::other("the body can't be base64 encoded"))?;
+        builder
+            .body(body)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+}
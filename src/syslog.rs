@@ -0,0 +1,298 @@
+//! sending rfc 5424 syslog messages, for --syslog: over udp (a datagram
+//! each), tcp (octet counted, rfc 6587) or a local unix socket like /dev/log
+
+use std::fmt;
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// how long a tcp collector may take to accept us or a message
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// where syslog messages go
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyslogTarget {
+    Udp(String, u16),
+    Tcp(String, u16),
+    Unix(PathBuf),
+}
+
+impl FromStr for SyslogTarget {
+    type Err = String;
+
+    /// `udp://host[:514]`, `tcp://host[:514]`, `unix:///dev/log` or just a
+    /// socket path starting with /
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with('/') {
+            return Ok(SyslogTarget::Unix(PathBuf::from(s)));
+        }
+        let (scheme, rest) = s.split_once("://").ok_or_else(|| {
+            format!("syslog target '{s}' must be udp://, tcp://, unix:// or a path")
+        })?;
+        if scheme == "unix" {
+            if rest.is_empty() {
+                return Err(format!("syslog target '{s}' has no socket path"));
+            }
+            return Ok(SyslogTarget::Unix(PathBuf::from(rest)));
+        }
+        let rest = rest.trim_end_matches('/');
+        // an ipv6 address comes in brackets, having colons of its own
+        let (host, port) = match rest.strip_prefix('[') {
+            Some(inner) => match inner.split_once(']') {
+                Some((host, "")) => (host, None),
+                Some((host, port)) => (host, port.strip_prefix(':')),
+                None => return Err(format!("unclosed '[' in syslog target '{s}'")),
+            },
+            None => match rest.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (rest, None),
+            },
+        };
+        if host.is_empty() {
+            return Err(format!("syslog target '{s}' has no host"));
+        }
+        let port = match port {
+            Some(port) => port
+                .parse()
+                .map_err(|_| format!("invalid port in syslog target '{s}'"))?,
+            None => 514,
+        };
+        match scheme {
+            "udp" => Ok(SyslogTarget::Udp(host.to_string(), port)),
+            "tcp" => Ok(SyslogTarget::Tcp(host.to_string(), port)),
+            _ => Err(format!(
+                "syslog target '{s}' must be udp://, tcp://, unix:// or a path"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SyslogTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bracket = |host: &str| {
+            if host.contains(':') {
+                format!("[{host}]")
+            } else {
+                host.to_string()
+            }
+        };
+        match self {
+            SyslogTarget::Udp(host, port) => write!(f, "udp://{}:{port}", bracket(host)),
+            SyslogTarget::Tcp(host, port) => write!(f, "tcp://{}:{port}", bracket(host)),
+            SyslogTarget::Unix(path) => write!(f, "unix://{}", path.display()),
+        }
+    }
+}
+
+/// the syslog facility messages are filed under
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Facility(u8);
+
+const FACILITIES: [&str; 24] = [
+    "kern", "user", "mail", "daemon", "auth", "syslog", "lpr", "news", "uucp", "cron", "authpriv",
+    "ftp", "ntp", "audit", "alert", "clock", "local0", "local1", "local2", "local3", "local4",
+    "local5", "local6", "local7",
+];
+
+impl Default for Facility {
+    /// user, as logger(1) does
+    fn default() -> Self {
+        Facility(1)
+    }
+}
+
+impl FromStr for Facility {
+    type Err = String;
+
+    /// a name like `daemon` or `local0`, or its number
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code = match s.parse::<u8>() {
+            Ok(n) => n,
+            Err(_) => FACILITIES
+                .iter()
+                .position(|name| name.eq_ignore_ascii_case(s))
+                .ok_or_else(|| format!("unknown syslog facility '{s}'"))?
+                as u8,
+        };
+        if usize::from(code) < FACILITIES.len() {
+            Ok(Facility(code))
+        } else {
+            Err(format!("syslog facility '{s}' is out of range (0-23)"))
+        }
+    }
+}
+
+impl fmt::Display for Facility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(FACILITIES[usize::from(self.0)])
+    }
+}
+
+/// message severities, rfc 5424 section 6.2.1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Severity {
+    Notice = 5,
+    Informational = 6,
+}
+
+/// one rfc 5424 message. `data` becomes a single structured data element
+/// `[id key="value" ...]`; values are escaped here.
+pub(crate) fn syslog_message(
+    facility: Facility,
+    severity: Severity,
+    timestamp: &str,
+    hostname: &str,
+    msgid: &str,
+    data: (&str, &[(&str, String)]),
+    text: &str,
+) -> String {
+    let (id, params) = data;
+    let mut element = format!("[{id}");
+    for (key, value) in params {
+        element += &format!(" {key}=\"{}\"", escape_param(value));
+    }
+    element.push(']');
+    format!(
+        "<{}>1 {timestamp} {} pscan {} {msgid} {element} {text}",
+        u16::from(facility.0) * 8 + severity as u16,
+        header_field(hostname),
+        std::process::id()
+    )
+}
+
+/// `"`, `\` and `]` are escaped in a param value
+fn escape_param(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// header fields are printable ascii with no spaces, or `-` when unknown
+fn header_field(s: &str) -> String {
+    let field: String = s
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .take(255)
+        .collect();
+    if field.is_empty() {
+        "-".to_string()
+    } else {
+        field
+    }
+}
+
+/// this machine's name, for the hostname field
+#[cfg(unix)]
+pub(crate) fn local_hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    // SAFETY: the buffer is valid for its whole length
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8(buf[..len].to_vec()).ok()
+}
+
+/// no portable way to ask: the hostname field stays `-`
+#[cfg(not(unix))]
+pub(crate) fn local_hostname() -> Option<String> {
+    None
+}
+
+/// a connection to a syslog collector
+pub(crate) struct SyslogSender {
+    target: SyslogTarget,
+    conn: Conn,
+}
+
+enum Conn {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixDatagram),
+}
+
+impl SyslogSender {
+    pub(crate) fn connect(target: &SyslogTarget) -> io::Result<SyslogSender> {
+        Ok(SyslogSender {
+            target: target.clone(),
+            conn: open(target)?,
+        })
+    }
+
+    /// send one message; a tcp collector that went away is dialled again
+    /// once before giving up
+    pub(crate) fn send(&mut self, message: &str) -> io::Result<()> {
+        match self.write(message) {
+            Err(_) if matches!(self.conn, Conn::Tcp(_)) => {
+                self.conn = open(&self.target)?;
+                self.write(message)
+            }
+            other => other,
+        }
+    }
+
+    fn write(&mut self, message: &str) -> io::Result<()> {
+        match &mut self.conn {
+            Conn::Udp(socket) => socket.send(message.as_bytes()).map(|_| ()),
+            Conn::Tcp(stream) => {
+                // octet counting, so messages may hold newlines
+                write!(stream, "{} {message}", message.len())?;
+                stream.flush()
+            }
+            #[cfg(unix)]
+            Conn::Unix(socket) => socket.send(message.as_bytes()).map(|_| ()),
+        }
+    }
+}
+
+fn resolve(host: &str, port: u16) -> io::Result<SocketAddr> {
+    (host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::other(format!("no address for '{host}'")))
+}
+
+fn open(target: &SyslogTarget) -> io::Result<Conn> {
+    match target {
+        SyslogTarget::Udp(host, port) => {
+            let addr = resolve(host, *port)?;
+            let local: SocketAddr = if addr.is_ipv4() {
+                "0.0.0.0:0".parse().unwrap()
+            } else {
+                "[::]:0".parse().unwrap()
+            };
+            let socket = UdpSocket::bind(local)?;
+            socket.connect(addr)?;
+            Ok(Conn::Udp(socket))
+        }
+        SyslogTarget::Tcp(host, port) => {
+            let stream = TcpStream::connect_timeout(&resolve(host, *port)?, TIMEOUT)?;
+            stream.set_write_timeout(Some(TIMEOUT))?;
+            Ok(Conn::Tcp(stream))
+        }
+        SyslogTarget::Unix(path) => open_unix(path),
+    }
+}
+
+#[cfg(unix)]
+fn open_unix(path: &std::path::Path) -> io::Result<Conn> {
+    let socket = std::os::unix::net::UnixDatagram::unbound()?;
+    socket.connect(path)?;
+    Ok(Conn::Unix(socket))
+}
+
+#[cfg(not(unix))]
+fn open_unix(_path: &std::path::Path) -> io::Result<Conn> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "unix sockets need a unix system",
+    ))
+}
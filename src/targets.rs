@@ -0,0 +1,445 @@
+use crate::dns::Resolver;
+use crate::ScanError;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+/// largest network we are willing to expand into individual hosts
+pub const MAX_NETWORK_HOSTS: u128 = 1 << 16;
+
+/// an ip network in cidr notation like 192.168.1.0/24
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNet {
+    pub fn new(addr: IpAddr, prefix: u8) -> Result<Self, String> {
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix > max {
+            return Err(format!("prefix length must be between 0 and {max}"));
+        }
+        Ok(IpNet { addr, prefix })
+    }
+
+    /// a network holding exactly one address
+    pub fn host(addr: IpAddr) -> Self {
+        let prefix = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        IpNet { addr, prefix }
+    }
+
+    /// parse either a cidr network or a bare ip (as a single-host network)
+    pub fn parse_ip_or_net(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        if s.contains('/') {
+            return s.parse();
+        }
+        s.parse::<IpAddr>()
+            .map(IpNet::host)
+            .map_err(|_| format!("invalid ip or network '{s}'"))
+    }
+
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// the network address (host bits cleared)
+    pub fn network(&self) -> IpAddr {
+        match self.addr {
+            IpAddr::V4(a) => IpAddr::V4(Ipv4Addr::from(u32::from(a) & v4_mask(self.prefix))),
+            IpAddr::V6(a) => IpAddr::V6(Ipv6Addr::from(u128::from(a) & v6_mask(self.prefix))),
+        }
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = v4_mask(self.prefix);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = v6_mask(self.prefix);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+
+    /// number of scannable hosts in the network
+    pub fn host_count(&self) -> u128 {
+        let (first, last) = self.host_bounds();
+        (last - first).saturating_add(1)
+    }
+
+    /// scannable host addresses in ascending order.
+    /// ipv4 networks skip the network and broadcast address unless
+    /// the prefix is /31 or /32, where every address is a host.
+    pub fn hosts(&self) -> impl Iterator<Item = IpAddr> {
+        let (first, last) = self.host_bounds();
+        let v4 = self.addr.is_ipv4();
+        (first..=last).map(move |n| {
+            if v4 {
+                IpAddr::V4(Ipv4Addr::from(n as u32))
+            } else {
+                IpAddr::V6(Ipv6Addr::from(n))
+            }
+        })
+    }
+
+    fn host_bounds(&self) -> (u128, u128) {
+        match self.addr {
+            IpAddr::V4(a) => {
+                let mask = v4_mask(self.prefix);
+                let first = (u32::from(a) & mask) as u128;
+                let last = first | (!mask) as u128;
+                if self.prefix >= 31 {
+                    (first, last)
+                } else {
+                    (first + 1, last - 1)
+                }
+            }
+            IpAddr::V6(a) => {
+                let mask = v6_mask(self.prefix);
+                let first = u128::from(a) & mask;
+                (first, first | !mask)
+            }
+        }
+    }
+}
+
+fn v4_mask(prefix: u8) -> u32 {
+    u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0)
+}
+
+fn v6_mask(prefix: u8) -> u128 {
+    u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0)
+}
+
+/// parse `IpNet` from a string like "10.0.0.0/8" or "2001:db8::/120"
+impl FromStr for IpNet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = s
+            .split_once('/')
+            .ok_or_else(|| "network must be in format addr/prefix".to_string())?;
+
+        let addr: IpAddr = addr
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid network address '{addr}'"))?;
+        let prefix: u8 = prefix
+            .trim()
+            .parse()
+            .map_err(|_| "prefix length must be a number".to_string())?;
+
+        IpNet::new(addr, prefix)
+    }
+}
+
+impl fmt::Display for IpNet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network(), self.prefix)
+    }
+}
+
+/// one scan target as given by the user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// an ip or a domain name
+    Host(String),
+    /// a cidr network that expands to all of its hosts
+    Network(IpNet),
+}
+
+impl Target {
+    /// expand the target into the ips to scan.
+    /// hosts return every resolved ip; networks return every host address.
+    pub fn expand(&self) -> Result<Vec<IpAddr>, ScanError> {
+        self.expand_with(&Resolver::system())
+    }
+
+    /// `expand`, resolving names with `resolver`
+    pub fn expand_with(&self, resolver: &Resolver) -> Result<Vec<IpAddr>, ScanError> {
+        match self {
+            Target::Host(host) => match zoned_ip(host)? {
+                Some((ip, _)) => resolver.resolve(&ip.to_string()),
+                None => resolver.resolve(host),
+            },
+            Target::Network(net) => {
+                let invalid = |message: String| ScanError::InvalidTarget {
+                    target: net.to_string(),
+                    message,
+                };
+                if !resolver.accepts(net.network()) {
+                    return Err(invalid(format!(
+                        "network {net} is not an {} network",
+                        resolver.ip_family()
+                    )));
+                }
+                if net.host_count() > MAX_NETWORK_HOSTS {
+                    return Err(invalid(format!(
+                        "network {net} has {} hosts, the limit is {MAX_NETWORK_HOSTS}",
+                        net.host_count()
+                    )));
+                }
+                Ok(net.hosts().collect())
+            }
+        }
+    }
+
+    pub fn is_network(&self) -> bool {
+        matches!(self, Target::Network(_))
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err("target must not be empty".to_string());
+        }
+        if s.contains('/') {
+            return Ok(Target::Network(s.parse()?));
+        }
+        Ok(Target::Host(s.to_string()))
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Host(host) => f.write_str(host),
+            Target::Network(net) => write!(f, "{net}"),
+        }
+    }
+}
+
+/// one ip to scan, with every target that expanded to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScanHost {
+    pub ip: IpAddr,
+    /// the targets (as written by the user) that produced this ip
+    pub names: Vec<String>,
+    /// the reverse dns name, once `resolve_ptr_names` has looked it up
+    pub ptr: Option<String>,
+    /// the interface index of a link-local target's zone (fe80::1%eth0),
+    /// 0 for none
+    pub scope_id: u32,
+}
+
+impl ScanHost {
+    /// the names given for this ip, then its ptr name if that's a new one
+    pub fn all_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.names.iter().map(String::as_str).collect();
+        if let Some(ptr) = self.ptr.as_deref() {
+            if !names.iter().any(|n| n.eq_ignore_ascii_case(ptr)) {
+                names.push(ptr);
+            }
+        }
+        names
+    }
+}
+
+/// expand targets into a deduplicated list of hosts to scan,
+/// in the order they were first seen. hostnames contribute only their
+/// first resolved ip unless `all_ips` is set; networks contribute every host.
+pub fn expand_targets(targets: &[Target], all_ips: bool) -> Result<Vec<ScanHost>, ScanError> {
+    expand_targets_with(targets, all_ips, &Resolver::system())
+}
+
+/// `expand_targets`, resolving names with `resolver`
+pub fn expand_targets_with(
+    targets: &[Target],
+    all_ips: bool,
+    resolver: &Resolver,
+) -> Result<Vec<ScanHost>, ScanError> {
+    // look every name up at once; the loop below then reads the cache
+    let names: Vec<&str> = targets
+        .iter()
+        .filter_map(|t| match t {
+            Target::Host(host) if !host.contains('%') => Some(host.as_str()),
+            _ => None,
+        })
+        .collect();
+    resolver.resolve_all(&names);
+
+    let mut hosts: Vec<ScanHost> = Vec::new();
+    // the same link-local address behind two interfaces is two hosts
+    let mut index: HashMap<(IpAddr, u32), usize> = HashMap::new();
+
+    for target in targets {
+        let mut ips = target.expand_with(resolver)?;
+        if !all_ips && !target.is_network() {
+            ips.truncate(1);
+        }
+        let scope_id = match target {
+            Target::Host(host) => zoned_ip(host)?.map_or(0, |(_, scope)| scope),
+            Target::Network(_) => 0,
+        };
+
+        let name = target.to_string();
+        for ip in ips {
+            match index.get(&(ip, scope_id)) {
+                Some(&i) => {
+                    if !hosts[i].names.contains(&name) {
+                        hosts[i].names.push(name.clone());
+                    }
+                }
+                None => {
+                    index.insert((ip, scope_id), hosts.len());
+                    hosts.push(ScanHost {
+                        ip,
+                        names: vec![name.clone()],
+                        ptr: None,
+                        scope_id,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(hosts)
+}
+
+/// an ipv6 address with a zone, like fe80::1%eth0 or fe80::1%2: the
+/// address and the zone's interface index. None for anything else.
+fn zoned_ip(target: &str) -> Result<Option<(IpAddr, u32)>, ScanError> {
+    let Some((addr, zone)) = target.split_once('%') else {
+        return Ok(None);
+    };
+    let invalid = |message: String| ScanError::InvalidTarget {
+        target: target.to_string(),
+        message,
+    };
+    let Ok(v6) = addr.parse::<Ipv6Addr>() else {
+        return Err(invalid(format!(
+            "target '{target}' has a zone but isn't an ipv6 address"
+        )));
+    };
+    let scope_id = match zone.parse::<u32>() {
+        Ok(index) => index,
+        Err(_) => crate::iface::interface_index(zone)
+            .ok_or_else(|| invalid(format!("unknown interface '{zone}' in target '{target}'")))?,
+    };
+    Ok(Some((IpAddr::V6(v6), scope_id)))
+}
+
+/// refuse the first of `hosts` that `allow` leaves out: when set, a host
+/// must be named by ip or by one of its names, or be in a network listed
+/// there. every path that probes hosts goes through this.
+pub fn check_allowed(hosts: &[ScanHost], allow: Option<&[Target]>) -> Result<(), ScanError> {
+    let Some(allow) = allow else {
+        return Ok(());
+    };
+    let allowed = |host: &ScanHost| {
+        allow.iter().any(|target| match target {
+            Target::Host(name) => {
+                name.parse::<IpAddr>() == Ok(host.ip) || host.names.iter().any(|n| n == name)
+            }
+            Target::Network(net) => net.contains(host.ip),
+        })
+    };
+    match hosts.iter().find(|host| !allowed(host)) {
+        Some(host) => Err(ScanError::NotAuthorized {
+            target: host.ip.to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// drop every host that falls inside one of the excluded networks
+pub fn exclude_hosts(hosts: &mut Vec<ScanHost>, excluded: &[IpNet]) {
+    hosts.retain(|h| !excluded.iter().any(|net| net.contains(h.ip)));
+}
+
+/// non-empty entries of a one-per-line list with their 1-based line numbers.
+/// `#` starts a comment.
+fn list_entries(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines().enumerate().filter_map(|(n, line)| {
+        let line = match line.split_once('#') {
+            Some((before, _)) => before,
+            None => line,
+        }
+        .trim();
+        (!line.is_empty()).then_some((n + 1, line))
+    })
+}
+
+/// parse a target list, one target per line.
+/// blank lines are skipped and `#` starts a comment.
+pub fn parse_target_list(text: &str) -> Result<Vec<Target>, String> {
+    list_entries(text)
+        .map(|(n, line)| line.parse::<Target>().map_err(|e| format!("line {n}: {e}")))
+        .collect()
+}
+
+/// parse an exclusion list of ips and cidr networks, one per line.
+/// blank lines are skipped and `#` starts a comment.
+pub fn parse_exclude_list(text: &str) -> Result<Vec<IpNet>, String> {
+    list_entries(text)
+        .map(|(n, line)| IpNet::parse_ip_or_net(line).map_err(|e| format!("line {n}: {e}")))
+        .collect()
+}
+
+/// look up the ptr record of every host, a few at a time
+pub fn resolve_ptr_names(hosts: &mut [ScanHost], resolver: &Resolver) {
+    hosts
+        .par_iter_mut()
+        .for_each(|host| host.ptr = resolver.reverse(host.ip));
+}
+
+/// the name the ip's ptr record points at, if it has one
+pub fn reverse_lookup(ip: IpAddr) -> Option<String> {
+    #[cfg(unix)]
+    {
+        use socket2::SockAddr;
+        use std::ffi::CStr;
+
+        let addr = SockAddr::from(std::net::SocketAddr::new(ip, 0));
+        let mut name = [0 as libc::c_char; libc::NI_MAXHOST as usize];
+        // SAFETY: addr is a valid sockaddr of the length it gives and name
+        // is writable for the length passed
+        let rc = unsafe {
+            libc::getnameinfo(
+                addr.as_ptr() as *const libc::sockaddr,
+                addr.len(),
+                name.as_mut_ptr(),
+                name.len() as libc::socklen_t,
+                std::ptr::null_mut(),
+                0,
+                libc::NI_NAMEREQD,
+            )
+        };
+        if rc != 0 {
+            return None;
+        }
+        // SAFETY: getnameinfo nul-terminates what it writes
+        let name = unsafe { CStr::from_ptr(name.as_ptr()) };
+        let name = name.to_string_lossy().trim_end_matches('.').to_string();
+        (!name.is_empty()).then_some(name)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = ip;
+        None
+    }
+}
+
+/// resolve a target (ip or domain) into one or more ip addresses
+/// with the system resolver
+pub fn resolve_target(target: &str) -> Result<Vec<IpAddr>, ScanError> {
+    Resolver::system().resolve(target)
+}
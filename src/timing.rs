@@ -0,0 +1,423 @@
+//! nmap-style timing templates, and the pacing of probes they ask for
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// lowest timeout the rtt estimate may pick, when none is given
+pub const DEFAULT_MIN_RTT_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// highest timeout the rtt estimate may pick, when none is given
+pub const DEFAULT_MAX_RTT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// a preset for timeout, retries, concurrency and the delay between
+/// probes, from -T0 (slowest, quietest) to -T5 (fastest)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Timing {
+    Paranoid,
+    Sneaky,
+    Polite,
+    Normal,
+    Aggressive,
+    Insane,
+}
+
+impl Timing {
+    pub const ALL: [Timing; 6] = [
+        Timing::Paranoid,
+        Timing::Sneaky,
+        Timing::Polite,
+        Timing::Normal,
+        Timing::Aggressive,
+        Timing::Insane,
+    ];
+
+    /// 0 for paranoid up to 5 for insane
+    pub fn level(self) -> u8 {
+        self as u8
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Timing::Paranoid => "paranoid",
+            Timing::Sneaky => "sneaky",
+            Timing::Polite => "polite",
+            Timing::Normal => "normal",
+            Timing::Aggressive => "aggressive",
+            Timing::Insane => "insane",
+        }
+    }
+
+    /// how long each attempt waits for an answer
+    pub fn timeout(self) -> Duration {
+        Duration::from_millis(match self {
+            Timing::Paranoid | Timing::Sneaky => 5000,
+            Timing::Polite => 2000,
+            Timing::Normal => 1000,
+            Timing::Aggressive => 500,
+            Timing::Insane => 250,
+        })
+    }
+
+    /// extra attempts for ports that didn't answer
+    pub fn retries(self) -> u8 {
+        match self {
+            Timing::Paranoid | Timing::Sneaky => 3,
+            Timing::Polite => 2,
+            Timing::Normal | Timing::Aggressive => 1,
+            Timing::Insane => 0,
+        }
+    }
+
+    /// probes in flight at once; the slow templates go one at a time
+    pub fn concurrency(self) -> usize {
+        match self {
+            Timing::Paranoid | Timing::Sneaky | Timing::Polite => 1,
+            Timing::Normal => 100,
+            Timing::Aggressive => 300,
+            Timing::Insane => 1000,
+        }
+    }
+
+    /// the least time between the starts of two probes
+    pub fn scan_delay(self) -> Duration {
+        match self {
+            Timing::Paranoid => Duration::from_secs(300),
+            Timing::Sneaky => Duration::from_secs(15),
+            Timing::Polite => Duration::from_millis(400),
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+impl FromStr for Timing {
+    type Err = String;
+
+    /// `4`, `T4` or `aggressive`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_ascii_lowercase();
+        let level = lower.strip_prefix('t').unwrap_or(&lower);
+        Timing::ALL
+            .into_iter()
+            .find(|t| level == t.level().to_string() || lower == t.name())
+            .ok_or_else(|| {
+                format!("invalid timing template '{s}' (expected 0-5 or a name like aggressive)")
+            })
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "T{} ({})", self.level(), self.name())
+    }
+}
+
+/// how often a paused pacer looks again
+const PAUSED_WAIT: Duration = Duration::from_millis(100);
+
+/// spaces probes out, keeping at least `delay` between the starts of any
+/// two and, with a rate, no more than `rate` starts a second (a token
+/// bucket). one pacer is shared by every worker and every host of a scan.
+#[derive(Debug)]
+pub(crate) struct Pacer {
+    delay: Duration,
+    rate: Option<u32>,
+    state: Mutex<PaceState>,
+    /// no probe may start while set, see `Scanner::set_paused`
+    paused: AtomicBool,
+}
+
+#[derive(Debug)]
+struct PaceState {
+    /// when the next probe may start, going by the delay
+    next: Option<Instant>,
+    /// probes that may start right away, going by the rate
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Default for Pacer {
+    fn default() -> Self {
+        Pacer::new(Duration::ZERO, None)
+    }
+}
+
+impl Pacer {
+    pub fn new(delay: Duration, rate: Option<u32>) -> Self {
+        let rate = rate.filter(|&r| r > 0);
+        Pacer {
+            delay,
+            rate,
+            state: Mutex::new(PaceState {
+                next: None,
+                tokens: rate.map_or(0.0, |r| burst(f64::from(r))),
+                refilled: Instant::now(),
+            }),
+            paused: AtomicBool::new(false),
+        }
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// most probes started per second, if capped
+    pub fn rate(&self) -> Option<u32> {
+        self.rate
+    }
+
+    /// claim the right to send a probe now, or learn how long until the
+    /// next one may go
+    pub fn try_take(&self) -> Result<(), Duration> {
+        if self.is_paused() {
+            return Err(PAUSED_WAIT);
+        }
+        if self.delay.is_zero() && self.rate.is_none() {
+            return Ok(());
+        }
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut wait = match state.next {
+            Some(at) => at.saturating_duration_since(now),
+            None => Duration::ZERO,
+        };
+        if let Some(rate) = self.rate {
+            let rate = f64::from(rate);
+            let elapsed = now.duration_since(state.refilled).as_secs_f64();
+            state.tokens = (state.tokens + elapsed * rate).min(burst(rate));
+            state.refilled = now;
+            if state.tokens < 1.0 {
+                wait = wait.max(Duration::from_secs_f64((1.0 - state.tokens) / rate));
+            }
+        }
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+        if !self.delay.is_zero() {
+            state.next = Some(now + self.delay);
+        }
+        if self.rate.is_some() {
+            state.tokens -= 1.0;
+        }
+        Ok(())
+    }
+
+    /// block until a probe may be sent. false when `cancelled` was set
+    /// while waiting.
+    pub fn wait(&self, cancelled: &AtomicBool) -> bool {
+        // sleep in short slices so cancellation is noticed quickly
+        const SLICE: Duration = Duration::from_millis(100);
+        loop {
+            if cancelled.load(Ordering::Relaxed) {
+                return false;
+            }
+            match self.try_take() {
+                Ok(()) => return true,
+                Err(wait) => thread::sleep(wait.min(SLICE)),
+            }
+        }
+    }
+}
+
+/// how many probes may go back to back under `rate`: a twentieth of a
+/// second's worth, so the cap holds over short windows too
+fn burst(rate: f64) -> f64 {
+    (rate / 20.0).max(1.0)
+}
+
+/// derives the probe timeout from the round trips seen so far, like tcp's
+/// retransmission timer (rfc 6298): the smoothed rtt plus four times its
+/// variation, kept within `min`..=`max`. until the first answer the
+/// configured timeout is used.
+#[derive(Debug)]
+pub(crate) struct RttEstimator {
+    min: Duration,
+    max: Duration,
+    initial: Duration,
+    /// smoothed rtt and its variation, once something answered
+    state: Mutex<Option<(Duration, Duration)>>,
+}
+
+impl RttEstimator {
+    pub fn new(min: Duration, max: Duration, initial: Duration) -> Self {
+        RttEstimator {
+            min,
+            max,
+            initial,
+            state: Mutex::new(None),
+        }
+    }
+
+    /// the same bounds, with nothing observed yet
+    pub fn fresh(&self) -> Self {
+        RttEstimator::new(self.min, self.max, self.initial)
+    }
+
+    pub fn bounds(&self) -> (Duration, Duration) {
+        (self.min, self.max)
+    }
+
+    /// how long the next probe should wait for an answer
+    pub fn timeout(&self) -> Duration {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let timeout = match *state {
+            Some((srtt, rttvar)) => srtt + rttvar * 4,
+            None => self.initial,
+        };
+        timeout.clamp(self.min, self.max)
+    }
+
+    /// fold in the round trip of a probe that got an answer
+    pub fn observe(&self, rtt: Duration) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        *state = Some(match *state {
+            None => (rtt, rtt / 2),
+            Some((srtt, rttvar)) => {
+                let delta = srtt.abs_diff(rtt);
+                (srtt * 7 / 8 + rtt / 8, rttvar * 3 / 4 + delta / 4)
+            }
+        });
+    }
+}
+
+/// aimd control of how many probes may be in flight, like tcp's congestion
+/// window: it grows while probes get answers (doubling per round trip up
+/// to the last cut, then by one per round trip) and halves when they go
+/// unanswered, at most once per `cooldown`. it stays within `min..=max`.
+#[derive(Debug)]
+pub(crate) struct Congestion {
+    min: usize,
+    max: usize,
+    state: Mutex<Window>,
+    freed: Condvar,
+}
+
+#[derive(Debug)]
+struct Window {
+    size: f64,
+    /// below this the window grows by one per answer
+    threshold: f64,
+    in_use: usize,
+    last_cut: Option<Instant>,
+}
+
+impl Congestion {
+    pub fn new(min: usize, max: usize) -> Self {
+        let max = max.max(1);
+        let min = min.clamp(1, max);
+        Congestion {
+            min,
+            max,
+            state: Mutex::new(Window {
+                size: max as f64,
+                threshold: max as f64,
+                in_use: 0,
+                last_cut: None,
+            }),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// the same bounds, fully open again
+    pub fn fresh(&self) -> Self {
+        Congestion::new(self.min, self.max)
+    }
+
+    pub fn min(&self) -> usize {
+        self.min
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Window> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// probes that may be in flight right now
+    pub fn limit(&self) -> usize {
+        (self.lock().size as usize).clamp(self.min, self.max)
+    }
+
+    /// a probe got an answer
+    pub fn answered(&self) {
+        let mut window = self.lock();
+        window.size += if window.size < window.threshold {
+            1.0
+        } else {
+            1.0 / window.size
+        };
+        window.size = window.size.min(self.max as f64);
+        drop(window);
+        self.freed.notify_all();
+    }
+
+    /// a probe got no answer (or failed) within `cooldown` of the last cut
+    /// is taken as part of the same loss
+    pub fn dropped(&self, cooldown: Duration) {
+        let now = Instant::now();
+        let mut window = self.lock();
+        if window
+            .last_cut
+            .is_some_and(|at| now.duration_since(at) < cooldown)
+        {
+            return;
+        }
+        window.size = (window.size / 2.0).max(self.min as f64);
+        window.threshold = window.size;
+        window.last_cut = Some(now);
+    }
+
+    /// a place in the window if it has room right now
+    pub fn try_acquire(&self) -> Option<Slot<'_>> {
+        let mut window = self.lock();
+        if window.in_use < (window.size as usize).clamp(self.min, self.max) {
+            window.in_use += 1;
+            Some(Slot(Some(self)))
+        } else {
+            None
+        }
+    }
+
+    /// block until the window has room for one more probe; None when
+    /// `cancelled` was set while waiting. the slot frees itself when dropped.
+    pub fn acquire<'a>(&'a self, cancelled: &AtomicBool) -> Option<Slot<'a>> {
+        let mut window = self.lock();
+        loop {
+            if cancelled.load(Ordering::Relaxed) {
+                return None;
+            }
+            if window.in_use < (window.size as usize).clamp(self.min, self.max) {
+                window.in_use += 1;
+                return Some(Slot(Some(self)));
+            }
+            // wake up now and then to notice cancellation
+            window = self
+                .freed
+                .wait_timeout(window, Duration::from_millis(100))
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+}
+
+/// one probe's place in a `Congestion` window, or in none when the
+/// concurrency is fixed
+pub(crate) struct Slot<'a>(pub Option<&'a Congestion>);
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        if let Some(congestion) = self.0 {
+            congestion.lock().in_use -= 1;
+            congestion.freed.notify_one();
+        }
+    }
+}
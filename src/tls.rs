@@ -0,0 +1,286 @@
+//! tls through rustls: --tls-probe's handshake with an open port, for the
+//! version and cipher the server settles on and the certificate it shows,
+//! and the verified client connections behind dns over tls and https,
+//! https webhooks and smtp
+
+use crate::Transport;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{
+    ClientConfig, ClientConnection, DigitallySignedStruct, ProtocolVersion, RootCertStore,
+    SignatureScheme, StreamOwned,
+};
+use std::io::{self, ErrorKind};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use x509_parser::prelude::{FromDer, GeneralName, X509Certificate};
+use x509_parser::time::ASN1Time;
+
+/// what a tls handshake revealed about a port
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TlsInfo {
+    /// negotiated protocol, e.g. "TLSv1.2"
+    pub version: String,
+    /// negotiated cipher suite (iana name, or hex when unknown)
+    pub cipher: String,
+    /// the leaf certificate; None when the server sent none
+    pub certificate: Option<CertInfo>,
+}
+
+/// the interesting parts of an x.509 certificate
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CertInfo {
+    /// e.g. "CN=example.com, O=Example"
+    pub subject: String,
+    pub issuer: String,
+    /// dns names and ip addresses from subjectAltName
+    pub sans: Vec<String>,
+    pub not_before: Option<SystemTime>,
+    pub not_after: Option<SystemTime>,
+}
+
+impl TlsInfo {
+    /// one line for tables: version, cipher, subject, sans and expiry
+    pub fn summary(&self) -> String {
+        let mut s = format!("{} {}", self.version, self.cipher);
+        if let Some(cert) = &self.certificate {
+            s.push_str(&format!(
+                "; subject {}; issuer {}",
+                cert.subject, cert.issuer
+            ));
+            if !cert.sans.is_empty() {
+                s.push_str(&format!("; sans {}", cert.sans.join(",")));
+            }
+            if let Some(t) = cert.not_after {
+                s.push_str(&format!("; expires {}", crate::report::util::rfc3339(t)));
+            }
+        }
+        s
+    }
+}
+
+/// tls handshake over a new connection to `addr`, sending `server_name`
+/// as sni. any certificate is taken, so the one shown can be reported;
+/// the connection is closed once the handshake is done. rustls speaks tls
+/// 1.2 and 1.3 only, so older servers fail the probe.
+pub fn tls_probe(
+    addr: SocketAddr,
+    server_name: Option<&str>,
+    timeout: Duration,
+) -> io::Result<TlsInfo> {
+    tls_probe_via(&Transport::direct(), addr, server_name, timeout)
+}
+
+/// `tls_probe` over connections from `transport`
+pub(crate) fn tls_probe_via(
+    transport: &Transport,
+    addr: SocketAddr,
+    server_name: Option<&str>,
+    timeout: Duration,
+) -> io::Result<TlsInfo> {
+    let deadline = Instant::now() + timeout;
+    // without a usable name, the address; rustls sends no sni for one
+    let name = match server_name.map(ServerName::try_from) {
+        Some(Ok(name)) => name.to_owned(),
+        _ => ServerName::IpAddress(addr.ip().into()),
+    };
+    let mut conn = ClientConnection::new(config(), name).map_err(io::Error::other)?;
+    let mut stream = transport.connect(addr, timeout)?;
+    while conn.is_handshaking() {
+        let left = deadline
+            .checked_duration_since(Instant::now())
+            .filter(|left| !left.is_zero())
+            .ok_or(ErrorKind::TimedOut)?;
+        stream.set_read_timeout(Some(left))?;
+        stream.set_write_timeout(Some(left))?;
+        conn.complete_io(&mut stream)?;
+    }
+    let info = TlsInfo {
+        version: match conn.protocol_version() {
+            Some(ProtocolVersion::TLSv1_2) => "TLSv1.2".to_string(),
+            Some(ProtocolVersion::TLSv1_3) => "TLSv1.3".to_string(),
+            Some(v) => format!("0x{:04x}", u16::from(v)),
+            None => return Err(io::Error::new(ErrorKind::InvalidData, "no tls version")),
+        },
+        cipher: conn
+            .negotiated_cipher_suite()
+            .map(|suite| {
+                let suite = suite.suite();
+                match suite.as_str() {
+                    // rustls marks tls 1.3 suites; the iana names don't
+                    Some(name) => name.replacen("TLS13_", "TLS_", 1),
+                    None => format!("0x{:04x}", u16::from(suite)),
+                }
+            })
+            .unwrap_or_default(),
+        certificate: conn
+            .peer_certificates()
+            .and_then(|chain| chain.first())
+            .and_then(|leaf| parse_certificate(leaf)),
+    };
+    conn.send_close_notify();
+    let _ = conn.write_tls(&mut stream);
+    Ok(info)
+}
+
+/// the client side of every probe, made once
+fn config() -> Arc<ClientConfig> {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+    CONFIG
+        .get_or_init(|| {
+            let provider = Arc::new(ring::default_provider());
+            let config = ClientConfig::builder_with_provider(provider.clone())
+                .with_safe_default_protocol_versions()
+                .expect("ring supports tls 1.2 and 1.3")
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(AnyCertificate(provider)))
+                .with_no_client_auth();
+            Arc::new(config)
+        })
+        .clone()
+}
+
+/// a verified tls session over a tcp connection
+pub(crate) type TlsStream = StreamOwned<ClientConnection, TcpStream>;
+
+/// connect to `addr` and complete a handshake, checking the certificate
+/// chain against the mozilla roots and its name (or ip) against `name`
+pub(crate) fn connect(addr: SocketAddr, name: &str, timeout: Duration) -> io::Result<TlsStream> {
+    let tcp = TcpStream::connect_timeout(&addr, timeout)?;
+    tcp.set_read_timeout(Some(timeout))?;
+    tcp.set_write_timeout(Some(timeout))?;
+    handshake(tcp, name)
+}
+
+/// the same handshake on a connection that is already open, for protocols
+/// that switch to tls part way (smtp's starttls)
+pub(crate) fn handshake(mut tcp: TcpStream, name: &str) -> io::Result<TlsStream> {
+    let server = ServerName::try_from(name)
+        .map_err(|_| io::Error::new(ErrorKind::InvalidInput, format!("bad server name '{name}'")))?
+        .to_owned();
+    let mut conn = ClientConnection::new(verified_config(), server).map_err(io::Error::other)?;
+    // finish here so an untrusted certificate fails the connect, not the
+    // first read
+    while conn.is_handshaking() {
+        conn.complete_io(&mut tcp).map_err(|e| match e.get_ref() {
+            Some(inner) if inner.is::<rustls::Error>() => io::Error::new(
+                ErrorKind::InvalidData,
+                format!("tls handshake with {name} failed: {inner}"),
+            ),
+            _ => e,
+        })?;
+    }
+    Ok(StreamOwned::new(conn, tcp))
+}
+
+/// the client side of verified connections, made once
+fn verified_config() -> Arc<ClientConfig> {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+    CONFIG
+        .get_or_init(|| {
+            let roots = RootCertStore {
+                roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+            };
+            let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .expect("ring supports tls 1.2 and 1.3")
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            Arc::new(config)
+        })
+        .clone()
+}
+
+/// takes whatever certificate the server shows, since the probe reports
+/// it rather than trusting it. handshake signatures are still checked.
+#[derive(Debug)]
+struct AnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// read subject, issuer, sans and validity from a der certificate.
+/// signatures are not checked.
+pub fn parse_certificate(der: &[u8]) -> Option<CertInfo> {
+    let (_, cert) = X509Certificate::from_der(der).ok()?;
+    let sans = match cert.subject_alternative_name() {
+        Ok(Some(san)) => san
+            .value
+            .general_names
+            .iter()
+            .filter_map(|name| match name {
+                GeneralName::DNSName(dns) => Some(dns.to_string()),
+                GeneralName::IPAddress(ip) => match ip.len() {
+                    4 => Some(IpAddr::from(<[u8; 4]>::try_from(*ip).ok()?).to_string()),
+                    16 => Some(IpAddr::from(<[u8; 16]>::try_from(*ip).ok()?).to_string()),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    let validity = cert.validity();
+    Some(CertInfo {
+        subject: cert.subject().to_string(),
+        issuer: cert.issuer().to_string(),
+        sans,
+        not_before: system_time(validity.not_before),
+        not_after: system_time(validity.not_after),
+    })
+}
+
+fn system_time(t: ASN1Time) -> Option<SystemTime> {
+    let secs = t.timestamp();
+    if secs >= 0 {
+        UNIX_EPOCH.checked_add(Duration::from_secs(secs as u64))
+    } else {
+        UNIX_EPOCH.checked_sub(Duration::from_secs(secs.unsigned_abs()))
+    }
+}
@@ -0,0 +1,304 @@
+//! the async engine on tokio (the `tokio` feature): a task per probe, as
+//! many at once as a semaphore sized to the congestion window allows
+
+use crate::engine::{enrich, record, sys::is_fd_exhaustion, OnResult};
+use crate::retry::RetryQueue;
+use crate::timing::Slot;
+use crate::transport::socket_for;
+use crate::udp;
+use crate::{banner_text, trace_attempt, PortState, Protocol, ScanResult, Scanner};
+use socket2::Type;
+use std::collections::HashMap;
+use std::io::{self, ErrorKind};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpSocket, UdpSocket};
+use tokio::runtime::{Handle, Runtime};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::task::JoinSet;
+use tokio::time;
+
+/// a runtime with `threads` workers to run `scan` on. None from inside
+/// another runtime, which can't block on this one.
+pub(crate) fn runtime(threads: usize) -> io::Result<Option<Runtime>> {
+    if Handle::try_current().is_ok() {
+        return Ok(None);
+    }
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(threads)
+        .enable_all()
+        .build()
+        .map(Some)
+}
+
+/// one attempt on a port, as its task saw it
+struct Attempt {
+    /// which of the probes in flight it was
+    id: usize,
+    port: u16,
+    attempt: u8,
+    /// for an answer, the banner (or udp reply) when banners are read
+    outcome: io::Result<Option<Vec<u8>>>,
+    /// from the start of the attempt to its answer, or giving up
+    elapsed: Duration,
+}
+
+/// scan `ports` on `ip` on `runtime`; semantics (retries, states,
+/// latency, pacing, cancellation) match the other engines. the probes run
+/// on the runtime's workers, so follow-up probes (tls, http) block only
+/// the calling thread, which starts no new ones meanwhile.
+pub(crate) fn scan<I>(
+    runtime: &Runtime,
+    scanner: &Scanner,
+    ip: IpAddr,
+    ports: I,
+    on_result: OnResult,
+) -> Vec<ScanResult>
+where
+    I: Iterator<Item = u16>,
+{
+    let mut results = runtime.block_on(drive(scanner, ip, ports, on_result));
+    results.sort_by_key(|r| r.port);
+    results
+}
+
+async fn drive<I>(
+    scanner: &Scanner,
+    ip: IpAddr,
+    mut ports: I,
+    on_result: OnResult<'_>,
+) -> Vec<ScanResult>
+where
+    I: Iterator<Item = u16>,
+{
+    // wait in short slices so cancellation is noticed quickly
+    const POLL_SLICE: Duration = Duration::from_millis(100);
+    // how soon to look again when other hosts hold the whole shared cap
+    const CAP_WAIT: Duration = Duration::from_millis(5);
+
+    let shared = Arc::new(scanner.clone());
+    let retry = &scanner.retry;
+    let udp = scanner.protocol == Protocol::Udp;
+    let mut size = scanner.window();
+    let permits = Semaphore::new(size);
+    let mut retry_queue = RetryQueue::default();
+    let mut probes: JoinSet<Attempt> = JoinSet::new();
+    // the ports in flight by probe, each holding its permit and its place
+    // under the cap shared across hosts until its attempt is settled here
+    let mut in_flight: HashMap<usize, (u16, SemaphorePermit, Slot)> = HashMap::new();
+    let mut next_id = 0;
+    let mut results = Vec::new();
+
+    // stopped gently, the probes in flight still get their answer
+    while !scanner.stopped() || (scanner.draining() && !probes.is_empty()) {
+        // the congestion window moves as answers come and go; the
+        // semaphore follows it
+        let window = scanner.window();
+        if window > size {
+            permits.add_permits(window - size);
+            size = window;
+        } else if window < size {
+            size -= permits.forget_permits(size - window);
+        }
+
+        // start what the semaphore has room for, as fast as the scan
+        // delay allows
+        let mut paced = None;
+        while !scanner.stopped() {
+            let Ok(permit) = permits.try_acquire() else {
+                break;
+            };
+            let next = retry_queue
+                .pop()
+                .or_else(|| ports.next().map(|port| (port, 0)));
+            let Some((port, attempt)) = next else {
+                break;
+            };
+            let Some(slot) = scanner.try_shared_slot() else {
+                retry_queue.push_front(port, attempt);
+                paced = Some(CAP_WAIT);
+                break;
+            };
+            if let Err(wait) = scanner.pacer.try_take() {
+                retry_queue.push_front(port, attempt);
+                paced = Some(wait);
+                break;
+            }
+            let id = next_id;
+            next_id += 1;
+            in_flight.insert(id, (port, permit, slot));
+            let scanner = shared.clone();
+            let addr = scanner.addr(ip, port);
+            let timeout = retry.timeout(scanner.probe_timeout(), attempt);
+            probes.spawn(async move {
+                let started = Instant::now();
+                let outcome = if udp {
+                    probe_udp(&scanner, addr, timeout).await
+                } else {
+                    probe_tcp(&scanner, addr, timeout).await
+                };
+                Attempt {
+                    id,
+                    port,
+                    attempt,
+                    elapsed: outcome.1.duration_since(started),
+                    outcome: outcome.0,
+                }
+            });
+        }
+
+        // the next probe the scan delay or a retry backoff holds back
+        let held = paced.or(retry_queue.next_due());
+        if probes.is_empty() {
+            if let Some(wait) = held {
+                time::sleep(wait.min(POLL_SLICE)).await;
+            } else if retry_queue.is_empty() {
+                break;
+            }
+            continue;
+        }
+
+        let wait = held.unwrap_or(POLL_SLICE).min(POLL_SLICE);
+        let first = tokio::select! {
+            Some(done) = probes.join_next() => done.expect("probe task panicked"),
+            () = time::sleep(wait) => continue,
+        };
+        // settle every attempt that's over before starting more, so the
+        // window has shrunk for the timeouts among them
+        let mut done = vec![first];
+        while let Some(more) = probes.try_join_next() {
+            done.push(more.expect("probe task panicked"));
+        }
+
+        for done in done {
+            let Attempt {
+                id,
+                port,
+                attempt,
+                outcome,
+                elapsed,
+            } = done;
+            in_flight.remove(&id);
+            if let Err(e) = &outcome {
+                if is_fd_exhaustion(e) && !probes.is_empty() {
+                    // out of descriptors: wait for in-flight probes to drain
+                    retry_queue.push_front(port, attempt);
+                    continue;
+                }
+            }
+            let state = match &outcome {
+                Ok(_) => PortState::Open,
+                Err(e) if udp => udp::state_for_error(e.kind()),
+                Err(e) => PortState::from_error_kind(e.kind()),
+            };
+            let error = outcome.as_ref().err().map(io::Error::kind);
+            trace_attempt(scanner.addr(ip, port), attempt, error, state);
+
+            let latency = match state {
+                PortState::Open | PortState::Closed => Some(elapsed),
+                _ => None,
+            };
+            match latency {
+                Some(rtt) => scanner.answered(rtt),
+                None => scanner.unanswered(state),
+            }
+            if attempt < retry.retries && retry.should_retry(state) {
+                retry_queue.schedule(retry, port, attempt + 1);
+                continue;
+            }
+            let mut result = ScanResult {
+                latency,
+                banner: outcome.ok().flatten().and_then(|b| banner_text(&b)),
+                ..ScanResult::new(port, state)
+            };
+            enrich(scanner, ip, &mut result);
+            record(scanner, on_result, &mut results, result);
+        }
+    }
+
+    // stopped: whatever never got an answer is reported as filtered (or
+    // unknown)
+    probes.shutdown().await;
+    let unanswered = in_flight
+        .into_values()
+        .map(|(port, ..)| port)
+        .chain(retry_queue.into_ports())
+        .chain(ports);
+    for port in unanswered {
+        record(
+            scanner,
+            on_result,
+            &mut results,
+            ScanResult::new(port, scanner.skipped_state()),
+        );
+    }
+    results
+}
+
+/// connect to `addr` from the configured source, then read a banner when
+/// asked to. the time is that of the connect's answer.
+async fn probe_tcp(
+    scanner: &Scanner,
+    addr: SocketAddr,
+    timeout: Duration,
+) -> (io::Result<Option<Vec<u8>>>, Instant) {
+    let connect = async {
+        let socket = scanner.transport.bound(addr, |local| {
+            let socket = socket_for(addr, local, Type::STREAM)?;
+            socket.set_nonblocking(true)?;
+            Ok(TcpSocket::from_std_stream(socket.into()))
+        })?;
+        socket.connect(addr).await
+    };
+    let connected = time::timeout(timeout, connect)
+        .await
+        .unwrap_or_else(|_| Err(ErrorKind::TimedOut.into()));
+    let answered = Instant::now();
+    let mut stream = match connected {
+        Ok(stream) => stream,
+        Err(e) => return (Err(e), answered),
+    };
+    let Some(opts) = scanner.banner else {
+        return (Ok(None), answered);
+    };
+    // whatever the service says first, until it stops or time is up
+    let mut banner = Vec::with_capacity(opts.max_bytes);
+    let mut stream = (&mut stream).take(opts.max_bytes as u64);
+    let _ = time::timeout(opts.timeout, stream.read_to_end(&mut banner)).await;
+    (Ok(Some(banner)), answered)
+}
+
+/// send `addr`'s service its probe and wait for a reply, which is the
+/// banner when banners are read
+async fn probe_udp(
+    scanner: &Scanner,
+    addr: SocketAddr,
+    timeout: Duration,
+) -> (io::Result<Option<Vec<u8>>>, Instant) {
+    let exchange = async {
+        let socket = scanner.transport.bound(addr, |local| {
+            let socket = socket_for(addr, local, Type::DGRAM)?;
+            socket.set_nonblocking(true)?;
+            socket.connect(&addr.into())?;
+            UdpSocket::from_std(socket.into())
+        })?;
+        socket.send(udp::udp_payload(addr.port())).await?;
+        let mut reply = vec![0u8; udp::MAX_REPLY];
+        let n = socket.recv(&mut reply).await?;
+        reply.truncate(n);
+        Ok(reply)
+    };
+    let reply = time::timeout(timeout, exchange)
+        .await
+        .unwrap_or_else(|_| Err(ErrorKind::TimedOut.into()));
+    let answered = Instant::now();
+    let reply = reply.map(|mut reply| {
+        scanner.banner.map(|opts| {
+            reply.truncate(opts.max_bytes);
+            reply
+        })
+    });
+    (reply, answered)
+}
@@ -0,0 +1,739 @@
+//! how tcp probes reach their target: directly, or tunnelled through a
+//! chain of proxies
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use socket2::{Domain, Socket, Type};
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{
+    IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6, TcpStream, ToSocketAddrs, UdpSocket,
+};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// how long each hop of a proxy chain may take to set up, by default
+pub const DEFAULT_HOP_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// where a local tor client listens for socks connections
+pub const TOR_SOCKS: &str = "socks5://127.0.0.1:9050";
+
+/// the protocol a proxy speaks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyKind {
+    /// an http proxy, tunnelling with CONNECT
+    Http,
+    /// a socks5 proxy (rfc 1928), with optional username/password auth
+    Socks5,
+}
+
+impl ProxyKind {
+    fn scheme(self) -> &'static str {
+        match self {
+            ProxyKind::Http => "http",
+            ProxyKind::Socks5 => "socks5",
+        }
+    }
+}
+
+/// a proxy that opens tunnels to targets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proxy {
+    kind: ProxyKind,
+    host: String,
+    port: u16,
+    /// `user:password` for the proxy's authentication
+    credentials: Option<String>,
+    /// the proxy's own address, looked up on first use
+    addr: OnceLock<SocketAddr>,
+}
+
+/// where a tunnel should lead
+#[derive(Clone, Copy)]
+enum Dest<'a> {
+    Addr(SocketAddr),
+    /// the next proxy of a chain, left for the previous one to resolve
+    Name(&'a str, u16),
+}
+
+impl fmt::Display for Dest<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // no ipv6 scope: it means nothing on the proxy's side
+        match *self {
+            Dest::Addr(SocketAddr::V6(a)) => write!(f, "[{}]:{}", a.ip(), a.port()),
+            Dest::Addr(a) => write!(f, "{a}"),
+            Dest::Name(host, port) if host.contains(':') => write!(f, "[{host}]:{port}"),
+            Dest::Name(host, port) => write!(f, "{host}:{port}"),
+        }
+    }
+}
+
+impl Proxy {
+    pub fn kind(&self) -> ProxyKind {
+        self.kind
+    }
+
+    /// where to connect to reach the proxy
+    fn addr(&self) -> io::Result<SocketAddr> {
+        if let Some(addr) = self.addr.get() {
+            return Ok(*addr);
+        }
+        let found = (self.host.as_str(), self.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| {
+                io::Error::new(ErrorKind::NotFound, format!("proxy {self} has no address"))
+            })?;
+        Ok(*self.addr.get_or_init(|| found))
+    }
+
+    /// ask the proxy, already connected over `stream`, for a tunnel to
+    /// `dest`. the proxy's verdict on the destination comes back as the
+    /// error kind a direct connect would have given: refused, or timed out.
+    /// a socks proxy is sent `auth` in place of its own credentials.
+    fn open_tunnel(
+        &self,
+        stream: &mut TcpStream,
+        dest: Dest,
+        deadline: Instant,
+        auth: Option<&str>,
+    ) -> io::Result<()> {
+        match self.kind {
+            ProxyKind::Http => self.http_connect(stream, dest, deadline)?,
+            ProxyKind::Socks5 => {
+                let auth = auth.or(self.credentials.as_deref());
+                self.socks5_connect(stream, dest, deadline, auth)?
+            }
+        }
+        stream.set_read_timeout(None)?;
+        stream.set_write_timeout(None)
+    }
+
+    fn http_connect(
+        &self,
+        stream: &mut TcpStream,
+        dest: Dest,
+        deadline: Instant,
+    ) -> io::Result<()> {
+        let mut request = format!("CONNECT {dest} HTTP/1.1\r\nHost: {dest}\r\n");
+        if let Some(credentials) = &self.credentials {
+            request += &format!(
+                "Proxy-Authorization: Basic {}\r\n",
+                STANDARD.encode(credentials)
+            );
+        }
+        request += "\r\n";
+        send(stream, request.as_bytes(), deadline)?;
+
+        // byte by byte, so nothing the target sends after the headers is lost
+        let mut head = Vec::new();
+        let mut byte = [0u8; 1];
+        while !head.ends_with(b"\r\n\r\n") {
+            if head.len() > 8192 {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("proxy {self} sent an oversized reply"),
+                ));
+            }
+            self.recv(stream, &mut byte, deadline)?;
+            head.push(byte[0]);
+        }
+
+        let head = String::from_utf8_lossy(&head);
+        let status_line = head.lines().next().unwrap_or_default();
+        let kind = match status_line.split_whitespace().nth(1) {
+            Some("200") => return Ok(()),
+            // squid and friends: "bad gateway" / "service unavailable" when
+            // the target refused, "gateway timeout" when it never answered
+            Some("502" | "503") => ErrorKind::ConnectionRefused,
+            Some("504") => ErrorKind::TimedOut,
+            Some("407") => ErrorKind::PermissionDenied,
+            _ => ErrorKind::Other,
+        };
+        Err(io::Error::new(
+            kind,
+            format!("proxy {self} answered '{status_line}' for {dest}"),
+        ))
+    }
+
+    fn socks5_connect(
+        &self,
+        stream: &mut TcpStream,
+        dest: Dest,
+        deadline: Instant,
+        credentials: Option<&str>,
+    ) -> io::Result<()> {
+        let methods: &[u8] = match credentials {
+            Some(_) => &[0, 2],
+            None => &[0],
+        };
+        let mut hello = vec![5, methods.len() as u8];
+        hello.extend_from_slice(methods);
+        send(stream, &hello, deadline)?;
+        let mut chosen = [0u8; 2];
+        self.recv(stream, &mut chosen, deadline)?;
+        if chosen[0] != 5 {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("proxy {self} doesn't speak socks5"),
+            ));
+        }
+        match (chosen[1], credentials) {
+            (0, _) => {}
+            (2, Some(credentials)) => {
+                let (user, password) = credentials.split_once(':').unwrap_or((credentials, ""));
+                let mut auth = vec![1, user.len() as u8];
+                auth.extend_from_slice(user.as_bytes());
+                auth.push(password.len() as u8);
+                auth.extend_from_slice(password.as_bytes());
+                send(stream, &auth, deadline)?;
+                let mut status = [0u8; 2];
+                self.recv(stream, &mut status, deadline)?;
+                if status[1] != 0 {
+                    return Err(io::Error::new(
+                        ErrorKind::PermissionDenied,
+                        format!("proxy {self} rejected the credentials"),
+                    ));
+                }
+            }
+            _ => {
+                return Err(io::Error::new(
+                    ErrorKind::PermissionDenied,
+                    format!("proxy {self} accepts none of our authentication methods"),
+                ))
+            }
+        }
+
+        let mut request = vec![5, 1, 0];
+        let (ip, name, port) = match dest {
+            Dest::Addr(addr) => (Some(addr.ip()), "", addr.port()),
+            Dest::Name(host, port) => (host.parse::<IpAddr>().ok(), host, port),
+        };
+        match ip {
+            Some(IpAddr::V4(ip)) => {
+                request.push(1);
+                request.extend_from_slice(&ip.octets());
+            }
+            Some(IpAddr::V6(ip)) => {
+                request.push(4);
+                request.extend_from_slice(&ip.octets());
+            }
+            None => {
+                request.extend_from_slice(&[3, name.len() as u8]);
+                request.extend_from_slice(name.as_bytes());
+            }
+        }
+        request.extend_from_slice(&port.to_be_bytes());
+        send(stream, &request, deadline)?;
+
+        let mut reply = [0u8; 4];
+        self.recv(stream, &mut reply, deadline)?;
+        let (kind, reason) = match reply[1] {
+            0 => (None, ""),
+            2 => (Some(ErrorKind::Other), "not allowed by its rules"),
+            3 => (Some(ErrorKind::NetworkUnreachable), "network unreachable"),
+            4 => (Some(ErrorKind::HostUnreachable), "host unreachable"),
+            5 => (Some(ErrorKind::ConnectionRefused), "connection refused"),
+            6 => (Some(ErrorKind::TimedOut), "timed out"),
+            _ => (Some(ErrorKind::Other), "general failure"),
+        };
+        if let Some(kind) = kind {
+            return Err(io::Error::new(
+                kind,
+                format!("proxy {self} couldn't connect to {dest}: {reason}"),
+            ));
+        }
+        // the address the proxy connected from, which nobody needs
+        let bound = match reply[3] {
+            1 => 4,
+            4 => 16,
+            3 => {
+                let mut len = [0u8; 1];
+                self.recv(stream, &mut len, deadline)?;
+                len[0] as usize
+            }
+            _ => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("proxy {self} sent a malformed reply"),
+                ))
+            }
+        };
+        self.recv(stream, &mut vec![0u8; bound + 2], deadline)
+    }
+
+    /// fill `buf` from the proxy before `deadline`
+    fn recv(&self, stream: &mut TcpStream, buf: &mut [u8], deadline: Instant) -> io::Result<()> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            stream.set_read_timeout(Some(left(deadline)?))?;
+            match stream.read(&mut buf[filled..]) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        ErrorKind::ConnectionAborted,
+                        format!("proxy {self} closed the connection"),
+                    ))
+                }
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Proxy {
+    type Err = String;
+
+    /// `http://[user:password@]host:port` or
+    /// `socks5://[user:password@]host[:port]` (port 1080 by default)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, rest) = if let Some(rest) = s.strip_prefix("http://") {
+            (ProxyKind::Http, rest)
+        } else if let Some(rest) = s.strip_prefix("socks5://") {
+            (ProxyKind::Socks5, rest)
+        } else {
+            return Err(format!(
+                "unsupported proxy '{s}' (expected http://host:port or socks5://host[:port])"
+            ));
+        };
+        let rest = rest.trim_end_matches('/');
+        let (credentials, authority) = match rest.rsplit_once('@') {
+            Some((credentials, authority)) => (Some(credentials.to_string()), authority),
+            None => (None, rest),
+        };
+        if credentials.as_ref().is_some_and(|c| {
+            let (user, password) = c.split_once(':').unwrap_or((c, ""));
+            user.len() > 255 || password.len() > 255
+        }) {
+            return Err(format!("credentials of proxy '{s}' are too long"));
+        }
+        let (host, port) = match authority.rsplit_once(':') {
+            // "[fd00::8]" has colons but no port
+            Some((host, port)) if !port.contains(']') => {
+                let port: u16 = port
+                    .parse()
+                    .map_err(|_| format!("invalid port in proxy '{s}'"))?;
+                (host, port)
+            }
+            _ if kind == ProxyKind::Socks5 => (authority, 1080),
+            _ => return Err(format!("proxy '{s}' needs a port")),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() || host.len() > 255 {
+            return Err(format!("proxy '{s}' has no usable host"));
+        }
+        Ok(Proxy {
+            kind,
+            host: host.to_string(),
+            port,
+            credentials,
+            addr: OnceLock::new(),
+        })
+    }
+}
+
+impl fmt::Display for Proxy {
+    /// the proxy without its credentials
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dest = Dest::Name(&self.host, self.port);
+        write!(f, "{}://{dest}", self.kind.scheme())
+    }
+}
+
+/// how to open tcp connections to targets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transport {
+    /// proxies in the order they're passed through; empty for direct
+    hops: Vec<Proxy>,
+    hop_timeout: Duration,
+    /// this run's tag for per-host socks credentials, when isolating
+    isolation: Option<String>,
+    /// the local address probes leave from, when not the routing's choice
+    source: Option<IpAddr>,
+    /// local ports probes leave from, tried in turn; empty for any
+    source_ports: Vec<u16>,
+    /// the network interface probes leave through
+    interface: Option<String>,
+    /// the interface's own addresses, looked up on first use
+    interface_addrs: OnceLock<Vec<IpAddr>>,
+}
+
+/// what a probe's socket is bound to before it connects
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Local<'a> {
+    pub addr: SocketAddr,
+    /// the interface to send through, where the os can pin a socket to one
+    pub device: Option<&'a str>,
+}
+
+impl fmt::Display for Local<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.device {
+            Some(device) => write!(f, "{} on {device}", self.addr),
+            None => write!(f, "{}", self.addr),
+        }
+    }
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Transport {
+            hops: Vec::new(),
+            hop_timeout: DEFAULT_HOP_TIMEOUT,
+            isolation: None,
+            source: None,
+            source_ports: Vec::new(),
+            interface: None,
+            interface_addrs: OnceLock::new(),
+        }
+    }
+}
+
+impl Transport {
+    /// plain connects from this host
+    pub fn direct() -> Self {
+        Transport::default()
+    }
+
+    /// every connection is a tunnel through `proxy`
+    pub fn via(proxy: Proxy) -> Self {
+        Transport::chain(vec![proxy])
+    }
+
+    /// every connection goes through each of `proxies` in turn, the last
+    /// one connecting to the target. each proxy is asked for the next by
+    /// name, so later hops may be names only the earlier ones can resolve.
+    pub fn chain(proxies: Vec<Proxy>) -> Self {
+        Transport {
+            hops: proxies,
+            ..Transport::default()
+        }
+    }
+
+    /// through the local tor client, each target host on its own circuit
+    pub fn tor() -> Self {
+        let proxy = TOR_SOCKS.parse().expect("TOR_SOCKS is a valid proxy");
+        Transport::via(proxy).isolate_hosts()
+    }
+
+    /// give the last proxy, if it speaks socks5, different credentials for
+    /// every target ip (and every run). tor's IsolateSOCKSAuth, on by
+    /// default, then builds a separate circuit for each, so no two hosts
+    /// are scanned from the same exit.
+    pub fn isolate_hosts(mut self) -> Self {
+        self.isolation = Some(format!("port_scanner-{:08x}", crate::rng::random_u32()));
+        self
+    }
+
+    /// how long reaching each proxy may take (default 3s); the last
+    /// proxy's connect to the target gets the scan's own timeout
+    pub fn hop_timeout(mut self, timeout: Duration) -> Self {
+        self.hop_timeout = timeout;
+        self
+    }
+
+    /// send everything from this local address (to the first proxy, when
+    /// there is one). targets of the other ip family can't be reached.
+    pub fn source_ip(mut self, ip: IpAddr) -> Self {
+        self.source = Some(ip);
+        self
+    }
+
+    pub fn source(&self) -> Option<IpAddr> {
+        self.source
+    }
+
+    /// send everything from one of these local ports, for firewalls that
+    /// trust traffic from, say, port 53. probes to the same target port
+    /// (retries, follow-ups, scans running side by side) would collide on
+    /// one port, so each probe moves on to the next port of the list when
+    /// its first choice is taken. binding below 1024 needs privileges.
+    pub fn source_ports(mut self, ports: impl IntoIterator<Item = u16>) -> Self {
+        self.source_ports = ports.into_iter().collect();
+        self
+    }
+
+    pub fn source_port_list(&self) -> &[u16] {
+        &self.source_ports
+    }
+
+    /// send everything through this network interface, for hosts whose
+    /// routes would pick another (a vpn leg, a second nic). linux pins
+    /// each socket to the interface (SO_BINDTODEVICE, needs root or
+    /// CAP_NET_RAW); elsewhere probes are sent from the interface's
+    /// address and the routing table has the last word.
+    pub fn interface(mut self, name: impl Into<String>) -> Self {
+        self.interface = Some(name.into());
+        self.interface_addrs = OnceLock::new();
+        self
+    }
+
+    pub fn interface_name(&self) -> Option<&str> {
+        self.interface.as_deref()
+    }
+
+    /// the address probes to `target` leave from, when it isn't left to
+    /// the routing: the source ip, or else the interface's address of the
+    /// target's family (link-local for link-local targets)
+    pub(crate) fn source_for(&self, target: IpAddr) -> Option<IpAddr> {
+        if self.source.is_some() {
+            return self.source;
+        }
+        let name = self.interface.as_deref()?;
+        let addrs = self
+            .interface_addrs
+            .get_or_init(|| crate::iface::interface_addrs(name).unwrap_or_default());
+        let link_local = |ip: &IpAddr| match ip {
+            IpAddr::V6(v6) => v6.segments()[0] & 0xffc0 == 0xfe80,
+            IpAddr::V4(_) => false,
+        };
+        let same_family = addrs.iter().filter(|ip| ip.is_ipv4() == target.is_ipv4());
+        same_family
+            .clone()
+            .find(|ip| link_local(ip) == link_local(&target))
+            .or_else(|| same_family.clone().next())
+            .copied()
+    }
+
+    /// the local addresses to bind before connecting to `addr`, in the
+    /// order to try them; empty when the kernel may choose
+    fn locals_for(&self, addr: SocketAddr) -> Vec<Local<'_>> {
+        let device = self.interface.as_deref();
+        let ip = self.source_for(addr.ip());
+        if ip.is_none() && device.is_none() && self.source_ports.is_empty() {
+            return Vec::new();
+        }
+        let ip = ip.unwrap_or(match addr {
+            SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+            SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+        });
+        // a link-local source needs the target's zone to bind
+        let scope = match addr {
+            SocketAddr::V6(a) => a.scope_id(),
+            SocketAddr::V4(_) => 0,
+        };
+        let local = |port| Local {
+            addr: match ip {
+                IpAddr::V6(v6) => SocketAddrV6::new(v6, port, 0, scope).into(),
+                IpAddr::V4(_) => SocketAddr::new(ip, port),
+            },
+            device,
+        };
+        if self.source_ports.is_empty() {
+            return vec![local(0)];
+        }
+        // spread target ports over the list so they rarely share a first choice
+        let start = addr.port() as usize % self.source_ports.len();
+        let (after, before) = self.source_ports.split_at(start);
+        before
+            .iter()
+            .chain(after)
+            .map(|&port| local(port))
+            .collect()
+    }
+
+    /// run `open` with each local address for `addr` in turn until one
+    /// isn't already taken for that target
+    pub(crate) fn bound<T>(
+        &self,
+        addr: SocketAddr,
+        mut open: impl FnMut(Option<Local>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let locals = self.locals_for(addr);
+        if locals.is_empty() {
+            return open(None);
+        }
+        let mut taken = None;
+        for local in locals {
+            match open(Some(local)) {
+                Err(e) if local.addr.port() != 0 && address_taken(&e) => taken = Some(e),
+                other => return other,
+            }
+        }
+        Err(taken.expect("at least one local address was tried"))
+    }
+
+    /// a plain tcp connect to `addr` from the configured source
+    fn dial(&self, addr: SocketAddr, timeout: Duration) -> io::Result<TcpStream> {
+        self.bound(addr, |local| match local {
+            None => TcpStream::connect_timeout(&addr, timeout),
+            Some(local) => connect_from(local, addr, timeout),
+        })
+    }
+
+    pub fn is_direct(&self) -> bool {
+        self.hops.is_empty()
+    }
+
+    pub fn hops(&self) -> &[Proxy] {
+        &self.hops
+    }
+
+    /// a connected stream to `addr`, within `timeout` once the chain is up
+    pub fn connect(&self, addr: SocketAddr, timeout: Duration) -> io::Result<TcpStream> {
+        let Some(last) = self.hops.last() else {
+            return self.dial(addr, timeout);
+        };
+        let mut stream = self.through_hops()?;
+        let auth = self
+            .isolation
+            .as_ref()
+            .map(|tag| format!("{tag}:{}", addr.ip()));
+        let deadline = Instant::now() + timeout;
+        last.open_tunnel(&mut stream, Dest::Addr(addr), deadline, auth.as_deref())?;
+        Ok(stream)
+    }
+
+    /// try the interface, the source address and the whole chain once, to
+    /// find a bad source or a broken hop before scanning
+    pub fn check(&self) -> io::Result<()> {
+        if let Some(name) = &self.interface {
+            if crate::iface::interface_index(name).is_none() {
+                return Err(io::Error::new(
+                    ErrorKind::NotFound,
+                    format!("no network interface named {name}"),
+                ));
+            }
+        }
+        let any = SocketAddr::from(([0, 0, 0, 0], 0));
+        if let Some(&local) = self
+            .locals_for(self.source.map_or(any, |ip| (ip, 0).into()))
+            .first()
+        {
+            try_bind(local)
+                .map_err(|e| io::Error::new(e.kind(), format!("can't send from {local}: {e}")))?;
+        }
+        if !self.is_direct() {
+            self.through_hops()?;
+        }
+        Ok(())
+    }
+
+    /// a stream to the last proxy of the chain. a failing hop is an Other
+    /// error naming it, so it never reads as the target's answer.
+    fn through_hops(&self) -> io::Result<TcpStream> {
+        let first = &self.hops[0];
+        let mut stream = first
+            .addr()
+            .and_then(|addr| self.dial(addr, self.hop_timeout))
+            .map_err(|e| io::Error::other(format!("can't reach proxy {first}: {e}")))?;
+        for (n, pair) in self.hops.windows(2).enumerate() {
+            let (from, to) = (&pair[0], &pair[1]);
+            let deadline = Instant::now() + self.hop_timeout;
+            from.open_tunnel(&mut stream, Dest::Name(&to.host, to.port), deadline, None)
+                .map_err(|e| {
+                    io::Error::other(format!("hop {} ({from}) can't reach {to}: {e}", n + 1))
+                })?;
+        }
+        Ok(stream)
+    }
+}
+
+/// the local address (or, with a fixed port, the whole 4-tuple) is in use
+fn address_taken(e: &io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::AddrInUse | ErrorKind::AddrNotAvailable)
+}
+
+/// a socket for `addr`, bound to `local` first when given. a fixed
+/// local port is shared with the other probes (SO_REUSEADDR) and freed
+/// as soon as the socket closes (a reset, no TIME_WAIT), so the next
+/// probe to the same target port can have it again.
+pub(crate) fn socket_for(addr: SocketAddr, local: Option<Local>, kind: Type) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(addr), kind, None)?;
+    let Some(Local {
+        addr: local,
+        device,
+    }) = local
+    else {
+        return Ok(socket);
+    };
+    if let Some(device) = device {
+        bind_device(&socket, device)?;
+    }
+    if local.port() != 0 {
+        socket.set_reuse_address(true)?;
+        if kind == Type::STREAM {
+            socket.set_linger(Some(Duration::ZERO))?;
+        }
+    }
+    socket.bind(&local.into())?;
+    Ok(socket)
+}
+
+/// pin `socket` to the interface called `name`
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn bind_device(socket: &Socket, name: &str) -> io::Result<()> {
+    socket.bind_device(Some(name.as_bytes()))
+}
+
+/// elsewhere the interface's address, bound like any other, has to do
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn bind_device(_socket: &Socket, _name: &str) -> io::Result<()> {
+    Ok(())
+}
+
+/// waits for a socket mio has no type for (a raw or packet socket) to have
+/// something to read. mio only reports new readiness, so read until the
+/// socket would block before waiting again.
+#[cfg(unix)]
+pub(crate) struct Readable {
+    poll: std::cell::RefCell<(mio::Poll, mio::Events)>,
+}
+
+#[cfg(unix)]
+impl Readable {
+    pub(crate) fn new(socket: &Socket) -> io::Result<Readable> {
+        use std::os::fd::AsRawFd;
+
+        let poll = mio::Poll::new()?;
+        poll.registry().register(
+            &mut mio::unix::SourceFd(&socket.as_raw_fd()),
+            mio::Token(0),
+            mio::Interest::READABLE,
+        )?;
+        Ok(Readable {
+            poll: std::cell::RefCell::new((poll, mio::Events::with_capacity(1))),
+        })
+    }
+
+    /// until something is readable, or `wait` passes
+    pub(crate) fn wait(&self, wait: Duration) -> io::Result<()> {
+        let (poll, events) = &mut *self.poll.borrow_mut();
+        poll.poll(events, Some(wait))
+    }
+}
+
+/// a blocking connect from `local` to `addr`, given up after `timeout`
+fn connect_from(local: Local, addr: SocketAddr, timeout: Duration) -> io::Result<TcpStream> {
+    let socket = socket_for(addr, Some(local), Type::STREAM)?;
+    socket.connect_timeout(&addr.into(), timeout)?;
+    Ok(socket.into())
+}
+
+/// a udp socket bound to `local`, shareable with other probes
+pub(crate) fn udp_from(local: Local) -> io::Result<UdpSocket> {
+    Ok(socket_for(local.addr, Some(local), Type::DGRAM)?.into())
+}
+
+/// whether `local` can be bound for a tcp probe at all
+fn try_bind(local: Local) -> io::Result<()> {
+    socket_for(local.addr, Some(local), Type::STREAM).map(drop)
+}
+
+/// write all of `buf` before `deadline`
+fn send(stream: &mut TcpStream, buf: &[u8], deadline: Instant) -> io::Result<()> {
+    stream.set_write_timeout(Some(left(deadline)?))?;
+    stream.write_all(buf)
+}
+
+/// what's left until `deadline`, or a timeout error once it has passed
+fn left(deadline: Instant) -> io::Result<Duration> {
+    let left = deadline.saturating_duration_since(Instant::now());
+    if left.is_zero() {
+        return Err(ErrorKind::TimedOut.into());
+    }
+    Ok(left)
+}
@@ -0,0 +1,592 @@
+//! --tui: a live view of a scan, drawn on the terminal with ratatui: a
+//! progress bar per host, the open ports found so far, probes a second,
+//! and keys to pause, skip a host or quit.
+
+use crate::{Progress, ProgressSnapshot, Protocol, ScanResult, Scanner, Services, Stopped};
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::terminal::{
+    self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::crossterm::{cursor, execute};
+use ratatui::layout::{Constraint, Flex, Layout};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Cell, LineGauge, Paragraph, Row, Table};
+use ratatui::{Frame, Terminal};
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tracing_subscriber::fmt::MakeWriter;
+
+/// log lines kept for the screen, and printed again once it closes
+const MESSAGES: usize = 200;
+
+/// log lines shown under the open ports
+const SHOWN_MESSAGES: usize = 3;
+
+/// how often the screen is drawn when no key is pressed
+const REDRAW: Duration = Duration::from_millis(250);
+
+/// how long to wait for a key before looking at the scan again
+const KEY_WAIT: Duration = Duration::from_millis(100);
+
+/// width of a host's progress bar
+const BAR: u16 = 20;
+
+/// what the live view knows of a scan. it is also where the console log
+/// layer writes, so messages show on the screen rather than under it, and
+/// it stands in for stdout, see `stdout`.
+#[derive(Clone)]
+pub struct Dashboard {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    services: Arc<Services>,
+    view: Mutex<View>,
+}
+
+struct View {
+    showing: bool,
+    protocol: Protocol,
+    /// every host, including those finished
+    hosts: Vec<HostRow>,
+    open: Vec<OpenPort>,
+    /// the host `s` skips, when it is still being scanned
+    selected: Option<IpAddr>,
+    paused: bool,
+    quitting: bool,
+    messages: VecDeque<String>,
+    /// stdout written while the screen is up
+    held: Vec<u8>,
+}
+
+struct HostRow {
+    ip: IpAddr,
+    ports: usize,
+    done: usize,
+    open: usize,
+    skip: Arc<AtomicBool>,
+    /// None while it is being scanned
+    finished: Option<Option<Stopped>>,
+}
+
+struct OpenPort {
+    ip: IpAddr,
+    port: u16,
+    service: Option<String>,
+    latency: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Pause,
+    Skip,
+    Quit,
+    Up,
+    Down,
+}
+
+impl Dashboard {
+    /// open ports are named from `services`
+    pub fn new(services: Arc<Services>) -> Self {
+        Dashboard {
+            shared: Arc::new(Shared {
+                services,
+                view: Mutex::new(View {
+                    showing: false,
+                    protocol: Protocol::Tcp,
+                    hosts: Vec::new(),
+                    open: Vec::new(),
+                    selected: None,
+                    paused: false,
+                    quitting: false,
+                    messages: VecDeque::new(),
+                    held: Vec::new(),
+                }),
+            }),
+        }
+    }
+
+    fn view(&self) -> MutexGuard<'_, View> {
+        self.shared.view.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// take over the terminal until the screen is dropped, showing the
+    /// scan `scanner` counts into `progress`. p (or space) pauses, s skips
+    /// the selected host, up and down (or k and j) select another, and q
+    /// (or ctrl-c) quits: once letting the probes in flight finish, twice
+    /// straight away. needs a terminal.
+    pub fn show(&self, scanner: &Scanner, progress: Arc<Progress>) -> io::Result<Screen> {
+        let tty = Tty::open()?;
+        {
+            let mut view = self.view();
+            view.showing = true;
+            view.protocol = scanner.protocol();
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let dashboard = self.clone();
+            let scanner = scanner.clone();
+            let stop = stop.clone();
+            thread::spawn(move || dashboard.run(&scanner, &progress, tty, &stop))
+        };
+        Ok(Screen {
+            dashboard: self.clone(),
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// stdout, held back while the screen is up and written out when it
+    /// closes
+    pub fn stdout(&self) -> HeldStdout {
+        HeldStdout(self.clone())
+    }
+
+    /// a host's scan of `ports` ports started; the flag skips it
+    pub fn host_started(&self, ip: IpAddr, ports: usize) -> Arc<AtomicBool> {
+        let skip = Arc::new(AtomicBool::new(false));
+        self.view().hosts.push(HostRow {
+            ip,
+            ports,
+            done: 0,
+            open: 0,
+            skip: skip.clone(),
+            finished: None,
+        });
+        skip
+    }
+
+    pub fn result(&self, ip: IpAddr, result: &ScanResult) {
+        let mut view = self.view();
+        if let Some(row) = view.hosts.iter_mut().rev().find(|h| h.ip == ip) {
+            row.done += 1;
+            if result.is_open() {
+                row.open += 1;
+            }
+        }
+        if result.is_open() {
+            view.open.push(OpenPort {
+                ip,
+                port: result.port,
+                service: result
+                    .service_name_in(&self.shared.services)
+                    .map(str::to_string),
+                latency: result.latency,
+            });
+        }
+    }
+
+    pub fn host_finished(&self, ip: IpAddr, stopped: Option<Stopped>) {
+        let mut view = self.view();
+        if let Some(row) = view.hosts.iter_mut().rev().find(|h| h.ip == ip) {
+            row.finished = Some(stopped);
+        }
+    }
+
+    fn run(&self, scanner: &Scanner, progress: &Progress, mut tty: Tty, stop: &AtomicBool) {
+        let mut drawn: Option<Instant> = None;
+        while !stop.load(Ordering::Relaxed) {
+            // waits a little for a key, so this doesn't spin
+            if event::poll(KEY_WAIT).unwrap_or(false) {
+                if let Ok(Event::Key(key)) = event::read() {
+                    if let Some(key) = Key::from_event(key) {
+                        self.press(key, scanner);
+                        drawn = None;
+                    }
+                }
+            }
+            if drawn.is_none_or(|at| at.elapsed() >= REDRAW) {
+                let now = progress.snapshot();
+                let view = self.view();
+                let _ = tty.terminal.draw(|frame| view.draw(frame, &now));
+                drawn = Some(Instant::now());
+            }
+        }
+    }
+
+    fn press(&self, key: Key, scanner: &Scanner) {
+        let mut view = self.view();
+        match key {
+            Key::Pause => {
+                view.paused = !view.paused;
+                scanner.set_paused(view.paused);
+            }
+            Key::Skip => {
+                if let Some(i) = view.selected() {
+                    view.hosts[i].skip.store(true, Ordering::SeqCst);
+                }
+            }
+            // once to let the probes in flight finish, again to drop them
+            Key::Quit if view.quitting => scanner.cancel_flag().store(true, Ordering::SeqCst),
+            Key::Quit => {
+                view.quitting = true;
+                scanner.stop_flag().store(true, Ordering::SeqCst);
+            }
+            Key::Up => view.select(-1),
+            Key::Down => view.select(1),
+        }
+    }
+}
+
+/// where the console log layer writes, see `log::Console`
+impl<'a> MakeWriter<'a> for Dashboard {
+    type Writer = &'a Dashboard;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self
+    }
+}
+
+/// each write is one whole log line from the layer
+impl Write for &Dashboard {
+    fn write(&mut self, line: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(line);
+        let text = text.trim_end_matches('\n');
+        let mut view = self.view();
+        if !view.showing {
+            drop(view);
+            eprintln!("{text}");
+            return Ok(line.len());
+        }
+        if view.messages.len() == MESSAGES {
+            view.messages.pop_front();
+        }
+        view.messages.push_back(text.to_string());
+        Ok(line.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl View {
+    /// the row of the selected host, or of the first one still being
+    /// scanned
+    fn selected(&self) -> Option<usize> {
+        let scanning = self.scanning();
+        self.selected
+            .and_then(|ip| scanning.iter().copied().find(|&i| self.hosts[i].ip == ip))
+            .or(scanning.first().copied())
+    }
+
+    fn select(&mut self, step: isize) {
+        let scanning = self.scanning();
+        let Some(last) = scanning.len().checked_sub(1) else {
+            return;
+        };
+        let at = self
+            .selected()
+            .and_then(|i| scanning.iter().position(|&s| s == i))
+            .unwrap_or(0);
+        let next = at.saturating_add_signed(step).min(last);
+        self.selected = Some(self.hosts[scanning[next]].ip);
+    }
+
+    fn scanning(&self) -> Vec<usize> {
+        (0..self.hosts.len())
+            .filter(|&i| self.hosts[i].finished.is_none())
+            .collect()
+    }
+
+    /// the whole screen
+    fn draw(&self, frame: &mut Frame, now: &ProgressSnapshot) {
+        let clock = |d: Duration| {
+            let secs = d.as_secs();
+            format!("{:02}:{:02}", secs / 60, secs % 60)
+        };
+        let mut header = format!(
+            "pscan  {}/{} hosts  {}/{} ports  {} probes/s  eta {}  {}",
+            now.hosts_done,
+            now.hosts_total,
+            now.probes_done,
+            now.probes_total,
+            now.rate.map_or("--".to_string(), |r| format!("{r:.0}")),
+            now.eta.map_or("--".to_string(), clock),
+            clock(now.elapsed)
+        );
+        if self.quitting {
+            header += "  quitting...";
+        } else if self.paused {
+            header += "  paused";
+        }
+
+        // hosts being scanned first, then the latest finished
+        let rows: Vec<usize> = self
+            .scanning()
+            .into_iter()
+            .chain(
+                (0..self.hosts.len())
+                    .rev()
+                    .filter(|&i| self.hosts[i].finished.is_some()),
+            )
+            .collect();
+        let room = (usize::from(frame.area().height) / 3).max(3);
+        let shown = if rows.len() > room {
+            room - 1
+        } else {
+            rows.len()
+        };
+        let more = rows.len() > shown;
+        let messages = self.messages.len().min(SHOWN_MESSAGES);
+        let [top, hosts, ports, log, keys] = Layout::vertical([
+            Constraint::Length(2),
+            Constraint::Length((shown + usize::from(more) + 1) as u16),
+            Constraint::Fill(1),
+            Constraint::Length(messages as u16),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        frame.render_widget(Paragraph::new(header), top);
+
+        let ip_width = rows[..shown]
+            .iter()
+            .map(|&i| self.hosts[i].ip.to_string().len())
+            .max()
+            .unwrap_or(0);
+        let lines =
+            Layout::vertical(vec![Constraint::Length(1); shown + usize::from(more)]).split(hosts);
+        let selected = self.selected();
+        for (&i, &line) in rows[..shown].iter().zip(lines.iter()) {
+            let host = &self.hosts[i];
+            let marker = if selected == Some(i) { '>' } else { ' ' };
+            let ratio = match host.ports {
+                0 => 1.0,
+                n => host.done.min(n) as f64 / n as f64,
+            };
+            let status = match host.finished {
+                None if host.skip.load(Ordering::Relaxed) => "skipping...",
+                None => "",
+                Some(None) => "done",
+                Some(Some(Stopped::Cancelled)) => "cancelled",
+                Some(Some(Stopped::Interrupted)) => "stopped",
+                Some(Some(Stopped::HostDown)) => "looks down",
+                Some(Some(Stopped::HostTimeout)) => "out of time",
+                Some(Some(Stopped::EnoughOpen)) => "enough open",
+                Some(Some(Stopped::Skipped)) => "skipped",
+            };
+            let [ip, bar, tally] = Layout::horizontal([
+                Constraint::Length(ip_width as u16 + 3),
+                Constraint::Length(BAR + 6),
+                Constraint::Fill(1),
+            ])
+            .areas(line);
+            frame.render_widget(Paragraph::new(format!("{marker} {}", host.ip)), ip);
+            frame.render_widget(
+                LineGauge::default()
+                    .ratio(ratio)
+                    .label(format!("{:>3}%", (ratio * 100.0) as u8))
+                    .filled_style(Style::new().fg(Color::Green)),
+                bar,
+            );
+            frame.render_widget(
+                Paragraph::new(format!("  {} open  {status}", host.open)),
+                tally,
+            );
+        }
+        if more {
+            frame.render_widget(
+                Paragraph::new(format!("  ... and {} more", rows.len() - shown)),
+                lines[shown],
+            );
+        }
+
+        // the latest found that fit, under the block's title and the header
+        let fit = usize::from(ports.height.saturating_sub(2));
+        let open = Style::new().fg(Color::Green);
+        let found = self.open[self.open.len().saturating_sub(fit)..]
+            .iter()
+            .map(|port| {
+                Row::new([
+                    Cell::from(port.ip.to_string()),
+                    Cell::from(format!("{}/{}", port.port, self.protocol)),
+                    Cell::from("open").style(open),
+                    Cell::from(port.service.clone().unwrap_or_default()),
+                    Cell::from(port.latency.map(|l| format!("{l:.1?}")).unwrap_or_default()),
+                ])
+            });
+        let ip_width = self
+            .open
+            .iter()
+            .map(|p| p.ip.to_string().len())
+            .max()
+            .unwrap_or(0)
+            .max("host".len());
+        let table = Table::new(
+            found,
+            [
+                Constraint::Length(ip_width as u16),
+                Constraint::Length(11),
+                Constraint::Length(5),
+                Constraint::Length(15),
+                Constraint::Fill(1),
+            ],
+        )
+        .flex(Flex::Legacy)
+        .header(Row::new(["host", "port", "state", "service", "latency"]).bold())
+        .block(
+            Block::new()
+                .borders(Borders::TOP)
+                .title(format!(" open ports ({}) ", self.open.len())),
+        );
+        frame.render_widget(table, ports);
+
+        let shown: Vec<Line> = self
+            .messages
+            .iter()
+            .skip(self.messages.len() - messages)
+            .map(|m| Line::raw(m.as_str()))
+            .collect();
+        frame.render_widget(Paragraph::new(shown), log);
+
+        let help = if self.paused {
+            " p resume  s skip host  up/down select  q quit "
+        } else {
+            " p pause  s skip host  up/down select  q quit "
+        };
+        frame.render_widget(Paragraph::new(help).reversed(), keys);
+    }
+}
+
+impl Key {
+    /// what a key press does, if anything
+    fn from_event(key: KeyEvent) -> Option<Key> {
+        if key.kind != KeyEventKind::Press {
+            return None;
+        }
+        match key.code {
+            // the terminal is raw, so ctrl-c comes as a key: it quits as
+            // it would otherwise
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Some(Key::Quit),
+            KeyCode::Up | KeyCode::Char('k') => Some(Key::Up),
+            KeyCode::Down | KeyCode::Char('j') => Some(Key::Down),
+            KeyCode::Char('p' | ' ') => Some(Key::Pause),
+            KeyCode::Char('s') => Some(Key::Skip),
+            KeyCode::Char('q') => Some(Key::Quit),
+            _ => None,
+        }
+    }
+}
+
+/// the terminal taken over by `Dashboard::show`. dropping it gives the
+/// terminal back, then prints the log messages shown and the stdout held
+/// back meanwhile.
+pub struct Screen {
+    dashboard: Dashboard,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let (messages, held) = {
+            let mut view = self.dashboard.view();
+            view.showing = false;
+            (
+                std::mem::take(&mut view.messages),
+                std::mem::take(&mut view.held),
+            )
+        };
+        let mut stderr = io::stderr().lock();
+        for line in messages {
+            let _ = writeln!(stderr, "{line}");
+        }
+        let mut stdout = io::stdout().lock();
+        let _ = stdout.write_all(&held);
+        let _ = stdout.flush();
+    }
+}
+
+/// see `Dashboard::stdout`
+pub struct HeldStdout(Dashboard);
+
+impl Write for HeldStdout {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut view = self.0.view();
+        if view.showing {
+            view.held.extend_from_slice(buf);
+            Ok(buf.len())
+        } else {
+            drop(view);
+            io::stdout().write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.0.view().showing {
+            Ok(())
+        } else {
+            io::stdout().flush()
+        }
+    }
+}
+
+/// the terminal the screen is drawn on: raw, so keys come as they are
+/// pressed, and on the alternate screen with the cursor hidden. all of it
+/// is undone when dropped.
+struct Tty {
+    terminal: Terminal<CrosstermBackend<sys::Out>>,
+}
+
+impl Tty {
+    fn open() -> io::Result<Self> {
+        let terminal = Terminal::new(CrosstermBackend::new(sys::out()?))?;
+        terminal::enable_raw_mode()?;
+        // from here on, dropping it puts the terminal back
+        let mut tty = Tty { terminal };
+        execute!(
+            tty.terminal.backend_mut(),
+            EnterAlternateScreen,
+            Clear(ClearType::All),
+            cursor::Hide
+        )?;
+        Ok(tty)
+    }
+}
+
+impl Drop for Tty {
+    fn drop(&mut self) {
+        let _ = execute!(
+            self.terminal.backend_mut(),
+            cursor::Show,
+            LeaveAlternateScreen
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// where the screen is written: the controlling terminal, whatever stdout
+/// and stderr are redirected to
+#[cfg(unix)]
+mod sys {
+    use std::fs::{File, OpenOptions};
+    use std::io;
+
+    pub(super) type Out = File;
+
+    pub(super) fn out() -> io::Result<Out> {
+        OpenOptions::new().write(true).open("/dev/tty")
+    }
+}
+
+#[cfg(not(unix))]
+mod sys {
+    use std::io;
+
+    pub(super) type Out = io::Stderr;
+
+    pub(super) fn out() -> io::Result<Out> {
+        Ok(io::stderr())
+    }
+}
@@ -0,0 +1,175 @@
+use crate::timing::Pacer;
+use crate::transport::{udp_from, Local};
+use crate::{trace_attempt, PortState, Protocol, RetryPolicy, ScanResult, Transport};
+use std::io::{self, ErrorKind};
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+
+/// largest reply kept from a udp port
+pub(crate) const MAX_REPLY: usize = 1500;
+
+/// requests the usual service on a port answers; most udp services stay
+/// silent when they get something they don't understand
+const PAYLOADS: &[(u16, &[u8])] = &[
+    // dns: root NS query
+    (
+        53,
+        b"\x12\x34\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00\x00\x02\x00\x01",
+    ),
+    // tftp: read request
+    (69, b"\x00\x01pscan.txt\x00octet\x00"),
+    // sunrpc: portmapper NULL call
+    (
+        111,
+        b"\x72\xfe\x1d\x13\x00\x00\x00\x00\x00\x00\x00\x02\x00\x01\x86\xa0\x00\x00\x00\x02\
+\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00",
+    ),
+    // ntp: v4 client request
+    (
+        123,
+        b"\xe3\x00\x04\xfa\x00\x01\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\
+\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\
+\x00\x00\x00\x00\x00\x00\x00\x00",
+    ),
+    // netbios-ns: node status query for '*'
+    (
+        137,
+        b"\x80\xf0\x00\x10\x00\x01\x00\x00\x00\x00\x00\x00\x20CKAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA\
+\x00\x00\x21\x00\x01",
+    ),
+    // snmp: v1 get sysDescr.0, community "public"
+    (
+        161,
+        b"\x30\x26\x02\x01\x00\x04\x06public\xa0\x19\x02\x01\x01\x02\x01\x00\x02\x01\x00\
+\x30\x0e\x30\x0c\x06\x08\x2b\x06\x01\x02\x01\x01\x01\x00\x05\x00",
+    ),
+    // ssdp: discover everything
+    (
+        1900,
+        b"M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\n\
+MX: 1\r\nST: ssdp:all\r\n\r\n",
+    ),
+    // sip: OPTIONS
+    (
+        5060,
+        b"OPTIONS sip:pscan SIP/2.0\r\nVia: SIP/2.0/UDP pscan;branch=z9hG4bK-pscan\r\n\
+From: <sip:pscan@pscan>;tag=pscan\r\nTo: <sip:pscan@pscan>\r\nCall-ID: pscan\r\n\
+CSeq: 1 OPTIONS\r\nMax-Forwards: 70\r\nContent-Length: 0\r\n\r\n",
+    ),
+    // mdns: service enumeration
+    (
+        5353,
+        b"\x00\x00\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x09_services\x07_dns-sd\x04_udp\
+\x05local\x00\x00\x0c\x00\x01",
+    ),
+    // memcached: stats, behind the udp frame header
+    (11211, b"\x00\x01\x00\x00\x00\x01\x00\x00stats\r\n"),
+];
+
+/// the datagram sent to a udp port: a protocol request for well-known
+/// services, empty for everything else
+pub fn udp_payload(port: u16) -> &'static [u8] {
+    PAYLOADS
+        .iter()
+        .find(|(p, _)| *p == port)
+        .map_or(&[], |(_, payload)| payload)
+}
+
+/// state for a udp probe that got an error back instead of a reply.
+/// a connected udp socket turns icmp port unreachable into
+/// ECONNREFUSED and the other unreachables into EHOSTUNREACH and friends.
+pub(crate) fn state_for_error(kind: ErrorKind) -> PortState {
+    match kind {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => PortState::OpenFiltered,
+        kind => PortState::from_error_kind(kind),
+    }
+}
+
+/// send the port's payload and wait for a reply, resending up to
+/// `retries` times while nothing comes back. the reply is handed back
+/// along with the result.
+pub(crate) fn probe_udp(
+    addr: SocketAddr,
+    transport: &Transport,
+    pacer: &Pacer,
+    timeout: Duration,
+    retry: &RetryPolicy,
+    cancelled: &AtomicBool,
+) -> (ScanResult, Option<Vec<u8>>) {
+    let mut result = ScanResult {
+        protocol: Protocol::Udp,
+        ..ScanResult::new(addr.port(), PortState::OpenFiltered)
+    };
+
+    for attempt in 0..=retry.retries {
+        if attempt > 0 && !retry.wait(attempt, cancelled) {
+            result.state = PortState::Filtered;
+            return (result, None);
+        }
+        if !pacer.wait(cancelled) {
+            result.state = PortState::Filtered;
+            return (result, None);
+        }
+
+        let started = Instant::now();
+        let timeout = retry.timeout(timeout, attempt);
+        let outcome = transport.bound(addr, |local| exchange(addr, local, timeout));
+        let elapsed = started.elapsed();
+
+        let reply = match outcome {
+            Ok(reply) => {
+                result.state = PortState::Open;
+                trace_attempt(addr, attempt, None, result.state);
+                Some(reply)
+            }
+            Err(e) => {
+                result.state = state_for_error(e.kind());
+                trace_attempt(addr, attempt, Some(e.kind()), result.state);
+                None
+            }
+        };
+        result.latency = match result.state {
+            PortState::Open | PortState::Closed => Some(elapsed),
+            _ => None,
+        };
+
+        if !retry.should_retry(result.state) {
+            return (result, reply);
+        }
+    }
+
+    (result, None)
+}
+
+fn exchange(addr: SocketAddr, local: Option<Local>, timeout: Duration) -> io::Result<Vec<u8>> {
+    let socket = match local {
+        Some(local) => udp_from(local)?,
+        None => UdpSocket::bind(match addr {
+            SocketAddr::V4(_) => SocketAddr::from(([0, 0, 0, 0], 0)),
+            SocketAddr::V6(_) => SocketAddr::from(([0u16; 8], 0)),
+        })?,
+    };
+    socket.connect(addr)?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.send(udp_payload(addr.port()))?;
+
+    let mut buf = vec![0u8; MAX_REPLY];
+    let deadline = Instant::now() + timeout;
+    loop {
+        match socket.recv(&mut buf) {
+            Ok(n) => {
+                buf.truncate(n);
+                return Ok(buf);
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    return Err(ErrorKind::TimedOut.into());
+                }
+                socket.set_read_timeout(Some(left))?;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
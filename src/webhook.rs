@@ -0,0 +1,207 @@
+//! posting json to an http(s) endpoint, for --webhook: signed with
+//! hmac-sha256 when there's a secret, retried with backoff when the
+//! endpoint fails. https goes through rustls, like dns over https does.
+
+use crate::{Backoff, RetryPolicy};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// how long one delivery may take to connect, send and get its answer
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// the header carrying the signature: `sha256=` and the hex hmac of the body
+pub const SIGNATURE_HEADER: &str = "X-Pscan-Signature-256";
+
+/// where findings are posted, and how
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    url: String,
+    tls: bool,
+    host: String,
+    port: u16,
+    path: String,
+    secret: Option<String>,
+    retry: RetryPolicy,
+}
+
+impl FromStr for Webhook {
+    type Err = String;
+
+    /// `http://host[:port][/path]` or `https://...`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (tls, rest) = match s.split_once("://") {
+            Some(("http", rest)) => (false, rest),
+            Some(("https", rest)) => (true, rest),
+            _ => return Err(format!("webhook '{s}' must be an http:// or https:// url")),
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let default_port = if tls { 443 } else { 80 };
+        // an ipv6 address comes in brackets, having colons of its own
+        let (host, port) = match authority.strip_prefix('[') {
+            Some(rest) => match rest.split_once(']') {
+                Some((host, "")) => (host, None),
+                Some((host, port)) => (host, port.strip_prefix(':')),
+                None => return Err(format!("unclosed '[' in webhook '{s}'")),
+            },
+            None => match authority.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        let port = match port {
+            Some(port) => port
+                .parse()
+                .map_err(|_| format!("invalid port in webhook '{s}'"))?,
+            None => default_port,
+        };
+        if host.is_empty() {
+            return Err(format!("webhook '{s}' has no host"));
+        }
+        Ok(Webhook {
+            url: s.to_string(),
+            tls,
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+            secret: None,
+            retry: RetryPolicy {
+                retries: 3,
+                backoff: Backoff::Exponential {
+                    base: Duration::from_secs(1),
+                    max: Duration::from_secs(30),
+                },
+                ..RetryPolicy::default()
+            },
+        })
+    }
+}
+
+impl fmt::Display for Webhook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.url)
+    }
+}
+
+impl Webhook {
+    /// sign every delivery with this shared secret
+    pub fn secret(mut self, secret: impl Into<String>) -> Self {
+        self.secret = Some(secret.into());
+        self
+    }
+
+    /// attempts after a failed one, 1s apart and doubling (3 by default)
+    pub fn retries(mut self, retries: u8) -> Self {
+        self.retry.retries = retries;
+        self
+    }
+
+    /// the signature header's value for `body`, when there's a secret
+    pub fn signature(&self, body: &str) -> Option<String> {
+        let secret = self.secret.as_ref()?;
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac takes any key length");
+        mac.update(body.as_bytes());
+        Some(format!(
+            "sha256={}",
+            hex::encode(mac.finalize().into_bytes())
+        ))
+    }
+
+    /// post `body`, trying again while the endpoint can't be reached or
+    /// doesn't answer 2xx; the last failure when it never does
+    pub fn deliver(&self, body: &str) -> io::Result<()> {
+        let mut attempt = 0;
+        loop {
+            match self.post(body) {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt >= self.retry.retries => return Err(e),
+                Err(_) => {
+                    attempt += 1;
+                    std::thread::sleep(self.retry.delay(attempt));
+                }
+            }
+        }
+    }
+
+    /// one attempt
+    pub fn post(&self, body: &str) -> io::Result<()> {
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::other(format!("no address for '{}'", self.host)))?;
+        let host = match (
+            self.host.parse::<Ipv6Addr>(),
+            self.port == self.default_port(),
+        ) {
+            (Ok(_), true) => format!("[{}]", self.host),
+            (Ok(_), false) => format!("[{}]:{}", self.host, self.port),
+            (Err(_), true) => self.host.clone(),
+            (Err(_), false) => format!("{}:{}", self.host, self.port),
+        };
+        let mut request = format!(
+            "POST {} HTTP/1.1\r\nHost: {host}\r\nUser-Agent: pscan/{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.path,
+            env!("CARGO_PKG_VERSION"),
+            body.len()
+        );
+        if let Some(signature) = self.signature(body) {
+            request += &format!("{SIGNATURE_HEADER}: {signature}\r\n");
+        }
+        request += "\r\n";
+        request += body;
+
+        let status = if self.tls {
+            exchange(crate::tls::connect(addr, &self.host, TIMEOUT)?, &request)?
+        } else {
+            exchange(connect(addr)?, &request)?
+        };
+        let code = status.split_whitespace().nth(1).unwrap_or_default();
+        if code.starts_with('2') && code.len() == 3 {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!(
+                "{} answered '{}'",
+                self.url,
+                status.trim_end()
+            )))
+        }
+    }
+
+    fn default_port(&self) -> u16 {
+        if self.tls {
+            443
+        } else {
+            80
+        }
+    }
+}
+
+fn connect(addr: SocketAddr) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    Ok(stream)
+}
+
+/// send the request and return the response's status line
+fn exchange(mut stream: impl Read + Write, request: &str) -> io::Result<String> {
+    stream.write_all(request.as_bytes())?;
+    stream.flush()?;
+    let mut status = String::new();
+    BufReader::new(stream).read_line(&mut status)?;
+    if status.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the webhook closed without answering",
+        ));
+    }
+    Ok(status)
+}
@@ -0,0 +1,99 @@
+//! the server side of websockets (rfc 6455), for the live event stream of
+//! the serve subcommand, on tungstenite. the upgrade request itself goes
+//! through the api's own request handling.
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::protocol::{CloseFrame, Role, WebSocket, WebSocketConfig};
+pub(crate) use tungstenite::Message;
+
+/// the largest frame a client may send; ours are events, theirs are
+/// control frames
+const MAX_CLIENT_FRAME: usize = 64 * 1024;
+
+/// the Sec-WebSocket-Accept answer to a client's Sec-WebSocket-Key
+pub(crate) fn accept_key(key: &str) -> String {
+    tungstenite::handshake::derive_accept_key(key.trim().as_bytes())
+}
+
+/// the sending half of an upgraded connection
+pub(crate) struct Sender(WebSocket<TcpStream>);
+
+/// the receiving half, on a clone of the same connection
+pub(crate) struct Receiver(WebSocket<Inbound>);
+
+/// split an upgraded connection: one thread sends events while another
+/// waits on the client's pings and close
+pub(crate) fn split(stream: TcpStream) -> io::Result<(Sender, Receiver)> {
+    let config = WebSocketConfig::default()
+        .max_frame_size(Some(MAX_CLIENT_FRAME))
+        .max_message_size(Some(MAX_CLIENT_FRAME));
+    let input = Inbound(stream.try_clone()?);
+    Ok((
+        Sender(WebSocket::from_raw_socket(
+            stream,
+            Role::Server,
+            Some(config),
+        )),
+        Receiver(WebSocket::from_raw_socket(
+            input,
+            Role::Server,
+            Some(config),
+        )),
+    ))
+}
+
+impl Sender {
+    pub(crate) fn send(&mut self, message: Message) -> io::Result<()> {
+        self.0.send(message).map_err(io_error)
+    }
+
+    /// a normal close, then nothing more goes out
+    pub(crate) fn close(&mut self) -> io::Result<()> {
+        let frame = CloseFrame {
+            code: CloseCode::Normal,
+            reason: "".into(),
+        };
+        self.0.close(Some(frame)).map_err(io_error)?;
+        match self.0.flush() {
+            Ok(()) | Err(tungstenite::Error::ConnectionClosed) => Ok(()),
+            Err(e) => Err(io_error(e)),
+        }
+    }
+}
+
+impl Receiver {
+    /// the next message, unmasked; an error once the client has closed
+    pub(crate) fn read(&mut self) -> io::Result<Message> {
+        self.0.read().map_err(io_error)
+    }
+}
+
+fn io_error(e: tungstenite::Error) -> io::Error {
+    match e {
+        tungstenite::Error::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    }
+}
+
+/// the connection as the receiving half sees it: whatever tungstenite
+/// would answer by itself (pongs, its half of the close) is dropped, as
+/// the sender answers on the same wire
+struct Inbound(TcpStream);
+
+impl Read for Inbound {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for Inbound {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,47 @@
+use port_scanner::{banner_text, BannerOptions, Engine, Scanner};
+use std::io::Write;
+use std::net::TcpListener;
+use std::time::Duration;
+
+#[test]
+fn banner_text_escapes_control_bytes() {
+    assert_eq!(
+        banner_text(b"SSH-2.0-OpenSSH_9.6\r\n").as_deref(),
+        Some("SSH-2.0-OpenSSH_9.6")
+    );
+    assert_eq!(
+        banner_text(b"220 hi\r\n\x00\xff").as_deref(),
+        Some("220 hi\\r\\n\\x00\\xff")
+    );
+    assert_eq!(banner_text(b" \r\n"), None);
+}
+
+#[test]
+fn both_engines_read_banners() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    std::thread::spawn(move || {
+        for mut conn in listener.incoming().flatten() {
+            let _ = conn.write_all(b"220 test ready\r\n");
+        }
+    });
+
+    let opts = BannerOptions {
+        max_bytes: 64,
+        timeout: Duration::from_millis(500),
+    };
+    for engine in [Engine::Threads, Engine::Async] {
+        let scanner = Scanner::builder()
+            .timeout(Duration::from_millis(200))
+            .engine(engine)
+            .banner(opts)
+            .build();
+        let results = scanner.scan("127.0.0.1".parse().unwrap(), [port]);
+        assert!(results[0].is_open(), "{engine}");
+        assert_eq!(
+            results[0].banner.as_deref(),
+            Some("220 test ready"),
+            "{engine}"
+        );
+    }
+}
@@ -0,0 +1,259 @@
+//! the port_scanner binary, run the way a user would
+
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::time::{Duration, Instant};
+
+/// a config directory for one test, holding `pscan/config.toml`
+fn config_home(name: &str, config: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("port_scanner-cli-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("pscan")).unwrap();
+    std::fs::write(dir.join("pscan").join("config.toml"), config).unwrap();
+    dir
+}
+
+/// run the binary with `args`, reading only the config under `home`
+fn run(home: &PathBuf, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_port_scanner"))
+        .args(args)
+        .env("XDG_CONFIG_HOME", home)
+        .env_remove("PSCAN_CONFIG")
+        .env_remove("PSCAN_ALLOW_TARGETS")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn discover_refuses_targets_the_config_doesnt_allow() {
+    let home = config_home("allow", "[targets]\nallow = [\"10.0.0.0/8\"]\n");
+    let out = run(&home, &["discover", "127.0.0.1", "--timeout-ms", "100"]);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(!out.status.success(), "{stderr}");
+    assert!(
+        stderr.contains("127.0.0.1 isn't among the authorized targets"),
+        "{stderr}"
+    );
+    std::fs::remove_dir_all(home).unwrap();
+}
+
+#[test]
+fn services_file_names_work_in_port_lists_and_reports() {
+    let home = config_home("services", "");
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let services = home.join("services");
+    std::fs::write(&services, format!("site-admin {port}/tcp\n")).unwrap();
+    let out = run(
+        &home,
+        &[
+            "--services-file",
+            services.to_str().unwrap(),
+            "-p",
+            "site-admin",
+            "--format",
+            "json",
+            "127.0.0.1",
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(stdout.contains(&format!("\"port\": {port}")), "{stdout}");
+    assert!(stdout.contains("\"site-admin\""), "{stdout}");
+    std::fs::remove_dir_all(home).unwrap();
+}
+
+#[test]
+fn https_chat_channels_are_accepted() {
+    let home = config_home("chat", "");
+    let closed = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = closed.local_addr().unwrap().port().to_string();
+    drop(closed);
+    let out = run(
+        &home,
+        &[
+            "--chat",
+            "https://hooks.slack.com/services/T0/B0/x",
+            "-p",
+            &port,
+            "127.0.0.1",
+        ],
+    );
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    // the scan runs and only the post itself can fail, offline or with a
+    // made up hook
+    assert_ne!(out.status.code(), Some(2), "{stderr}");
+    assert!(
+        String::from_utf8_lossy(&out.stdout).contains("open ports found: 0"),
+        "{stderr}"
+    );
+    if !out.status.success() {
+        assert!(stderr.contains("slack channel:"), "{stderr}");
+    }
+    std::fs::remove_dir_all(home).unwrap();
+}
+
+#[test]
+fn fail_on_sets_the_exit_status() {
+    let home = config_home("fail-on", "");
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port().to_string();
+    let scan = |extra: &[&str]| {
+        let mut args = vec!["-p", &port, "--timeout-ms", "500", "-q"];
+        args.extend_from_slice(extra);
+        args.push("127.0.0.1");
+        run(&home, &args).status.code()
+    };
+
+    assert_eq!(scan(&[]), Some(0));
+    assert_eq!(scan(&["--fail-on", "none"]), Some(0));
+    assert_eq!(scan(&["--fail-on", "open"]), Some(2));
+    assert_eq!(scan(&["--fail-on", "maybe"]), Some(2), "a usage error");
+
+    let baseline = home.join("baseline.json");
+    let baseline = baseline.to_str().unwrap();
+    assert_eq!(scan(&["-o", baseline]), Some(0));
+    let change = ["--fail-on", "change", "--baseline", baseline];
+    assert_eq!(scan(&change), Some(0));
+    // change can't tell without something to compare with
+    assert_eq!(scan(&["--fail-on", "change"]), Some(1));
+    drop(listener);
+    assert_eq!(scan(&change), Some(2));
+    assert_eq!(scan(&["--fail-on", "open"]), Some(0));
+    std::fs::remove_dir_all(home).unwrap();
+}
+
+#[test]
+fn wait_exits_once_the_port_is_up() {
+    let home = config_home("wait-up", "");
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port().to_string();
+    let out = run(
+        &home,
+        &["wait", "-H", "127.0.0.1", "-p", &port, "--timeout", "5s"],
+    );
+    assert_eq!(
+        out.status.code(),
+        Some(0),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    std::fs::remove_dir_all(home).unwrap();
+}
+
+#[test]
+fn wait_times_out_on_a_closed_port() {
+    let home = config_home("wait-closed", "");
+    let port = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port().to_string()
+    };
+    let out = run(
+        &home,
+        &[
+            "wait",
+            "-H",
+            "127.0.0.1",
+            "-p",
+            &port,
+            "--timeout",
+            "1s",
+            "--interval",
+            "200ms",
+        ],
+    );
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert_eq!(out.status.code(), Some(1), "{stderr}");
+    assert!(stderr.contains("timed out"), "{stderr}");
+    assert!(
+        stderr.contains(&format!("waiting for 127.0.0.1:{port}")),
+        "{stderr}"
+    );
+    std::fs::remove_dir_all(home).unwrap();
+}
+
+#[test]
+fn wait_retries_until_the_port_comes_up() {
+    let home = config_home("wait-late", "");
+    let port = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    };
+    let (done, finished) = std::sync::mpsc::channel::<()>();
+    let late = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_secs(1));
+        let listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+        let _ = finished.recv();
+        drop(listener);
+    });
+    let started = Instant::now();
+    let out = run(
+        &home,
+        &[
+            "wait",
+            "-H",
+            "127.0.0.1",
+            "-p",
+            &port.to_string(),
+            "--timeout",
+            "10s",
+            "--interval",
+            "200ms",
+        ],
+    );
+    done.send(()).unwrap();
+    late.join().unwrap();
+    assert_eq!(
+        out.status.code(),
+        Some(0),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(started.elapsed() >= Duration::from_secs(1));
+    std::fs::remove_dir_all(home).unwrap();
+}
+
+/// a listener whose accept queue is full: linux drops further syns, so
+/// probes to it time out like a firewalled port
+fn black_hole() -> (TcpListener, Vec<TcpStream>, u16) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut queued = Vec::new();
+    while let Ok(stream) = TcpStream::connect_timeout(&addr, Duration::from_millis(200)) {
+        queued.push(stream);
+    }
+    (listener, queued, addr.port())
+}
+
+#[test]
+fn max_scan_time_stops_the_scan_with_its_own_status() {
+    let home = config_home("max-scan-time", "");
+    let (_hole, _queued, port) = black_hole();
+    let out = run(
+        &home,
+        &[
+            "-p",
+            &port.to_string(),
+            "--timeout-ms",
+            "3000",
+            "--max-scan-time",
+            "1s",
+            "--format",
+            "json",
+            "127.0.0.1",
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert_eq!(
+        out.status.code(),
+        Some(3),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(stdout.contains(r#""cancelled": true"#), "{stdout}");
+    std::fs::remove_dir_all(home).unwrap();
+}
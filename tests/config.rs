@@ -0,0 +1,197 @@
+use port_scanner::{Config, Profile, ScanHost, Timing, BUILTIN_PROFILES};
+
+fn host(ip: &str, names: &[&str]) -> ScanHost {
+    ScanHost {
+        ip: ip.parse().unwrap(),
+        names: names.iter().map(|n| n.to_string()).collect(),
+        ptr: None,
+        scope_id: 0,
+    }
+}
+
+#[test]
+fn config_from_toml() {
+    let config = Config::parse(
+        r#"
+        timeout_ms = 200
+        threads = 16
+        format = "json"
+        webhooks = ["http://hooks.example.com/pscan", "http://other.example.com/"]
+
+        [targets]
+        allow = ["10.0.0.0/8", "scanme.example.com"]
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.timeout_ms, Some(200));
+    assert_eq!(config.threads, Some(16));
+    assert_eq!(config.format.as_deref(), Some("json"));
+    assert_eq!(config.webhooks.len(), 2);
+
+    assert!(config.authorizes(&host("10.1.2.3", &["10.0.0.0/8"])));
+    assert!(config.authorizes(&host("203.0.113.7", &["scanme.example.com"])));
+    assert!(!config.authorizes(&host("192.168.1.1", &["192.168.1.1"])));
+    // without an allow list anything goes
+    assert!(Config::default().authorizes(&host("192.168.1.1", &[])));
+
+    for (text, error) in [
+        ("timeout_ms = 0\n", "timeout_ms must be at least 1"),
+        ("threads = \"8\"\n", "line 1: invalid type: string"),
+        ("format = \"yaml\"\n", "format: unknown format 'yaml'"),
+        ("webhooks = [\"ftp://x\"]\n", "webhooks[0]:"),
+        (
+            "[targets]\nallow = [\"10.0.0.0/33\"]\n",
+            "targets.allow[0]:",
+        ),
+        (
+            "[targets]\nallow = [\"ok\", 7]\n",
+            "line 2: invalid type: integer `7`, expected a string",
+        ),
+        ("[targets]\ndeny = []\n", "line 2: unknown field `deny`"),
+        ("timeout = 5\n", "unknown field `timeout`"),
+    ] {
+        let err = Config::parse(text).unwrap_err();
+        assert!(err.contains(error), "{err}");
+    }
+}
+
+#[test]
+fn later_configs_override_earlier_ones() {
+    let system = Config::parse(
+        "timeout_ms = 500\nthreads = 4\nwebhooks = \"http://a.example.com/\"\n\
+         [targets]\nallow = \"10.0.0.0/8\"\n",
+    )
+    .unwrap();
+    let user = Config::parse("timeout_ms = 100\nformat = \"csv\"\n").unwrap();
+    let config = system.merge(user);
+    assert_eq!(config.timeout_ms, Some(100));
+    assert_eq!(config.threads, Some(4));
+    assert_eq!(config.format.as_deref(), Some("csv"));
+    assert_eq!(config.webhooks.len(), 1);
+    assert!(config.allow.is_some());
+}
+
+#[test]
+fn profiles_come_from_the_config_or_are_built_in() {
+    let config = Config::parse(
+        r#"
+        [profiles.mail]
+        ports = [25, "465", "587"]
+        timing = "polite"
+        timeout_ms = 1500
+        probes = ["banner", "tls"]
+
+        [profiles.web]
+        top_ports = 20
+        "#,
+    )
+    .unwrap();
+    let mail = config.profile("mail").unwrap();
+    assert_eq!(mail.ports, Some("25,465,587".parse().unwrap()));
+    assert_eq!(mail.timing, Some(Timing::Polite));
+    assert_eq!(mail.timeout_ms, Some(1500));
+    assert!(mail.banner && mail.tls_probe && !mail.http_probe);
+
+    // the config's own wins over a built-in one of the same name
+    assert_eq!(config.profile("web").unwrap().top_ports, Some(20));
+    for name in BUILTIN_PROFILES {
+        assert_eq!(Config::default().profile(name).ok(), Profile::builtin(name));
+        assert!(Profile::builtin(name).is_some(), "{name}");
+    }
+    let err = config.profile("nope").unwrap_err();
+    assert!(err.contains("quick, web, db, full, mail)"), "{err}");
+
+    for (text, error) in [
+        ("[profiles.x]\nports = \"1-70000\"\n", "profiles.x.ports:"),
+        ("[profiles.x]\ntiming = \"fast\"\n", "profiles.x.timing:"),
+        (
+            "[profiles.x]\nprobes = [\"ssh\"]\n",
+            "profiles.x.probes[0]: unknown probe 'ssh'",
+        ),
+        ("[profiles.x]\ntop_ports = 0\n", "profiles.x.top_ports 0"),
+        ("[profiles.x]\nports = [22]\ntop_ports = 5\n", "not both"),
+        (
+            "[profiles.x]\nthreads = 5\n",
+            "line 2: unknown field `threads`",
+        ),
+    ] {
+        let err = Config::parse(text).unwrap_err();
+        assert!(err.contains(error), "{err}");
+    }
+}
+
+#[test]
+fn environment_variables_make_a_layer_of_their_own() {
+    let vars = |pairs: &[(&str, &str)]| {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+    };
+    let env = Config::from_vars(vars(&[
+        ("PSCAN_TIMEOUT_MS", "250"),
+        ("PSCAN_FORMAT", "jsonl"),
+        ("PSCAN_PROFILE", "web"),
+        (
+            "PSCAN_WEBHOOKS",
+            "http://a.example.com/, http://b.example.com/",
+        ),
+        ("PSCAN_ALLOW_TARGETS", "10.0.0.0/8,db.internal"),
+        ("PSCAN_CONFIG", "/etc/pscan/other.toml"),
+        ("HOME", "/root"),
+    ]))
+    .unwrap();
+    assert_eq!(env.timeout_ms, Some(250));
+    assert_eq!(env.format.as_deref(), Some("jsonl"));
+    assert_eq!(env.profile.as_deref(), Some("web"));
+    assert_eq!(env.webhooks.len(), 2);
+    assert!(env.authorizes(&host("10.9.9.9", &[])));
+    assert!(env.authorizes(&host("192.168.0.2", &["db.internal"])));
+
+    // over the files, under the command line
+    let file = Config::parse("timeout_ms = 100\nthreads = 8\n").unwrap();
+    let config = file.merge(env);
+    assert_eq!(config.timeout_ms, Some(250));
+    assert_eq!(config.threads, Some(8));
+
+    for (pairs, error) in [
+        (
+            &[("PSCAN_THREADS", "many")][..],
+            "PSCAN_THREADS: threads must be a number",
+        ),
+        (
+            &[("PSCAN_TIMEOUT_MS", "0")][..],
+            "PSCAN_TIMEOUT_MS: timeout_ms must be at least 1",
+        ),
+        (
+            &[("PSCAN_FORMAT", "yaml")][..],
+            "PSCAN_FORMAT: format: unknown format",
+        ),
+    ] {
+        let err = Config::from_vars(vars(pairs)).unwrap_err();
+        assert!(err.contains(error), "{err}");
+    }
+
+    // names that aren't settings are left alone
+    let other = Config::from_vars(vars(&[("PSCAN_VERSION", "1"), ("PSCAN_THREADS", "4")])).unwrap();
+    assert_eq!(other.threads, Some(4));
+}
+
+#[cfg(unix)]
+#[test]
+fn only_pscan_values_have_to_be_utf8() {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    let junk = || OsString::from_vec(b"\xff\xfe".to_vec());
+    let env = Config::from_vars([
+        (OsString::from("LANG_JUNK"), junk()),
+        (OsString::from_vec(b"\xffKEY".to_vec()), junk()),
+        (OsString::from("PSCAN_THREADS"), OsString::from("2")),
+    ])
+    .unwrap();
+    assert_eq!(env.threads, Some(2));
+
+    let err = Config::from_vars([(OsString::from("PSCAN_FORMAT"), junk())]).unwrap_err();
+    assert!(err.contains("PSCAN_FORMAT: not valid utf-8"), "{err}");
+}
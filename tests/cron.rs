@@ -0,0 +1,60 @@
+use port_scanner::Cron;
+use std::time::{Duration, UNIX_EPOCH};
+
+#[test]
+fn cron_expressions_parse() {
+    for ok in [
+        "0 2 * * *",
+        "*/15 9-17 * * mon-fri",
+        "0,30 * 1,15 jan-jun 7",
+        "5/10 * * * *",
+        "@daily",
+        "@Weekly",
+    ] {
+        assert!(ok.parse::<Cron>().is_ok(), "{ok}");
+    }
+    for bad in [
+        "",
+        "* * * *",
+        "60 * * * *",
+        "* 24 * * *",
+        "* * 0 * *",
+        "* * * 13 *",
+        "* * * * 8",
+        "*/0 * * * *",
+        "5-1 * * * *",
+        "* * * smarch *",
+        "@fortnightly",
+    ] {
+        assert!(bad.parse::<Cron>().is_err(), "{bad}");
+    }
+    assert_eq!(
+        "0 2 * * *".parse::<Cron>().unwrap().to_string(),
+        "0 2 * * *"
+    );
+}
+
+#[test]
+fn next_after_lands_on_a_matching_minute() {
+    let start = UNIX_EPOCH + Duration::from_secs(1_700_000_030);
+    let every_quarter: Cron = "*/15 * * * *".parse().unwrap();
+    let next = every_quarter.next_after(start).unwrap();
+    let secs = next.duration_since(UNIX_EPOCH).unwrap().as_secs();
+    assert!(next > start && next <= start + Duration::from_secs(15 * 60));
+    assert_eq!(secs % 60, 0);
+
+    let hourly: Cron = "@hourly".parse().unwrap();
+    let next = hourly.next_after(start).unwrap();
+    assert!(next > start && next <= start + Duration::from_secs(3600));
+    // the next one is an hour on, and a scan that ran late doesn't repeat
+    assert_eq!(
+        hourly.next_after(next).unwrap(),
+        next + Duration::from_secs(3600)
+    );
+
+    assert!("0 0 30 2 *"
+        .parse::<Cron>()
+        .unwrap()
+        .next_after(start)
+        .is_none());
+}
@@ -0,0 +1,137 @@
+use port_scanner::{diff_scans, merge_scans, Change, PortState, SavedScan};
+
+const OLD: &str = r#"{
+  "scanner": "pscan",
+  "parameters": {
+    "targets": ["10.0.0.0/30"],
+    "ports": "1-1024",
+    "show_closed": false
+  },
+  "hosts": [
+    {
+      "ip": "10.0.0.1",
+      "names": ["10.0.0.0/30"],
+      "elapsed_ms": 12.500,
+      "incomplete": false,
+      "open_count": 2,
+      "ports": [
+        {"port": 22, "state": "open", "service": "ssh", "latency_ms": 0.410, "version": {"product":"OpenSSH","version":"8.9","info":null,"os":null,"hostname":null}},
+        {"port": 23, "state": "open", "service": "telnet", "latency_ms": 0.380}
+      ]
+    },
+    {
+      "ip": "10.0.0.2",
+      "names": ["10.0.0.0/30"],
+      "elapsed_ms": 40.000,
+      "incomplete": false,
+      "open_count": 1,
+      "ports": [
+        {"port": 443, "state": "open", "service": "https", "latency_ms": null}
+      ]
+    }
+  ]
+}"#;
+
+const NEW: &str = r#"{"type":"start","time":"2024-05-01T10:00:00Z","targets":["10.0.0.0/30"],"hosts":2,"ports":"1-1024","port_count":1024}
+{"type":"port","time":"2024-05-01T10:00:00Z","ip":"10.0.0.1","names":["10.0.0.0/30"],"port":22,"state":"open","service":"ssh","latency_ms":0.400,"version":{"product":"OpenSSH","version":"9.6","info":null,"os":null,"hostname":null}}
+{"type":"port","time":"2024-05-01T10:00:00Z","ip":"10.0.0.1","names":["10.0.0.0/30"],"port":80,"state":"open","service":"http","latency_ms":0.300}
+{"type":"host","time":"2024-05-01T10:00:01Z","ip":"10.0.0.1","names":["10.0.0.0/30"],"open_count":2,"elapsed_ms":11.000,"incomplete":false}
+{"type":"host","time":"2024-05-01T10:00:01Z","ip":"10.0.0.2","names":["10.0.0.0/30"],"open_count":0,"elapsed_ms":9.000,"incomplete":true}
+{"type":"summary","time":"2024-05-01T10:00:01Z","hosts_scanned":2,"hosts_total":2,"hosts_with_open":1,"open_ports":2,"elapsed_ms":20.000,"cancelled":true}
+"#;
+
+#[test]
+fn json_and_jsonl_reports_load() {
+    let old = SavedScan::parse(OLD).unwrap();
+    assert_eq!(old.hosts.len(), 2);
+    let host = &old.hosts[0];
+    assert_eq!(host.host.ip.to_string(), "10.0.0.1");
+    assert_eq!(host.results[0].state, PortState::Open);
+    assert_eq!(
+        host.results[0].service.as_ref().unwrap().summary(),
+        "OpenSSH 8.9"
+    );
+    assert!(old.covers(1024) && !old.covers(8080));
+
+    let new = SavedScan::parse(NEW).unwrap();
+    assert_eq!(new.hosts.len(), 2);
+    assert_eq!(new.hosts[0].results.len(), 2);
+    assert!(new.hosts[1].incomplete);
+
+    assert!(SavedScan::parse("{\"hosts\": [").is_err());
+    assert!(SavedScan::parse("{}").is_err());
+}
+
+#[test]
+fn diff_finds_opened_closed_and_changed_ports() {
+    let old = SavedScan::parse(OLD).unwrap();
+    let new = SavedScan::parse(NEW).unwrap();
+    let changes: Vec<(String, u16, Change)> = diff_scans(&old, &new)
+        .into_iter()
+        .map(|c| (c.ip.to_string(), c.port, c.change))
+        .collect();
+    // 10.0.0.2 was cut short, so its missing 443 isn't a closure
+    assert_eq!(
+        changes,
+        [
+            ("10.0.0.1".to_string(), 22, Change::ServiceChanged),
+            ("10.0.0.1".to_string(), 23, Change::Closed),
+            ("10.0.0.1".to_string(), 80, Change::Opened),
+        ]
+    );
+    assert!(diff_scans(&old, &old).is_empty());
+}
+
+#[test]
+fn checkpoints_keep_the_command_and_unfinished_hosts() {
+    let text = format!(
+        "{}\n{}",
+        r#"{"type":"checkpoint","args":["port_scanner","10.0.0.0/30","-p","1-1024"]}"#,
+        NEW.lines().take(3).collect::<Vec<_>>().join("\n")
+    );
+    let saved = SavedScan::parse(&text).unwrap();
+    assert_eq!(
+        saved.command,
+        ["port_scanner", "10.0.0.0/30", "-p", "1-1024"]
+    );
+    assert_eq!(saved.hosts.len(), 1);
+    // port lines without the host's closing line: cut off mid-host
+    assert!(saved.hosts[0].incomplete);
+    assert_eq!(saved.hosts[0].results.len(), 2);
+    assert!(SavedScan::parse(NEW).unwrap().command.is_empty());
+}
+
+#[test]
+fn merge_keeps_the_latest_answer_for_each_port() {
+    let old = SavedScan::parse(OLD).unwrap();
+    let new = SavedScan::parse(NEW).unwrap();
+    // the json report doesn't say when it ran, so the jsonl one is newer
+    // whichever way round they're given
+    for (scans, new_at) in [(vec![old.clone(), new.clone()], 1), (vec![new, old], 0)] {
+        let merged = merge_scans(&scans);
+        let ports: Vec<(String, u16, usize)> = merged
+            .iter()
+            .flat_map(|h| {
+                h.results
+                    .iter()
+                    .map(|(r, i)| (h.host.ip.to_string(), r.port, *i))
+            })
+            .collect();
+        // 23 is gone: the newer scan covered it and didn't list it. the
+        // newer scan was cut short on 10.0.0.2, so the older 443 stays
+        assert_eq!(
+            ports,
+            [
+                ("10.0.0.1".to_string(), 22, new_at),
+                ("10.0.0.1".to_string(), 80, new_at),
+                ("10.0.0.2".to_string(), 443, 1 - new_at),
+            ]
+        );
+        assert_eq!(
+            merged[0].results[0].0.service.as_ref().unwrap().summary(),
+            "OpenSSH 9.6"
+        );
+        assert!(!merged[1].incomplete);
+        assert_eq!(merged[1].sources, [0, 1]);
+    }
+}
@@ -0,0 +1,93 @@
+use port_scanner::{discover, raw_sockets_available, DiscoverOptions, Liveness, MacAddr};
+use std::net::{IpAddr, TcpListener};
+use std::time::Duration;
+
+fn closed_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+#[test]
+fn tcp_pings_count_accepts_and_resets() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let open = listener.local_addr().unwrap().port();
+    let localhost: IpAddr = "127.0.0.1".parse().unwrap();
+    let opts = DiscoverOptions {
+        timeout: Duration::from_millis(300),
+        icmp: false,
+        ..DiscoverOptions::default()
+    };
+
+    let live = discover(
+        &[localhost],
+        &DiscoverOptions {
+            ports: vec![open],
+            ..opts.clone()
+        },
+    )
+    .unwrap();
+    assert_eq!(live.len(), 1);
+    assert_eq!(live[0].ip, localhost);
+    assert_eq!(live[0].reason, Liveness::Accepted(open));
+    assert_eq!(live[0].reason.to_string(), format!("syn-ack/{open}"));
+
+    // a reset is just as good a sign of life
+    let closed = closed_port();
+    let live = discover(
+        &[localhost],
+        &DiscoverOptions {
+            ports: vec![closed],
+            ..opts
+        },
+    )
+    .unwrap();
+    assert_eq!(live[0].reason, Liveness::Refused(closed));
+    drop(listener);
+}
+
+#[test]
+fn echo_sweep_finds_loopback() {
+    if !raw_sockets_available() {
+        eprintln!("no raw sockets here, skipping");
+        return;
+    }
+    let hosts: Vec<IpAddr> = vec!["127.0.0.1".parse().unwrap(), "::1".parse().unwrap()];
+    let opts = DiscoverOptions {
+        ports: vec![closed_port()],
+        timeout: Duration::from_millis(300),
+        ..DiscoverOptions::default()
+    };
+    let live = discover(&hosts, &opts).unwrap();
+    let ips: Vec<IpAddr> = live.iter().map(|h| h.ip).collect();
+    assert_eq!(ips, hosts);
+    assert!(live.iter().all(|h| h.reason == Liveness::EchoReply));
+}
+
+#[test]
+fn arp_leaves_hosts_off_the_lan_to_the_pings() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let open = listener.local_addr().unwrap().port();
+    // loopback is never an ethernet subnet, so only the tcp ping answers
+    let opts = DiscoverOptions {
+        ports: vec![open],
+        timeout: Duration::from_millis(300),
+        icmp: false,
+        arp: true,
+        ..DiscoverOptions::default()
+    };
+    let live = discover(&["127.0.0.1".parse().unwrap()], &opts).unwrap();
+    assert_eq!(live.len(), 1);
+    assert_eq!(live[0].reason, Liveness::Accepted(open));
+    assert_eq!(live[0].mac, None);
+    drop(listener);
+}
+
+#[test]
+fn mac_addresses_display_in_colon_hex() {
+    let mac = MacAddr([0x02, 0xfc, 0, 0x0a, 0xbc, 0xff]);
+    assert_eq!(mac.to_string(), "02:fc:00:0a:bc:ff");
+    assert_eq!(MacAddr::BROADCAST.to_string(), "ff:ff:ff:ff:ff:ff");
+}
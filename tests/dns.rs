@@ -0,0 +1,204 @@
+use port_scanner::{expand_targets_with, parse_dns_server, IpFamily, Resolver, ScanError, Target};
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// a dns server that knows a few names and one reverse, and counts the
+/// questions it was asked
+fn fake_server() -> (SocketAddr, Arc<AtomicUsize>) {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+    let asked = Arc::new(AtomicUsize::new(0));
+    let counter = asked.clone();
+    thread::spawn(move || {
+        let mut buf = [0u8; 512];
+        loop {
+            let (n, from) = socket.recv_from(&mut buf).unwrap();
+            counter.fetch_add(1, Ordering::Relaxed);
+            let query = &buf[..n];
+            let question = &query[12..];
+            let name_end = question.iter().position(|&b| b == 0).unwrap();
+            let qtype = u16::from_be_bytes([question[name_end + 1], question[name_end + 2]]);
+            let name: Vec<String> = {
+                let mut labels = Vec::new();
+                let mut at = 0;
+                while question[at] != 0 {
+                    let len = question[at] as usize;
+                    labels.push(String::from_utf8_lossy(&question[at + 1..at + 1 + len]).into());
+                    at += 1 + len;
+                }
+                labels
+            };
+            let name = name.join(".");
+
+            let mut answers: Vec<(u16, Vec<u8>)> = Vec::new();
+            let mut rcode = 0;
+            match (name.as_str(), qtype) {
+                ("db.internal", 1) => answers.push((1, vec![10, 0, 0, 5])),
+                ("web.internal", 1) => answers.push((1, vec![10, 0, 0, 6])),
+                ("dual.internal", 1) => answers.push((1, vec![10, 0, 0, 8])),
+                ("dual.internal", 28) => {
+                    let mut v6 = [0u8; 16];
+                    v6[15] = 8;
+                    answers.push((28, vec![0xfd; 16]));
+                    answers.push((28, v6.to_vec()));
+                }
+                ("db.internal" | "web.internal", _) => {}
+                ("5.0.0.10.in-addr.arpa", 12) => {
+                    answers.push((12, b"\x05db-01\x07example\x03net\x00".to_vec()))
+                }
+                _ => rcode = 3,
+            }
+
+            let mut reply = query[..2].to_vec();
+            reply.extend_from_slice(&[
+                0x81,
+                0x80 | rcode,
+                0,
+                1,
+                0,
+                answers.len() as u8,
+                0,
+                0,
+                0,
+                0,
+            ]);
+            reply.extend_from_slice(question);
+            for (rtype, data) in answers {
+                reply.extend_from_slice(&[0xc0, 0x0c]);
+                reply.extend_from_slice(&rtype.to_be_bytes());
+                reply.extend_from_slice(&[0, 1, 0, 0, 0, 60]);
+                reply.extend_from_slice(&(data.len() as u16).to_be_bytes());
+                reply.extend_from_slice(&data);
+            }
+            socket.send_to(&reply, from).unwrap();
+        }
+    });
+    (addr, asked)
+}
+
+#[test]
+fn names_resolve_through_the_given_server() {
+    let (server, _) = fake_server();
+    let resolver = Resolver::with_server(server);
+    let ip: IpAddr = "10.0.0.5".parse().unwrap();
+
+    // one a and one aaaa question
+    assert_eq!(resolver.resolve("db.internal").unwrap(), vec![ip]);
+    let targets = vec![Target::Host("10.0.0.9".to_string())];
+    let hosts = expand_targets_with(&targets, false, &resolver).unwrap();
+    assert_eq!(hosts[0].ip, "10.0.0.9".parse::<IpAddr>().unwrap());
+
+    assert_eq!(resolver.reverse(ip).as_deref(), Some("db-01.example.net"));
+    let err = resolver.resolve("nope.internal").unwrap_err();
+    assert!(format!("{err:#}").contains("no such domain"), "{err:#}");
+}
+
+#[test]
+fn bulk_lookups_ask_once_per_name() {
+    let (server, asked) = fake_server();
+    let resolver = Resolver::with_server(server);
+    let targets: Vec<Target> = ["db.internal", "web.internal", "DB.internal", "10.0.0.5"]
+        .iter()
+        .map(|t| Target::Host(t.to_string()))
+        .collect();
+
+    let hosts = expand_targets_with(&targets, false, &resolver).unwrap();
+    let ips: Vec<String> = hosts.iter().map(|h| h.ip.to_string()).collect();
+    assert_eq!(ips, ["10.0.0.5", "10.0.0.6"]);
+    assert_eq!(hosts[0].names, ["db.internal", "DB.internal", "10.0.0.5"]);
+    // an a and an aaaa question per distinct name
+    assert_eq!(asked.load(Ordering::Relaxed), 4);
+
+    // clones share the cache, failures included
+    let again = resolver.clone();
+    assert!(again.resolve("nope.internal").is_err());
+    assert!(again.resolve("web.internal").is_ok());
+    assert!(resolver.resolve("nope.internal").is_err());
+    assert_eq!(asked.load(Ordering::Relaxed), 5);
+}
+
+#[test]
+fn lookups_can_be_limited_to_one_family() {
+    let (server, asked) = fake_server();
+    let v4 = Resolver::with_server(server).family(IpFamily::V4);
+    assert_eq!(v4.resolve("db.internal").unwrap().len(), 1);
+    // no aaaa question
+    assert_eq!(asked.load(Ordering::Relaxed), 1);
+    assert!(v4.resolve("::1").is_err());
+
+    let v6 = Resolver::with_server(server).family(IpFamily::V6);
+    let err = v6.resolve("db.internal").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "no ipv6 addresses found for target 'db.internal'"
+    );
+    assert!(matches!(err, ScanError::ResolveFailed { target, .. } if target == "db.internal"));
+    let net = vec![Target::Network("10.0.0.0/30".parse().unwrap())];
+    assert!(expand_targets_with(&net, false, &v6).is_err());
+
+    // both families: ipv4 first, each ascending, whatever the server's order
+    let any = Resolver::with_server(server);
+    let ips: Vec<String> = any
+        .resolve("dual.internal")
+        .unwrap()
+        .iter()
+        .map(|ip| ip.to_string())
+        .collect();
+    assert_eq!(
+        ips,
+        ["10.0.0.8", "::8", "fdfd:fdfd:fdfd:fdfd:fdfd:fdfd:fdfd:fdfd"]
+    );
+}
+
+#[test]
+fn dns_servers_parse_with_or_without_a_port() {
+    assert_eq!(
+        parse_dns_server("10.0.0.53"),
+        Ok("10.0.0.53:53".parse().unwrap())
+    );
+    assert_eq!(
+        parse_dns_server("[fd00::53]:5353"),
+        Ok("[fd00::53]:5353".parse().unwrap())
+    );
+    assert!(parse_dns_server("dns.internal").is_err());
+}
+
+#[test]
+fn encrypted_servers_default_their_ports() {
+    let dot = Resolver::over_tls("127.0.0.1").unwrap();
+    assert_eq!(dot.server(), Some("127.0.0.1:853".parse().unwrap()));
+    let doh = Resolver::over_https("https://[::1]:8443/resolve").unwrap();
+    assert_eq!(doh.server(), Some("[::1]:8443".parse().unwrap()));
+    assert!(Resolver::over_https("http://127.0.0.1/dns-query").is_err());
+}
+
+#[test]
+fn answers_to_another_question_are_ignored() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let server = socket.local_addr().unwrap();
+    thread::spawn(move || {
+        let mut buf = [0u8; 512];
+        let (n, from) = socket.recv_from(&mut buf).unwrap();
+        let query = &buf[..n];
+        let reply = |question: &[u8], ip: [u8; 4]| {
+            let mut reply = query[..2].to_vec();
+            reply.extend_from_slice(&[0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0]);
+            reply.extend_from_slice(question);
+            reply.extend_from_slice(&[0xc0, 0x0c, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4]);
+            reply.extend_from_slice(&ip);
+            reply
+        };
+        // the right id, but the answer to someone else's question
+        let forged = reply(b"\x04evil\x08internal\x00\x00\x01\x00\x01", [6, 6, 6, 6]);
+        socket.send_to(&forged, from).unwrap();
+        socket
+            .send_to(&reply(&query[12..], [10, 0, 0, 5]), from)
+            .unwrap();
+    });
+
+    let resolver = Resolver::with_server(server).family(IpFamily::V4);
+    let ip: IpAddr = "10.0.0.5".parse().unwrap();
+    assert_eq!(resolver.resolve("db.internal").unwrap(), vec![ip]);
+}
@@ -0,0 +1,116 @@
+use port_scanner::{scan_ip, scan_ip_async, scan_ip_with, Engine, PortState, ScanError, Scanner};
+use std::net::{IpAddr, Ipv4Addr, TcpListener};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+
+const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// one listening port and one port that was free a moment ago
+fn open_and_closed() -> (TcpListener, u16, u16) {
+    let listener = TcpListener::bind((LOCALHOST, 0)).unwrap();
+    let open = listener.local_addr().unwrap().port();
+    let closed = {
+        let tmp = TcpListener::bind((LOCALHOST, 0)).unwrap();
+        tmp.local_addr().unwrap().port()
+    };
+    (listener, open, closed)
+}
+
+#[test]
+fn engine_parse() {
+    assert_eq!("async".parse::<Engine>().unwrap(), Engine::Async);
+    assert_eq!("Threads".parse::<Engine>().unwrap(), Engine::Threads);
+    assert!("tokio".parse::<Engine>().is_err());
+}
+
+#[test]
+fn engines_agree_on_local_ports() {
+    let (_listener, open, closed) = open_and_closed();
+    let timeout = Duration::from_millis(500);
+    let cancelled = Arc::new(AtomicBool::new(false));
+
+    let threads = scan_ip(
+        LOCALHOST,
+        [open, closed],
+        timeout,
+        0,
+        true,
+        2,
+        None,
+        cancelled.clone(),
+    )
+    .unwrap();
+    let async_results =
+        scan_ip_async(LOCALHOST, [open, closed], timeout, 0, 16, None, cancelled).unwrap();
+
+    for results in [threads, async_results] {
+        assert_eq!(results.len(), 2);
+        let state_of = |port| results.iter().find(|r| r.port == port).unwrap().state;
+        assert_eq!(state_of(open), PortState::Open);
+        assert_eq!(state_of(closed), PortState::Closed);
+    }
+}
+
+#[test]
+fn one_scanner_serves_every_ip() {
+    let (_listener, open, closed) = open_and_closed();
+    for engine in [Engine::Threads, Engine::Async] {
+        let scanner = Scanner::builder()
+            .timeout(Duration::from_millis(500))
+            .engine(engine)
+            .concurrency(4)
+            .build();
+        for ip in [LOCALHOST, IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))] {
+            let results = scan_ip_with(&scanner, ip, [open, closed]).unwrap();
+            let state_of = |port| results.iter().find(|r| r.port == port).unwrap().state;
+            assert_eq!(state_of(open), PortState::Open, "{engine}");
+            assert_eq!(state_of(closed), PortState::Closed, "{engine}");
+        }
+    }
+}
+
+#[test]
+fn async_engine_keeps_every_port_of_a_wide_scan() {
+    let (_listener, open, _) = open_and_closed();
+    let cancelled = Arc::new(AtomicBool::new(false));
+
+    // enough in flight for several event loops on a multi-core machine
+    let ports: Vec<u16> = (1..=3000).chain([open]).collect();
+    let results = scan_ip_async(
+        LOCALHOST,
+        ports.clone(),
+        Duration::from_millis(500),
+        0,
+        2048,
+        None,
+        cancelled,
+    )
+    .unwrap();
+
+    let mut seen: Vec<u16> = results.iter().map(|r| r.port).collect();
+    seen.dedup();
+    assert_eq!(seen.len(), ports.len());
+    assert!(results
+        .iter()
+        .any(|r| r.port == open && r.state == PortState::Open));
+}
+
+#[test]
+fn scans_cancelled_up_front_fail() {
+    let cancelled = Arc::new(AtomicBool::new(true));
+    let timeout = Duration::from_millis(50);
+    let threads = scan_ip(
+        LOCALHOST,
+        [1, 2],
+        timeout,
+        0,
+        true,
+        2,
+        None,
+        cancelled.clone(),
+    );
+    assert_eq!(threads.unwrap_err(), ScanError::Cancelled);
+    let async_results = scan_ip_async(LOCALHOST, [1, 2], timeout, 0, 16, None, cancelled);
+    assert_eq!(async_results.unwrap_err(), ScanError::Cancelled);
+}
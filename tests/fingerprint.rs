@@ -0,0 +1,151 @@
+use port_scanner::fingerprint::pattern;
+use port_scanner::{Engine, Scanner, ServiceProbes};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn groups(source: &str, text: &str) -> Option<Vec<Option<String>>> {
+    let p = pattern(source, false, false).unwrap();
+    p.captures(text.as_bytes()).map(|g| {
+        g.iter()
+            .map(|m| m.map(|m| String::from_utf8_lossy(m.as_bytes()).into_owned()))
+            .collect()
+    })
+}
+
+#[test]
+fn pattern_features() {
+    let g = groups(r"^SSH-([\d.]+)-(\w+)_([\w.]+)", "SSH-2.0-OpenSSH_9.6p1 x").unwrap();
+    assert_eq!(g[1].as_deref(), Some("2.0"));
+    assert_eq!(g[2].as_deref(), Some("OpenSSH"));
+    assert_eq!(g[3].as_deref(), Some("9.6p1"));
+
+    // alternation, optional groups, counted repeats, lazy quantifiers
+    assert!(groups("^(?:foo|bar)baz$", "barbaz").is_some());
+    assert!(groups("^(?:foo|bar)baz$", "bazbaz").is_none());
+    let g = groups(r"a(x)?b", "ab").unwrap();
+    assert_eq!(g[1], None);
+    assert!(groups(r"^\d{3}-\d{2,}$", "220-12").is_some());
+    assert!(groups(r"^\d{3}-\d{2,}$", "22-123").is_none());
+    let g = groups("<(.+?)>", "<a><b>").unwrap();
+    assert_eq!(g[1].as_deref(), Some("a"));
+    // unanchored patterns search; '.' stops at newlines unless s is set
+    assert!(groups("ESMTP", "220 mail ESMTP ready").is_some());
+    assert!(groups("^a.b", "a\nb").is_none());
+    assert!(pattern("^a.b", false, true).unwrap().is_match(b"a\nb"));
+    assert!(pattern("^ssh", true, false).unwrap().is_match(b"SSH-2.0"));
+    // binary protocols
+    assert!(pattern(r"^.\0\0\0\x0a", false, true)
+        .unwrap()
+        .is_match(b"J\0\0\0\x0a8.0.36\0"));
+    assert!(groups(r"^[^\r\n]+$", "no newline").is_some());
+}
+
+#[test]
+fn bad_patterns_are_rejected() {
+    for bad in ["(abc", "abc)", "[abc", r"\q", "a{3,1}", "*a", r"\x4"] {
+        assert!(pattern(bad, false, false).is_err(), "{bad}");
+    }
+}
+
+#[test]
+fn builtin_rules_classify_replies() {
+    let probes = ServiceProbes::builtin();
+
+    let m = probes
+        .match_response("NULL", b"SSH-2.0-OpenSSH_9.6p1 Ubuntu-3ubuntu13\r\n")
+        .unwrap();
+    assert_eq!(m.service, "ssh");
+    assert_eq!(m.product.as_deref(), Some("OpenSSH"));
+    assert_eq!(m.version.as_deref(), Some("9.6p1"));
+    assert_eq!(
+        m.summary(),
+        "OpenSSH 9.6p1 (protocol 2.0; Ubuntu-3ubuntu13)"
+    );
+
+    let m = probes
+        .match_response("NULL", b"220 mx.example.com ESMTP Postfix (Debian)\r\n")
+        .unwrap();
+    assert_eq!(m.service, "smtp");
+    assert_eq!(m.hostname.as_deref(), Some("mx.example.com"));
+
+    let m = probes
+        .match_response("NULL", b"J\0\0\0\x0a8.0.36-0ubuntu0.22.04.1\0\x08\0\0\0")
+        .unwrap();
+    assert_eq!(
+        (m.service.as_str(), m.version.as_deref()),
+        ("mysql", Some("8.0.36-0ubuntu0.22.04.1"))
+    );
+
+    let m = probes
+        .match_response(
+            "GetRequest",
+            b"HTTP/1.1 200 OK\r\nServer: nginx/1.25.3\r\n\r\n",
+        )
+        .unwrap();
+    assert_eq!(m.service, "http");
+    assert_eq!(m.product.as_deref(), Some("nginx"));
+    assert_eq!(m.version.as_deref(), Some("1.25.3"));
+
+    assert!(probes.match_response("NULL", b"hello there").is_none());
+    assert!(probes.match_response("NoSuchProbe", b"SSH-2.0-x").is_none());
+}
+
+#[test]
+fn rule_file_errors_name_the_line() {
+    let err = ServiceProbes::parse("match ssh m|^SSH|\n").unwrap_err();
+    assert!(err.starts_with("line 1:"), "{err}");
+    let err = ServiceProbes::parse("Probe TCP NULL q||\n\nmatch ssh m|^(SSH|\n").unwrap_err();
+    assert!(err.starts_with("line 3:"), "{err}");
+    assert!(ServiceProbes::parse("Probe UDP x q||").is_err());
+    assert!(ServiceProbes::parse("Probe TCP NULL q||\nmatch ssh m|x| z/y/").is_err());
+}
+
+#[test]
+fn local_rules_take_precedence() {
+    let mut probes = ServiceProbes::builtin();
+    let local = ServiceProbes::parse(
+        "# a site-specific daemon\n\
+         Probe TCP NULL q||\n\
+         match acme m|^SSH-2\\.0-OpenSSH_([\\w.]+) acme| p/acme gateway/ v/$1/ cpe:/a:acme:gw/\n",
+    )
+    .unwrap();
+    probes.extend(local);
+    let m = probes
+        .match_response("NULL", b"SSH-2.0-OpenSSH_9.6 acme\r\n")
+        .unwrap();
+    assert_eq!(m.service, "acme");
+    assert_eq!(m.product.as_deref(), Some("acme gateway"));
+}
+
+#[test]
+fn scanners_identify_a_listener() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    // says nothing until it gets a request, so only GetRequest can match
+    std::thread::spawn(move || {
+        for mut conn in listener.incoming().flatten() {
+            let mut buf = [0u8; 1024];
+            let _ = conn.set_read_timeout(Some(Duration::from_secs(2)));
+            if conn.read(&mut buf).unwrap_or(0) > 0 {
+                let _ =
+                    conn.write_all(b"HTTP/1.0 200 OK\r\nServer: Apache/2.4.58 (Ubuntu)\r\n\r\n");
+            }
+        }
+    });
+
+    let probes = Arc::new(ServiceProbes::builtin());
+    for engine in [Engine::Threads, Engine::Async] {
+        let scanner = Scanner::builder()
+            .timeout(Duration::from_millis(200))
+            .engine(engine)
+            .version_detect(probes.clone(), Duration::from_millis(300))
+            .build();
+        let results = scanner.scan(addr.ip(), [addr.port()]);
+        let m = results[0].service.as_ref().expect("service detected");
+        assert_eq!(m.product.as_deref(), Some("Apache httpd"));
+        assert_eq!(m.version.as_deref(), Some("2.4.58"));
+        assert_eq!(results[0].service_name(), Some("http"));
+    }
+}
@@ -0,0 +1,51 @@
+use port_scanner::{port_histories, HostRecord, PortRecord, PortState};
+
+fn record(scan_id: i64, ports: &str, open: &[u16]) -> HostRecord {
+    HostRecord {
+        scan_id,
+        started: format!("2026-01-0{scan_id}T02:00:00Z"),
+        ip: "10.0.0.5".parse().unwrap(),
+        names: vec!["10.0.0.5".to_string()],
+        ports: Some(ports.parse().unwrap()),
+        incomplete: false,
+        results: open
+            .iter()
+            .map(|&port| PortRecord {
+                port,
+                state: PortState::Open,
+                service: None,
+                version: None,
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn port_history_follows_scans_that_covered_the_port() {
+    let mut cut_short = record(4, "1-10000", &[]);
+    cut_short.incomplete = true;
+    let records = vec![
+        record(1, "1-10000", &[22]),
+        record(2, "1-10000", &[22, 8080]),
+        // didn't look at 8080
+        record(3, "22", &[22]),
+        cut_short,
+        record(5, "1-10000", &[22]),
+    ];
+    let histories = port_histories(&records);
+    assert_eq!(histories.len(), 2);
+
+    let ssh = &histories[0];
+    assert_eq!(ssh.port, 22);
+    // the incomplete scan didn't store 22, so says nothing about it
+    assert_eq!(ssh.seen.len(), 4);
+    assert_eq!(ssh.open_count(), 4);
+
+    let web = &histories[1];
+    assert_eq!(web.port, 8080);
+    let scans: Vec<i64> = web.seen.iter().map(|s| s.scan_id).collect();
+    assert_eq!(scans, [1, 2, 5]);
+    assert_eq!(web.first_open().unwrap().scan_id, 2);
+    assert_eq!(web.last_open().unwrap().started, "2026-01-02T02:00:00Z");
+    assert!(!web.seen.last().unwrap().is_open());
+}
@@ -0,0 +1,61 @@
+use port_scanner::{http_probe, parse_response, Scanner};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::time::Duration;
+
+#[test]
+fn status_server_and_title() {
+    let raw = b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nserver: nginx/1.25\r\n\r\n\
+<html><head><TITLE>\n  Tom &amp; Jerry's\n  page </TITLE></head></html>";
+    let info = parse_response(raw).unwrap();
+    assert_eq!(info.status, 200);
+    assert_eq!(info.server.as_deref(), Some("nginx/1.25"));
+    assert_eq!(info.title.as_deref(), Some("Tom & Jerry's page"));
+}
+
+#[test]
+fn missing_parts_and_non_http() {
+    let info = parse_response(b"HTTP/1.0 301 Moved\r\nLocation: /x\r\n\r\n").unwrap();
+    assert_eq!((info.status, info.server, info.title), (301, None, None));
+    // truncated before the closing tag still yields a title
+    let info = parse_response(b"HTTP/1.1 200 OK\r\n\r\n<title>cut off").unwrap();
+    assert_eq!(info.title.as_deref(), Some("cut off"));
+    assert!(parse_response(b"SSH-2.0-OpenSSH_9.6\r\n").is_none());
+    assert!(parse_response(b"").is_none());
+}
+
+#[test]
+fn probe_sends_host_header() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        for mut conn in listener.incoming().flatten() {
+            let mut buf = [0u8; 1024];
+            let n = conn.read(&mut buf).unwrap_or(0);
+            let request = String::from_utf8_lossy(&buf[..n]).into_owned();
+            let host = request
+                .lines()
+                .find_map(|l| l.strip_prefix("Host: "))
+                .unwrap_or("")
+                .to_string();
+            let _ = write!(
+                conn,
+                "HTTP/1.1 404 Not Found\r\nServer: test\r\nConnection: close\r\n\r\n<title>{host}</title>"
+            );
+        }
+    });
+
+    let info = http_probe(addr, Some("web.internal"), Duration::from_secs(2)).unwrap();
+    assert_eq!(info.status, 404);
+    assert_eq!(info.title, Some(format!("web.internal:{}", addr.port())));
+
+    let scanner = Scanner::builder()
+        .timeout(Duration::from_millis(200))
+        .http_probe(Duration::from_secs(2))
+        .build();
+    let results = scanner.scan(addr.ip(), [addr.port()]);
+    assert_eq!(
+        results[0].http.as_ref().unwrap().server.as_deref(),
+        Some("test")
+    );
+}
@@ -0,0 +1,122 @@
+use port_scanner::log::{Console, LogFile, LogFormat};
+use port_scanner::{Engine, Scanner};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, TcpListener};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::Level;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::prelude::*;
+
+const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// keeps every line written to it
+#[derive(Clone, Default)]
+struct Capture(Arc<Mutex<Vec<u8>>>);
+
+impl Capture {
+    fn lines(&self) -> Vec<String> {
+        String::from_utf8(self.0.lock().unwrap().clone())
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+impl io::Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for Capture {
+    type Writer = Capture;
+
+    fn make_writer(&'a self) -> Capture {
+        self.clone()
+    }
+}
+
+// a global subscriber is for the whole process, so this is the only test
+// that sets one
+#[test]
+fn scans_emit_host_spans_and_probe_events() {
+    let capture = Capture::default();
+    tracing_subscriber::registry()
+        .with(Console.layer(capture.clone(), Level::TRACE))
+        .init();
+
+    let listener = TcpListener::bind((LOCALHOST, 0)).unwrap();
+    let open = listener.local_addr().unwrap().port();
+    let closed = {
+        let gone = TcpListener::bind((LOCALHOST, 0)).unwrap();
+        gone.local_addr().unwrap().port()
+    };
+    for engine in [Engine::Threads, Engine::Async] {
+        capture.0.lock().unwrap().clear();
+        let scanner = Scanner::builder()
+            .timeout(Duration::from_millis(500))
+            .engine(engine)
+            .concurrency(1)
+            .build();
+        scanner.scan_host(LOCALHOST, [open, closed], |_| {});
+        let lines = capture.lines();
+        // probes may run on worker threads, outside the host's span
+        for probe in [
+            format!("127.0.0.1:{open} attempt 1: answered, open"),
+            format!("127.0.0.1:{closed} attempt 1: connection refused, closed"),
+        ] {
+            assert!(
+                lines
+                    .iter()
+                    .any(|l| l.starts_with("trace: ") && l.ends_with(&probe)),
+                "{lines:?}"
+            );
+        }
+        assert!(
+            lines
+                .last()
+                .unwrap()
+                .starts_with("debug: [host ip=127.0.0.1] 1 open of 2 ports in "),
+            "{lines:?}"
+        );
+    }
+}
+
+#[test]
+fn log_files_rotate_by_size() {
+    let dir = std::env::temp_dir().join(format!("port_scanner-log-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("pscan.log");
+    let file = LogFile::create(&path, LogFormat::Json, 400, 2).unwrap();
+    let subscriber = tracing_subscriber::registry().with(file.layer());
+    tracing::subscriber::with_default(subscriber, || {
+        let _span = tracing::info_span!("host", ip = %"10.0.0.5").entered();
+        for n in 0..20 {
+            tracing::trace!(target: "port_scanner", "probe {n}");
+        }
+    });
+
+    let mut names: Vec<String> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    // the oldest lines are gone, past the two files kept
+    assert_eq!(names, ["pscan.log", "pscan.log.1", "pscan.log.2"]);
+    for name in &names {
+        assert!(std::fs::metadata(dir.join(name)).unwrap().len() <= 400);
+    }
+    let last = std::fs::read_to_string(&path).unwrap();
+    assert!(last.ends_with(
+        "\"level\":\"trace\",\"target\":\"port_scanner\",\"spans\":[{\"name\":\"host\",\"fields\":\"ip=10.0.0.5\"}],\"message\":\"probe 19\"}\n"
+    ), "{last}");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,117 @@
+use port_scanner::{Policy, PortState, ScanHost, ScanResult, Violation};
+
+const POLICY: &str = r#"
+# perimeter
+[default]
+open = []
+
+[hosts."10.0.0.5"]
+open = [80, 443]   # web
+allow = "8000-8100"
+
+[hosts.'db.internal']
+open = ["postgresql"]
+
+[hosts."10.0.0.0/24"]
+open = [22]
+
+[hosts]
+"10.0.0.0/28".open = [22, 2222]
+"#;
+
+fn host(ip: &str, names: &[&str]) -> ScanHost {
+    ScanHost {
+        ip: ip.parse().unwrap(),
+        names: names.iter().map(|n| n.to_string()).collect(),
+        ptr: None,
+        scope_id: 0,
+    }
+}
+
+fn result(port: u16, state: PortState) -> ScanResult {
+    ScanResult::new(port, state)
+}
+
+#[test]
+fn rules_go_to_the_most_specific_match() {
+    let policy = Policy::parse(POLICY).unwrap();
+    assert_eq!(policy.hosts.len(), 4);
+    let open_of = |h: &ScanHost| policy.rule_for(h).unwrap().open.to_string();
+    assert_eq!(open_of(&host("10.0.0.5", &["10.0.0.5"])), "80,443");
+    assert_eq!(open_of(&host("10.0.0.99", &["db.internal"])), "5432");
+    assert_eq!(open_of(&host("10.0.0.9", &[])), "22,2222");
+    assert_eq!(open_of(&host("10.0.0.200", &[])), "22");
+    assert_eq!(open_of(&host("192.0.2.1", &[])), "");
+    assert!(Policy::parse("[hosts.\"10.0.0.1\"]\nopen = [22]")
+        .unwrap()
+        .rule_for(&host("192.0.2.1", &[]))
+        .is_none());
+}
+
+#[test]
+fn check_lists_unexpected_and_missing_ports() {
+    let policy = Policy::parse(POLICY).unwrap();
+    let rule = policy.rule_for(&host("10.0.0.5", &[])).unwrap();
+    let results = [
+        result(22, PortState::Open),
+        result(80, PortState::Open),
+        result(443, PortState::Filtered),
+        result(8080, PortState::Open),
+    ];
+    assert_eq!(
+        rule.check(&results),
+        [
+            (22, Violation::UnexpectedOpen),
+            (443, Violation::NotOpen(Some(PortState::Filtered))),
+        ]
+    );
+    assert_eq!(rule.check(&[]).len(), 2);
+}
+
+#[test]
+fn policies_are_any_valid_toml() {
+    let policy = Policy::parse(
+        r#"
+        default = { open = [22] }
+        hosts."10.0.0.5" = { open = '80,443', allow = """
+8000-8100""" }
+
+        [hosts.'db.internal']
+        open = [5432]
+        "#,
+    )
+    .unwrap();
+    assert_eq!(policy.default.unwrap().open.to_string(), "22");
+    let web = &policy
+        .hosts
+        .iter()
+        .find(|(t, _)| t.to_string() == "10.0.0.5")
+        .unwrap()
+        .1;
+    assert_eq!(web.open.to_string(), "80,443");
+    assert_eq!(web.allow.to_string(), "8000-8100");
+    assert_eq!(policy.hosts.len(), 2);
+}
+
+#[test]
+fn bad_policies_are_refused() {
+    for (text, error) in [
+        ("[default]\nopen = [22", "line 2"),
+        ("[default]\nopen = 22", "expected a port list"),
+        ("[default]\nclosed = [22]", "unknown field `closed`"),
+        (
+            "[default]\nopen = [22]\nopen = [23]",
+            "line 3: duplicate key",
+        ),
+        ("[servers]\nopen = [22]", "unknown field `servers`"),
+        ("[hosts.\"10.0.0.0/99\"]\nopen = [22]", "10.0.0.0/99"),
+        ("[default]\nopen = [70000]", "70000"),
+        (
+            "[default]\nopen = [22] extra",
+            "line 2: unexpected key or value",
+        ),
+    ] {
+        let e = Policy::parse(text).unwrap_err();
+        assert!(e.contains(error), "{text:?}: {e}");
+    }
+}
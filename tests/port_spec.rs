@@ -0,0 +1,105 @@
+use port_scanner::{PortSpec, Rng, ScanError};
+
+#[test]
+fn parse_mixed_list() {
+    let spec: PortSpec = "22,80,443,8000-8002,9000".parse().unwrap();
+    assert_eq!(spec.to_vec(), vec![22, 80, 443, 8000, 8001, 8002, 9000]);
+    assert_eq!(spec.len(), 7);
+}
+
+#[test]
+fn parse_single_port() {
+    let spec: PortSpec = "80".parse().unwrap();
+    assert_eq!(spec.to_vec(), vec![80]);
+}
+
+#[test]
+fn dedups_and_sorts() {
+    let spec: PortSpec = "443,20-25,22,80,24-30".parse().unwrap();
+    assert_eq!(spec.to_string(), "20-30,80,443");
+    assert_eq!(spec.len(), 13);
+}
+
+#[test]
+fn rejects_bad_entries() {
+    assert!("".parse::<PortSpec>().is_err());
+    assert!("22,,80".parse::<PortSpec>().is_err());
+    assert!("0".parse::<PortSpec>().is_err());
+    assert!("nosuchservice".parse::<PortSpec>().is_err());
+    assert!("100-1".parse::<PortSpec>().is_err());
+    assert_eq!(
+        "22,nosuchservice".parse::<PortSpec>(),
+        Err(ScanError::InvalidPortSpec(
+            "unknown port or service name 'nosuchservice'".to_string()
+        ))
+    );
+}
+
+#[test]
+fn parse_service_names() {
+    let spec: PortSpec = "ssh,HTTP,https,postgres,8000-8001".parse().unwrap();
+    assert_eq!(spec.to_vec(), vec![22, 80, 443, 5432, 8000, 8001]);
+}
+
+#[test]
+fn top_ports_are_ranked() {
+    let spec = PortSpec::top(3);
+    assert_eq!(spec.to_vec(), vec![23, 80, 443]);
+    assert_eq!(PortSpec::top(100).len(), 100);
+    assert_eq!(PortSpec::top(1000).len(), 1000);
+    assert_eq!(PortSpec::top_udp(1000).len(), 1000);
+}
+
+#[test]
+fn all_keyword_is_full_range() {
+    for s in ["all", "ALL", "-"] {
+        let spec: PortSpec = s.parse().unwrap();
+        assert_eq!(spec.len(), 65535);
+        assert_eq!(spec.to_string(), "1-65535");
+    }
+}
+
+#[test]
+fn difference_removes_excluded_ports() {
+    let spec: PortSpec = "1-1000".parse().unwrap();
+    let excluded: PortSpec = "25,135-139,445,990-2000".parse().unwrap();
+    let left = spec.difference(&excluded);
+    assert_eq!(left.to_string(), "1-24,26-134,140-444,446-989");
+    assert!(!left.contains(137));
+    assert_eq!(left.len(), 1000 - 1 - 5 - 1 - 11);
+}
+
+#[test]
+fn difference_of_everything_is_empty() {
+    let spec: PortSpec = "22,80".parse().unwrap();
+    assert!(spec.difference(&PortSpec::all()).is_empty());
+}
+
+#[test]
+fn union_merges_specs() {
+    let a: PortSpec = "20-25".parse().unwrap();
+    let b: PortSpec = "26,80".parse().unwrap();
+    assert_eq!(a.union(&b).to_string(), "20-26,80");
+}
+
+#[test]
+fn display_round_trips() {
+    for s in ["22", "1-1024", "21-23,80,443,8000-8100", "1-65535"] {
+        let spec: PortSpec = s.parse().unwrap();
+        assert_eq!(spec.to_string(), s);
+        assert_eq!(spec.to_string().parse::<PortSpec>().unwrap(), spec);
+    }
+}
+
+#[test]
+fn shuffled_is_a_reproducible_permutation() {
+    let spec: PortSpec = "1-1000,8080".parse().unwrap();
+    let order = spec.shuffled(&mut Rng::new(42));
+    assert_eq!(order, spec.shuffled(&mut Rng::new(42)));
+    assert_ne!(order, spec.shuffled(&mut Rng::new(43)));
+    assert_ne!(order, spec.to_vec());
+
+    let mut sorted = order.clone();
+    sorted.sort_unstable();
+    assert_eq!(sorted, spec.to_vec());
+}
@@ -0,0 +1,26 @@
+use port_scanner::PortState;
+use std::io::ErrorKind;
+
+#[test]
+fn refused_is_closed() {
+    assert_eq!(
+        PortState::from_error_kind(ErrorKind::ConnectionRefused),
+        PortState::Closed
+    );
+}
+
+#[test]
+fn timeout_is_filtered() {
+    assert_eq!(
+        PortState::from_error_kind(ErrorKind::TimedOut),
+        PortState::Filtered
+    );
+}
+
+#[test]
+fn other_errors_are_errors() {
+    assert_eq!(
+        PortState::from_error_kind(ErrorKind::PermissionDenied),
+        PortState::Error
+    );
+}
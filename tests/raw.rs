@@ -0,0 +1,87 @@
+use port_scanner::{raw_sockets_available, Engine, PortState, ScanType, Scanner};
+use std::net::TcpListener;
+use std::time::Duration;
+
+#[test]
+fn scan_types_parse() {
+    assert_eq!("syn".parse::<ScanType>(), Ok(ScanType::Syn));
+    assert_eq!(" Connect ".parse::<ScanType>(), Ok(ScanType::Connect));
+    assert_eq!("XMAS".parse::<ScanType>(), Ok(ScanType::Xmas));
+    assert!("xmas-tree".parse::<ScanType>().is_err());
+    assert!(ScanType::Syn.is_raw());
+    assert!(!ScanType::Connect.is_raw());
+}
+
+#[test]
+fn syn_scan_classifies_ports() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let open = listener.local_addr().unwrap().port();
+    let closed = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    // without raw sockets the scan falls back to connect, with the same answers
+    if !raw_sockets_available() {
+        eprintln!("no raw sockets here, checking the connect fallback");
+    }
+    for engine in [Engine::Threads, Engine::Async] {
+        let scanner = Scanner::builder()
+            .scan_type(ScanType::Syn)
+            .engine(engine)
+            .timeout(Duration::from_millis(500))
+            .build();
+        let results = scanner.scan("127.0.0.1".parse().unwrap(), [open, closed]);
+        let state_of = |port| results.iter().find(|r| r.port == port).unwrap().state;
+        assert_eq!(state_of(open), PortState::Open, "{engine}");
+        assert_eq!(state_of(closed), PortState::Closed, "{engine}");
+    }
+    drop(listener);
+}
+
+#[test]
+fn stealth_scans_only_hear_from_closed_ports() {
+    if !raw_sockets_available() {
+        eprintln!("no raw sockets here, skipping");
+        return;
+    }
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let open = listener.local_addr().unwrap().port();
+    let closed = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    for scan_type in [ScanType::Fin, ScanType::Null, ScanType::Xmas] {
+        let scanner = Scanner::builder()
+            .scan_type(scan_type)
+            .timeout(Duration::from_millis(300))
+            .build();
+        let results = scanner.scan("127.0.0.1".parse().unwrap(), [open, closed]);
+        let state_of = |port| results.iter().find(|r| r.port == port).unwrap().state;
+        assert_eq!(state_of(open), PortState::OpenFiltered, "{scan_type}");
+        assert_eq!(state_of(closed), PortState::Closed, "{scan_type}");
+    }
+    drop(listener);
+}
+
+#[test]
+fn ack_scan_reports_unfiltered_ports() {
+    if !raw_sockets_available() {
+        eprintln!("no raw sockets here, skipping");
+        return;
+    }
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let open = listener.local_addr().unwrap().port();
+    let scanner = Scanner::builder()
+        .scan_type(ScanType::Ack)
+        .timeout(Duration::from_millis(300))
+        .build();
+    // nothing filters loopback, and open and closed ports both reset an ack
+    let results = scanner.scan("127.0.0.1".parse().unwrap(), [open, open + 1]);
+    assert!(results.iter().all(|r| r.state == PortState::Unfiltered));
+    assert!(results.iter().all(|r| r.latency.is_some()));
+    drop(listener);
+}
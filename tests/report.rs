@@ -0,0 +1,740 @@
+use port_scanner::report::{self, ColorChoice, HostReport, ReportOptions, Reporter, ScanSummary};
+use port_scanner::{
+    serve_metrics, ChatKind, ChatTarget, Facility, Metrics, PortState, SavedHost, SavedScan,
+    ScanHost, ScanResult, ServiceMatch, SyslogTarget, Webhook, SIGNATURE_HEADER,
+};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// a writer the test can read back after the reporter is done with it
+#[derive(Clone, Default)]
+struct Buf(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Buf {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Buf {
+    fn text(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+fn sample_host() -> ScanHost {
+    ScanHost {
+        ip: "10.0.0.5".parse().unwrap(),
+        names: vec!["db.internal".to_string()],
+        ptr: None,
+        scope_id: 0,
+    }
+}
+
+fn sample_results() -> Vec<ScanResult> {
+    vec![
+        ScanResult {
+            latency: Some(Duration::from_micros(1500)),
+            ..ScanResult::new(22, PortState::Open)
+        },
+        ScanResult {
+            latency: Some(Duration::from_micros(200)),
+            ..ScanResult::new(23, PortState::Closed)
+        },
+        ScanResult::new(25, PortState::Filtered),
+    ]
+}
+
+/// render one host with the given format and return the output
+fn render(format: &str, opts: &ReportOptions) -> String {
+    render_results(format, opts, &sample_results())
+}
+
+fn render_results(format: &str, opts: &ReportOptions, results: &[ScanResult]) -> String {
+    let buf = Buf::default();
+    let mut reporter = report::create(format, Box::new(buf.clone()), opts).unwrap();
+    let host = sample_host();
+    reporter
+        .host(&HostReport {
+            host: &host,
+            results,
+            elapsed: Duration::from_millis(10),
+            incomplete: false,
+        })
+        .unwrap();
+    reporter.finish(&ScanSummary::default()).unwrap();
+    buf.text()
+}
+
+#[test]
+fn unknown_format_is_rejected() {
+    let err = report::create("yaml", Box::new(io::sink()), &ReportOptions::default())
+        .err()
+        .unwrap();
+    assert!(err.contains("table"));
+}
+
+#[test]
+fn table_lists_open_ports_only_by_default() {
+    let out = render("table", &ReportOptions::default());
+    assert!(out.contains("target ip: 10.0.0.5 (db.internal)"));
+    assert!(out.contains("22        open      1.50 ms     ssh"));
+    assert!(!out.contains("closed"));
+    assert!(out.contains("open ports found: 1"));
+    assert!(out.contains("filtered ports  : 1"));
+}
+
+#[test]
+fn table_show_closed_lists_everything() {
+    let opts = ReportOptions {
+        show_closed: true,
+        ..ReportOptions::default()
+    };
+    let out = render("table", &opts);
+    assert!(out.contains("23        closed"));
+    assert!(out.contains("25        filtered  -"));
+}
+
+#[test]
+fn table_colors_states_only_when_asked() {
+    let opts = ReportOptions {
+        show_closed: true,
+        color: true,
+        ..ReportOptions::default()
+    };
+    let out = render("table", &opts);
+    // padded before it is painted, so the columns still line up
+    assert!(
+        out.contains("22        \x1b[32mopen    \x1b[0m  1.50 ms     ssh"),
+        "{out}"
+    );
+    assert!(out.contains("23        \x1b[31mclosed  \x1b[0m"), "{out}");
+    assert!(
+        out.contains("25        \x1b[33mfiltered\x1b[0m  -"),
+        "{out}"
+    );
+    // machine formats never are
+    assert!(!render("csv", &opts).contains('\x1b'));
+
+    assert_eq!("never".parse::<ColorChoice>().unwrap(), ColorChoice::Never);
+    assert!(ColorChoice::Always.enabled(false));
+    assert!(!ColorChoice::Never.enabled(true));
+    assert!(!ColorChoice::Auto.enabled(false));
+    assert!("sometimes".parse::<ColorChoice>().is_err());
+}
+
+#[test]
+fn json_document_has_hosts_and_ports() {
+    let out = render(
+        "json",
+        &ReportOptions {
+            show_closed: true,
+            ..ReportOptions::default()
+        },
+    );
+    let doc: serde_json::Value = serde_json::from_str(&out).unwrap();
+    let host = &doc["hosts"][0];
+    assert_eq!(host["ip"], "10.0.0.5");
+    assert_eq!(host["names"], serde_json::json!(["db.internal"]));
+    assert_eq!(
+        host["ports"][0],
+        serde_json::json!({"port": 22, "state": "open", "service": "ssh", "latency_ms": 1.5})
+    );
+    assert!(out.contains("\"latency_ms\": null"));
+}
+
+#[test]
+fn rfc3339_timestamps() {
+    let t = UNIX_EPOCH + Duration::from_secs(951_782_400 + 3_723);
+    assert_eq!(report::util::rfc3339(t), "2000-02-29T01:02:03Z");
+    assert_eq!(report::util::rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+}
+
+#[test]
+fn jsonl_streams_one_line_per_result() {
+    let buf = Buf::default();
+    let mut reporter =
+        report::create("jsonl", Box::new(buf.clone()), &ReportOptions::default()).unwrap();
+    let host = sample_host();
+    for r in sample_results() {
+        reporter.result(&host, &r).unwrap();
+    }
+
+    let out = buf.text();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 1, "only the open port is streamed: {out}");
+    assert!(lines[0].starts_with("{\"type\":\"port\""));
+    assert!(lines[0].contains("\"port\":22,\"state\":\"open\",\"service\":\"ssh\""));
+}
+
+#[test]
+fn csv_rows_with_header() {
+    let out = render(
+        "csv",
+        &ReportOptions {
+            show_closed: true,
+            ..ReportOptions::default()
+        },
+    );
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines[0], "host,ip,port,state,service,latency_ms");
+    assert_eq!(lines[1], "db.internal,10.0.0.5,22,open,ssh,1.500");
+    assert_eq!(lines[3], "db.internal,10.0.0.5,25,filtered,smtp,");
+}
+
+#[test]
+fn ptr_names_are_added_when_looked_up() {
+    let opts = ReportOptions {
+        ptr: true,
+        ..ReportOptions::default()
+    };
+    let host = ScanHost {
+        ptr: Some("db-01.example.net".to_string()),
+        ..sample_host()
+    };
+    let render_ptr = |format: &str| {
+        let buf = Buf::default();
+        let mut reporter = report::create(format, Box::new(buf.clone()), &opts).unwrap();
+        reporter
+            .host(&HostReport {
+                host: &host,
+                results: &sample_results(),
+                elapsed: Duration::from_millis(10),
+                incomplete: false,
+            })
+            .unwrap();
+        reporter.finish(&ScanSummary::default()).unwrap();
+        buf.text()
+    };
+
+    let csv = render_ptr("csv");
+    assert!(csv.starts_with("host,ip,port,state,service,latency_ms,ptr\n"));
+    assert!(csv.contains("db.internal,10.0.0.5,22,open,ssh,1.500,db-01.example.net\n"));
+    assert!(render_ptr("json").contains("\"ptr\": \"db-01.example.net\","));
+    assert!(render_ptr("nmap-xml").contains("<hostname name=\"db-01.example.net\" type=\"PTR\"/>"));
+    assert!(render_ptr("table").contains("ptr      : db-01.example.net\n"));
+    assert!(render_ptr("grepable").contains("Host: 10.0.0.5 (db-01.example.net)\tStatus: Up"));
+
+    // an ip without a ptr record still gets the field when it was asked for
+    assert!(render("json", &opts).contains("\"ptr\": null,"));
+}
+
+#[test]
+fn csv_quotes_when_needed() {
+    assert_eq!(report::util::csv_field("plain"), "plain");
+    assert_eq!(report::util::csv_field("a,b"), "\"a,b\"");
+    assert_eq!(report::util::csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+}
+
+#[test]
+fn nmap_xml_folds_closed_ports() {
+    let out = render("nmap-xml", &ReportOptions::default());
+    assert!(out.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE nmaprun>"));
+    assert!(out.contains("<address addr=\"10.0.0.5\" addrtype=\"ipv4\"/>"));
+    assert!(out.contains("<hostname name=\"db.internal\" type=\"user\"/>"));
+    assert!(out.contains("<extraports state=\"closed\" count=\"1\">"));
+    assert!(out.contains(
+        "<port protocol=\"tcp\" portid=\"22\"><state state=\"open\" reason=\"syn-ack\" reason_ttl=\"0\"/><service name=\"ssh\" method=\"table\" conf=\"3\"/></port>"
+    ));
+    assert!(!out.contains("portid=\"23\""));
+    assert!(out.trim_end().ends_with("</nmaprun>"));
+}
+
+#[test]
+fn ctime_matches_nmap_style() {
+    let t = UNIX_EPOCH + Duration::from_secs(951_782_400 + 3_723);
+    assert_eq!(report::util::ctime(t), "Tue Feb 29 01:02:03 2000");
+    assert_eq!(report::util::ctime(UNIX_EPOCH), "Thu Jan  1 00:00:00 1970");
+}
+
+#[test]
+fn grepable_host_line() {
+    let out = render("grepable", &ReportOptions::default());
+    assert!(out.contains("Host: 10.0.0.5 (db.internal)\tStatus: Up\n"));
+    assert!(out.contains(
+        "Host: 10.0.0.5 (db.internal)\tPorts: 22/open/tcp//ssh///\tIgnored State: closed (1)\n"
+    ));
+}
+
+#[test]
+fn html_is_standalone_with_sortable_table() {
+    let out = render("html", &ReportOptions::default());
+    assert!(out.starts_with("<!DOCTYPE html>"));
+    assert!(out.contains("<h2>10.0.0.5 <small>(db.internal)</small></h2>"));
+    assert!(out.contains("<td class=\"open\">open</td><td>ssh</td><td>1.50</td>"));
+    assert!(out.contains("<table class=\"ports\">"));
+    assert!(out.contains("<script>"));
+    assert!(out.trim_end().ends_with("</html>"));
+}
+
+#[test]
+fn xml_escape_covers_markup() {
+    assert_eq!(
+        report::util::xml_escape("<a href=\"x\">&'"),
+        "&lt;a href=&quot;x&quot;&gt;&amp;&#39;"
+    );
+}
+
+#[test]
+fn markdown_table_per_host() {
+    let out = render("md", &ReportOptions::default());
+    assert!(out.contains("## 10.0.0.5 (db.internal)\n"));
+    assert!(out.contains("| port | state | service | latency (ms) |\n| ---: | --- | --- | ---: |\n| 22 | open | ssh | 1.50 |\n"));
+    assert!(out.contains("## summary"));
+    assert_eq!(report::util::md_cell("a|b"), "a\\|b");
+}
+
+#[test]
+fn sarif_reports_each_open_port() {
+    let out = render(
+        "sarif",
+        &ReportOptions {
+            show_closed: true,
+            ..ReportOptions::default()
+        },
+    );
+    assert!(out.contains("\"version\": \"2.1.0\""));
+    assert!(out.contains("\"uri\": \"tcp://10.0.0.5:22\""));
+    assert!(out.contains("port 22/tcp (ssh) is open on 10.0.0.5"));
+    assert_eq!(out.matches("\"ruleId\": \"PSCAN001\"").count(), 1);
+}
+
+#[test]
+fn cef_and_leef_have_a_line_per_open_port() {
+    let out = render("cef", &ReportOptions::default());
+    assert_eq!(out.lines().count(), 1, "{out}");
+    assert!(out.starts_with("CEF:0|pscan|pscan|"), "{out}");
+    assert!(out.contains("|port-open|port open|5|rt="), "{out}");
+    assert!(
+        out.contains(" dst=10.0.0.5 dhost=db.internal dpt=22 proto=TCP app=ssh act=open "),
+        "{out}"
+    );
+
+    let out = render(
+        "leef",
+        &ReportOptions {
+            show_closed: true,
+            ..ReportOptions::default()
+        },
+    );
+    assert_eq!(out.lines().count(), 3, "{out}");
+    assert!(out.starts_with("LEEF:1.0|pscan|pscan|"), "{out}");
+    assert!(out.contains("|port-open|devTime="), "{out}");
+    assert!(out.contains("\tdst=10.0.0.5\tdstHostName=db.internal\tdstPort=22\tproto=TCP\tstate=open\tservice=ssh\t"), "{out}");
+    assert!(out.contains("|port-filtered|"), "{out}");
+}
+
+#[test]
+fn format_guessed_from_extension() {
+    use port_scanner::report::format_for_path;
+    use std::path::Path;
+
+    assert_eq!(format_for_path(Path::new("scan.json")), Some("json"));
+    assert_eq!(format_for_path(Path::new("out/scan.XML")), Some("nmap-xml"));
+    assert_eq!(format_for_path(Path::new("scan.gnmap")), Some("grepable"));
+    assert_eq!(format_for_path(Path::new("scan")), None);
+    assert_eq!(format_for_path(Path::new("scan.bin")), None);
+}
+
+#[test]
+fn multi_reporter_writes_to_each() {
+    use port_scanner::report::{MultiReporter, Reporter};
+
+    let (a, b) = (Buf::default(), Buf::default());
+    let opts = ReportOptions::default();
+    let mut multi = MultiReporter::new(vec![
+        report::create("csv", Box::new(a.clone()), &opts).unwrap(),
+        report::create("jsonl", Box::new(b.clone()), &opts).unwrap(),
+    ]);
+    let host = sample_host();
+    let results = sample_results();
+    multi
+        .host(&HostReport {
+            host: &host,
+            results: &results,
+            elapsed: Duration::from_millis(5),
+            incomplete: false,
+        })
+        .unwrap();
+
+    assert!(a.text().starts_with("host,ip,port"));
+    assert!(b.text().contains("\"type\":\"host\""));
+}
+
+#[test]
+fn banners_show_up_in_each_format() {
+    let mut results = sample_results();
+    results[0].banner = Some("SSH-2.0-OpenSSH_9.6".to_string());
+    let opts = ReportOptions {
+        banners: true,
+        ..ReportOptions::default()
+    };
+
+    let table = render_results("table", &opts, &results);
+    assert!(table.contains("ssh           SSH-2.0-OpenSSH_9.6"));
+    let csv = render_results("csv", &opts, &results);
+    assert!(csv.starts_with("host,ip,port,state,service,latency_ms,banner\n"));
+    assert!(csv.contains(",ssh,1.500,SSH-2.0-OpenSSH_9.6\n"));
+    let json = render_results("json", &opts, &results);
+    assert!(json.contains("\"banner\": \"SSH-2.0-OpenSSH_9.6\""));
+    let xml = render_results("nmap-xml", &opts, &results);
+    assert!(xml.contains("<script id=\"banner\" output=\"SSH-2.0-OpenSSH_9.6\"/>"));
+    let grepable = render_results("grepable", &opts, &results);
+    assert!(grepable.contains("22/open/tcp//ssh//SSH-2.0-OpenSSH_9.6/"));
+}
+
+#[test]
+fn detected_versions_replace_the_port_guess() {
+    let mut results = sample_results();
+    results[0].service = Some(ServiceMatch {
+        service: "ssh".to_string(),
+        product: Some("OpenSSH".to_string()),
+        version: Some("9.6p1".to_string()),
+        info: Some("protocol 2.0".to_string()),
+        os: None,
+        hostname: None,
+    });
+    let opts = ReportOptions {
+        versions: true,
+        ..ReportOptions::default()
+    };
+
+    let xml = render_results("nmap-xml", &opts, &results);
+    assert!(xml.contains(
+        "<service name=\"ssh\" product=\"OpenSSH\" version=\"9.6p1\" extrainfo=\"protocol 2.0\" method=\"probed\" conf=\"10\"/>"
+    ));
+    let grepable = render_results("grepable", &opts, &results);
+    assert!(grepable.contains("22/open/tcp//ssh//OpenSSH 9.6p1 (protocol 2.0)/"));
+    let csv = render_results("csv", &opts, &results);
+    assert!(csv.contains(",ssh,1.500,OpenSSH,9.6p1,protocol 2.0\n"));
+    let table = render_results("table", &opts, &results);
+    assert!(table.contains("version: OpenSSH 9.6p1 (protocol 2.0)"));
+}
+
+#[test]
+fn watch_prints_only_what_changed() {
+    let buf = Buf::default();
+    let mut reporter = report::WatchReporter::new(Box::new(buf.clone()), &ReportOptions::default());
+    let host = sample_host();
+    let mut results = sample_results();
+    let mut scan = |results: &[ScanResult]| {
+        reporter
+            .host(&HostReport {
+                host: &host,
+                results,
+                elapsed: Duration::from_millis(10),
+                incomplete: false,
+            })
+            .unwrap();
+    };
+    scan(&results);
+    scan(&results);
+    results[0].state = PortState::Filtered;
+    results[1].state = PortState::Open;
+    scan(&results);
+
+    let text = buf.text();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 3, "{text}");
+    assert!(lines[0].ends_with(" open 10.0.0.5 22/tcp ssh"), "{text}");
+    assert!(
+        lines[1].ends_with(" closed 10.0.0.5 22/tcp (now filtered)"),
+        "{text}"
+    );
+    assert!(
+        lines[2].ends_with(" opened 10.0.0.5 23/tcp telnet"),
+        "{text}"
+    );
+}
+
+#[test]
+fn store_appends_scans_and_reopens() {
+    let path = std::env::temp_dir().join(format!("port_scanner-store-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let opts = ReportOptions::default();
+    let mut store = match report::StoreReporter::open(&path, &opts) {
+        Ok(store) => store,
+        // built without the sqlite feature
+        Err(e) => {
+            assert_eq!(e.kind(), io::ErrorKind::Unsupported, "{e}");
+            return;
+        }
+    };
+    let info = report::ScanInfo {
+        command: "port_scanner 10.0.0.5".to_string(),
+        targets: vec!["10.0.0.5".to_string()],
+        host_count: 1,
+        excluded_hosts: 0,
+        ports: "22-25".to_string(),
+        port_count: 4,
+        excluded_ports: None,
+        timeout_ms: 1000,
+        retries: 0,
+        engine: Default::default(),
+        concurrency: 1,
+        started: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+    };
+    let host = sample_host();
+    let results = sample_results();
+    store.begin(&info).unwrap();
+    store
+        .host(&HostReport {
+            host: &host,
+            results: &results,
+            elapsed: Duration::from_millis(10),
+            incomplete: false,
+        })
+        .unwrap();
+    store.finish(&ScanSummary::default()).unwrap();
+    drop(store);
+
+    // a second scan goes into the same, already migrated, database
+    let mut store = report::StoreReporter::open(&path, &opts).unwrap();
+    store.begin(&info).unwrap();
+    store.finish(&ScanSummary::default()).unwrap();
+    drop(store);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn metrics_follow_the_last_scan() {
+    let metrics = Arc::new(Metrics::new());
+    let mut reporter = report::MetricsReporter::new(metrics.clone());
+    let host = sample_host();
+    let results = sample_results();
+    for _ in 0..2 {
+        reporter
+            .host(&HostReport {
+                host: &host,
+                results: &results,
+                elapsed: Duration::from_millis(10),
+                incomplete: false,
+            })
+            .unwrap();
+        reporter.finish(&ScanSummary::default()).unwrap();
+    }
+    let text = metrics.render();
+    assert!(text.contains("pscan_scans_total 2\n"), "{text}");
+    assert!(text.contains("pscan_probes_total{state=\"open\"} 2\n"));
+    assert!(text.contains("pscan_probes_total{state=\"filtered\"} 2\n"));
+    assert!(text.contains("pscan_open_ports{host=\"10.0.0.5\",port=\"22\"} 1\n"));
+    // 1.5ms and 0.2ms, twice
+    assert!(text.contains("pscan_probe_duration_seconds_bucket{le=\"0.001\"} 2\n"));
+    assert!(text.contains("pscan_probe_duration_seconds_bucket{le=\"0.005\"} 4\n"));
+    assert!(text.contains("pscan_probe_duration_seconds_count 4\n"));
+
+    // a scan that finds nothing open empties the gauge
+    reporter.finish(&ScanSummary::default()).unwrap();
+    assert!(!metrics.render().contains("pscan_open_ports{"));
+
+    let addr = serve_metrics("127.0.0.1:0".parse().unwrap(), metrics).unwrap();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: test\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+    assert!(response.contains("pscan_scans_total 3\n"), "{response}");
+}
+
+#[test]
+fn webhook_signs_what_it_posts() {
+    // rfc 4231, test case 2
+    let webhook: Webhook = "http://example.com/hook".parse().unwrap();
+    let webhook = webhook.secret("Jefe");
+    assert_eq!(
+        webhook.signature("what do ya want for nothing?").unwrap(),
+        "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+    assert!("ftp://example.com/".parse::<Webhook>().is_err());
+    assert!("https://example.com/hook".parse::<Webhook>().is_ok());
+    assert!("http://[::1/".parse::<Webhook>().is_err());
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hooks/scan", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0; 4096];
+        // the client waits for the answer before closing: read up to the
+        // end of the json
+        while !request.ends_with(b"]}") {
+            let n = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..n]);
+        }
+        stream
+            .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+            .unwrap();
+        String::from_utf8(request).unwrap()
+    });
+
+    let webhook: Webhook = url.parse().unwrap();
+    let webhook = webhook.secret("s3cret").retries(0);
+    let mut reporter = report::WebhookReporter::new(webhook.clone(), &ReportOptions::default());
+    let host = sample_host();
+    let results = sample_results();
+    reporter
+        .host(&HostReport {
+            host: &host,
+            results: &results,
+            elapsed: Duration::from_millis(10),
+            incomplete: false,
+        })
+        .unwrap();
+    reporter.finish(&ScanSummary::default()).unwrap();
+
+    let request = server.join().unwrap();
+    let (head, body) = request.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("POST /hooks/scan HTTP/1.1\r\n"), "{head}");
+    assert!(head.contains("Content-Type: application/json\r\n"));
+    let signature = webhook.signature(body).unwrap();
+    let expected = format!("{SIGNATURE_HEADER}: {signature}");
+    assert!(head.lines().any(|line| line == expected), "{head}");
+    assert!(body.starts_with("{\"event\":\"scan.finished\""), "{body}");
+    assert!(body.contains("\"ip\":\"10.0.0.5\""), "{body}");
+    assert!(body.contains("{\"port\":22,"), "{body}");
+}
+
+#[test]
+fn chat_posts_what_changed_since_the_baseline() {
+    let slack: ChatTarget = "https://hooks.slack.com/services/T0/B0/x".parse().unwrap();
+    assert_eq!(slack.kind, ChatKind::Slack);
+    // the url is a secret, so it isn't shown
+    assert_eq!(slack.to_string(), "slack channel");
+    let teams: ChatTarget = "teams:http://127.0.0.1/hook".parse().unwrap();
+    assert_eq!(teams.kind, ChatKind::Teams);
+    assert_eq!(teams.payload("a\nb"), r#"{"text":"a  \nb"}"#);
+    let discord: ChatTarget = "https://discord.com/api/webhooks/1/x".parse().unwrap();
+    let long = discord.payload(&"x".repeat(3000));
+    assert_eq!(long.chars().filter(|&c| c == 'x').count(), 1999);
+    let teams: ChatTarget = "https://acme.webhook.office.com/webhookb2/x"
+        .parse()
+        .unwrap();
+    assert_eq!(teams.kind, ChatKind::Teams);
+    assert!("http://example.com/hook".parse::<ChatTarget>().is_err());
+    assert!("slack:ftp://example.com/".parse::<ChatTarget>().is_err());
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("slack:http://{}/chat", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0; 4096];
+        while !request.ends_with(b"\"}") {
+            let n = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..n]);
+        }
+        stream.write_all(b"HTTP/1.1 200 OK\r\n\r\n").unwrap();
+        String::from_utf8(request).unwrap()
+    });
+
+    // 23 was open before, 22 wasn't
+    let mut before = sample_results();
+    before[0].state = PortState::Closed;
+    before[1].state = PortState::Open;
+    let baseline = SavedScan {
+        hosts: vec![SavedHost {
+            host: sample_host(),
+            results: before,
+            elapsed: Duration::from_millis(10),
+            incomplete: false,
+            finished: None,
+        }],
+        ..SavedScan::default()
+    };
+    let mut reporter =
+        report::ChatReporter::new(vec![url.parse().unwrap()], &ReportOptions::default())
+            .baseline(baseline);
+    let host = sample_host();
+    let results = sample_results();
+    reporter
+        .host(&HostReport {
+            host: &host,
+            results: &results,
+            elapsed: Duration::from_millis(10),
+            incomplete: false,
+        })
+        .unwrap();
+    reporter.finish(&ScanSummary::default()).unwrap();
+
+    let request = server.join().unwrap();
+    let (head, body) = request.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("POST /chat HTTP/1.1\r\n"), "{head}");
+    assert!(
+        body.contains(r"changed since the baseline: 1 opened, 1 closed\n  opened 10.0.0.5 22/tcp ssh\n  closed 10.0.0.5 23/tcp telnet"),
+        "{body}"
+    );
+}
+
+#[test]
+fn syslog_sends_one_message_per_finding() {
+    assert_eq!(
+        "udp://logs.example.com".parse::<SyslogTarget>().unwrap(),
+        SyslogTarget::Udp("logs.example.com".into(), 514)
+    );
+    assert_eq!(
+        "tcp://[::1]:6514".parse::<SyslogTarget>().unwrap(),
+        SyslogTarget::Tcp("::1".into(), 6514)
+    );
+    assert_eq!(
+        "/dev/log".parse::<SyslogTarget>().unwrap(),
+        "unix:///dev/log".parse().unwrap()
+    );
+    assert!("logs.example.com".parse::<SyslogTarget>().is_err());
+    assert_eq!("local0".parse::<Facility>().unwrap().to_string(), "local0");
+    assert!("local8".parse::<Facility>().is_err());
+
+    let collector = UdpSocket::bind("127.0.0.1:0").unwrap();
+    collector
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let target: SyslogTarget = format!("udp://{}", collector.local_addr().unwrap())
+        .parse()
+        .unwrap();
+    let mut reporter = report::SyslogReporter::connect(
+        &target,
+        "local0".parse().unwrap(),
+        &ReportOptions::default(),
+    )
+    .unwrap();
+    let host = sample_host();
+    let results = sample_results();
+    reporter
+        .host(&HostReport {
+            host: &host,
+            results: &results,
+            elapsed: Duration::from_millis(10),
+            incomplete: false,
+        })
+        .unwrap();
+    reporter.finish(&ScanSummary::default()).unwrap();
+
+    let mut buf = [0; 2048];
+    let mut recv = || {
+        let n = collector.recv(&mut buf).unwrap();
+        String::from_utf8(buf[..n].to_vec()).unwrap()
+    };
+    // local0 (16) * 8 + notice (5)
+    let port = recv();
+    assert!(port.starts_with("<133>1 "), "{port}");
+    assert!(
+        port.contains(" pscan ")
+            && port.contains(
+                " port [pscan@32473 ip=\"10.0.0.5\" port=\"22\" proto=\"tcp\" state=\"open\""
+            ),
+        "{port}"
+    );
+    assert!(port.ends_with("] 10.0.0.5 22/tcp open ssh"), "{port}");
+    // closed and filtered ports stay out without show_closed
+    let scan = recv();
+    assert!(scan.starts_with("<134>1 "), "{scan}");
+    assert!(scan.contains(" scan [pscan@32473 hosts=\"0\""), "{scan}");
+}
@@ -0,0 +1,118 @@
+use port_scanner::{probe_port_with, Backoff, Engine, PortState, RetryPolicy, Scanner};
+use std::net::{IpAddr, Ipv4Addr, TcpListener, TcpStream};
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+
+const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// a listener whose accept queue is full, so its port never answers
+fn black_hole() -> (TcpListener, Vec<TcpStream>, u16) {
+    let listener = TcpListener::bind((LOCALHOST, 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut queued = Vec::new();
+    while let Ok(stream) = TcpStream::connect_timeout(&addr, Duration::from_millis(200)) {
+        queued.push(stream);
+    }
+    (listener, queued, addr.port())
+}
+
+#[test]
+fn backoff_and_timeouts_grow_per_retry() {
+    let ms = Duration::from_millis;
+    let policy = RetryPolicy {
+        retries: 5,
+        backoff: Backoff::Exponential {
+            base: ms(100),
+            max: ms(500),
+        },
+        jitter: false,
+        timeout_growth: 2.0,
+        retry_refused: false,
+    };
+    let delays: Vec<_> = (1..=5).map(|n| policy.delay(n)).collect();
+    assert_eq!(delays, [ms(100), ms(200), ms(400), ms(500), ms(500)]);
+    assert_eq!(policy.timeout(ms(50), 0), ms(50));
+    assert_eq!(policy.timeout(ms(50), 3), ms(400));
+
+    let jittered = RetryPolicy {
+        backoff: Backoff::Fixed(ms(100)),
+        jitter: true,
+        ..policy
+    };
+    for n in 1..=5 {
+        let delay = jittered.delay(n);
+        assert!(delay >= ms(50) && delay <= ms(100), "{delay:?}");
+    }
+
+    assert_eq!(RetryPolicy::new(3).delay(1), Duration::ZERO);
+    assert_eq!(RetryPolicy::default().retries, 0);
+}
+
+#[test]
+fn retries_wait_out_the_backoff() {
+    let (_hole, _queued, port) = black_hole();
+    let timeout = Duration::from_millis(50);
+    let policy = RetryPolicy {
+        retries: 2,
+        backoff: Backoff::Fixed(Duration::from_millis(100)),
+        ..RetryPolicy::default()
+    };
+    // three 50ms attempts, 100ms apart
+    let expected = Duration::from_millis(350);
+
+    let started = Instant::now();
+    let result = probe_port_with(LOCALHOST, port, timeout, &policy, &AtomicBool::new(false));
+    assert_eq!(result.state, PortState::Filtered);
+    assert!(started.elapsed() >= expected);
+
+    for engine in [Engine::Threads, Engine::Async] {
+        let scanner = Scanner::builder()
+            .engine(engine)
+            .timeout(timeout)
+            .retry_policy(policy)
+            .build();
+        assert_eq!(scanner.retries(), 2);
+        let started = Instant::now();
+        assert_eq!(
+            scanner.scan(LOCALHOST, [port])[0].state,
+            PortState::Filtered
+        );
+        assert!(started.elapsed() >= expected, "{engine}");
+    }
+}
+
+#[test]
+fn refused_ports_are_only_retried_on_request() {
+    let port = TcpListener::bind((LOCALHOST, 0))
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let backoff = Duration::from_millis(150);
+    let policy = RetryPolicy {
+        retries: 2,
+        backoff: Backoff::Fixed(backoff),
+        ..RetryPolicy::default()
+    };
+    assert!(!policy.should_retry(PortState::Closed));
+    assert!(policy.should_retry(PortState::Filtered));
+
+    for engine in [Engine::Threads, Engine::Async] {
+        let scan = |policy| {
+            let scanner = Scanner::builder()
+                .engine(engine)
+                .retry_policy(policy)
+                .build();
+            let started = Instant::now();
+            assert_eq!(scanner.scan(LOCALHOST, [port])[0].state, PortState::Closed);
+            started.elapsed()
+        };
+        // a refusal is final: no backoff is ever waited out
+        assert!(scan(policy) < backoff, "{engine}");
+        let again = RetryPolicy {
+            retry_refused: true,
+            ..policy
+        };
+        assert!(scan(again) >= backoff * 2, "{engine}");
+    }
+}
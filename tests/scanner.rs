@@ -0,0 +1,512 @@
+use port_scanner::{socket_limit, Engine, PortState, Progress, ScanType, Scanner, Stopped, Timing};
+use std::net::{IpAddr, Ipv4Addr, TcpListener};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+#[test]
+fn builder_defaults() {
+    let scanner = Scanner::builder().build();
+    assert_eq!(scanner.timeout(), Duration::from_millis(50));
+    assert_eq!(scanner.retries(), 0);
+    assert_eq!(scanner.engine(), Engine::Threads);
+    assert!(scanner.concurrency() >= 1);
+}
+
+#[test]
+fn concurrency_stays_under_the_descriptor_limit() {
+    let Some(limit) = socket_limit() else {
+        return;
+    };
+    let scanner = Scanner::builder()
+        .engine(Engine::Async)
+        .concurrency(usize::MAX)
+        .build();
+    assert_eq!(scanner.concurrency(), limit);
+
+    // a raw scan sends everything through one socket
+    let scanner = Scanner::builder()
+        .scan_type(ScanType::Syn)
+        .concurrency(usize::MAX)
+        .build();
+    assert_eq!(scanner.concurrency(), usize::MAX);
+}
+
+#[test]
+fn scan_reports_open_port_and_progress() {
+    let listener = TcpListener::bind((LOCALHOST, 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let counter = Arc::new(AtomicUsize::new(0));
+
+    for engine in [Engine::Threads, Engine::Async] {
+        counter.store(0, Ordering::Relaxed);
+        let scanner = Scanner::builder()
+            .timeout(Duration::from_millis(500))
+            .engine(engine)
+            .concurrency(4)
+            .progress(counter.clone())
+            .build();
+
+        let results = scanner.scan(LOCALHOST, [port]);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].state, PortState::Open);
+        assert_eq!(counter.load(Ordering::Relaxed), 1);
+    }
+}
+
+#[test]
+fn cancelled_scanner_reports_filtered() {
+    let scanner = Scanner::builder().concurrency(1).build();
+    scanner.cancel_flag().store(true, Ordering::Relaxed);
+
+    let results = scanner.scan(LOCALHOST, 1..=3);
+    assert_eq!(results.len(), 3);
+    assert!(results.iter().all(|r| r.state == PortState::Filtered));
+}
+
+#[test]
+fn scan_each_sees_every_port() {
+    let seen = std::sync::Mutex::new(Vec::new());
+    let scanner = Scanner::builder().concurrency(2).build();
+
+    let results = scanner.scan_each(LOCALHOST, [1, 2, 3], |r| {
+        seen.lock().unwrap().push(r.port);
+    });
+
+    let mut seen = seen.into_inner().unwrap();
+    seen.sort();
+    assert_eq!(seen, vec![1, 2, 3]);
+    assert_eq!(results.len(), 3);
+}
+
+#[test]
+fn scans_run_on_a_given_thread_pool() {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(2)
+        .thread_name(|i| format!("given-{i}"))
+        .build()
+        .unwrap();
+    let scanner = Scanner::builder()
+        .concurrency(4)
+        .thread_pool(Arc::new(pool))
+        .build();
+
+    // every scan, and every copy of the scanner, uses the same workers
+    for scanner in [scanner.clone(), scanner] {
+        let threads = std::sync::Mutex::new(Vec::new());
+        let results = scanner
+            .try_scan_host(LOCALHOST, 1..=8, |_| {
+                let name = std::thread::current().name().map(str::to_string);
+                threads.lock().unwrap().push(name);
+            })
+            .unwrap()
+            .results;
+        assert_eq!(results.len(), 8);
+        let threads = threads.into_inner().unwrap();
+        assert!(
+            threads
+                .iter()
+                .all(|t| t.as_deref().is_some_and(|t| t.starts_with("given-"))),
+            "{threads:?}"
+        );
+    }
+}
+
+#[test]
+fn scan_stream_delivers_results_then_closes() {
+    let listener = TcpListener::bind((LOCALHOST, 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let scanner = Scanner::builder()
+        .timeout(Duration::from_millis(500))
+        .engine(Engine::Async)
+        .build();
+
+    let rx = scanner.scan_stream(LOCALHOST, vec![port]);
+    let results: Vec<_> = rx.iter().collect();
+    assert_eq!(results.len(), 1);
+    assert!(results[0].is_open());
+}
+
+#[test]
+fn timing_templates_set_every_knob_and_yield_to_later_calls() {
+    assert_eq!("4".parse::<Timing>(), Ok(Timing::Aggressive));
+    assert_eq!("T1".parse::<Timing>(), Ok(Timing::Sneaky));
+    assert_eq!("Polite".parse::<Timing>(), Ok(Timing::Polite));
+    assert!("6".parse::<Timing>().is_err());
+
+    let scanner = Scanner::builder().timing(Timing::Polite).build();
+    assert_eq!(scanner.timeout(), Duration::from_secs(2));
+    assert_eq!(scanner.retries(), 2);
+    assert_eq!(scanner.concurrency(), 1);
+    assert_eq!(scanner.scan_delay(), Duration::from_millis(400));
+
+    let scanner = Scanner::builder()
+        .timing(Timing::Insane)
+        .timeout(Duration::from_millis(10))
+        .build();
+    assert_eq!(scanner.timeout(), Duration::from_millis(10));
+    assert_eq!(scanner.concurrency(), 1000);
+    assert_eq!(scanner.scan_delay(), Duration::ZERO);
+}
+
+#[test]
+fn scan_delay_spaces_probes_across_workers() {
+    let listener = TcpListener::bind((LOCALHOST, 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let delay = Duration::from_millis(100);
+
+    for engine in [Engine::Threads, Engine::Async] {
+        let scanner = Scanner::builder()
+            .engine(engine)
+            .concurrency(8)
+            .scan_delay(delay)
+            .build();
+        let started = Instant::now();
+        let results = scanner.scan(LOCALHOST, [port; 4]);
+        assert!(results.iter().all(|r| r.state == PortState::Open));
+        // four probes need three gaps, however many workers there are
+        assert!(
+            started.elapsed() >= delay * 3,
+            "{engine}: {:?}",
+            started.elapsed()
+        );
+    }
+}
+
+#[test]
+fn max_rate_caps_probes_per_second() {
+    let listener = TcpListener::bind((LOCALHOST, 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    for engine in [Engine::Threads, Engine::Async] {
+        let scanner = Scanner::builder()
+            .engine(engine)
+            .concurrency(16)
+            .max_rate(200)
+            .build();
+        assert_eq!(scanner.max_rate(), Some(200));
+        let started = Instant::now();
+        let results = scanner.scan(LOCALHOST, [port; 40]);
+        assert_eq!(results.len(), 40);
+        // a burst of 10, then the other 30 at 200 a second
+        assert!(
+            started.elapsed() >= Duration::from_millis(140),
+            "{engine}: {:?}",
+            started.elapsed()
+        );
+    }
+}
+
+/// a listener whose accept queue is full: linux drops further syns, so
+/// probes to it time out like a firewalled port
+fn black_hole() -> (TcpListener, Vec<std::net::TcpStream>, u16) {
+    let listener = TcpListener::bind((LOCALHOST, 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut queued = Vec::new();
+    while let Ok(stream) = std::net::TcpStream::connect_timeout(&addr, Duration::from_millis(200)) {
+        queued.push(stream);
+    }
+    (listener, queued, addr.port())
+}
+
+#[test]
+fn adaptive_timeout_follows_the_measured_rtt() {
+    let open = TcpListener::bind((LOCALHOST, 0)).unwrap();
+    let open_port = open.local_addr().unwrap().port();
+    let (_hole, _queued, silent) = black_hole();
+
+    for engine in [Engine::Threads, Engine::Async] {
+        let scanner = Scanner::builder()
+            .engine(engine)
+            .concurrency(1)
+            .timeout(Duration::from_secs(2))
+            .adaptive_timeout(Duration::from_millis(20), Duration::from_secs(2))
+            .build();
+        assert_eq!(
+            scanner.rtt_bounds(),
+            Some((Duration::from_millis(20), Duration::from_secs(2)))
+        );
+        let started = Instant::now();
+        let results = scanner.scan(LOCALHOST, [open_port, silent, silent, silent]);
+        let count = |state| results.iter().filter(|r| r.state == state).count();
+        assert_eq!((count(PortState::Open), count(PortState::Filtered)), (1, 3));
+        // loopback answers in microseconds, so the silent port gets the
+        // 20ms floor instead of the 2s it would have without an answer
+        assert!(
+            started.elapsed() < Duration::from_secs(1),
+            "{engine}: {:?}",
+            started.elapsed()
+        );
+    }
+}
+
+#[test]
+fn adaptive_concurrency_backs_off_while_probes_go_unanswered() {
+    let (_hole, _queued, silent) = black_hole();
+    let timeout = Duration::from_millis(100);
+
+    for engine in [Engine::Threads, Engine::Async] {
+        let scan = |builder: port_scanner::ScannerBuilder| {
+            let scanner = builder
+                .engine(engine)
+                .concurrency(16)
+                .timeout(timeout)
+                .build();
+            let started = Instant::now();
+            let results = scanner.scan(LOCALHOST, [silent; 32]);
+            assert!(results.iter().all(|r| r.state == PortState::Filtered));
+            started.elapsed()
+        };
+        // two rounds of 16 at a fixed concurrency
+        let fixed = scan(Scanner::builder());
+        assert!(fixed < timeout * 4, "{engine}: {fixed:?}");
+        // 16, 8, 4, 2, 1, 1 as every round times out
+        let adaptive = scan(Scanner::builder().adaptive_concurrency(1));
+        assert!(adaptive >= timeout * 5, "{engine}: {adaptive:?}");
+    }
+}
+
+#[test]
+fn hosts_that_never_answer_are_given_up_on() {
+    let (_hole, _queued, silent) = black_hole();
+    let closed = TcpListener::bind((LOCALHOST, 0))
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let timeout = Duration::from_millis(50);
+
+    for engine in [Engine::Threads, Engine::Async] {
+        let scanner = Scanner::builder()
+            .engine(engine)
+            .concurrency(1)
+            .timeout(timeout)
+            .skip_dead_hosts(4)
+            .build();
+        assert_eq!(scanner.dead_after(), Some(4));
+
+        let started = Instant::now();
+        let outcome = scanner.scan_host(LOCALHOST, [silent; 40], |_| {});
+        assert_eq!(outcome.stopped, Some(Stopped::HostDown), "{engine}");
+        assert_eq!(outcome.results.len(), 40);
+        assert!(outcome
+            .results
+            .iter()
+            .all(|r| r.state == PortState::Filtered));
+        assert!(
+            started.elapsed() < timeout * 20,
+            "{engine}: {:?}",
+            started.elapsed()
+        );
+
+        // one reset up front and the host is alive, however quiet the rest
+        let ports = std::iter::once(closed).chain([silent; 6]);
+        let outcome = scanner.scan_host(LOCALHOST, ports, |_| {});
+        assert_eq!(outcome.stopped, None, "{engine}");
+    }
+}
+
+#[test]
+fn host_timeout_leaves_the_rest_unknown() {
+    let (_hole, _queued, silent) = black_hole();
+    let timeout = Duration::from_millis(100);
+    let limit = Duration::from_millis(300);
+
+    for engine in [Engine::Threads, Engine::Async] {
+        let scanner = Scanner::builder()
+            .engine(engine)
+            .concurrency(1)
+            .timeout(timeout)
+            .host_timeout(limit)
+            .build();
+        assert_eq!(scanner.host_timeout(), Some(limit));
+
+        let started = Instant::now();
+        let outcome = scanner.scan_host(LOCALHOST, [silent; 20], |_| {});
+        assert_eq!(outcome.stopped, Some(Stopped::HostTimeout), "{engine}");
+        assert_eq!(outcome.results.len(), 20);
+        assert!(outcome
+            .results
+            .iter()
+            .any(|r| r.state == PortState::Unknown));
+        assert!(
+            started.elapsed() < limit + timeout * 3,
+            "{engine}: {:?}",
+            started.elapsed()
+        );
+    }
+}
+
+#[test]
+fn stopped_scans_finish_the_probes_in_flight() {
+    let (_hole, _queued, silent) = black_hole();
+    let timeout = Duration::from_millis(300);
+
+    for engine in [Engine::Threads, Engine::Async] {
+        let scanner = Scanner::builder()
+            .engine(engine)
+            .concurrency(1)
+            .timeout(timeout)
+            .build();
+        let started = Instant::now();
+        let outcome = std::thread::scope(|s| {
+            let scan = s.spawn(|| scanner.scan_host(LOCALHOST, [silent; 5], |_| {}));
+            std::thread::sleep(Duration::from_millis(100));
+            scanner.stop_flag().store(true, Ordering::SeqCst);
+            scan.join().unwrap()
+        });
+        // the probe in flight ran out its timeout, the rest never went out
+        assert!(started.elapsed() >= timeout, "{engine}");
+        assert_eq!(outcome.stopped, Some(Stopped::Interrupted), "{engine}");
+        assert_eq!(outcome.results.len(), 5);
+        let unknown = outcome
+            .results
+            .iter()
+            .filter(|r| r.state == PortState::Unknown)
+            .count();
+        assert_eq!(unknown, 4, "{engine}: {:?}", outcome.results);
+    }
+}
+
+#[test]
+fn hosts_scanned_side_by_side_share_the_in_flight_cap() {
+    let (_hole, _queued, silent) = black_hole();
+    let timeout = Duration::from_millis(100);
+
+    for engine in [Engine::Threads, Engine::Async] {
+        let scanner = Scanner::builder()
+            .engine(engine)
+            .concurrency(4)
+            .timeout(timeout)
+            .max_in_flight(2)
+            .build();
+        assert_eq!(scanner.max_in_flight(), Some(2));
+
+        // two hosts' worth of silent probes, four at a time each, but only
+        // two at a time between them: four rounds of timeouts
+        let started = Instant::now();
+        std::thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| {
+                    let outcome = scanner.scan_host(LOCALHOST, [silent; 4], |_| {});
+                    assert_eq!(outcome.results.len(), 4);
+                });
+            }
+        });
+        let elapsed = started.elapsed();
+        assert!(elapsed >= timeout * 4, "{engine}: {elapsed:?}");
+        assert!(elapsed < timeout * 12, "{engine}: {elapsed:?}");
+    }
+}
+
+#[test]
+fn max_open_stops_at_enough_open_ports() {
+    let listener = TcpListener::bind((LOCALHOST, 0)).unwrap();
+    let open = listener.local_addr().unwrap().port();
+    let closed = TcpListener::bind((LOCALHOST, 0))
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    for engine in [Engine::Threads, Engine::Async] {
+        let scanner = Scanner::builder()
+            .engine(engine)
+            .concurrency(1)
+            .max_open(1)
+            .build();
+        assert_eq!(scanner.max_open(), Some(1));
+
+        let ports = std::iter::once(open).chain([closed; 10]);
+        let outcome = scanner.scan_host(LOCALHOST, ports, |_| {});
+        assert_eq!(outcome.stopped, Some(Stopped::EnoughOpen), "{engine}");
+        let count = |state| outcome.results.iter().filter(|r| r.state == state).count();
+        assert_eq!(count(PortState::Open), 1);
+        assert_eq!(count(PortState::Unknown), 10);
+
+        // fewer open ports than asked for: everything is probed
+        let outcome = Scanner::builder()
+            .engine(engine)
+            .max_open(2)
+            .build()
+            .scan_host(LOCALHOST, [open, closed], |_| {});
+        assert_eq!(outcome.stopped, None, "{engine}");
+    }
+}
+
+#[test]
+fn paused_scans_wait_and_skipped_hosts_stop() {
+    let listener = TcpListener::bind((LOCALHOST, 0)).unwrap();
+    let open = listener.local_addr().unwrap().port();
+
+    for engine in [Engine::Threads, Engine::Async] {
+        let scanner = Scanner::builder().engine(engine).concurrency(1).build();
+        scanner.set_paused(true);
+        let done = AtomicUsize::new(0);
+        std::thread::scope(|s| {
+            let scan = s.spawn(|| {
+                scanner.scan_host(LOCALHOST, [open; 3], |_| {
+                    done.fetch_add(1, Ordering::Relaxed);
+                })
+            });
+            std::thread::sleep(Duration::from_millis(300));
+            assert_eq!(done.load(Ordering::Relaxed), 0, "{engine}");
+            scanner.set_paused(false);
+            let outcome = scan.join().unwrap();
+            assert_eq!(outcome.stopped, None, "{engine}");
+            assert!(outcome.results.iter().all(|r| r.is_open()));
+        });
+
+        // skipping one host leaves its ports unknown
+        let skip = Arc::new(AtomicBool::new(true));
+        let outcome = scanner
+            .clone()
+            .with_skip_flag(skip)
+            .scan_host(LOCALHOST, [open; 3], |_| {});
+        assert_eq!(outcome.stopped, Some(Stopped::Skipped), "{engine}");
+        assert!(outcome
+            .results
+            .iter()
+            .all(|r| r.state == PortState::Unknown));
+    }
+}
+
+#[test]
+fn progress_counts_hosts_and_ports_across_scans() {
+    // open on both hosts
+    let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0)).unwrap();
+    let open = listener.local_addr().unwrap().port();
+    let closed = TcpListener::bind((LOCALHOST, 0))
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let other = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2));
+
+    let progress = Arc::new(Progress::new());
+    progress.start(3, 3);
+    // a resumed host: one of its ports was done before
+    progress.carry(other, 1);
+    let scanner = Scanner::builder().track(progress.clone()).build();
+    for ip in [LOCALHOST, other] {
+        scanner.scan_host(ip, [open, closed, closed], |_| {
+            let now = progress.snapshot();
+            assert_eq!(now.hosts.len(), 1);
+            assert_eq!(now.hosts[0].ip, ip);
+        });
+    }
+
+    let now = progress.snapshot();
+    assert_eq!((now.hosts_done, now.hosts_total), (2, 3));
+    // the carried port was scanned again, but the totals stop at the ports
+    assert_eq!((now.probes_done, now.probes_total), (7, 9));
+    assert_eq!(now.open_ports, 2);
+    assert!(now.hosts.is_empty());
+    assert!((now.fraction() - 7.0 / 9.0).abs() < 1e-9);
+
+    progress.start(1, 3);
+    let now = progress.snapshot();
+    assert_eq!((now.hosts_done, now.probes_done, now.open_ports), (0, 0, 0));
+}
@@ -0,0 +1,135 @@
+#![cfg(feature = "serde")]
+
+use port_scanner::report::{HostReport, ScanInfo, ScanSummary};
+use port_scanner::{
+    CertInfo, Engine, HttpInfo, PortRange, PortState, Protocol, ScanHost, ScanResult, ServiceMatch,
+    TlsInfo,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Debug;
+use std::time::{Duration, SystemTime};
+
+/// `value` through json and back comes out the same
+fn round_trips<T: Serialize + DeserializeOwned + PartialEq + Debug>(value: &T) {
+    let json = serde_json::to_string(value).unwrap();
+    let back: T = serde_json::from_str(&json).unwrap();
+    assert_eq!(&back, value, "{json}");
+}
+
+const STATES: [PortState; 7] = [
+    PortState::Open,
+    PortState::Closed,
+    PortState::Filtered,
+    PortState::Error,
+    PortState::OpenFiltered,
+    PortState::Unfiltered,
+    PortState::Unknown,
+];
+
+/// a result with every probe's findings filled in
+fn probed(port: u16) -> ScanResult {
+    ScanResult {
+        latency: Some(Duration::from_micros(1234)),
+        banner: Some("SSH-2.0-OpenSSH_9.6\r\n".to_string()),
+        tls: Some(TlsInfo {
+            version: "TLSv1.3".to_string(),
+            cipher: "TLS_AES_128_GCM_SHA256".to_string(),
+            certificate: Some(CertInfo {
+                subject: "CN=example.com".to_string(),
+                issuer: "CN=Example CA".to_string(),
+                sans: vec!["example.com".to_string(), "192.0.2.1".to_string()],
+                not_before: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+                not_after: None,
+            }),
+        }),
+        http: Some(HttpInfo {
+            status: 200,
+            server: Some("nginx".to_string()),
+            title: None,
+        }),
+        service: Some(ServiceMatch {
+            service: "ssh".to_string(),
+            product: Some("OpenSSH".to_string()),
+            version: Some("9.6p1".to_string()),
+            info: Some("protocol 2.0".to_string()),
+            os: None,
+            hostname: None,
+        }),
+        ..ScanResult::new(port, PortState::Open)
+    }
+}
+
+#[test]
+fn scan_results_round_trip_through_json() {
+    for state in STATES {
+        round_trips(&state);
+        round_trips(&ScanResult::new(443, state));
+        round_trips(&ScanResult {
+            protocol: Protocol::Udp,
+            ..ScanResult::new(53, state)
+        });
+    }
+    round_trips(&probed(22));
+    round_trips(&PortRange { start: 20, end: 25 });
+}
+
+#[test]
+fn reports_round_trip_through_json() {
+    round_trips(&ScanHost {
+        ip: "fe80::1".parse().unwrap(),
+        names: vec!["router.lan".to_string()],
+        ptr: Some("router.lan".to_string()),
+        scope_id: 2,
+    });
+    round_trips(&ScanInfo {
+        command: "pscan -p 22,80 192.0.2.0/30".to_string(),
+        targets: vec!["192.0.2.0/30".to_string()],
+        host_count: 4,
+        excluded_hosts: 1,
+        ports: "22,80".to_string(),
+        port_count: 2,
+        excluded_ports: Some("25".to_string()),
+        timeout_ms: 500,
+        retries: 1,
+        engine: Engine::Async,
+        concurrency: 64,
+        started: SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+    });
+    round_trips(&ScanSummary {
+        targets: 1,
+        hosts_total: 4,
+        hosts_scanned: 3,
+        hosts_with_open: 2,
+        open_ports: 5,
+        elapsed: Duration::from_millis(2500),
+        cancelled: true,
+    });
+}
+
+#[test]
+fn host_reports_serialize_their_results() {
+    let host = ScanHost {
+        ip: "192.0.2.1".parse().unwrap(),
+        names: vec![],
+        ptr: None,
+        scope_id: 0,
+    };
+    let results: Vec<ScanResult> = STATES
+        .iter()
+        .enumerate()
+        .map(|(i, &state)| ScanResult::new(8000 + i as u16, state))
+        .collect();
+    let json = serde_json::to_value(HostReport {
+        host: &host,
+        results: &results,
+        elapsed: Duration::from_secs(1),
+        incomplete: false,
+    })
+    .unwrap();
+    // a host report borrows what it shows, so it reads back as its parts
+    let back: Vec<ScanResult> = serde_json::from_value(json["results"].clone()).unwrap();
+    assert_eq!(back, results);
+    let back: ScanHost = serde_json::from_value(json["host"].clone()).unwrap();
+    assert_eq!(back, host);
+}
@@ -0,0 +1,310 @@
+use port_scanner::{GrpcServer, ServeOptions, Server};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// the pscan.v1 messages and client
+#[cfg(feature = "grpc")]
+mod proto {
+    tonic::include_proto!("pscan.v1");
+}
+
+/// send one request and return the status code and body
+fn request(addr: SocketAddr, method: &str, path: &str, token: &str, body: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "{method} {path} HTTP/1.1\r\nHost: test\r\nAuthorization: Bearer {token}\r\nContent-Length: {}\r\n\r\n{body}",
+        body.len()
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let status = response[9..12].parse().unwrap();
+    let body = response.split_once("\r\n\r\n").unwrap().1.to_string();
+    (status, body)
+}
+
+#[test]
+fn serve_runs_submitted_scans() {
+    let open = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = open.local_addr().unwrap().port();
+    let opts = ServeOptions {
+        token: Some("s3cret".to_string()),
+        ..ServeOptions::default()
+    };
+    let server = Server::bind("127.0.0.1:0".parse().unwrap(), opts).unwrap();
+    let addr = server.local_addr().unwrap();
+    let waker = server.waker().unwrap();
+    let stop = Arc::new(AtomicBool::new(false));
+    let running = {
+        let stop = stop.clone();
+        std::thread::spawn(move || server.run(&stop))
+    };
+
+    assert_eq!(request(addr, "GET", "/scans", "wrong", "").0, 401);
+    let (status, body) = request(
+        addr,
+        "POST",
+        "/scans",
+        "s3cret",
+        &format!(r#"{{"targets": ["127.0.0.1"], "ports": "{port}", "timeout_ms": 500}}"#),
+    );
+    assert_eq!(status, 202, "{body}");
+    assert!(body.starts_with(r#"{"id":1,"#), "{body}");
+    assert_eq!(
+        request(addr, "POST", "/scans", "s3cret", r#"{"ports": "80"}"#).0,
+        400
+    );
+    assert_eq!(request(addr, "GET", "/scans/9", "s3cret", "").0, 404);
+
+    let started = Instant::now();
+    loop {
+        let (_, body) = request(addr, "GET", "/scans/1", "s3cret", "");
+        if body.contains(r#""status":"done""#) {
+            assert!(body.contains(r#""open_ports":1"#), "{body}");
+            break;
+        }
+        assert!(started.elapsed() < Duration::from_secs(10), "{body}");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    let (status, body) = request(addr, "GET", "/scans/1/results", "s3cret", "");
+    assert_eq!(status, 200);
+    let report: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let found = &report["hosts"][0]["ports"][0];
+    assert_eq!(found["port"], port, "{body}");
+    assert_eq!(found["state"], "open", "{body}");
+    assert_eq!(request(addr, "DELETE", "/scans/1", "s3cret", "").0, 409);
+
+    // the event stream of a finished scan: its status, then done and a close
+    let mut ws = TcpStream::connect(addr).unwrap();
+    write!(
+        ws,
+        "GET /scans/1/events?token=s3cret HTTP/1.1\r\nHost: test\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n"
+    )
+    .unwrap();
+    let mut stream = Vec::new();
+    ws.read_to_end(&mut stream).unwrap();
+    let text = String::from_utf8_lossy(&stream);
+    assert!(text.starts_with("HTTP/1.1 101 "), "{text}");
+    assert!(
+        text.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"),
+        "{text}"
+    );
+    assert!(text.contains(r#"{"event":"progress","scan":{"id":1,"#));
+    assert!(text.contains(r#"{"event":"done","scan":{"id":1,"status":"done","#));
+    // the last frame is a normal close
+    assert!(stream.ends_with(&[0x88, 2, 0x03, 0xe8]), "{text}");
+
+    stop.store(true, Ordering::SeqCst);
+    waker.wake();
+    running.join().unwrap().unwrap();
+}
+
+#[test]
+#[cfg(not(feature = "grpc"))]
+fn grpc_needs_the_feature() {
+    let Err(e) = GrpcServer::bind("127.0.0.1:0".parse().unwrap(), ServeOptions::default()) else {
+        panic!("a grpc server without the grpc feature");
+    };
+    assert_eq!(e.kind(), std::io::ErrorKind::Unsupported);
+}
+
+#[test]
+#[cfg(feature = "grpc")]
+fn grpc_streams_scan_results() {
+    use proto::scanner_client::ScannerClient;
+    use proto::{CancelReply, CancelRequest, ScanRequest};
+    use tonic::codegen::http::uri::PathAndQuery;
+    use tonic::transport::Endpoint;
+    use tonic::{Code, Request};
+
+    let open = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = open.local_addr().unwrap().port();
+    let opts = ServeOptions {
+        token: Some("s3cret".to_string()),
+        ..ServeOptions::default()
+    };
+    let server = GrpcServer::bind("127.0.0.1:0".parse().unwrap(), opts).unwrap();
+    let addr = server.local_addr().unwrap();
+    let stop = Arc::new(AtomicBool::new(false));
+    let running = {
+        let stop = stop.clone();
+        std::thread::spawn(move || server.run(&stop))
+    };
+
+    fn authorized<T>(message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        let token = "Bearer s3cret".parse().unwrap();
+        request.metadata_mut().insert("authorization", token);
+        request
+    }
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let channel = Endpoint::from_shared(format!("http://{addr}"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let mut client = ScannerClient::new(channel.clone());
+        let scan = ScanRequest {
+            targets: vec!["127.0.0.1".to_string()],
+            ports: port.to_string(),
+            scan_id: "t1".to_string(),
+            ..ScanRequest::default()
+        };
+
+        let refused = client.start_scan(scan.clone()).await.unwrap_err();
+        assert_eq!(refused.code(), Code::Unauthenticated);
+
+        let response = client.start_scan(authorized(scan)).await.unwrap();
+        assert_eq!(response.metadata().get("scan-id").unwrap(), "t1");
+        let mut results = response.into_inner();
+        let mut found = Vec::new();
+        while let Some(result) = results.message().await.unwrap() {
+            found.push(result);
+        }
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].ip, "127.0.0.1");
+        assert_eq!(found[0].port, u32::from(port));
+        assert_eq!(found[0].state, "open");
+
+        let cancel = CancelRequest {
+            scan_id: "t1".to_string(),
+        };
+        let missing = client.cancel(authorized(cancel)).await.unwrap_err();
+        assert_eq!(missing.code(), Code::NotFound);
+
+        let mut grpc = tonic::client::Grpc::new(channel);
+        grpc.ready().await.unwrap();
+        let unknown = grpc
+            .unary::<_, CancelReply, _>(
+                authorized(CancelRequest::default()),
+                PathAndQuery::from_static("/pscan.v1.Scanner/Nope"),
+                tonic_prost::ProstCodec::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(unknown.code(), Code::Unimplemented);
+    });
+
+    stop.store(true, Ordering::SeqCst);
+    running.join().unwrap().unwrap();
+}
+
+#[test]
+fn serve_jobs_keep_to_the_allow_list() {
+    let opts = ServeOptions {
+        allow: Some(vec!["10.0.0.0/8".parse().unwrap()]),
+        ..ServeOptions::default()
+    };
+    let server = Server::bind("127.0.0.1:0".parse().unwrap(), opts).unwrap();
+    let addr = server.local_addr().unwrap();
+    let waker = server.waker().unwrap();
+    let stop = Arc::new(AtomicBool::new(false));
+    let running = {
+        let stop = stop.clone();
+        std::thread::spawn(move || server.run(&stop))
+    };
+
+    let (status, body) = request(
+        addr,
+        "POST",
+        "/scans",
+        "",
+        r#"{"targets": ["127.0.0.1"], "ports": "80", "timeout_ms": 100}"#,
+    );
+    assert_eq!(status, 202, "{body}");
+    let started = Instant::now();
+    loop {
+        let (_, body) = request(addr, "GET", "/scans/1", "", "");
+        if body.contains(r#""status":"failed""#) {
+            assert!(
+                body.contains("isn't among the authorized targets"),
+                "{body}"
+            );
+            break;
+        }
+        assert!(started.elapsed() < Duration::from_secs(10), "{body}");
+        std::thread::sleep(Duration::from_millis(20));
+    }
+
+    stop.store(true, Ordering::SeqCst);
+    waker.wake();
+    running.join().unwrap().unwrap();
+}
+
+/// a listener whose accept queue is full: linux drops further syns, so
+/// probes to it time out like a firewalled port
+fn black_hole() -> (TcpListener, Vec<TcpStream>, u16) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut queued = Vec::new();
+    while let Ok(stream) = TcpStream::connect_timeout(&addr, Duration::from_millis(200)) {
+        queued.push(stream);
+    }
+    (listener, queued, addr.port())
+}
+
+#[test]
+fn serve_turns_away_too_many_jobs_and_big_bodies() {
+    let (_hole, _queued, port) = black_hole();
+    let opts = ServeOptions {
+        max_pending: 1,
+        ..ServeOptions::default()
+    };
+    let server = Server::bind("127.0.0.1:0".parse().unwrap(), opts).unwrap();
+    let addr = server.local_addr().unwrap();
+    let waker = server.waker().unwrap();
+    let stop = Arc::new(AtomicBool::new(false));
+    let running = {
+        let stop = stop.clone();
+        std::thread::spawn(move || server.run(&stop))
+    };
+
+    let slow = format!(r#"{{"targets": ["127.0.0.1"], "ports": "{port}", "timeout_ms": 5000}}"#);
+    let (status, body) = request(addr, "POST", "/scans", "", &slow);
+    assert_eq!(status, 202, "{body}");
+    let (status, body) = request(addr, "POST", "/scans", "", &slow);
+    assert_eq!(status, 503, "{body}");
+    assert!(body.contains("already waiting or running"), "{body}");
+
+    // once the first is over there's room again
+    assert_eq!(request(addr, "DELETE", "/scans/1", "", "").0, 202);
+    let started = Instant::now();
+    loop {
+        let (_, body) = request(addr, "GET", "/scans/1", "", "");
+        if body.contains(r#""status":"cancelled""#) {
+            break;
+        }
+        assert!(started.elapsed() < Duration::from_secs(10), "{body}");
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    let (status, body) = request(
+        addr,
+        "POST",
+        "/scans",
+        "",
+        r#"{"targets": ["127.0.0.1"], "ports": "1", "timeout_ms": 100}"#,
+    );
+    assert_eq!(status, 202, "{body}");
+    assert!(body.starts_with(r#"{"id":2,"#), "{body}");
+
+    // refused on its length alone, before any of it is read
+    let mut big = TcpStream::connect(addr).unwrap();
+    write!(
+        big,
+        "POST /scans HTTP/1.1\r\nHost: test\r\nContent-Length: {}\r\n\r\n",
+        1024 * 1024
+    )
+    .unwrap();
+    let mut response = String::new();
+    big.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 413 "), "{response}");
+
+    stop.store(true, Ordering::SeqCst);
+    waker.wake();
+    running.join().unwrap().unwrap();
+}
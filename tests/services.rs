@@ -0,0 +1,115 @@
+use port_scanner::services::{self, Services};
+use port_scanner::Protocol::{Tcp, Udp};
+use port_scanner::{PortState, ScanResult};
+
+#[test]
+fn name_and_port_round_trip() {
+    assert_eq!(services::port_for("ssh", Tcp), Some(22));
+    assert_eq!(services::name_for(22, Tcp), Some("ssh"));
+    assert_eq!(services::port_for("PostgreSQL", Tcp), Some(5432));
+    assert_eq!(services::name_for(5432, Tcp), Some("postgres"));
+    assert_eq!(services::name_for(445, Tcp), Some("smb"));
+    assert_eq!(services::port_for("microsoft-ds", Tcp), Some(445));
+    // the registry has far more than the common ports
+    assert_eq!(services::name_for(873, Tcp), Some("rsync"));
+    assert_eq!(services::name_for(123, Udp), Some("ntp"));
+    assert_eq!(services::port_for("tftp", Udp), Some(69));
+    assert_eq!(services::port_for("tftp", Tcp), None);
+}
+
+#[test]
+fn results_are_named_for_their_own_protocol() {
+    let udp = |port| ScanResult {
+        protocol: Udp,
+        ..ScanResult::new(port, PortState::OpenFiltered)
+    };
+    assert_eq!(udp(514).service_name(), Some("syslog"));
+    assert_eq!(udp(69).service_name(), Some("tftp"));
+    assert_eq!(
+        ScanResult::new(514, PortState::Open).service_name(),
+        Some("shell")
+    );
+}
+
+#[test]
+fn unknown_lookups() {
+    assert_eq!(services::port_for("nope", Tcp), None);
+    assert_eq!(services::name_for(3, Tcp), None);
+}
+
+#[test]
+fn site_files_rename_and_add_ports() {
+    let mut table = Services::builtin();
+    let site = Services::parse(
+        "# ours\n\
+         billing    7001/tcp   invoices   # the billing api\n\
+         web-proxy  8080/tcp\n\
+         gossip     7946/sctp\n\
+         http-mgmt  9090/tcp  0.000123  # nmap-services style\n",
+    )
+    .unwrap();
+    table.extend(site);
+    assert_eq!(table.name_for(7001, Tcp), Some("billing"));
+    assert_eq!(table.port_for("INVOICES", Tcp), Some(7001));
+    assert_eq!(table.name_for(8080, Tcp), Some("web-proxy"));
+    assert_eq!(table.name_for(22, Tcp), Some("ssh"));
+    assert_eq!(table.port_for("0.000123", Tcp), None);
+    assert_eq!(table.name_for(7946, Udp), None);
+
+    for (text, error) in [
+        ("billing\n", "line 1: expected 'name port/protocol'"),
+        ("# x\nbilling 70000/tcp\n", "line 2: invalid port '70000'"),
+    ] {
+        let err = Services::parse(text).unwrap_err();
+        assert!(err.contains(error), "{err}");
+    }
+}
+
+#[test]
+fn reads_the_iana_registry_csv() {
+    // rows as iana.org writes them: quoted descriptions with commas and
+    // line breaks, unnamed reserved ports, ranges and other protocols
+    let registry = "\u{feff}Service Name,Port Number,Transport Protocol,Description,Assignee,\
+Contact,Registration Date,Modification Date,Reference,Service Code,Unauthorized Use Reported,\
+Assignment Notes\r\n\
+,0,tcp,Reserved,[Jon_Postel],[Jon_Postel],,,,,,\r\n\
+tcpmux,1,tcp,TCP Port Service Multiplexer,[Mark_Lottor],[Mark_Lottor],,,,,,\r\n\
+ssh,22,tcp,The Secure Shell (SSH) Protocol,,,,,[RFC4251],,,\"Defined TXT keys: u=<username>, p=<password>\"\r\n\
+x11,6000-6063,tcp,\"X Window System,\nover tcp\",[Stephen_Gildea],,,,,,,\r\n\
+syslog,514,udp,,,,,,,,,\r\n\
+diameter,3868,sctp,DIAMETER,,,,,,,,\r\n";
+    let table = Services::parse(registry).unwrap();
+    assert_eq!(table.name_for(0, Tcp), None);
+    assert_eq!(table.name_for(1, Tcp), Some("tcpmux"));
+    assert_eq!(table.name_for(22, Tcp), Some("ssh"));
+    assert_eq!(table.name_for(6010, Tcp), Some("x11"));
+    assert_eq!(table.port_for("X11", Tcp), Some(6000));
+    assert_eq!(table.name_for(514, Udp), Some("syslog"));
+    assert_eq!(table.port_for("diameter", Tcp), None);
+
+    let bad = "Service Name,Port Number,Transport Protocol\nssh,22-x,tcp\n";
+    let err = Services::parse(bad).unwrap_err();
+    assert!(err.contains("line 2: invalid port '22-x'"), "{err}");
+    let unterminated = "Service Name,Port Number,Transport Protocol\nssh,22,tcp,\"oops\n";
+    let err = Services::parse(unterminated).unwrap_err();
+    assert!(err.contains("line 2: unterminated"), "{err}");
+}
+
+#[test]
+fn common_ports_go_first() {
+    let mut ports: Vec<u16> = (1..=1000).collect();
+    services::common_first(&mut ports, false);
+    assert_eq!(&ports[..4], &[80, 23, 443, 21]);
+    // the rest stay in the order they were in
+    let tail: Vec<u16> = ports
+        .iter()
+        .copied()
+        .filter(|p| !services::TOP_PORTS.contains(p))
+        .collect();
+    assert!(tail.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(ports.len(), 1000);
+
+    let mut ports = vec![5000, 161, 53];
+    services::common_first(&mut ports, true);
+    assert_eq!(ports, [161, 53, 5000]);
+}
@@ -0,0 +1,122 @@
+use port_scanner::{SmtpConfig, SmtpTls};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+
+#[test]
+fn smtp_config_from_toml() {
+    let config = SmtpConfig::parse(
+        r#"
+        [smtp]
+        host = "smtp.example.com"
+        from = "pscan@example.com"
+        username = "pscan"
+        password = "hunter2"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.host, "smtp.example.com");
+    assert_eq!(config.tls, SmtpTls::StartTls);
+    assert_eq!(config.port, 587);
+    assert_eq!(config.username.as_deref(), Some("pscan"));
+
+    let implicit =
+        SmtpConfig::parse("[smtp]\nhost = \"h\"\nfrom = \"f@h\"\ntls = \"implicit\"\n").unwrap();
+    assert_eq!(implicit.port, 465);
+
+    for (text, error) in [
+        ("[smtp]\nfrom = \"f@h\"\n", "smtp.host is missing"),
+        ("host = \"h\"\n", "no [smtp] table"),
+        (
+            "[smtp]\nhost = \"h\"\nfrom = \"f@h\"\ntls = \"ssl\"\n",
+            "smtp.tls 'ssl'",
+        ),
+        (
+            "[smtp]\nhost = \"h\"\nfrom = \"f@h\"\nport = 70000\n",
+            "out of range",
+        ),
+        (
+            "[smtp]\nhost = \"h\"\nfrom = \"f@h\"\nusername = \"u\"\n",
+            "both",
+        ),
+        (
+            "[smtp]\nhost = \"h\"\nfrom = \"f@h\"\nuser = \"u\"\n",
+            "line 4: unknown field `user`",
+        ),
+        (
+            "[smtp]\nhost = \"h\"\nfrom = \"f@h\"\ntls = \"none\"\nusername = \"u\"\npassword = \"p\"\n",
+            "line 4: smtp.tls \"none\" would send the password in the clear",
+        ),
+    ] {
+        let err = SmtpConfig::parse(text).unwrap_err();
+        assert!(err.contains(error), "{err}");
+    }
+}
+
+#[test]
+fn smtp_sends_a_message() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut out = stream.try_clone().unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        let mut said = Vec::new();
+        out.write_all(b"220 test ESMTP\r\n").unwrap();
+        while let Some(Ok(line)) = lines.next() {
+            let reply: &[u8] = match line.as_str() {
+                l if l.starts_with("EHLO") => b"250-test\r\n250 AUTH PLAIN\r\n",
+                l if l.starts_with("AUTH") => b"235 ok\r\n",
+                "DATA" => {
+                    said.push(line);
+                    out.write_all(b"354 go on\r\n").unwrap();
+                    for line in lines.by_ref() {
+                        let line = line.unwrap();
+                        if line == "." {
+                            break;
+                        }
+                        said.push(line);
+                    }
+                    out.write_all(b"250 queued\r\n").unwrap();
+                    continue;
+                }
+                "QUIT" => {
+                    out.write_all(b"221 bye\r\n").unwrap();
+                    break;
+                }
+                _ => b"250 ok\r\n",
+            };
+            said.push(line);
+            out.write_all(reply).unwrap();
+        }
+        said
+    });
+
+    let config = SmtpConfig {
+        host: "127.0.0.1".into(),
+        port,
+        tls: SmtpTls::None,
+        from: "pscan@example.com".into(),
+        username: None,
+        password: None,
+    };
+    config
+        .send(
+            &["a@example.com".into(), "b@example.com".into()],
+            "pscan: 1 port opened",
+            "text/plain",
+            "22/tcp open\n",
+        )
+        .unwrap();
+
+    let said = server.join().unwrap();
+    assert_eq!(said[0], "EHLO pscan");
+    assert_eq!(said[1], "MAIL FROM:<pscan@example.com>");
+    assert_eq!(said[2], "RCPT TO:<a@example.com>");
+    assert_eq!(said[3], "RCPT TO:<b@example.com>");
+    assert_eq!(said[4], "DATA");
+    assert!(said.contains(&"Subject: pscan: 1 port opened".to_string()));
+    assert!(said.contains(&"To: a@example.com, b@example.com".to_string()));
+    assert!(said.contains(&"Content-Type: text/plain; charset=utf-8".to_string()));
+    // the body, base64, with crlf line ends as text/* goes by mail
+    assert_eq!(said.last().unwrap(), "MjIvdGNwIG9wZW4NCg==");
+}
@@ -0,0 +1,126 @@
+use port_scanner::{
+    exclude_hosts, expand_targets, parse_exclude_list, parse_target_list, IpNet, ScanError, Target,
+};
+use std::net::IpAddr;
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+#[test]
+fn ipv4_network_skips_network_and_broadcast() {
+    let net: IpNet = "192.168.1.0/30".parse().unwrap();
+    let hosts: Vec<IpAddr> = net.hosts().collect();
+    assert_eq!(hosts, vec![ip("192.168.1.1"), ip("192.168.1.2")]);
+    assert_eq!("10.0.0.0/24".parse::<IpNet>().unwrap().host_count(), 254);
+}
+
+#[test]
+fn small_prefixes_keep_every_address() {
+    let net: IpNet = "10.0.0.4/31".parse().unwrap();
+    assert_eq!(net.host_count(), 2);
+    let net: IpNet = "10.0.0.4/32".parse().unwrap();
+    assert_eq!(net.hosts().collect::<Vec<_>>(), vec![ip("10.0.0.4")]);
+}
+
+#[test]
+fn network_is_normalized() {
+    let net: IpNet = "10.1.2.3/16".parse().unwrap();
+    assert_eq!(net.to_string(), "10.1.0.0/16");
+    assert!(net.contains(ip("10.1.255.1")));
+    assert!(!net.contains(ip("10.2.0.1")));
+}
+
+#[test]
+fn ipv6_network_expands() {
+    let net: IpNet = "2001:db8::/126".parse().unwrap();
+    assert_eq!(net.host_count(), 4);
+}
+
+#[test]
+fn rejects_bad_networks() {
+    assert!("10.0.0.0/33".parse::<IpNet>().is_err());
+    assert!("10.0.0.0/x".parse::<IpNet>().is_err());
+    assert!("nothost/24".parse::<IpNet>().is_err());
+}
+
+#[test]
+fn target_parse_and_expand() {
+    let t: Target = "127.0.0.1".parse().unwrap();
+    assert!(!t.is_network());
+    assert_eq!(t.expand().unwrap(), vec![ip("127.0.0.1")]);
+
+    let t: Target = "127.0.0.0/29".parse().unwrap();
+    assert!(t.is_network());
+    assert_eq!(t.expand().unwrap().len(), 6);
+
+    let t: Target = "10.0.0.0/8".parse().unwrap();
+    assert!(matches!(
+        t.expand(),
+        Err(ScanError::InvalidTarget { target, .. }) if target == "10.0.0.0/8"
+    ));
+}
+
+#[test]
+fn target_list_skips_comments_and_blanks() {
+    let text = "# hosts\n127.0.0.1\n\n  10.0.0.0/30  # lab\n";
+    let targets = parse_target_list(text).unwrap();
+    assert_eq!(targets.len(), 2);
+    assert!(targets[1].is_network());
+
+    let err = parse_target_list("127.0.0.1\n10.0.0.0/99\n").unwrap_err();
+    assert!(err.starts_with("line 2:"));
+}
+
+#[test]
+fn expand_targets_dedupes_and_keeps_names() {
+    let targets: Vec<Target> = ["127.0.0.1", "127.0.0.0/30", "127.0.0.2"]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+    let hosts = expand_targets(&targets, false).unwrap();
+
+    let ips: Vec<IpAddr> = hosts.iter().map(|h| h.ip).collect();
+    assert_eq!(ips, vec![ip("127.0.0.1"), ip("127.0.0.2")]);
+    assert_eq!(hosts[0].names, vec!["127.0.0.1", "127.0.0.0/30"]);
+    assert_eq!(hosts[1].names, vec!["127.0.0.0/30", "127.0.0.2"]);
+}
+
+#[test]
+fn exclusions_remove_hosts() {
+    let targets = vec!["127.0.0.0/29".parse::<Target>().unwrap()];
+    let mut hosts = expand_targets(&targets, false).unwrap();
+    let excluded = parse_exclude_list("127.0.0.1\n127.0.0.4/30 # upper half\n").unwrap();
+    exclude_hosts(&mut hosts, &excluded);
+
+    let ips: Vec<IpAddr> = hosts.iter().map(|h| h.ip).collect();
+    assert_eq!(ips, vec![ip("127.0.0.2"), ip("127.0.0.3")]);
+}
+
+#[test]
+fn exclusion_entries_accept_bare_ips() {
+    let net = IpNet::parse_ip_or_net("10.0.0.5").unwrap();
+    assert_eq!(net.prefix(), 32);
+    assert!(net.contains(ip("10.0.0.5")));
+    assert!(!net.contains(ip("10.0.0.6")));
+    assert!(IpNet::parse_ip_or_net("example.com").is_err());
+}
+
+#[test]
+fn zoned_link_local_targets_keep_their_interface() {
+    let targets: Vec<Target> = ["fe80::1%1", "fe80::1%2", "fe80::1", "fe80::1%1"]
+        .iter()
+        .map(|t| t.parse().unwrap())
+        .collect();
+    let hosts = expand_targets(&targets, false).unwrap();
+    let scopes: Vec<(IpAddr, u32)> = hosts.iter().map(|h| (h.ip, h.scope_id)).collect();
+    assert_eq!(
+        scopes,
+        vec![(ip("fe80::1"), 1), (ip("fe80::1"), 2), (ip("fe80::1"), 0)]
+    );
+    assert_eq!(hosts[0].names, ["fe80::1%1"]);
+
+    let bad = |t: &str| expand_targets(&[t.parse().unwrap()], false).is_err();
+    assert!(bad("fe80::1%no-such-interface"));
+    assert!(bad("10.0.0.1%1"));
+}
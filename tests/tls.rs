@@ -0,0 +1,116 @@
+use port_scanner::{parse_certificate, tls_probe, Engine, Resolver, Scanner};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::{CipherSuite, ServerConfig, ServerConnection, SupportedProtocolVersion};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+// self-signed, CN=test.local, O=Pscan Test, sans test.local, www.test.local, 127.0.0.1
+const CERT: &[u8] = include_bytes!("data/test-cert.der");
+// its p-256 key, pkcs#8
+const KEY: &[u8] = include_bytes!("data/test-key.der");
+
+#[test]
+fn certificate_fields() {
+    let cert = parse_certificate(CERT).unwrap();
+    assert_eq!(cert.subject, "CN=test.local, O=Pscan Test");
+    assert_eq!(cert.issuer, cert.subject);
+    assert_eq!(cert.sans, ["test.local", "www.test.local", "127.0.0.1"]);
+    let not_after = cert.not_after.unwrap().duration_since(UNIX_EPOCH).unwrap();
+    let not_before = cert.not_before.unwrap().duration_since(UNIX_EPOCH).unwrap();
+    assert_eq!((not_after - not_before).as_secs(), 365 * 86_400);
+}
+
+#[test]
+fn garbage_is_not_a_certificate() {
+    assert!(parse_certificate(b"\x30\x03\x02\x01").is_none());
+    assert!(parse_certificate(&[]).is_none());
+}
+
+/// a rustls server showing the test certificate, speaking only
+/// `versions`, with one cipher suite for each
+fn tls_server(versions: &[&'static SupportedProtocolVersion]) -> u16 {
+    let mut provider = rustls::crypto::ring::default_provider();
+    provider.cipher_suites.retain(|s| {
+        matches!(
+            s.suite(),
+            CipherSuite::TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256
+                | CipherSuite::TLS13_AES_128_GCM_SHA256
+        )
+    });
+    let config = ServerConfig::builder_with_provider(Arc::new(provider))
+        .with_protocol_versions(versions)
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![CertificateDer::from(CERT)],
+            PrivateKeyDer::Pkcs8(KEY.into()),
+        )
+        .unwrap();
+    let config = Arc::new(config);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    std::thread::spawn(move || {
+        for mut conn in listener.incoming().flatten() {
+            let config = config.clone();
+            std::thread::spawn(move || {
+                let mut server = ServerConnection::new(config).unwrap();
+                while server.is_handshaking() {
+                    if server.complete_io(&mut conn).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    port
+}
+
+#[test]
+fn probe_reads_hello_and_certificate() {
+    let port = tls_server(&[&rustls::version::TLS12]);
+    let addr = format!("127.0.0.1:{port}").parse().unwrap();
+
+    let info = tls_probe(addr, Some("test.local"), Duration::from_secs(2)).unwrap();
+    assert_eq!(info.version, "TLSv1.2");
+    assert_eq!(info.cipher, "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256");
+    assert_eq!(
+        info.certificate.unwrap().subject,
+        "CN=test.local, O=Pscan Test"
+    );
+}
+
+#[test]
+fn probe_reads_the_certificate_over_tls13() {
+    let port = tls_server(&[&rustls::version::TLS13]);
+    let addr = format!("127.0.0.1:{port}").parse().unwrap();
+
+    let info = tls_probe(addr, Some("test.local"), Duration::from_secs(2)).unwrap();
+    assert_eq!(info.version, "TLSv1.3");
+    assert_eq!(info.cipher, "TLS_AES_128_GCM_SHA256");
+    assert_eq!(info.certificate.unwrap().sans.len(), 3);
+}
+
+#[test]
+fn scanner_attaches_tls_to_open_ports() {
+    let port = tls_server(&[&rustls::version::TLS12]);
+    for engine in [Engine::Threads, Engine::Async] {
+        let scanner = Scanner::builder()
+            .timeout(Duration::from_millis(200))
+            .engine(engine)
+            .tls_probe(Duration::from_secs(2))
+            .build();
+        let results = scanner.scan("127.0.0.1".parse().unwrap(), [port]);
+        let tls = results[0].tls.as_ref().expect("tls details");
+        assert_eq!(tls.version, "TLSv1.2", "{engine}");
+    }
+}
+
+#[test]
+fn dns_over_tls_refuses_an_untrusted_certificate() {
+    let port = tls_server(&[&rustls::version::TLS13]);
+    let resolver = Resolver::over_tls(&format!("127.0.0.1:{port}")).unwrap();
+    let err = resolver.resolve("example.com").unwrap_err().to_string();
+    assert!(err.contains("invalid peer certificate"), "{err}");
+}
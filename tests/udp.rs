@@ -0,0 +1,62 @@
+use port_scanner::{udp_payload, BannerOptions, Engine, PortState, Protocol, Scanner};
+use std::net::UdpSocket;
+use std::time::Duration;
+
+#[test]
+fn well_known_ports_get_protocol_payloads() {
+    // dns: one question, root NS
+    let dns = udp_payload(53);
+    assert_eq!(&dns[4..6], &[0, 1]);
+    assert!(dns.ends_with(&[0, 0, 2, 0, 1]));
+    // ntp: 48-byte v4 client request
+    assert_eq!(udp_payload(123).len(), 48);
+    assert_eq!(udp_payload(123)[0], 0xe3);
+    // snmp: a ber sequence whose length covers the rest
+    let snmp = udp_payload(161);
+    assert_eq!(snmp[0], 0x30);
+    assert_eq!(snmp[1] as usize, snmp.len() - 2);
+    assert!(udp_payload(40000).is_empty());
+}
+
+#[test]
+fn both_engines_classify_udp_ports() {
+    let answering = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let open = answering.local_addr().unwrap().port();
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 1500];
+        while let Ok((_, from)) = answering.recv_from(&mut buf) {
+            let _ = answering.send_to(b"hello\r\n", from);
+        }
+    });
+    // bound but never answers
+    let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let quiet = silent.local_addr().unwrap().port();
+    // nothing bound here: loopback answers with port unreachable
+    let closed = UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    for engine in [Engine::Threads, Engine::Async] {
+        let scanner = Scanner::builder()
+            .protocol(Protocol::Udp)
+            .timeout(Duration::from_millis(300))
+            .engine(engine)
+            .banner(BannerOptions::default())
+            .build();
+        let results = scanner.scan("127.0.0.1".parse().unwrap(), [open, quiet, closed]);
+        let state_of = |port| results.iter().find(|r| r.port == port).unwrap().state;
+        assert_eq!(state_of(open), PortState::Open, "{engine}");
+        assert_eq!(state_of(quiet), PortState::OpenFiltered, "{engine}");
+        assert_eq!(state_of(closed), PortState::Closed, "{engine}");
+        assert!(
+            results.iter().all(|r| r.protocol == Protocol::Udp),
+            "{engine}"
+        );
+
+        let answer = results.iter().find(|r| r.port == open).unwrap();
+        assert_eq!(answer.banner.as_deref(), Some("hello"), "{engine}");
+    }
+    drop(silent);
+}